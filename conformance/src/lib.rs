@@ -0,0 +1,241 @@
+//! Conformance checks which run the same binary protocol command sequences
+//! against a real memcached and memcrsd and diff the responses.
+//!
+//! Real memcached is used only if `MEMCACHED_BIN` environment variable
+//! points to the memcached executable, otherwise checks are skipped.
+pub mod packet;
+
+use integration_tests::{get_free_port, wait_until_listening, TestServer};
+use packet::{opcode, Request, Response};
+use std::fmt;
+use std::net::{SocketAddr, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+/// Environment variable with a path to memcached executable
+pub const MEMCACHED_BIN: &str = "MEMCACHED_BIN";
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Server under test, real memcached process is killed on drop
+pub enum Target {
+    Memcached { addr: SocketAddr, process: Child },
+    Memcrs { server: TestServer },
+}
+
+impl Target {
+    /// Starts memcached if `MEMCACHED_BIN` is set
+    pub fn memcached() -> Option<Target> {
+        let binary = std::env::var(MEMCACHED_BIN).ok()?;
+        let addr = SocketAddr::from(([127, 0, 0, 1], get_free_port()));
+        let process = Command::new(binary)
+            .args(["-l", "127.0.0.1", "-p", &addr.port().to_string(), "-U", "0"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Cannot start memcached");
+        wait_until_listening(addr);
+        Some(Target::Memcached { addr, process })
+    }
+
+    pub fn memcrs() -> Target {
+        Target::Memcrs {
+            server: TestServer::start(),
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        match self {
+            Target::Memcached { addr, .. } => *addr,
+            Target::Memcrs { server } => server.addr(),
+        }
+    }
+}
+
+impl Drop for Target {
+    fn drop(&mut self) {
+        if let Target::Memcached { process, .. } = self {
+            let _ = process.kill();
+            let _ = process.wait();
+        }
+    }
+}
+
+/// Named sequence of requests sent over a single connection
+pub struct Scenario {
+    pub name: &'static str,
+    pub steps: Vec<Request>,
+}
+
+impl Scenario {
+    pub fn new(name: &'static str, steps: Vec<Request>) -> Scenario {
+        Scenario { name, steps }
+    }
+}
+
+/// Responses to a single request, error if server didn't respond
+pub type Exchange = Result<Vec<Response>, String>;
+
+/// Runs a scenario and returns normalized responses for every step,
+/// after the first missing response connection is out of sync
+/// so the remaining steps are not sent.
+pub fn run(addr: SocketAddr, scenario: &Scenario) -> Vec<Exchange> {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(RESPONSE_TIMEOUT)).unwrap();
+    stream.set_nodelay(true).unwrap();
+    let mut last_cas = 0;
+    let mut exchanges = Vec::with_capacity(scenario.steps.len());
+    for (opaque, request) in scenario.steps.iter().enumerate() {
+        let packet = request.encode(opaque as u32, last_cas);
+        let exchange = packet::write_request(&mut stream, &packet)
+            .and_then(|_| read_responses(&mut stream, request.opcode))
+            .map_err(|err| format!("no response: {}", err));
+        if let Ok(responses) = &exchange {
+            if let Some(response) = responses.iter().rev().find(|r| r.cas() != 0) {
+                last_cas = response.cas();
+            }
+        }
+        let failed = exchange.is_err();
+        exchanges.push(exchange.map(|responses| normalize(request.opcode, responses)));
+        if failed {
+            break;
+        }
+    }
+    exchanges
+}
+
+fn read_responses(stream: &mut TcpStream, request_opcode: u8) -> std::io::Result<Vec<Response>> {
+    let mut responses = vec![Response::read_from(stream)?];
+    if request_opcode == opcode::STAT {
+        // stats are terminated by a response with an empty key and body
+        while !responses.last().unwrap().body.is_empty() {
+            responses.push(Response::read_from(stream)?);
+        }
+    }
+    Ok(responses)
+}
+
+/// Masks server specific parts of responses
+fn normalize(request_opcode: u8, mut responses: Vec<Response>) -> Vec<Response> {
+    responses.iter_mut().for_each(Response::normalize_cas);
+    match request_opcode {
+        opcode::VERSION => responses.iter_mut().for_each(mask_body),
+        opcode::STAT => {
+            // stat names and values differ, only presence of stats is compared
+            let terminator = responses.pop();
+            responses.truncate(1);
+            responses.iter_mut().for_each(mask_body);
+            responses.extend(terminator);
+        }
+        _ => {}
+    }
+    responses
+}
+
+fn mask_body(response: &mut Response) {
+    response.header[2..5].fill(0);
+    response.header[8..12].fill(0);
+    response.body.clear();
+}
+
+/// Difference between memcached and memcrsd responses to a single step
+pub struct Discrepancy {
+    pub step: usize,
+    pub request: Request,
+    pub memcached: Option<Exchange>,
+    pub memcrs: Option<Exchange>,
+}
+
+/// Result of a scenario comparison, empty if servers behave the same
+pub struct Report {
+    pub scenario: &'static str,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+impl Report {
+    pub fn is_empty(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+}
+
+/// Compares memcrsd with memcached, returns None if memcached is not available
+pub fn compare(scenario: &Scenario) -> Option<Report> {
+    let memcached = Target::memcached()?;
+    let memcrs = Target::memcrs();
+    let expected = run(memcached.addr(), scenario);
+    let actual = run(memcrs.addr(), scenario);
+
+    let steps = expected.len().max(actual.len());
+    let discrepancies = (0..steps)
+        .filter(|step| expected.get(*step) != actual.get(*step))
+        .map(|step| Discrepancy {
+            step,
+            request: scenario.steps[step].clone(),
+            memcached: expected.get(step).cloned(),
+            memcrs: actual.get(step).cloned(),
+        })
+        .collect();
+    Some(Report {
+        scenario: scenario.name,
+        discrepancies,
+    })
+}
+
+/// Runs a scenario and fails with a diff report on any discrepancy,
+/// scenario is skipped if memcached is not available
+pub fn assert_conforms(scenario: Scenario) {
+    match compare(&scenario) {
+        Some(report) => assert!(report.is_empty(), "{}", report),
+        None => eprintln!(
+            "{}: skipped, set {} to run conformance checks",
+            scenario.name, MEMCACHED_BIN
+        ),
+    }
+}
+
+struct DisplayExchange<'a>(&'a Option<Exchange>);
+
+impl fmt::Display for DisplayExchange<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            None => write!(f, "<not sent>"),
+            Some(Err(err)) => write!(f, "<{}>", err),
+            Some(Ok(responses)) => {
+                for response in responses {
+                    write!(
+                        f,
+                        "[opcode={:#04x} status={:#06x} key_len={} cas={} body={:?}] ",
+                        response.opcode(),
+                        response.status(),
+                        response.key_length(),
+                        if response.cas() == 0 { "unset" } else { "set" },
+                        String::from_utf8_lossy(&response.body),
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "scenario '{}': {} discrepancies",
+            self.scenario,
+            self.discrepancies.len()
+        )?;
+        for discrepancy in &self.discrepancies {
+            writeln!(
+                f,
+                "  step {} opcode {:#04x} key {:?}",
+                discrepancy.step,
+                discrepancy.request.opcode,
+                String::from_utf8_lossy(&discrepancy.request.key)
+            )?;
+            writeln!(f, "    memcached: {}", DisplayExchange(&discrepancy.memcached))?;
+            writeln!(f, "    memcrsd:   {}", DisplayExchange(&discrepancy.memcrs))?;
+        }
+        Ok(())
+    }
+}
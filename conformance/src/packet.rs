@@ -0,0 +1,219 @@
+//! Minimal binary protocol encoder and decoder, written independently of
+//! memcrs codec so both servers are driven by exactly the same bytes.
+use std::io::{self, Read, Write};
+
+pub const HEADER_SIZE: usize = 24;
+const REQUEST_MAGIC: u8 = 0x80;
+
+pub mod opcode {
+    pub const GET: u8 = 0x00;
+    pub const SET: u8 = 0x01;
+    pub const ADD: u8 = 0x02;
+    pub const REPLACE: u8 = 0x03;
+    pub const DELETE: u8 = 0x04;
+    pub const INCREMENT: u8 = 0x05;
+    pub const DECREMENT: u8 = 0x06;
+    pub const FLUSH: u8 = 0x08;
+    pub const NOOP: u8 = 0x0a;
+    pub const VERSION: u8 = 0x0b;
+    pub const APPEND: u8 = 0x0e;
+    pub const PREPEND: u8 = 0x0f;
+    pub const STAT: u8 = 0x10;
+    pub const TOUCH: u8 = 0x1c;
+    pub const GET_AND_TOUCH: u8 = 0x1d;
+}
+
+/// CAS value sent with a request
+#[derive(Debug, Clone, Copy)]
+pub enum Cas {
+    Value(u64),
+    /// CAS returned by the most recent response with non zero CAS,
+    /// CAS values are server specific so they cannot be hardcoded
+    Last,
+}
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub opcode: u8,
+    pub key: Vec<u8>,
+    pub extras: Vec<u8>,
+    pub value: Vec<u8>,
+    pub cas: Cas,
+}
+
+impl Request {
+    fn new(opcode: u8, key: &str) -> Request {
+        Request {
+            opcode,
+            key: key.as_bytes().to_vec(),
+            extras: Vec::new(),
+            value: Vec::new(),
+            cas: Cas::Value(0),
+        }
+    }
+
+    pub fn get(key: &str) -> Request {
+        Request::new(opcode::GET, key)
+    }
+
+    pub fn set(key: &str, value: &str, flags: u32, expiration: u32) -> Request {
+        Request::storage(opcode::SET, key, value, flags, expiration)
+    }
+
+    pub fn add(key: &str, value: &str, flags: u32, expiration: u32) -> Request {
+        Request::storage(opcode::ADD, key, value, flags, expiration)
+    }
+
+    pub fn replace(key: &str, value: &str, flags: u32, expiration: u32) -> Request {
+        Request::storage(opcode::REPLACE, key, value, flags, expiration)
+    }
+
+    pub fn append(key: &str, value: &str) -> Request {
+        Request::new(opcode::APPEND, key).with_value(value)
+    }
+
+    pub fn prepend(key: &str, value: &str) -> Request {
+        Request::new(opcode::PREPEND, key).with_value(value)
+    }
+
+    pub fn increment(key: &str, delta: u64, initial: u64, expiration: u32) -> Request {
+        Request::delta(opcode::INCREMENT, key, delta, initial, expiration)
+    }
+
+    pub fn decrement(key: &str, delta: u64, initial: u64, expiration: u32) -> Request {
+        Request::delta(opcode::DECREMENT, key, delta, initial, expiration)
+    }
+
+    pub fn delete(key: &str) -> Request {
+        Request::new(opcode::DELETE, key)
+    }
+
+    pub fn flush() -> Request {
+        Request::new(opcode::FLUSH, "")
+    }
+
+    pub fn noop() -> Request {
+        Request::new(opcode::NOOP, "")
+    }
+
+    pub fn version() -> Request {
+        Request::new(opcode::VERSION, "")
+    }
+
+    pub fn stat() -> Request {
+        Request::new(opcode::STAT, "")
+    }
+
+    pub fn touch(key: &str, expiration: u32) -> Request {
+        let mut request = Request::new(opcode::TOUCH, key);
+        request.extras = expiration.to_be_bytes().to_vec();
+        request
+    }
+
+    pub fn get_and_touch(key: &str, expiration: u32) -> Request {
+        let mut request = Request::new(opcode::GET_AND_TOUCH, key);
+        request.extras = expiration.to_be_bytes().to_vec();
+        request
+    }
+
+    pub fn with_cas(mut self, cas: Cas) -> Request {
+        self.cas = cas;
+        self
+    }
+
+    fn with_value(mut self, value: &str) -> Request {
+        self.value = value.as_bytes().to_vec();
+        self
+    }
+
+    fn storage(opcode: u8, key: &str, value: &str, flags: u32, expiration: u32) -> Request {
+        let mut request = Request::new(opcode, key).with_value(value);
+        request.extras.extend_from_slice(&flags.to_be_bytes());
+        request.extras.extend_from_slice(&expiration.to_be_bytes());
+        request
+    }
+
+    fn delta(opcode: u8, key: &str, delta: u64, initial: u64, expiration: u32) -> Request {
+        let mut request = Request::new(opcode, key);
+        request.extras.extend_from_slice(&delta.to_be_bytes());
+        request.extras.extend_from_slice(&initial.to_be_bytes());
+        request.extras.extend_from_slice(&expiration.to_be_bytes());
+        request
+    }
+
+    pub fn encode(&self, opaque: u32, last_cas: u64) -> Vec<u8> {
+        let cas = match self.cas {
+            Cas::Value(cas) => cas,
+            Cas::Last => last_cas,
+        };
+        let body_length = self.extras.len() + self.key.len() + self.value.len();
+        let mut packet = Vec::with_capacity(HEADER_SIZE + body_length);
+        packet.push(REQUEST_MAGIC);
+        packet.push(self.opcode);
+        packet.extend_from_slice(&(self.key.len() as u16).to_be_bytes());
+        packet.push(self.extras.len() as u8);
+        packet.push(0); // data type
+        packet.extend_from_slice(&0u16.to_be_bytes()); // vbucket id
+        packet.extend_from_slice(&(body_length as u32).to_be_bytes());
+        packet.extend_from_slice(&opaque.to_be_bytes());
+        packet.extend_from_slice(&cas.to_be_bytes());
+        packet.extend_from_slice(&self.extras);
+        packet.extend_from_slice(&self.key);
+        packet.extend_from_slice(&self.value);
+        packet
+    }
+}
+
+/// Raw response packet split into header and body
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub header: [u8; HEADER_SIZE],
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Response> {
+        let mut header = [0u8; HEADER_SIZE];
+        reader.read_exact(&mut header)?;
+        let body_length = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+        let mut body = vec![0u8; body_length as usize];
+        reader.read_exact(&mut body)?;
+        Ok(Response { header, body })
+    }
+
+    pub fn opcode(&self) -> u8 {
+        self.header[1]
+    }
+
+    pub fn key_length(&self) -> u16 {
+        u16::from_be_bytes([self.header[2], self.header[3]])
+    }
+
+    pub fn status(&self) -> u16 {
+        u16::from_be_bytes([self.header[6], self.header[7]])
+    }
+
+    pub fn cas(&self) -> u64 {
+        let mut cas = [0u8; 8];
+        cas.copy_from_slice(&self.header[16..24]);
+        u64::from_be_bytes(cas)
+    }
+
+    /// CAS values are server specific, only the fact that CAS
+    /// is set is compared
+    pub fn normalize_cas(&mut self) {
+        let normalized: u64 = if self.cas() == 0 { 0 } else { 1 };
+        self.header[16..24].copy_from_slice(&normalized.to_be_bytes());
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.header.to_vec();
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+}
+
+pub fn write_request<W: Write>(writer: &mut W, packet: &[u8]) -> io::Result<()> {
+    writer.write_all(packet)?;
+    writer.flush()
+}
@@ -0,0 +1,139 @@
+use conformance::packet::{Cas, Request};
+use conformance::{assert_conforms, Scenario};
+
+#[test]
+fn get_set() {
+    assert_conforms(Scenario::new(
+        "get_set",
+        vec![
+            Request::get("foo"),
+            Request::set("foo", "bar", 0xCAFE, 0),
+            Request::get("foo"),
+            Request::set("foo", "baz", 0, 0),
+            Request::get("foo"),
+        ],
+    ));
+}
+
+#[test]
+fn add_replace() {
+    assert_conforms(Scenario::new(
+        "add_replace",
+        vec![
+            Request::replace("foo", "bar", 0, 0),
+            Request::add("foo", "bar", 0, 0),
+            Request::add("foo", "baz", 0, 0),
+            Request::replace("foo", "baz", 0, 0),
+            Request::get("foo"),
+        ],
+    ));
+}
+
+#[test]
+fn append_prepend() {
+    assert_conforms(Scenario::new(
+        "append_prepend",
+        vec![
+            Request::append("foo", "_end"),
+            Request::prepend("foo", "begin_"),
+            Request::set("foo", "bar", 0, 0),
+            Request::append("foo", "_end"),
+            Request::prepend("foo", "begin_"),
+            Request::get("foo"),
+        ],
+    ));
+}
+
+#[test]
+fn incr_decr() {
+    assert_conforms(Scenario::new(
+        "incr_decr",
+        vec![
+            Request::increment("counter", 5, 10, 0),
+            Request::increment("counter", 5, 10, 0),
+            Request::decrement("counter", 100, 0, 0),
+            Request::increment("missing", 1, 0, 0xffffffff),
+            Request::set("counter", &u64::MAX.to_string(), 0, 0),
+            Request::increment("counter", 2, 0, 0),
+            Request::set("text", "bar", 0, 0),
+            Request::increment("text", 1, 0, 0),
+        ],
+    ));
+}
+
+#[test]
+fn delete() {
+    assert_conforms(Scenario::new(
+        "delete",
+        vec![
+            Request::delete("foo"),
+            Request::set("foo", "bar", 0, 0),
+            Request::delete("foo"),
+            Request::get("foo"),
+        ],
+    ));
+}
+
+#[test]
+fn flush_all() {
+    assert_conforms(Scenario::new(
+        "flush_all",
+        vec![
+            Request::set("foo", "bar", 0, 0),
+            Request::set("bar", "foo", 0, 0),
+            Request::flush(),
+            Request::get("foo"),
+            Request::get("bar"),
+        ],
+    ));
+}
+
+#[test]
+fn cas() {
+    assert_conforms(Scenario::new(
+        "cas",
+        vec![
+            Request::set("foo", "bar", 0, 0),
+            Request::get("foo"),
+            Request::set("foo", "baz", 0, 0).with_cas(Cas::Last),
+            Request::set("foo", "baz", 0, 0).with_cas(Cas::Value(0xDEAD_BEEF)),
+            Request::get("foo"),
+        ],
+    ));
+}
+
+#[test]
+fn version() {
+    assert_conforms(Scenario::new("version", vec![Request::version()]));
+}
+
+#[test]
+#[ignore = "stats are not implemented by memcrsd yet"]
+fn stats() {
+    assert_conforms(Scenario::new("stats", vec![Request::stat(), Request::noop()]));
+}
+
+#[test]
+fn touch() {
+    assert_conforms(Scenario::new(
+        "touch",
+        vec![
+            Request::touch("foo", 60),
+            Request::set("foo", "bar", 0, 0),
+            Request::touch("foo", 60),
+            Request::get("foo"),
+        ],
+    ));
+}
+
+#[test]
+fn gat() {
+    assert_conforms(Scenario::new(
+        "gat",
+        vec![
+            Request::get_and_touch("foo", 60),
+            Request::set("foo", "bar", 0xCAFE, 0),
+            Request::get_and_touch("foo", 60),
+        ],
+    ));
+}
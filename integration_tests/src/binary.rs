@@ -0,0 +1,55 @@
+//! Minimal binary protocol encoder for tests which need control over
+//! exact bytes sent to a server, i.e. pipelining or partial requests.
+use std::io::Read;
+use std::net::TcpStream;
+
+const HEADER_LENGTH: usize = 24;
+const REQUEST_MAGIC: u8 = 0x80;
+const RESPONSE_MAGIC: u8 = 0x81;
+const OPCODE_GET: u8 = 0x00;
+const OPCODE_SET: u8 = 0x01;
+
+/// Response header fields checked by tests
+#[derive(Debug, PartialEq, Eq)]
+pub struct ResponseHeader {
+    pub status: u16,
+    pub opaque: u32,
+}
+
+fn request(opcode: u8, extras: &[u8], key: &[u8], value: &[u8], opaque: u32) -> Vec<u8> {
+    let body_length = extras.len() + key.len() + value.len();
+    let mut request = vec![REQUEST_MAGIC, opcode];
+    request.extend_from_slice(&(key.len() as u16).to_be_bytes());
+    request.extend_from_slice(&[extras.len() as u8, 0, 0, 0]);
+    request.extend_from_slice(&(body_length as u32).to_be_bytes());
+    request.extend_from_slice(&opaque.to_be_bytes());
+    request.extend_from_slice(&0u64.to_be_bytes());
+    request.extend_from_slice(extras);
+    request.extend_from_slice(key);
+    request.extend_from_slice(value);
+    request
+}
+
+/// Get request, opaque is echoed back in a response
+pub fn get_request(key: &[u8], opaque: u32) -> Vec<u8> {
+    request(OPCODE_GET, &[], key, &[], opaque)
+}
+
+/// Set request without flags and expiration
+pub fn set_request(key: &[u8], value: &[u8], opaque: u32) -> Vec<u8> {
+    request(OPCODE_SET, &[0; 8], key, value, opaque)
+}
+
+/// Reads a single response, its body is skipped
+pub fn read_response(stream: &mut TcpStream) -> ResponseHeader {
+    let mut header = [0u8; HEADER_LENGTH];
+    stream.read_exact(&mut header).unwrap();
+    assert_eq!(header[0], RESPONSE_MAGIC);
+    let body_length = u32::from_be_bytes(header[8..12].try_into().unwrap());
+    let mut body = vec![0u8; body_length as usize];
+    stream.read_exact(&mut body).unwrap();
+    ResponseHeader {
+        status: u16::from_be_bytes(header[6..8].try_into().unwrap()),
+        opaque: u32::from_be_bytes(header[12..16].try_into().unwrap()),
+    }
+}
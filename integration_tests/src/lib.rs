@@ -0,0 +1,261 @@
+//! Helpers for end-to-end tests which exercise memcrsd over a real TCP
+//! connection using the `memcache` client crate.
+pub mod binary;
+
+use memcrs::cache::cache::{Cache, StorageSnapshot};
+use memcrs::memcache::builder::{MemcacheStoreBuilder, MemcacheStoreConfig};
+use memcrs::memcache::stats::Stats;
+use memcrs::memcache::store::MemcStore;
+use memcrs::memcache_server::auth::SaslAuthenticator;
+use memcrs::memcache_server::grpc::{self, AdminService};
+use memcrs::memcache_server::memc_tcp::{MemcacheServerConfig, MemcacheTcpServer, Protocol};
+use memcrs::memcache_server::metrics::{self, MetricsState};
+use memcrs::memcache_server::server_state::ServerState;
+use memcrs::memcache_server::tls::TlsAcceptor;
+use memcrs::server::timer::SystemTimer;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MEMORY_LIMIT: u64 = 64 * 1024 * 1024;
+const ITEM_SIZE_LIMIT: u32 = 1024 * 1024;
+const CONNECTION_LIMIT: u32 = 64;
+const LISTEN_BACKLOG: u32 = 64;
+const CLIENT_TIMEOUT_SECS: u32 = 60;
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default time a single test is allowed to run before it is failed
+pub const TEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// memcrsd server running in a background thread
+pub struct TestServer {
+    addr: SocketAddr,
+    state: Arc<ServerState>,
+    store: Arc<dyn Cache + Send + Sync>,
+    metrics_addr: Option<SocketAddr>,
+    grpc_addr: Option<SocketAddr>,
+}
+
+/// Server config used by `TestServer::start`
+pub fn default_config() -> MemcacheServerConfig {
+    MemcacheServerConfig::new(
+        CLIENT_TIMEOUT_SECS,
+        CONNECTION_LIMIT,
+        ITEM_SIZE_LIMIT,
+        LISTEN_BACKLOG,
+    )
+    .with_protocol(Protocol::Auto)
+}
+
+impl TestServer {
+    /// Starts a server listening on a random ephemeral port on localhost
+    pub fn start() -> TestServer {
+        TestServer::start_with_config(default_config())
+    }
+
+    /// Starts a server with a given config on a random ephemeral port on localhost
+    pub fn start_with_config(config: MemcacheServerConfig) -> TestServer {
+        TestServer::start_with_store_config(config, MemcacheStoreConfig::new(MEMORY_LIMIT))
+    }
+
+    /// Same as start_with_config, but a store is built from store_config
+    pub fn start_with_store_config(
+        config: MemcacheServerConfig,
+        store_config: MemcacheStoreConfig,
+    ) -> TestServer {
+        TestServer::start_server(config, store_config, |server| server)
+    }
+
+    /// Starts a server which serves clients over TLS
+    pub fn start_with_tls(acceptor: Arc<TlsAcceptor>) -> TestServer {
+        TestServer::start_server(
+            default_config(),
+            MemcacheStoreConfig::new(MEMORY_LIMIT),
+            |server| server.with_tls(acceptor),
+        )
+    }
+
+    /// Starts a server which requires binary protocol clients to authenticate
+    pub fn start_with_authenticator(
+        config: MemcacheServerConfig,
+        authenticator: Arc<SaslAuthenticator>,
+    ) -> TestServer {
+        TestServer::start_server(config, MemcacheStoreConfig::new(MEMORY_LIMIT), |server| {
+            server.with_authenticator(authenticator)
+        })
+    }
+
+    /// Starts a server and Prometheus metrics endpoint sharing its stats
+    pub fn start_with_metrics() -> TestServer {
+        let stats = Arc::new(Stats::new());
+        let server_stats = Arc::clone(&stats);
+        let mut server = TestServer::start_server(
+            default_config(),
+            MemcacheStoreConfig::new(MEMORY_LIMIT),
+            |server| server.with_stats(server_stats),
+        );
+        let addr = SocketAddr::from(([127, 0, 0, 1], get_free_port()));
+        let store = Arc::new(MemcStore::new(Arc::clone(&server.store)).with_stats(stats));
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(metrics::serve(addr, Arc::new(MetricsState::new(store))))
+        });
+        wait_until_listening(addr);
+        server.metrics_addr = Some(addr);
+        server
+    }
+
+    /// Starts a server and gRPC admin API sharing its store
+    pub fn start_with_grpc() -> TestServer {
+        let mut server = TestServer::start();
+        let addr = SocketAddr::from(([127, 0, 0, 1], get_free_port()));
+        let store = Arc::new(MemcStore::new(Arc::clone(&server.store)));
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(grpc::serve(addr, AdminService::new(store)))
+        });
+        wait_until_listening(addr);
+        server.grpc_addr = Some(addr);
+        server
+    }
+
+    fn start_server<F>(
+        config: MemcacheServerConfig,
+        store_config: MemcacheStoreConfig,
+        configure: F,
+    ) -> TestServer
+    where
+        F: FnOnce(MemcacheTcpServer) -> MemcacheTcpServer + Send + 'static,
+    {
+        let addr = SocketAddr::from(([127, 0, 0, 1], get_free_port()));
+        let state = Arc::new(ServerState::new(CONNECTION_LIMIT));
+        let server_state = Arc::clone(&state);
+        let timer = Arc::new(SystemTimer::new());
+        let store = MemcacheStoreBuilder::from_config(store_config, timer.clone());
+        let server_store = Arc::clone(&store);
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            let mut server =
+                configure(MemcacheTcpServer::new(config, server_store).with_state(server_state));
+            runtime.block_on(async move {
+                tokio::spawn(async move { timer.run().await });
+                server.run(addr).await
+            })
+        });
+        wait_until_listening(addr);
+        TestServer {
+            addr,
+            state,
+            store,
+            metrics_addr: None,
+            grpc_addr: None,
+        }
+    }
+
+    /// Returns `memcache` client connected to a server using binary protocol
+    pub fn client(&self) -> memcache::Client {
+        memcache::Client::connect(self.url()).unwrap()
+    }
+
+    pub fn url(&self) -> String {
+        format!(
+            "memcache://{}?timeout=5&tcp_nodelay=true&protocol=binary",
+            self.addr
+        )
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Copies current server store state, see `reset_to_snapshot`
+    pub fn take_snapshot(&self) -> StorageSnapshot {
+        self.store.take_snapshot()
+    }
+
+    /// Restores store state, so tests can share a single server
+    pub fn reset_to_snapshot(&self, snapshot: &StorageSnapshot) {
+        self.store.reset_to_snapshot(snapshot)
+    }
+
+    /// Connects to a server with a raw TCP stream, reads time out after 5s
+    pub fn connect(&self) -> TcpStream {
+        let stream = TcpStream::connect(self.addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream
+    }
+
+    /// URL of metrics endpoint of a server started by start_with_metrics
+    pub fn metrics_url(&self) -> String {
+        let addr = self
+            .metrics_addr
+            .expect("Server was started without metrics");
+        format!("http://{}/metrics", addr)
+    }
+
+    /// URL of gRPC admin API of a server started by start_with_grpc
+    pub fn grpc_url(&self) -> String {
+        let addr = self.grpc_addr.expect("Server was started without gRPC");
+        format!("http://{}", addr)
+    }
+
+    /// State shared with a server, i.e. to check connection stats
+    pub fn state(&self) -> &Arc<ServerState> {
+        &self.state
+    }
+}
+
+/// Returns a port which is free at the moment of a call
+pub fn get_free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().port()
+}
+
+/// Blocks until a server accepts connections on addr
+pub fn wait_until_listening(addr: SocketAddr) {
+    let start = Instant::now();
+    while TcpStream::connect(addr).is_err() {
+        if start.elapsed() > STARTUP_TIMEOUT {
+            panic!("Server didn't start listening on {}", addr);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Runs test body in a separate thread and fails if it doesn't
+/// finish within `TEST_TIMEOUT`, so a misbehaving server cannot hang
+/// the whole test suite.
+pub fn with_timeout<F>(test: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        test();
+        let _ = sender.send(());
+    });
+    match receiver.recv_timeout(TEST_TIMEOUT) {
+        Ok(()) => handle.join().unwrap(),
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            // test panicked, propagate its panic
+            if let Err(panic) = handle.join() {
+                std::panic::resume_unwind(panic);
+            }
+        }
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            panic!("Test didn't finish within {:?}", TEST_TIMEOUT)
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use integration_tests::{with_timeout, TestServer};
+use memcrs::memcache_server::grpc::proto::memcache_admin_client::MemcacheAdminClient;
+use memcrs::memcache_server::grpc::proto::{
+    DeleteRequest, Empty, FlushRequest, GetRequest, MemoryLimitRequest,
+};
+
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+#[test]
+fn grpc_admin_api_should_manage_records_of_server() {
+    with_timeout(|| {
+        let server = TestServer::start_with_grpc();
+        let client = server.client();
+        client.set("foo", "bar", 100).unwrap();
+        client.set("baz", "bar", 0).unwrap();
+
+        block_on(async {
+            let mut admin = MemcacheAdminClient::connect(server.grpc_url())
+                .await
+                .unwrap();
+            let stats = admin.get_stats(Empty {}).await.unwrap().into_inner();
+            assert_eq!(stats.curr_items, 2);
+
+            let record = admin
+                .get_key(GetRequest { key: "foo".into() })
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(&record.value[..], b"bar");
+            assert_eq!(record.expiration, 100);
+
+            let deleted = admin
+                .delete_key(DeleteRequest { key: "foo".into() })
+                .await
+                .unwrap()
+                .into_inner();
+            assert!(deleted.deleted);
+            let err = admin
+                .get_key(GetRequest { key: "foo".into() })
+                .await
+                .unwrap_err();
+            assert_eq!(err.code(), tonic::Code::NotFound);
+
+            admin
+                .flush_all(FlushRequest { delay_secs: 0 })
+                .await
+                .unwrap();
+            let stats = admin.get_stats(Empty {}).await.unwrap().into_inner();
+            assert_eq!(stats.curr_items, 0);
+            assert_eq!(stats.cmd_flush_affected_items, 1);
+
+            // a store without eviction policy rejects records over a new limit
+            admin
+                .set_memory_limit(MemoryLimitRequest { bytes: 1 })
+                .await
+                .unwrap();
+        });
+        let value = "x".repeat(1024);
+        assert!(client.set("large", value.as_str(), 0).is_err());
+    });
+}
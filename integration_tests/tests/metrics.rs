@@ -0,0 +1,23 @@
+use integration_tests::{with_timeout, TestServer};
+
+#[test]
+fn metrics_endpoint_should_expose_command_counters() {
+    with_timeout(|| {
+        let server = TestServer::start_with_metrics();
+        let client = server.client();
+        client.set("foo", "bar", 0).unwrap();
+        let _: Option<String> = client.get("foo").unwrap();
+        let _: Option<String> = client.get("missing").unwrap();
+
+        let response = reqwest::blocking::get(server.metrics_url()).unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/plain; version=0.0.4"
+        );
+        let body = response.text().unwrap();
+        assert!(body.contains("memcrs_cmd_get_total 2\n"));
+        assert!(body.contains("memcrs_get_hits_total 1\n"));
+        assert!(body.contains("memcrs_curr_items 1\n"));
+    });
+}
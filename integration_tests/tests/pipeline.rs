@@ -0,0 +1,42 @@
+use integration_tests::binary::{get_request, read_response};
+use integration_tests::{default_config, with_timeout, TestServer};
+use std::io::Write;
+
+const PIPELINE_LENGTH: u32 = 1000;
+fn pipeline(opaques: std::ops::Range<u32>) -> Vec<u8> {
+    opaques
+        .flat_map(|opaque| get_request(b"foo", opaque))
+        .collect()
+}
+
+#[test]
+fn pipelined_responses_should_arrive_before_whole_pipeline_is_sent() {
+    with_timeout(|| {
+        let server = TestServer::start();
+        let mut stream = server.connect();
+        let half = PIPELINE_LENGTH / 2;
+
+        stream.write_all(&pipeline(0..half)).unwrap();
+        // rest of a pipeline is not sent yet
+        assert_eq!(read_response(&mut stream).opaque, 0);
+
+        stream.write_all(&pipeline(half..PIPELINE_LENGTH)).unwrap();
+        for opaque in 1..PIPELINE_LENGTH {
+            assert_eq!(read_response(&mut stream).opaque, opaque);
+        }
+    });
+}
+
+#[test]
+fn deep_pipeline_should_be_flushed_after_max_pipeline_depth() {
+    with_timeout(|| {
+        let server = TestServer::start_with_config(default_config().with_max_pipeline_depth(2));
+        let mut stream = server.connect();
+
+        stream.write_all(&pipeline(0..PIPELINE_LENGTH)).unwrap();
+        for opaque in 0..PIPELINE_LENGTH {
+            assert_eq!(read_response(&mut stream).opaque, opaque);
+        }
+        assert!(server.state().pipeline_flushes() > 0);
+    });
+}
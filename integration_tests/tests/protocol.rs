@@ -0,0 +1,214 @@
+use integration_tests::{with_timeout, TestServer};
+use memcache::MemcacheError;
+use std::collections::HashMap;
+
+type GetsResult = HashMap<String, (Vec<u8>, u32, Option<u64>)>;
+
+fn get_cas(client: &memcache::Client, key: &str) -> u64 {
+    let result: GetsResult = client.gets(&[key]).unwrap();
+    let (_value, _flags, cas) = result.get(key).unwrap();
+    cas.unwrap()
+}
+
+#[test]
+fn set_get_round_trip() {
+    with_timeout(|| {
+        let client = TestServer::start().client();
+        client.set("foo", "bar", 0).unwrap();
+        let value: Option<String> = client.get("foo").unwrap();
+        assert_eq!(value, Some(String::from("bar")));
+
+        let value: Option<String> = client.get("missing").unwrap();
+        assert_eq!(value, None);
+    });
+}
+
+#[test]
+fn cas_update_should_succeed_when_cas_matches() {
+    with_timeout(|| {
+        let client = TestServer::start().client();
+        client.set("foo", "bar", 0).unwrap();
+        let cas = get_cas(&client, "foo");
+        assert!(client.cas("foo", "baz", 0, cas).unwrap());
+
+        let value: Option<String> = client.get("foo").unwrap();
+        assert_eq!(value, Some(String::from("baz")));
+        assert_ne!(get_cas(&client, "foo"), cas);
+    });
+}
+
+#[test]
+fn cas_update_should_fail_when_cas_mismatch() {
+    with_timeout(|| {
+        let client = TestServer::start().client();
+        client.set("foo", "bar", 0).unwrap();
+        let cas = get_cas(&client, "foo");
+        client.set("foo", "concurrent update", 0).unwrap();
+        assert!(!client.cas("foo", "baz", 0, cas).unwrap());
+
+        let value: Option<String> = client.get("foo").unwrap();
+        assert_eq!(value, Some(String::from("concurrent update")));
+    });
+}
+
+#[test]
+fn add_should_fail_when_key_exists() {
+    with_timeout(|| {
+        let client = TestServer::start().client();
+        client.add("foo", "bar", 0).unwrap();
+        assert!(client.add("foo", "baz", 0).is_err());
+
+        let value: Option<String> = client.get("foo").unwrap();
+        assert_eq!(value, Some(String::from("bar")));
+    });
+}
+
+#[test]
+fn replace_should_fail_when_key_not_exists() {
+    with_timeout(|| {
+        let client = TestServer::start().client();
+        assert!(client.replace("foo", "bar", 0).is_err());
+        let value: Option<String> = client.get("foo").unwrap();
+        assert_eq!(value, None);
+
+        client.set("foo", "bar", 0).unwrap();
+        client.replace("foo", "baz", 0).unwrap();
+        let value: Option<String> = client.get("foo").unwrap();
+        assert_eq!(value, Some(String::from("baz")));
+    });
+}
+
+#[test]
+fn append_prepend_should_modify_value() {
+    with_timeout(|| {
+        let client = TestServer::start().client();
+        client.set("foo", "bar", 0).unwrap();
+        client.append("foo", "_end").unwrap();
+        client.prepend("foo", "begin_").unwrap();
+
+        let value: Option<String> = client.get("foo").unwrap();
+        assert_eq!(value, Some(String::from("begin_bar_end")));
+    });
+}
+
+#[test]
+fn incr_decr_should_wrap_and_saturate() {
+    with_timeout(|| {
+        let client = TestServer::start().client();
+        assert_eq!(client.increment("counter", 10).unwrap(), 0);
+        assert_eq!(client.increment("counter", 10).unwrap(), 10);
+        assert_eq!(client.decrement("counter", 3).unwrap(), 7);
+        // decrement never goes below 0
+        assert_eq!(client.decrement("counter", 100).unwrap(), 0);
+
+        // increment wraps around at 64 bits
+        client.set("counter", u64::MAX.to_string(), 0).unwrap();
+        assert_eq!(client.increment("counter", 2).unwrap(), 1);
+    });
+}
+
+#[test]
+fn multi_get_should_return_only_stored_keys() {
+    with_timeout(|| {
+        let client = TestServer::start().client();
+        client.set("key1", "value1", 0).unwrap();
+        client.set("key2", "value2", 0).unwrap();
+
+        let result: HashMap<String, String> = client.gets(&["key1", "missing", "key2"]).unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result["key1"], "value1");
+        assert_eq!(result["key2"], "value2");
+    });
+}
+
+#[test]
+fn flush_should_remove_all_keys() {
+    with_timeout(|| {
+        let client = TestServer::start().client();
+        for i in 0..10 {
+            client.set(&format!("key{}", i), i, 0).unwrap();
+        }
+        client.flush().unwrap();
+        for i in 0..10 {
+            let value: Option<u32> = client.get(&format!("key{}", i)).unwrap();
+            assert_eq!(value, None);
+        }
+    });
+}
+
+#[test]
+fn delete_should_remove_key() {
+    with_timeout(|| {
+        let client = TestServer::start().client();
+        client.set("foo", "bar", 0).unwrap();
+        assert!(client.delete("foo").unwrap());
+        assert!(!client.delete("foo").unwrap());
+        let value: Option<String> = client.get("foo").unwrap();
+        assert_eq!(value, None);
+    });
+}
+
+#[test]
+fn touch_should_update_expiration() {
+    with_timeout(|| {
+        let client = TestServer::start().client();
+        client.set("foo", "bar", 1).unwrap();
+        assert!(client.touch("foo", 60).unwrap());
+        assert!(!client.touch("missing", 60).unwrap());
+    });
+}
+
+#[test]
+fn version_should_return_server_version() {
+    with_timeout(|| {
+        let client = TestServer::start().client();
+        let version = client.version().unwrap();
+        assert_eq!(version.len(), 1);
+        assert_eq!(version[0].1, memcrs::version::MEMCRS_VERSION);
+    });
+}
+
+#[test]
+fn invalid_key_should_be_rejected() {
+    with_timeout(|| {
+        let client = TestServer::start().client();
+        let key = "k".repeat(251);
+        match client.set(&key, "bar", 0) {
+            Err(MemcacheError::ClientError(_)) => {}
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    });
+}
+
+#[test]
+fn incr_on_non_numeric_value_should_return_error() {
+    with_timeout(|| {
+        let client = TestServer::start().client();
+        client.set("foo", "bar", 0).unwrap();
+        match client.increment("foo", 1) {
+            Err(MemcacheError::CommandError(_)) => {}
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    });
+}
+
+#[test]
+fn reset_to_snapshot_should_isolate_tests_sharing_a_server() {
+    with_timeout(|| {
+        let server = TestServer::start();
+        let client = server.client();
+        client.set("base", "value", 0).unwrap();
+        let snapshot = server.take_snapshot();
+
+        // test A
+        client.set("base", "changed", 0).unwrap();
+        client.set("other", "value", 0).unwrap();
+        server.reset_to_snapshot(&snapshot);
+
+        // test B
+        let value: Option<String> = client.get("base").unwrap();
+        assert_eq!(value, Some(String::from("value")));
+        let value: Option<String> = client.get("other").unwrap();
+        assert_eq!(value, None);
+    });
+}
@@ -0,0 +1,93 @@
+use integration_tests::binary::{get_request, read_response};
+use integration_tests::{default_config, with_timeout, TestServer};
+use memcrs::memcache_server::auth::SaslAuthenticator;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+const AUTHENTICATION_ERROR: u16 = 0x20;
+
+/// Credentials file written to a temp directory, removed on drop
+struct UsersFile {
+    path: PathBuf,
+}
+
+impl UsersFile {
+    fn new(name: &str, content: &str) -> UsersFile {
+        let path =
+            std::env::temp_dir().join(format!("memcrs-it-users-{}-{}", name, std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        UsersFile { path }
+    }
+
+    fn authenticator(&self) -> Arc<SaslAuthenticator> {
+        Arc::new(SaslAuthenticator::from_file(&self.path).unwrap())
+    }
+}
+
+impl Drop for UsersFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn client_url(server: &TestServer, credentials: &str) -> String {
+    format!(
+        "memcache://{}@{}?timeout=5&connect_timeout=5&protocol=binary",
+        credentials,
+        server.addr()
+    )
+}
+
+#[test]
+fn authenticated_client_should_set_and_get_value() {
+    with_timeout(|| {
+        let users = UsersFile::new("auth", "alice:secret\n");
+        let server = TestServer::start_with_authenticator(default_config(), users.authenticator());
+
+        let client = memcache::Client::connect(client_url(&server, "alice:secret")).unwrap();
+        client.set("foo", "bar", 0).unwrap();
+        let value: Option<String> = client.get("foo").unwrap();
+        assert_eq!(value, Some(String::from("bar")));
+
+        let wrong_password = memcache::Client::connect(client_url(&server, "alice:wrong"))
+            .and_then(|client| client.get::<String>("foo"));
+        assert!(wrong_password.is_err());
+    });
+}
+
+#[test]
+fn unauthenticated_binary_requests_should_be_refused() {
+    with_timeout(|| {
+        let users = UsersFile::new("refused", "alice:secret\n");
+        let server = TestServer::start_with_authenticator(default_config(), users.authenticator());
+        let mut stream = server.connect();
+        stream.write_all(&get_request(b"foo", 7)).unwrap();
+        let response = read_response(&mut stream);
+        assert_eq!(response.status, AUTHENTICATION_ERROR);
+        assert_eq!(response.opaque, 7);
+    });
+}
+
+#[test]
+fn text_client_should_be_disconnected_unless_unauthenticated_text_is_allowed() {
+    with_timeout(|| {
+        let users = UsersFile::new("text", "alice:secret\n");
+        let server = TestServer::start_with_authenticator(default_config(), users.authenticator());
+        let mut stream = server.connect();
+        stream.write_all(b"get foo\r\n").unwrap();
+        let mut buffer = Vec::new();
+        let _ = stream.read_to_end(&mut buffer);
+        assert!(buffer.is_empty());
+
+        let server = TestServer::start_with_authenticator(
+            default_config().with_unauthenticated_text(true),
+            users.authenticator(),
+        );
+        let mut stream = server.connect();
+        stream.write_all(b"get foo\r\n").unwrap();
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line).unwrap();
+        assert_eq!(line, "END\r\n");
+    });
+}
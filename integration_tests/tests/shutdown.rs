@@ -0,0 +1,91 @@
+use integration_tests::binary::{read_response, set_request};
+use integration_tests::{default_config, with_timeout, TestServer};
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// returns true if a server closed a connection
+fn is_closed(stream: &mut TcpStream) -> bool {
+    let mut buffer = [0u8; 1];
+    match stream.read(&mut buffer) {
+        Ok(0) => true,
+        Ok(_) => false,
+        Err(err) => err.kind() == ErrorKind::ConnectionReset,
+    }
+}
+
+#[test]
+fn in_flight_request_should_complete_after_shutdown() {
+    with_timeout(|| {
+        let server = TestServer::start_with_config(default_config().with_shutdown_timeout_secs(5));
+        let mut stream = server.connect();
+        let request = set_request(b"foo", b"some value", 1);
+        let (head, tail) = request.split_at(request.len() - 4);
+
+        stream.write_all(head).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        server.state().shutdown();
+        thread::sleep(Duration::from_millis(100));
+        stream.write_all(tail).unwrap();
+
+        let response = read_response(&mut stream);
+        assert_eq!(response.status, 0);
+        assert_eq!(response.opaque, 1);
+        assert!(is_closed(&mut stream));
+    });
+}
+
+#[test]
+fn stuck_request_should_be_aborted_after_shutdown_timeout() {
+    with_timeout(|| {
+        let server = TestServer::start_with_config(default_config().with_shutdown_timeout_secs(1));
+        let mut stream = server.connect();
+        let request = set_request(b"foo", b"some value", 1);
+
+        stream.write_all(&request[..request.len() - 4]).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        let start = Instant::now();
+        server.state().shutdown();
+
+        assert!(is_closed(&mut stream));
+        assert!(start.elapsed() < Duration::from_secs(4));
+    });
+}
+
+#[test]
+fn idle_connection_should_be_closed_on_shutdown() {
+    with_timeout(|| {
+        let server = TestServer::start();
+        let mut stream = server.connect();
+        thread::sleep(Duration::from_millis(100));
+        let start = Instant::now();
+        server.state().shutdown();
+
+        assert!(is_closed(&mut stream));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    });
+}
+
+#[test]
+fn large_text_set_should_be_stored_and_answered_after_shutdown() {
+    with_timeout(|| {
+        let server = TestServer::start_with_config(default_config().with_shutdown_timeout_secs(5));
+        let mut stream = server.connect();
+        let value = vec![b'a'; 512 * 1024];
+        let mut request = format!("set foo 0 0 {}\r\n", value.len()).into_bytes();
+        request.extend_from_slice(&value);
+        request.extend_from_slice(b"\r\n");
+        let (head, tail) = request.split_at(request.len() / 2);
+
+        stream.write_all(head).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        server.state().shutdown();
+        thread::sleep(Duration::from_millis(100));
+        stream.write_all(tail).unwrap();
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        assert_eq!(response, b"STORED\r\n");
+    });
+}
@@ -0,0 +1,80 @@
+use integration_tests::{default_config, with_timeout, TestServer};
+use memcrs::memcache_server::auth::SaslAuthenticator;
+use memcrs::memcache_server::signals::{handle_signals, ConfigReloader};
+use std::process::Command;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+
+fn send_hangup() {
+    let status = Command::new("kill")
+        .args(["-HUP", &std::process::id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+fn client_url(server: &TestServer, credentials: &str) -> String {
+    format!(
+        "memcache://{}@{}?timeout=5&connect_timeout=5&protocol=binary",
+        credentials,
+        server.addr()
+    )
+}
+
+#[test]
+fn sighup_should_reload_users_and_keep_serving_clients() {
+    with_timeout(|| {
+        let path = std::env::temp_dir().join(format!("memcrs-it-sighup-{}", std::process::id()));
+        std::fs::write(&path, "alice:secret\n").unwrap();
+        let authenticator = Arc::new(SaslAuthenticator::from_file(&path).unwrap());
+        let probe = Arc::clone(&authenticator);
+        let server =
+            TestServer::start_with_authenticator(default_config(), Arc::clone(&authenticator));
+
+        let state = Arc::clone(server.state());
+        let (registered, on_registered) = std::sync::mpsc::channel();
+        let handler = thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(async move {
+                // a process isn't terminated by SIGHUP once a handler is registered
+                let _hangup = signal(SignalKind::hangup()).unwrap();
+                registered.send(()).unwrap();
+                let (sweep_interval, _) = watch::channel(Duration::ZERO);
+                let reloader =
+                    ConfigReloader::new(sweep_interval).with_authenticator(authenticator);
+                handle_signals(state, reloader).await.unwrap()
+            })
+        });
+        on_registered.recv().unwrap();
+
+        std::fs::write(&path, "bob:other\n").unwrap();
+        // a signal may be received before a handler waits for it, so it is repeated
+        let start = Instant::now();
+        while probe.authenticate_plain(b"\0bob\0other").is_none() {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "Users weren't reloaded"
+            );
+            send_hangup();
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        let client = memcache::Client::connect(client_url(&server, "bob:other")).unwrap();
+        client.set("foo", "bar", 0).unwrap();
+        let value: Option<String> = client.get("foo").unwrap();
+        assert_eq!(value, Some(String::from("bar")));
+        let removed_user = memcache::Client::connect(client_url(&server, "alice:secret"))
+            .and_then(|client| client.get::<String>("foo"));
+        assert!(removed_user.is_err());
+
+        server.state().shutdown();
+        handler.join().unwrap();
+        std::fs::remove_file(path).unwrap();
+    });
+}
@@ -0,0 +1,253 @@
+use integration_tests::binary::get_request;
+use integration_tests::{default_config, with_timeout, TestServer};
+use memcrs::memcache::builder::MemcacheStoreConfig;
+use memcrs::memcache_server::memc_tcp::Protocol;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+
+fn read_lines(stream: &TcpStream, count: usize) -> Vec<String> {
+    let mut reader = BufReader::new(stream);
+    (0..count)
+        .map(|_| {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            line
+        })
+        .collect()
+}
+
+#[test]
+fn text_protocol_set_get_round_trip() {
+    with_timeout(|| {
+        let server = TestServer::start();
+        let mut stream = server.connect();
+        stream
+            .write_all(b"set foo 5 0 3\r\nbar\r\nget foo missing\r\nincr foo 1\r\n")
+            .unwrap();
+        assert_eq!(
+            read_lines(&stream, 5),
+            vec![
+                "STORED\r\n",
+                "VALUE foo 5 3\r\n",
+                "bar\r\n",
+                "END\r\n",
+                "CLIENT_ERROR cannot increment or decrement non-numeric value\r\n",
+            ]
+        );
+    });
+}
+
+#[test]
+fn text_and_binary_clients_should_share_store() {
+    with_timeout(|| {
+        let server = TestServer::start();
+        server.client().set("foo", "bar", 0).unwrap();
+        let mut stream = server.connect();
+        stream.write_all(b"get foo\r\n").unwrap();
+        assert_eq!(
+            read_lines(&stream, 3),
+            vec!["VALUE foo 0 3\r\n", "bar\r\n", "END\r\n"]
+        );
+    });
+}
+
+#[test]
+fn binary_client_should_be_disconnected_if_binary_protocol_is_disabled() {
+    with_timeout(|| {
+        let server = TestServer::start_with_config(default_config().with_protocol(Protocol::Text));
+        let mut stream = server.connect();
+        stream.write_all(&get_request(b"foo", 1)).unwrap();
+        let mut buffer = Vec::new();
+        let _ = stream.read_to_end(&mut buffer);
+        assert!(buffer.is_empty());
+    });
+}
+
+#[test]
+fn text_client_should_be_disconnected_if_only_binary_protocol_is_served() {
+    with_timeout(|| {
+        let server =
+            TestServer::start_with_config(default_config().with_protocol(Protocol::Binary));
+        let mut stream = server.connect();
+        stream.write_all(b"get foo\r\n").unwrap();
+        let mut buffer = Vec::new();
+        let _ = stream.read_to_end(&mut buffer);
+        assert!(buffer.is_empty());
+    });
+}
+
+#[test]
+fn text_protocol_touch_should_extend_expiration() {
+    with_timeout(|| {
+        let server = TestServer::start();
+        let mut stream = server.connect();
+        stream
+            .write_all(b"set foo 0 1 3\r\nbar\r\ntouch foo 60\r\ntouch missing 60\r\n")
+            .unwrap();
+        assert_eq!(
+            read_lines(&stream, 3),
+            vec!["STORED\r\n", "TOUCHED\r\n", "NOT_FOUND\r\n"]
+        );
+        // a record would expire after a second without a touch
+        std::thread::sleep(std::time::Duration::from_millis(2100));
+        stream.write_all(b"get foo\r\n").unwrap();
+        assert_eq!(
+            read_lines(&stream, 3),
+            vec!["VALUE foo 0 3\r\n", "bar\r\n", "END\r\n"]
+        );
+    });
+}
+
+#[test]
+fn text_protocol_gat_should_return_values_and_set_expiration() {
+    with_timeout(|| {
+        let server = TestServer::start();
+        let mut stream = server.connect();
+        stream
+            .write_all(b"set foo 5 0 3\r\nbar\r\ngets foo\r\ngat 1 foo missing\r\n")
+            .unwrap();
+        let lines = read_lines(&stream, 7);
+        assert_eq!(lines[0], "STORED\r\n");
+        let cas = lines[1].trim_end().rsplit(' ').next().unwrap().to_string();
+        assert_eq!(&lines[4..], ["VALUE foo 5 3\r\n", "bar\r\n", "END\r\n"]);
+
+        stream.write_all(b"gats 1 foo\r\n").unwrap();
+        assert_eq!(
+            read_lines(&stream, 3),
+            vec![
+                format!("VALUE foo 5 3 {}\r\n", cas),
+                "bar\r\n".to_string(),
+                "END\r\n".to_string()
+            ]
+        );
+        // gat set expiration to a second
+        std::thread::sleep(std::time::Duration::from_millis(2100));
+        stream.write_all(b"get foo\r\n").unwrap();
+        assert_eq!(read_lines(&stream, 1), vec!["END\r\n"]);
+    });
+}
+
+#[test]
+fn set_should_fail_when_memory_limit_is_reached() {
+    with_timeout(|| {
+        let server = TestServer::start_with_store_config(
+            default_config(),
+            MemcacheStoreConfig::new(1024 * 1024),
+        );
+        let mut stream = server.connect();
+        let value = "x".repeat(1000);
+        let mut stored = 0;
+        let response = loop {
+            let command = format!("set key{} 0 0 {}\r\n{}\r\n", stored, value.len(), value);
+            stream.write_all(command.as_bytes()).unwrap();
+            let response = read_lines(&stream, 1).remove(0);
+            if response != "STORED\r\n" {
+                break response;
+            }
+            stored += 1;
+            assert!(stored < 1024, "memory limit is not enforced");
+        };
+        assert_eq!(response, "SERVER_ERROR out of memory storing object\r\n");
+        assert!(stored > 900);
+
+        stream.write_all(b"delete key0\r\n").unwrap();
+        assert_eq!(read_lines(&stream, 1), vec!["DELETED\r\n"]);
+        let command = format!("set key{} 0 0 {}\r\n{}\r\n", stored, value.len(), value);
+        stream.write_all(command.as_bytes()).unwrap();
+        assert_eq!(read_lines(&stream, 1), vec!["STORED\r\n"]);
+    });
+}
+
+#[test]
+fn text_protocol_stats_should_report_counters() {
+    with_timeout(|| {
+        let server = TestServer::start();
+        let mut stream = server.connect();
+        stream.write_all(b"set foo 0 0 3\r\nbar\r\n").unwrap();
+        assert_eq!(read_lines(&stream, 1), vec!["STORED\r\n"]);
+        stream.write_all(b"get foo\r\nstats\r\n").unwrap();
+        let lines = read_lines(&stream, 26);
+        assert!(lines[3].starts_with("STAT uptime "));
+        assert!(lines.contains(&String::from("STAT cmd_get 1\r\n")));
+        assert!(lines.contains(&String::from("STAT get_hits 1\r\n")));
+        assert!(lines.contains(&String::from("STAT cmd_set 1\r\n")));
+        assert!(lines.contains(&String::from("STAT curr_items 1\r\n")));
+        // requests are counted once they are received
+        assert!(lines.contains(&String::from("STAT bytes_read 36\r\n")));
+        assert!(lines.contains(&String::from("STAT slow_commands 0\r\n")));
+        assert_eq!(lines[25], "END\r\n");
+
+        stream.write_all(b"stats items\r\n").unwrap();
+        let lines = read_lines(&stream, 5);
+        assert_eq!(lines[0], "STAT items:1:number 1\r\n");
+        assert_eq!(lines[4], "END\r\n");
+    });
+}
+
+#[test]
+fn text_protocol_version_should_return_crate_version() {
+    with_timeout(|| {
+        let server = TestServer::start();
+        let mut stream = server.connect();
+        stream.write_all(b"version\r\n").unwrap();
+        assert_eq!(
+            read_lines(&stream, 1),
+            vec![format!("VERSION {}\r\n", memcrs::version::MEMCRS_VERSION)]
+        );
+    });
+}
+
+#[test]
+fn text_protocol_noreply_should_not_send_response() {
+    with_timeout(|| {
+        let server = TestServer::start();
+        let mut stream = server.connect();
+        stream
+            .write_all(b"set foo 0 0 3 noreply\r\nbar\r\n")
+            .unwrap();
+        stream
+            .set_read_timeout(Some(std::time::Duration::from_millis(200)))
+            .unwrap();
+        let mut buffer = [0u8; 16];
+        let err = stream.read(&mut buffer).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+        ));
+
+        // errors are sent even if no reply is requested
+        stream.set_read_timeout(None).unwrap();
+        stream
+            .write_all(b"incr foo 1 noreply\r\nget foo\r\n")
+            .unwrap();
+        assert_eq!(
+            read_lines(&stream, 4),
+            vec![
+                "CLIENT_ERROR cannot increment or decrement non-numeric value\r\n",
+                "VALUE foo 0 3\r\n",
+                "bar\r\n",
+                "END\r\n"
+            ]
+        );
+    });
+}
+
+#[test]
+fn idle_client_should_be_disconnected_after_idle_timeout() {
+    with_timeout(|| {
+        let server = TestServer::start_with_config(default_config().with_idle_timeout_secs(1));
+        let mut stream = server.connect();
+        stream.write_all(b"stats settings\r\n").unwrap();
+        assert_eq!(
+            read_lines(&stream, 2),
+            vec!["STAT idle_timeout 1\r\n", "END\r\n"]
+        );
+
+        std::thread::sleep(std::time::Duration::from_millis(1500));
+        // a write may fail once a server closed a socket
+        let _ = stream.write_all(b"get foo\r\n");
+        let mut buffer = Vec::new();
+        let _ = stream.read_to_end(&mut buffer);
+        assert!(buffer.is_empty());
+    });
+}
@@ -0,0 +1,138 @@
+use integration_tests::{with_timeout, TestServer};
+use memcrs::memcache_server::tls::TlsAcceptor;
+use rustls::crypto::ring;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Self-signed certificate written to a temp directory, removed on drop
+struct Certificate {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+}
+
+impl Certificate {
+    fn generate(name: &str) -> Certificate {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let prefix = format!("memcrs-it-{}-{}", name, std::process::id());
+        let dir = std::env::temp_dir();
+        let certificate = Certificate {
+            cert_path: dir.join(format!("{}.crt", prefix)),
+            key_path: dir.join(format!("{}.key", prefix)),
+        };
+        std::fs::write(&certificate.cert_path, certified.cert.pem()).unwrap();
+        std::fs::write(&certificate.key_path, certified.signing_key.serialize_pem()).unwrap();
+        certificate
+    }
+
+    fn cert_path(&self) -> &str {
+        self.cert_path.to_str().unwrap()
+    }
+
+    fn key_path(&self) -> &str {
+        self.key_path.to_str().unwrap()
+    }
+}
+
+impl Drop for Certificate {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.cert_path);
+        let _ = std::fs::remove_file(&self.key_path);
+    }
+}
+
+// memcache client is used with binary protocol, text protocol
+// requests are sent directly by text_request
+fn tls_url(server: &TestServer, options: &str) -> String {
+    format!(
+        "memcache+tls://{}?timeout=5&connect_timeout=5&verify_mode=none&protocol=binary{}",
+        server.addr(),
+        options
+    )
+}
+
+// a client trusts a self-signed certificate of a server
+fn text_request(
+    server: &TestServer,
+    server_cert: &Certificate,
+    request: &[u8],
+    response_length: usize,
+) -> String {
+    let mut roots = RootCertStore::empty();
+    roots
+        .add(CertificateDer::from_pem_file(&server_cert.cert_path).unwrap())
+        .unwrap();
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let server_name = ServerName::try_from("localhost").unwrap();
+    let connection = ClientConnection::new(Arc::new(config), server_name).unwrap();
+    let socket = std::net::TcpStream::connect(server.addr()).unwrap();
+    socket
+        .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+        .unwrap();
+    let mut stream = StreamOwned::new(connection, socket);
+    stream.write_all(request).unwrap();
+    let mut response = vec![0u8; response_length];
+    stream.read_exact(&mut response).unwrap();
+    String::from_utf8(response).unwrap()
+}
+
+#[test]
+fn tls_client_should_set_and_get_value() {
+    with_timeout(|| {
+        let server_cert = Certificate::generate("server");
+        let acceptor =
+            TlsAcceptor::from_pem_files(&server_cert.cert_path, &server_cert.key_path, None)
+                .unwrap();
+        let server = TestServer::start_with_tls(Arc::new(acceptor));
+
+        let client = memcache::Client::connect(tls_url(&server, "")).unwrap();
+        client.set("foo", "bar", 0).unwrap();
+        let value: Option<String> = client.get("foo").unwrap();
+        assert_eq!(value, Some(String::from("bar")));
+
+        let response = text_request(
+            &server,
+            &server_cert,
+            b"set baz 0 0 3\r\nqux\r\nget baz\r\n",
+            33,
+        );
+        assert_eq!(response, "STORED\r\nVALUE baz 0 3\r\nqux\r\nEND\r\n");
+    });
+}
+
+#[test]
+fn mutual_tls_should_reject_client_without_trusted_certificate() {
+    with_timeout(|| {
+        let server_cert = Certificate::generate("mtls-server");
+        let client_cert = Certificate::generate("mtls-client");
+        let acceptor = TlsAcceptor::from_pem_files(
+            &server_cert.cert_path,
+            &server_cert.key_path,
+            Some(&client_cert.cert_path),
+        )
+        .unwrap();
+        let server = TestServer::start_with_tls(Arc::new(acceptor));
+
+        let anonymous = memcache::Client::connect(tls_url(&server, ""))
+            .and_then(|client| client.set("foo", "bar", 0));
+        assert!(anonymous.is_err());
+
+        // a failed handshake doesn't stop a server
+        let options = format!(
+            "&cert_path={}&key_path={}",
+            client_cert.cert_path(),
+            client_cert.key_path()
+        );
+        let client = memcache::Client::connect(tls_url(&server, &options)).unwrap();
+        client.set("foo", "bar", 0).unwrap();
+        let value: Option<String> = client.get("foo").unwrap();
+        assert_eq!(value, Some(String::from("bar")));
+    });
+}
@@ -0,0 +1,102 @@
+use integration_tests::{default_config, with_timeout};
+use memcrs::memcache::builder::{MemcacheStoreBuilder, MemcacheStoreConfig};
+use memcrs::memcache_server::memc_tcp::MemcacheTcpServer;
+use memcrs::memcache_server::server_state::ServerState;
+use memcrs::server::timer::SystemTimer;
+use std::io::{Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn start_unix_server(path: PathBuf, mode: u32) -> (Arc<ServerState>, thread::JoinHandle<()>) {
+    let state = Arc::new(ServerState::new(16));
+    let server_state = Arc::clone(&state);
+    let socket_path = path.clone();
+    let handle = thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let timer = Arc::new(SystemTimer::new());
+        let store =
+            MemcacheStoreBuilder::from_config(MemcacheStoreConfig::new(1024 * 1024), timer.clone());
+        let mut server = MemcacheTcpServer::new(default_config(), store).with_state(server_state);
+        runtime.block_on(async move {
+            tokio::spawn(async move { timer.run().await });
+            server.run_unix(socket_path, mode).await.unwrap()
+        })
+    });
+    wait_until(|| UnixStream::connect(&path).is_ok());
+    (state, handle)
+}
+
+fn wait_until(condition: impl Fn() -> bool) {
+    let start = Instant::now();
+    while !condition() {
+        if start.elapsed() > Duration::from_secs(5) {
+            panic!("Condition wasn't met within 5s");
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn socket_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("memcrs-it-{}-{}.sock", name, std::process::id()))
+}
+
+fn file_mode(path: &Path) -> u32 {
+    std::fs::metadata(path).unwrap().permissions().mode() & 0o777
+}
+
+#[test]
+fn unix_socket_client_should_set_and_get_value() {
+    with_timeout(|| {
+        let path = socket_path("set-get");
+        let (state, server) = start_unix_server(path.clone(), 0o600);
+        assert_eq!(file_mode(&path), 0o600);
+
+        let client = memcache::Client::connect(format!(
+            "memcache+unix://{}?protocol=binary",
+            path.display()
+        ))
+        .unwrap();
+        client.set("foo", "bar", 0).unwrap();
+        let value: Option<String> = client.get("foo").unwrap();
+        assert_eq!(value, Some(String::from("bar")));
+        drop(client);
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream.write_all(b"get foo\r\n").unwrap();
+        let mut response = [0u8; 25];
+        stream.read_exact(&mut response).unwrap();
+        assert_eq!(&response, b"VALUE foo 0 3\r\nbar\r\nEND\r\n");
+        drop(stream);
+
+        // a socket file is removed on shutdown
+        state.shutdown();
+        server.join().unwrap();
+        assert!(!path.exists());
+    });
+}
+
+#[test]
+fn unix_socket_should_replace_stale_socket_file() {
+    with_timeout(|| {
+        let path = socket_path("stale");
+        // a socket file left by a server which was killed
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let (state, server) = start_unix_server(path.clone(), 0o660);
+        assert_eq!(file_mode(&path), 0o660);
+        state.shutdown();
+        server.join().unwrap();
+        assert!(!path.exists());
+    });
+}
@@ -0,0 +1,552 @@
+use bytes::Bytes;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use memcrs::cache::cache::Cache;
+use memcrs::memcache::builder::{MemcacheStoreBuilder, MemcacheStoreConfig};
+use memcrs::memcache::store::{IncrementParam, KeyType, MemcStore, Meta, Record};
+use memcrs::memory_store::store::{
+    DefaultMemoryStore, FxMemoryStore, MemoryStore, SipMemoryStore, StoreHasher,
+};
+use memcrs::server::timer::SystemTimer;
+use std::cell::Cell;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MEMORY_LIMIT: u64 = 1024 * 1024 * 1024;
+const THREADS: u64 = 4;
+const MULTI_GET_KEYS: usize = 16;
+const LARGE_VALUE_SIZE: usize = 100 * 1024;
+// largest value a store accepts by default
+const MAX_VALUE_SIZE: usize = 1024 * 1024;
+const WARMUP_KEYS: u64 = 1_000_000;
+const WARMUP_AVG_ITEM_SIZE: usize = 256;
+const LARGE_BATCH_KEYS: u64 = 256;
+const MULTI_GET_BATCH_KEYS: u64 = 100;
+const INCR_BATCH_KEYS: u64 = 64;
+const HASHER_BENCH_KEYS: u64 = 10_000;
+const SHARD_COUNTS: [usize; 3] = [64, 256, 1024];
+const SHARD_BENCH_THREADS: u64 = 16;
+#[cfg(feature = "compression")]
+const JSON_VALUE_SIZE: usize = 10 * 1024;
+#[cfg(feature = "compression")]
+const JSON_BENCH_KEYS: u64 = 1000;
+#[cfg(feature = "compression")]
+const COMPRESSION_MIN_SIZE: usize = 1024;
+#[cfg(feature = "rayon")]
+const PARALLEL_GET_BATCH_SIZES: [u64; 5] = [1, 8, 32, 128, 512];
+
+fn create_storage() -> Arc<MemcStore> {
+    let timer = Arc::new(SystemTimer::new());
+    let config = MemcacheStoreConfig::new(MEMORY_LIMIT);
+    Arc::new(MemcStore::new(MemcacheStoreBuilder::from_config(
+        config, timer,
+    )))
+}
+
+fn key(id: u64) -> KeyType {
+    Bytes::from(format!("bench_key_{}", id))
+}
+
+fn value() -> Bytes {
+    Bytes::from_static(b"memcrs benchmark value of a moderate size")
+}
+
+fn populate(storage: &MemcStore, keys: u64) {
+    for id in 0..keys {
+        storage.set(key(id), Record::new(value(), 0, 0, 0)).unwrap();
+    }
+}
+
+/// Runs `op` `iters` times split evenly across `THREADS` threads sharing
+/// the same storage and returns the wall clock time it took.
+fn run_concurrent<F>(storage: &Arc<MemcStore>, iters: u64, op: F) -> Duration
+where
+    F: Fn(&MemcStore, u64, u64) + Send + Sync + Copy + 'static,
+{
+    run_on_threads(storage, THREADS, iters, op)
+}
+
+fn run_on_threads<F>(storage: &Arc<MemcStore>, threads: u64, iters: u64, op: F) -> Duration
+where
+    F: Fn(&MemcStore, u64, u64) + Send + Sync + Copy + 'static,
+{
+    let per_thread = iters / threads + 1;
+    let start = Instant::now();
+    let handles: Vec<_> = (0..threads)
+        .map(|thread_id| {
+            let storage = Arc::clone(storage);
+            thread::spawn(move || {
+                for i in 0..per_thread {
+                    op(&storage, thread_id, i);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    start.elapsed()
+}
+
+fn get_hit(storage: &MemcStore, _thread_id: u64, i: u64) {
+    black_box(storage.get(&key(i % 1024)).unwrap());
+}
+
+fn get_miss(storage: &MemcStore, _thread_id: u64, i: u64) {
+    black_box(storage.get(&key(i + 1024)).unwrap_err());
+}
+
+fn set_no_cas(storage: &MemcStore, _thread_id: u64, i: u64) {
+    black_box(
+        storage
+            .set(key(i % 1024), Record::new(value(), 0, 0, 0))
+            .unwrap(),
+    );
+}
+
+thread_local! {
+    static LAST_CAS: Cell<u64> = const { Cell::new(0) };
+}
+
+fn set_cas_update(storage: &MemcStore, thread_id: u64, _i: u64) {
+    // every thread updates its own key so CAS updates never conflict,
+    // first update in a thread is unconditional as LAST_CAS is 0
+    let cas = LAST_CAS.with(|cas| cas.get());
+    let status = storage
+        .set(key(thread_id + THREADS), Record::new(value(), cas, 0, 0))
+        .unwrap();
+    LAST_CAS.with(|cas| cas.set(status.cas));
+}
+
+fn delete(storage: &MemcStore, thread_id: u64, i: u64) {
+    let key = key(thread_id * 1_000_000_000 + i);
+    storage
+        .set(key.clone(), Record::new(value(), 0, 0, 0))
+        .unwrap();
+    black_box(storage.delete(key, Meta::new(0, 0, 0)).unwrap());
+}
+
+fn increment(storage: &MemcStore, thread_id: u64, _i: u64) {
+    let delta = IncrementParam::from(1);
+    black_box(
+        storage
+            .increment(Meta::new(0, 0, 0), key(thread_id), delta)
+            .unwrap(),
+    );
+}
+
+fn append(storage: &MemcStore, thread_id: u64, i: u64) {
+    // keep values small, otherwise we would measure memcpy of a growing value
+    let key = key(thread_id);
+    if i.is_multiple_of(64) {
+        storage
+            .set(key.clone(), Record::new(value(), 0, 0, 0))
+            .unwrap();
+    }
+    let record = Record::new(Bytes::from_static(b"x"), 0, 0, 0);
+    black_box(storage.append(key, record).unwrap());
+}
+
+fn get_many(storage: &MemcStore, _thread_id: u64, i: u64) {
+    // keys of a multi-key get are looked up with a single Cache::get_many
+    let first = (i * MULTI_GET_KEYS as u64) % 1024;
+    let keys: Vec<KeyType> = (first..first + MULTI_GET_KEYS as u64).map(key).collect();
+    black_box(storage.get_many(&keys));
+}
+
+type Operation = fn(&MemcStore, u64, u64);
+
+fn operations() -> Vec<(&'static str, Operation)> {
+    vec![
+        ("get_hit", get_hit),
+        ("get_miss", get_miss),
+        ("set_no_cas", set_no_cas),
+        ("set_cas_update", set_cas_update),
+        ("delete", delete),
+        ("increment", increment),
+        ("append", append),
+        ("get_many_16", get_many),
+    ]
+}
+
+fn prepare(storage: &MemcStore) {
+    populate(storage, 1024);
+    for thread_id in 0..THREADS {
+        storage
+            .set(key(thread_id), Record::new(Bytes::from("0"), 0, 0, 0))
+            .unwrap();
+    }
+}
+
+fn single_thread_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("storage_single_thread");
+    for (name, op) in operations() {
+        let storage = create_storage();
+        prepare(&storage);
+        let mut i: u64 = 0;
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                op(&storage, 0, i);
+                i += 1;
+            })
+        });
+    }
+    group.finish();
+}
+
+fn concurrent_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("storage_4_threads");
+    for (name, op) in operations() {
+        let storage = create_storage();
+        prepare(&storage);
+        group.bench_function(name, |b| {
+            b.iter_custom(|iters| run_concurrent(&storage, iters, op))
+        });
+    }
+    group.finish();
+}
+
+// every 10th operation of a thread is a set, reads and writes of
+// hot keys are spread over all shards
+fn get_set_mixed(storage: &MemcStore, thread_id: u64, i: u64) {
+    let key = key((thread_id * 7919 + i) % 1024);
+    if i.is_multiple_of(10) {
+        black_box(storage.set(key, Record::new(value(), 0, 0, 0)).unwrap());
+    } else {
+        black_box(storage.get(&key).unwrap());
+    }
+}
+
+fn shard_count_benchmark(c: &mut Criterion) {
+    // more shards mean less contention, but more locks and hash tables
+    let mut group = c.benchmark_group("storage_shard_count");
+    for shard_count in SHARD_COUNTS {
+        let timer = Arc::new(SystemTimer::new());
+        let config = MemcacheStoreConfig::new(MEMORY_LIMIT).with_shard_count(Some(shard_count));
+        let storage = Arc::new(MemcStore::new(MemcacheStoreBuilder::from_config(
+            config, timer,
+        )));
+        populate(&storage, 1024);
+        group.bench_function(format!("mixed_{}_shards", shard_count), |b| {
+            b.iter_custom(|iters| {
+                run_on_threads(&storage, SHARD_BENCH_THREADS, iters, get_set_mixed)
+            })
+        });
+    }
+    group.finish();
+}
+
+type StoreFactory = fn() -> MemoryStore;
+
+fn warmup_benchmark(c: &mut Criterion) {
+    // inserting into an empty store, pre-sized store doesn't have to rehash
+    let mut group = c.benchmark_group("storage_warmup");
+    group.sample_size(10);
+    let keys: Vec<KeyType> = (0..WARMUP_KEYS).map(key).collect();
+    let create_stores: [(&str, StoreFactory); 2] = [
+        ("insert_1m_default_capacity", || {
+            MemoryStore::new(Arc::new(SystemTimer::new()))
+        }),
+        ("insert_1m_with_capacity", || {
+            MemoryStore::with_capacity(
+                Arc::new(SystemTimer::new()),
+                WARMUP_KEYS * WARMUP_AVG_ITEM_SIZE as u64,
+                WARMUP_AVG_ITEM_SIZE,
+            )
+        }),
+    ];
+    for (name, create_store) in create_stores {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                create_store,
+                |store| {
+                    for key in &keys {
+                        store
+                            .set(key.clone(), Record::new(value(), 0, 0, 0))
+                            .unwrap();
+                    }
+                    store
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn get_cas_benchmark(c: &mut Criterion) {
+    // compares polling CAS with fetching a whole record holding a large value
+    let mut group = c.benchmark_group("storage_large_value");
+    let storage = create_storage();
+    let key = key(0);
+    let large_value = Bytes::from(vec![b'x'; LARGE_VALUE_SIZE]);
+    storage
+        .set(key.clone(), Record::new(large_value, 0, 0, 0))
+        .unwrap();
+    group.bench_function("get_100k", |b| {
+        b.iter(|| black_box(storage.get(&key).unwrap()))
+    });
+    group.bench_function("get_cas_100k", |b| {
+        b.iter(|| black_box(storage.get_cas(&key).unwrap()))
+    });
+    group.bench_function("get_value_bytes_100k", |b| {
+        b.iter(|| black_box(storage.get_value_bytes(&key).unwrap()))
+    });
+    // values are reference counted, so a get of a 1MiB value doesn't copy it
+    let max_key = Bytes::from_static(b"max_value");
+    let max_value = Bytes::from(vec![b'x'; MAX_VALUE_SIZE]);
+    storage
+        .set(max_key.clone(), Record::new(max_value, 0, 0, 0))
+        .unwrap();
+    group.bench_function("get_1m", |b| {
+        b.iter(|| black_box(storage.get(&max_key).unwrap()))
+    });
+    group.finish();
+}
+
+fn get_many_benchmark(c: &mut Criterion) {
+    // large batch lookup i.e. by a cache warming process
+    let mut group = c.benchmark_group("storage_get_many");
+    let storage = create_storage();
+    populate(&storage, LARGE_BATCH_KEYS);
+    let keys: Vec<KeyType> = (0..LARGE_BATCH_KEYS).map(key).collect();
+    group.bench_function("get_many_256", |b| {
+        b.iter(|| black_box(storage.get_many(&keys)))
+    });
+    // keys of a multi-key get command are read with a lock per shard
+    let multi_get_keys: Vec<KeyType> = (0..MULTI_GET_BATCH_KEYS).map(key).collect();
+    group.bench_function("get_many_100", |b| {
+        b.iter(|| black_box(storage.get_many(&multi_get_keys)))
+    });
+    group.bench_function("get_100_sequential", |b| {
+        b.iter(|| {
+            for key in &multi_get_keys {
+                black_box(storage.get(key).unwrap());
+            }
+        })
+    });
+    #[cfg(feature = "rayon")]
+    group.bench_function("get_many_parallel_256", |b| {
+        b.iter(|| black_box(storage.get_many_parallel(&keys)))
+    });
+    group.finish();
+}
+
+fn incr_many_benchmark(c: &mut Criterion) {
+    // batch of counters updated for a single event, i.e. by a rate limiter
+    let mut group = c.benchmark_group("storage_incr_many");
+    let storage = create_storage();
+    let keys: Vec<KeyType> = (0..INCR_BATCH_KEYS).map(key).collect();
+    for key in &keys {
+        storage
+            .set(key.clone(), Record::new(Bytes::from("0"), 0, 0, 0))
+            .unwrap();
+    }
+    group.bench_function("increment_64", |b| {
+        b.iter(|| {
+            for key in &keys {
+                black_box(
+                    storage
+                        .increment(Meta::new(0, 0, 0), key.clone(), IncrementParam::from(1))
+                        .unwrap(),
+                );
+            }
+        })
+    });
+    group.bench_function("incr_many_64", |b| {
+        b.iter_batched(
+            || {
+                keys.iter()
+                    .map(|key| (key.clone(), Meta::new(0, 0, 0), IncrementParam::from(1)))
+                    .collect::<Vec<_>>()
+            },
+            |ops| black_box(storage.incr_many(ops)),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+// key shapes: short numeric ids, UUIDs and long URL paths
+fn hasher_bench_keys() -> [(&'static str, Vec<KeyType>); 3] {
+    let keys = |format_key: fn(u64) -> String| {
+        (0..HASHER_BENCH_KEYS)
+            .map(|id| Bytes::from(format_key(id)))
+            .collect()
+    };
+    [
+        ("short", keys(|id| format!("{:08}", id))),
+        (
+            "uuid",
+            keys(|id| format!("{:08x}-94a1-4e6e-b3a2-{:012x}", id, id * 7919)),
+        ),
+        (
+            "path",
+            keys(|id| format!("/static/assets/images/products/{}/{}", id, "x".repeat(160))),
+        ),
+    ]
+}
+
+fn bench_hasher<H: StoreHasher>(
+    group: &mut criterion::BenchmarkGroup<criterion::measurement::WallTime>,
+    name: &str,
+    store: MemoryStore<H>,
+    keys: &[KeyType],
+) {
+    for key in keys {
+        store
+            .set(key.clone(), Record::new(value(), 0, 0, 0))
+            .unwrap();
+    }
+    group.bench_function(name, |b| {
+        b.iter(|| {
+            for key in keys {
+                black_box(store.get(key).unwrap());
+            }
+        })
+    });
+}
+
+fn hasher_benchmark(c: &mut Criterion) {
+    // compares key hash functions on lookups of different key shapes
+    let mut group = c.benchmark_group("storage_hasher");
+    for (shape, keys) in hasher_bench_keys() {
+        let timer = Arc::new(SystemTimer::new());
+        bench_hasher(
+            &mut group,
+            &format!("ahash_{}", shape),
+            DefaultMemoryStore::new(timer.clone()),
+            &keys,
+        );
+        bench_hasher(
+            &mut group,
+            &format!("siphash_{}", shape),
+            SipMemoryStore::with_hasher(timer.clone(), Default::default()),
+            &keys,
+        );
+        bench_hasher(
+            &mut group,
+            &format!("fxhash_{}", shape),
+            FxMemoryStore::with_hasher(timer, Default::default()),
+            &keys,
+        );
+    }
+    group.finish();
+}
+
+#[cfg(feature = "rayon")]
+fn parallel_get_threshold_benchmark(c: &mut Criterion) {
+    // compares sequential and parallel lookup of a batch, parallel lookup
+    // should pay off for batches bigger than DEFAULT_PARALLEL_GET_THRESHOLD
+    let mut group = c.benchmark_group("storage_parallel_get_threshold");
+    let timer = Arc::new(SystemTimer::new());
+    let config = MemcacheStoreConfig::new(MEMORY_LIMIT);
+    let storage = MemcStore::new(MemcacheStoreBuilder::from_config(config, timer))
+        .with_parallel_get_threshold(0);
+    populate(
+        &storage,
+        PARALLEL_GET_BATCH_SIZES[PARALLEL_GET_BATCH_SIZES.len() - 1],
+    );
+    for batch_size in PARALLEL_GET_BATCH_SIZES {
+        let keys: Vec<KeyType> = (0..batch_size).map(key).collect();
+        group.bench_function(format!("sequential_{}", batch_size), |b| {
+            b.iter(|| black_box(storage.get_many(&keys)))
+        });
+        group.bench_function(format!("parallel_{}", batch_size), |b| {
+            b.iter(|| black_box(storage.get_many_parallel(&keys)))
+        });
+    }
+    group.finish();
+}
+
+// criterion_group doesn't support cfg attributes on its targets
+#[cfg(not(feature = "rayon"))]
+fn parallel_get_threshold_benchmark(_c: &mut Criterion) {}
+
+// serialized objects of a moderate entropy, as an application usually caches
+#[cfg(feature = "compression")]
+fn json_value(id: u64) -> Bytes {
+    let mut value = String::from("[");
+    let mut item = 0;
+    while value.len() < JSON_VALUE_SIZE {
+        value.push_str(&format!(
+            "{{\"id\":{},\"user\":\"user_{}\",\"active\":{},\"score\":{:.3},\"tags\":[\"cache\",\"bench\"]}},",
+            item,
+            id * 31 + item,
+            item % 2 == 0,
+            (id + item) as f64 / 7.0
+        ));
+        item += 1;
+    }
+    value.truncate(JSON_VALUE_SIZE - 1);
+    value.push(']');
+    Bytes::from(value)
+}
+
+#[cfg(feature = "compression")]
+fn create_json_storage(compression: bool) -> MemcStore {
+    let timer = Arc::new(SystemTimer::new());
+    let config = MemcacheStoreConfig::new(MEMORY_LIMIT)
+        .with_compression(compression.then_some(COMPRESSION_MIN_SIZE));
+    let storage = MemcStore::new(MemcacheStoreBuilder::from_config(config, timer));
+    for id in 0..JSON_BENCH_KEYS {
+        storage
+            .set(key(id), Record::new(json_value(id), 0, 0, 0))
+            .unwrap();
+    }
+    storage
+}
+
+#[cfg(feature = "compression")]
+fn compression_benchmark(c: &mut Criterion) {
+    // latency overhead of lz4 on 10 KB JSON values, memory saved by it
+    // is printed as criterion only measures time
+    let mut group = c.benchmark_group("storage_compression");
+    for (name, compression) in [("uncompressed", false), ("lz4", true)] {
+        let storage = create_json_storage(compression);
+        println!(
+            "storage_compression/{}: {} values of {} bytes use {} bytes",
+            name,
+            JSON_BENCH_KEYS,
+            JSON_VALUE_SIZE,
+            storage.memory_used()
+        );
+        let value = json_value(0);
+        let id = Cell::new(0);
+        group.bench_function(format!("set_{}", name), |b| {
+            b.iter(|| {
+                let key_id = id.get();
+                id.set((key_id + 1) % JSON_BENCH_KEYS);
+                storage
+                    .set(key(key_id), Record::new(value.clone(), 0, 0, 0))
+                    .unwrap()
+            })
+        });
+        group.bench_function(format!("get_{}", name), |b| {
+            b.iter(|| {
+                let key_id = id.get();
+                id.set((key_id + 1) % JSON_BENCH_KEYS);
+                black_box(storage.get(&key(key_id)).unwrap())
+            })
+        });
+    }
+    group.finish();
+}
+
+#[cfg(not(feature = "compression"))]
+fn compression_benchmark(_c: &mut Criterion) {}
+
+criterion_group!(
+    benches,
+    single_thread_benchmark,
+    concurrent_benchmark,
+    shard_count_benchmark,
+    get_cas_benchmark,
+    warmup_benchmark,
+    get_many_benchmark,
+    incr_many_benchmark,
+    hasher_benchmark,
+    parallel_get_threshold_benchmark,
+    compression_benchmark
+);
+criterion_main!(benches);
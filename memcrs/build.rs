@@ -0,0 +1,15 @@
+// gRPC code of proto/memcrs.proto is checked in, so it is regenerated
+// only with grpc-codegen feature and builds don't need protoc
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc-codegen")]
+    {
+        println!("cargo:rerun-if-changed=proto/memcrs.proto");
+        let descriptors = protox::compile(["memcrs.proto"], ["proto"]).unwrap();
+        tonic_prost_build::configure()
+            .bytes(".")
+            .out_dir("src/memcache_server/grpc")
+            .compile_fds(descriptors)
+            .unwrap();
+    }
+}
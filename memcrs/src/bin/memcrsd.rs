@@ -1,3 +1,5 @@
+#![deny(unsafe_code)]
+
 use log::info;
 use std::env;
 use std::process;
@@ -13,16 +15,29 @@ use jemallocator::Jemalloc;
 #[global_allocator]
 static GLOBAL: Jemalloc = Jemalloc;
 
-fn get_log_level(verbose: u8) -> tracing::Level {
-    // Vary the output based on how many times the user used the "verbose" flag
-    // // (i.e. 'myprog -v -v -v' or 'myprog -vvv' vs 'myprog -v'
-    match verbose {
-        0 => tracing::Level::ERROR,
-        1 => tracing::Level::WARN,
-        2 => tracing::Level::INFO,
-        3 => tracing::Level::DEBUG,
-        _ => tracing::Level::TRACE,
-    }
+#[cfg(feature = "cpu-profiling")]
+fn start_cpu_profiling(output: std::path::PathBuf, duration_secs: Option<u64>) {
+    use memcrs::server::profiler::{profile_cpu, ProfilingStop};
+    let duration = duration_secs.map(std::time::Duration::from_secs);
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Cannot create profiler runtime");
+        match runtime.block_on(profile_cpu(output, duration)) {
+            Ok(ProfilingStop::Elapsed) => {}
+            // a server is shut down gracefully on SIGTERM as well
+            Ok(ProfilingStop::Terminated) => {}
+            Err(err) => {
+                log::error!("CPU profiling failed: {}", err);
+            }
+        }
+    });
+}
+
+#[cfg(not(feature = "cpu-profiling"))]
+fn start_cpu_profiling(_output: std::path::PathBuf, _duration_secs: Option<u64>) {
+    log::warn!("memcrsd was built without cpu-profiling feature, --profile-cpu is ignored");
 }
 
 fn main() {
@@ -31,40 +46,68 @@ fn main() {
     let cli_config = match memcrs::memcache::cli::parser::parse(env::args().collect()) {
         Ok(config) => config,
         Err(err) => {
-            eprint!("{}", err);
+            eprintln!("{}", err.trim_end());
             process::exit(1);
         }
     };
-    // Vary the output based on how many times the user used the "verbose" flag
-    // (i.e. 'myprog -v -v -v' or 'myprog -vvv' vs 'myprog -v'
-    tracing_subscriber::fmt()
-        .with_max_level(get_log_level(cli_config.verbose))
-        .init();
+    if cli_config.print_config_template {
+        print!(
+            "{}",
+            memcrs::memcache::cli::parser::MemcrsArgs::example_config_toml()
+        );
+        return;
+    }
+    let config = cli_config.into_server_config();
+    memcrs::server::logging::init(config.logging.verbose);
 
-    info!("Listen address: {}", cli_config.listen_address.to_string());
-    info!("Listen port: {}", cli_config.port);
-    info!("Connection limit: {}", cli_config.connection_limit);
-    info!("Number of threads: {}", cli_config.threads);
-    info!("Runtime type: {}", cli_config.runtime_type.as_str());
+    if let Some(config_file) = &config.config_file {
+        info!("Config file: {}", config_file.path().display());
+    }
+    info!(
+        "Listen address: {}",
+        config.network.listen_address.to_string()
+    );
+    info!("Listen port: {}", config.network.port);
+    if let Some(unix_socket) = &config.network.unix_socket {
+        info!("Unix socket: {}", unix_socket.path.display());
+    }
+    info!("Connection limit: {}", config.network.connection_limit);
+    info!("Number of threads: {}", config.runtime.threads);
+    info!("Runtime type: {}", config.runtime.runtime_type.as_str());
     info!(
         "Max item size: {}",
-        cli_config
+        config
+            .storage
             .item_size_limit
             .get_appropriate_unit(true)
             .to_string()
     );
     info!(
         "Memory limit: {}",
-        byte_unit::Byte::from_bytes(cli_config.memory_limit.into())
+        byte_unit::Byte::from_bytes(config.storage.memory_limit.into())
             .get_appropriate_unit(true)
             .to_string()
     );
 
+    if let Some(output) = config.profiling.cpu_profile.clone() {
+        start_cpu_profiling(output, config.profiling.duration_secs);
+    }
+
+    // clients which don't finish in time are aborted by listeners,
+    // a process exits regardless once shutdown timeout elapsed
+    let drain_timeout = std::time::Duration::from_secs(config.network.shutdown_timeout_secs as u64);
     let system_timer: Arc<memcrs::server::timer::SystemTimer> =
         Arc::new(memcrs::server::timer::SystemTimer::new());
-    let parent_runtime = memcrs::memcache_server::runtime_builder::create_memcrs_server(
-        cli_config,
+    let (parent_runtime, state) = memcrs::memcache_server::runtime_builder::create_memcrs_server(
+        config,
         system_timer.clone(),
     );
-    parent_runtime.block_on(system_timer.run())
+    parent_runtime.block_on(async move {
+        tokio::select! {
+            _ = system_timer.run() => {}
+            _ = state.wait_for_drain(drain_timeout) => info!("Server shut down"),
+        }
+    });
+    #[cfg(feature = "opentelemetry")]
+    memcrs::server::telemetry::shutdown();
 }
@@ -1,5 +1,6 @@
 use super::error::{CacheError, Result};
 use bytes::Bytes;
+use serde_derive::Serialize;
 
 /// Cache key type
 pub type KeyType = Bytes;
@@ -10,24 +11,70 @@ pub type ValueType = Bytes;
 /// Meta data stored with cache value
 #[derive(Clone, Debug)]
 pub struct CacheMetaData {
-    pub(crate) timestamp: u64,
+    // timer milliseconds when a record was stored, expiration and age
+    // of a record are counted from it
+    pub(crate) inserted_at: u64,
+    // timer milliseconds of a last read or store of a record, reads
+    // update it only if a store tracks accesses
+    pub(crate) accessed_at: u64,
     pub(crate) cas: u64,
     pub(crate) flags: u32,
-    pub(crate) time_to_live: u32,
+    // milliseconds, 0 if a record never expires
+    pub(crate) time_to_live: u64,
+    // logical group of a record, all records of a group
+    // are removed with Cache::flush_by_tag
+    pub(crate) tag: Option<u64>,
+    // value is compressed by CompressedCache, kept apart from flags
+    // as all 32 bits of flags belong to clients
+    #[cfg(feature = "compression")]
+    pub(crate) compressed: bool,
 }
 
 impl CacheMetaData {
+    /// Time to live is in seconds, as sent by clients
     pub fn new(cas: u64, flags: u32, time_to_live: u32) -> CacheMetaData {
         CacheMetaData {
-            timestamp: 0,
+            inserted_at: 0,
+            accessed_at: 0,
             cas,
             flags,
-            time_to_live,
+            time_to_live: time_to_live as u64 * 1000,
+            tag: None,
+            #[cfg(feature = "compression")]
+            compressed: false,
         }
     }
 
+    /// Time to live in seconds, rounded down
     pub fn get_expiration(&self) -> u32 {
-        self.time_to_live
+        (self.time_to_live / 1000) as u32
+    }
+
+    /// Returns true if time to live is set and already elapsed at current_time
+    /// timer milliseconds
+    pub fn is_expired(&self, current_time: u64) -> bool {
+        self.time_to_live != 0 && self.inserted_at + self.time_to_live <= current_time
+    }
+
+    /// Time to live left at current_time timer milliseconds, so a record
+    /// stored again with it keeps its expiration. At least 1 if time to
+    /// live is set, 0 means a record never expires.
+    pub(crate) fn time_to_live_left(&self, current_time: u64) -> u64 {
+        match self.time_to_live {
+            0 => 0,
+            time_to_live => (self.inserted_at + time_to_live)
+                .saturating_sub(current_time)
+                .max(1),
+        }
+    }
+
+    /// Changes time to live so a record expires at deadline timer
+    /// milliseconds, unless it already expires sooner
+    pub(crate) fn expire_at(&mut self, deadline: u64) {
+        let time_to_live = deadline.saturating_sub(self.inserted_at).max(1);
+        if self.time_to_live == 0 || self.time_to_live > time_to_live {
+            self.time_to_live = time_to_live;
+        }
     }
 
     pub const fn len(&self) -> usize {
@@ -52,6 +99,19 @@ impl Record {
         Record { header, value }
     }
 
+    /// Creates a record which belongs to a group of records with a given tag
+    pub fn new_with_tag(
+        value: ValueType,
+        cas: u64,
+        flags: u32,
+        expiration: u32,
+        tag: u64,
+    ) -> Record {
+        let mut record = Record::new(value, cas, flags, expiration);
+        record.header.tag = Some(tag);
+        record
+    }
+
     pub fn len(&self) -> usize {
         self.header.len() + self.value.len()
     }
@@ -59,6 +119,20 @@ impl Record {
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    /// Bytes a record takes in a store, a header and a value handle
+    /// stored inline in a map plus a value, used by memory accounting
+    /// and eviction
+    pub fn size_estimate(&self) -> usize {
+        std::mem::size_of::<CacheMetaData>() + std::mem::size_of::<ValueType>() + self.value.len()
+    }
+
+    /// Bytes of a value allocated on a heap. Bytes has no spare capacity,
+    /// a value is a view of exactly len bytes, but it may point into
+    /// a larger buffer shared with other values, which is not counted.
+    pub fn heap_size(&self) -> usize {
+        self.value.len()
+    }
 }
 
 impl PartialEq for Record {
@@ -69,11 +143,71 @@ impl PartialEq for Record {
 
 /// Result of set operation on cache
 /// cas indicates version stored in cache
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SetStatus {
     pub cas: u64,
 }
 
+// records are moved between server threads, fails to compile
+// if a field which is not thread safe is added
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Record>();
+    assert_send_sync::<CacheMetaData>();
+    assert_send_sync::<SetStatus>();
+};
+
+/// Number of records and bytes stored in a single store shard,
+/// large variance between shards indicates a skewed key space.
+/// bytes_used counts only keys and values, without record headers
+/// counted by Cache::memory_used.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ShardStats {
+    pub shard_id: usize,
+    pub item_count: usize,
+    pub bytes_used: u64,
+}
+
+/// Value sizes below which 50%, 95% and 99% of stored values are
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ValueSizePercentiles {
+    pub p50_value_size: u64,
+    pub p95_value_size: u64,
+    pub p99_value_size: u64,
+}
+
+// index of a power of two bucket with upper bound 2^index holding a size,
+// sizes 0 and 1 are in the first bucket
+fn size_bucket(size: u64) -> usize {
+    if size <= 1 {
+        return 0;
+    }
+    (u64::BITS - (size - 1).leading_zeros()) as usize
+}
+
+// sizes is reordered by a partial sort
+fn size_percentile(sizes: &mut [u64], percentile: usize) -> u64 {
+    if sizes.is_empty() {
+        return 0;
+    }
+    let index = (sizes.len() - 1) * percentile / 100;
+    *sizes.select_nth_unstable(index).1
+}
+
+/// Precondition that has to be met for a conditional set to store a record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetCondition {
+    /// record is stored only if key is not in a store (add)
+    IfNotExists,
+    /// record is stored only if key is already in a store (replace)
+    IfExists,
+    /// record is stored if stored CAS is equal to a given value,
+    /// if key is not in a store record is inserted
+    IfCasMatches(u64),
+    /// record is always stored (set)
+    Unconditional,
+}
+
 /// Read only view over a store
 pub trait CacheReadOnlyView<'a> {
     fn len(&self) -> usize;
@@ -93,8 +227,31 @@ pub mod impl_details {
     }
 }
 
+/// Live records sorted by a key: key => (value, flags, expiration, cas)
+#[cfg(any(test, feature = "debug"))]
+pub type DebugDump = std::collections::BTreeMap<KeyType, (ValueType, u32, u32, u64)>;
+
+/// Copy of all records and CAS counter of a store, see Cache::take_snapshot
+#[derive(Clone, Debug)]
+pub struct StorageSnapshot {
+    pub(crate) records: Vec<(KeyType, Record)>,
+    pub(crate) cas_id: u64,
+}
+
+impl StorageSnapshot {
+    /// Number of records in a snapshot
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
+
 pub type RemoveIfResult = Vec<Option<(KeyType, Record)>>;
 pub type CachePredicate = dyn FnMut(&KeyType, &Record) -> bool;
+pub type RecordPredicate<'a> = dyn Fn(&Record) -> bool + Send + Sync + 'a;
 
 // An abstraction over a generic store key <=> value store
 pub trait Cache: impl_details::CacheImplDetails {
@@ -112,6 +269,43 @@ pub trait Cache: impl_details::CacheImplDetails {
         }
     }
 
+    /// Returns a result of get for each key, in the same order as keys.
+    /// A store may read all keys of a shard under a single lock, so it is
+    /// faster than a get of every key, i.e. for a multi-key get command.
+    fn get_many(&self, keys: &[KeyType]) -> Vec<Result<Record>>;
+
+    /// Same as get, but returns None instead of waiting if a shard holding
+    /// a key is locked, so a caller can retry later. An expired record
+    /// is reported as not found, but is not removed, as removal would
+    /// have to wait for a write lock.
+    fn try_get(&self, key: &KeyType) -> Option<Result<Record>>;
+
+    /// Returns a record together with timer micros at which it was read,
+    /// the time is taken while a record is locked, so it is not
+    /// modified between the read and the time
+    fn get_with_metadata(&self, key: &KeyType) -> Result<(Record, u64)>;
+
+    /// Returns only CAS value associated with a key,
+    /// record value is not cloned
+    fn get_cas(&self, key: &KeyType) -> Result<u64>;
+
+    /// Returns only flags associated with a key,
+    /// record value is not cloned
+    fn get_flags(&self, key: &KeyType) -> Result<u32>;
+
+    /// Returns only value associated with a key, it is a shared
+    /// handle to stored bytes, so value is not copied
+    fn get_value_bytes(&self, key: &KeyType) -> Result<ValueType>;
+
+    /// Changes expiration of a record without changing its value and CAS,
+    /// a new expiration is counted from now. NotFound is returned if key
+    /// doesn't exist or its record already expired.
+    fn touch(&self, key: &KeyType, expiration: u32) -> Result<()>;
+
+    /// Same as touch, but returns a touched record, a record is read
+    /// and touched under a single lock
+    fn get_and_touch(&self, key: &KeyType, expiration: u32) -> Result<Record>;
+
     /// Sets value that will be associated with a store.
     /// If value already exists in a store CAS field is compared
     /// and depending on CAS value comparison value is set or rejected.
@@ -121,10 +315,65 @@ pub trait Cache: impl_details::CacheImplDetails {
     ///   returned with status KeyExists
     fn set(&self, key: KeyType, record: Record) -> Result<SetStatus>;
 
+    /// Sets all records as set does, returns results in the same order
+    /// as items. CAS values of records without CAS are assigned from
+    /// a single reserved range, so they are unique and increasing.
+    fn set_many(&self, items: Vec<(KeyType, Record)>) -> Vec<Result<SetStatus>>;
+
+    /// Sets all records or none of them. Shards of all keys are write
+    /// locked while keys and CAS values are checked and records are
+    /// stored, so other threads see either none or all of the records.
+    /// Repeated keys are invalid. If any item fails nothing is stored
+    /// and TransactionFailed with errors of failed items is returned.
+    fn atomic_multi_set(&self, items: Vec<(KeyType, Record)>) -> Result<Vec<SetStatus>>;
+
+    /// Sets value only if condition is met, check and insert are atomic.
+    ///
+    /// - IfNotExists: if key exists KeyExists is returned
+    /// - IfExists: if key doesn't exist NotFound is returned
+    /// - IfCasMatches: if stored CAS is different KeyExists is returned,
+    ///   stored CAS is equal to given CAS + 1
+    /// - Unconditional: value is always set
+    ///
+    /// Except for IfCasMatches record CAS field is ignored and
+    /// a new CAS value is assigned.
+    fn conditional_set(
+        &self,
+        key: KeyType,
+        record: Record,
+        condition: SetCondition,
+    ) -> Result<SetStatus>;
+
+    /// Replaces a record only if a stored record satisfies a predicate,
+    /// predicate check and replace are atomic:
+    ///
+    /// - if key is not found NotFound is returned
+    /// - if predicate returns false KeyExists is returned
+    fn replace_if(
+        &self,
+        key: KeyType,
+        new_record: Record,
+        predicate: &RecordPredicate<'_>,
+    ) -> Result<SetStatus>;
+
+    /// Swaps a record if stored CAS is equal to expected_cas and returns
+    /// the replaced record, check and swap are atomic:
+    ///
+    /// - if stored CAS is different KeyExists is returned
+    /// - if key is not found record is inserted and None is returned
+    ///   instead of a replaced record
+    /// - stored CAS is equal to expected_cas + 1
+    fn compare_and_swap(
+        &self,
+        key: KeyType,
+        expected_cas: u64,
+        new_record: Record,
+    ) -> Result<(SetStatus, Option<Record>)>;
+
     /// Removes a value associated with a key a returns it to a caller if CAS
     /// value comparison is successful or header.CAS is equal to 0:
     ///
-    /// - if header.CAS != to stored record CAS KeyExists is returned
+    /// - if header.CAS != to stored record CAS CasMismatch is returned
     /// - if key is not found NotFound is returned
     fn delete(&self, key: KeyType, header: CacheMetaData) -> Result<Record>;
 
@@ -133,15 +382,134 @@ pub trait Cache: impl_details::CacheImplDetails {
     /// - if header.ttl is set to 0 values are removed immediately,
     /// - if header.ttl>0 values are removed from a store after
     ///   ttl expiration
-    fn flush(&self, header: CacheMetaData);
+    ///
+    /// Returns number of removed records or records which expiration
+    /// was updated
+    fn flush(&self, header: CacheMetaData) -> usize;
+
+    /// Removes all records tagged with a given tag, untagged records
+    /// and records with other tags are kept.
+    ///
+    /// Returns removed records, so eviction policies can account for them
+    fn flush_by_tag(&self, tag: u64) -> Vec<(KeyType, Record)>;
 
     /// Number of key value pairs stored in store
     fn len(&self) -> usize;
 
+    /// Approximate number of bytes used by stored keys and records
+    fn memory_used(&self) -> u64;
+
+    /// Writes of values longer than this many bytes fail with ValueTooLarge
+    fn max_value_size(&self) -> usize;
+
+    /// Changes memory limit of a store. An eviction policy evicts records
+    /// over a lower limit on following writes, a store without eviction
+    /// rejects writes with OutOfMemory until enough records are removed.
+    fn set_memory_limit(&self, memory_limit: u64) -> Result<()>;
+
+    /// Age in seconds of the least recently stored record,
+    /// expired records are skipped, None if there are no records
+    fn oldest_item_age_secs(&self) -> Option<u64>;
+
+    /// Age in seconds of the most recently stored record,
+    /// expired records are skipped, None if there are no records
+    fn newest_item_age_secs(&self) -> Option<u64>;
+
+    /// Number of expired records which weren't removed yet,
+    /// records are not removed by this call
+    fn count_expired(&self) -> usize;
+
+    /// Removes all expired records, as reads remove expired records
+    /// all of them were never fetched after expiration
+    fn remove_expired_items(&self) -> RemoveIfResult;
+
+    /// Total number of records which expired without being fetched
+    fn expired_unfetched(&self) -> u64;
+
+    /// Total number of records affected by all flushes
+    fn flush_affected_items(&self) -> u64;
+
+    /// Total number of records removed by an eviction policy to make
+    /// room for new ones
+    fn evictions(&self) -> u64;
+
+    /// Timer timestamp when a store was created
+    fn start_time(&self) -> u64;
+
+    /// Current timer timestamp, expiration uses milliseconds of the same timer
+    fn current_time(&self) -> u64;
+
+    /// Monotonic time of a store timer, used to measure durations,
+    /// see Timer::now_instant
+    fn current_instant(&self) -> std::time::Instant;
+
+    /// Current timer milliseconds, the clock records expire by
+    fn current_millis(&self) -> u64;
+
+    /// Records and bytes stored in each shard, ordered by shard id
+    fn per_shard_stats(&self) -> Vec<ShardStats>;
+
+    /// Index of a shard holding a key, the same as shard_id of per_shard_stats
+    fn shard_for_key(&self, key: &[u8]) -> usize;
+
+    /// Up to n randomly picked keys with their access times in timer
+    /// milliseconds, used to approximate LRU eviction. A key may be picked
+    /// more than once and the sample is not uniform, but it is cheap to take.
+    fn get_random_sample(&self, n: usize) -> Vec<(KeyType, u64)>;
+
+    /// Sizes of all stored values in no particular order, expired
+    /// records which weren't removed yet are included
+    fn value_sizes(&self) -> Vec<u64>;
+
+    /// Number of stored values in bucket_count power of two size ranges,
+    /// as (size_upper_bound, count) pairs, upper bounds are 1, 2, 4, ...
+    /// The last bucket counts also values bigger than its upper bound,
+    /// so counts sum up to a number of records.
+    fn stats_histogram(&self, bucket_count: usize) -> Vec<(u64, u64)> {
+        let mut histogram: Vec<(u64, u64)> = (0..bucket_count)
+            .map(|bucket| (1u64.checked_shl(bucket as u32).unwrap_or(u64::MAX), 0))
+            .collect();
+        if let Some(last_bucket) = bucket_count.checked_sub(1) {
+            for size in self.value_sizes() {
+                histogram[size_bucket(size).min(last_bucket)].1 += 1;
+            }
+        }
+        histogram
+    }
+
+    /// Median, 95th and 99th percentile of stored value sizes,
+    /// all are 0 in an empty store
+    fn value_size_percentiles(&self) -> ValueSizePercentiles {
+        let mut sizes = self.value_sizes();
+        ValueSizePercentiles {
+            p50_value_size: size_percentile(&mut sizes, 50),
+            p95_value_size: size_percentile(&mut sizes, 95),
+            p99_value_size: size_percentile(&mut sizes, 99),
+        }
+    }
+
+    /// Returns (expiration time, key) pairs of records which are not
+    /// expired, soonest expiring first and records which never expire,
+    /// with expiration time 0, last. Expiration time is in timer seconds.
+    fn keys_by_expiry(&self) -> Vec<(u64, KeyType)>;
+
+    /// Returns sorted keys which start with prefix, keys of expired
+    /// records which weren't removed yet are included
+    fn prefix_scan(&self, prefix: &[u8]) -> Vec<KeyType>;
+
+    /// Copies all records (including expired ones) and CAS counter,
+    /// shards are copied one by one so concurrent writes may be
+    /// partially visible in a snapshot
+    fn take_snapshot(&self) -> StorageSnapshot;
+
+    /// Removes all records and restores records and CAS counter
+    /// from a snapshot, i.e. to reset a store between tests
+    fn reset_to_snapshot(&self, snapshot: &StorageSnapshot);
+
     fn is_empty(&self) -> bool;
 
     /// Returns a read-only view over a stroe
-    fn as_read_only(&self) -> Box<dyn CacheReadOnlyView>;
+    fn as_read_only(&self) -> Box<dyn CacheReadOnlyView<'_>>;
 
     /// Removes key-value pairs from a store for which
     /// f predicate returns true
@@ -149,4 +517,37 @@ pub trait Cache: impl_details::CacheImplDetails {
 
     /// Removes key value and returns as an option
     fn remove(&self, key: &KeyType) -> Option<(KeyType, Record)>;
+
+    /// Returns all non-expired records sorted by a key, so the whole
+    /// store state can be compared in a deterministic way
+    #[cfg(any(test, feature = "debug"))]
+    fn debug_dump(&self) -> DebugDump;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(inserted_at: u64, time_to_live: u32) -> CacheMetaData {
+        let mut header = CacheMetaData::new(0, 0, time_to_live);
+        header.inserted_at = inserted_at;
+        header
+    }
+
+    #[test]
+    fn header_should_expire_once_time_to_live_elapsed() {
+        // stored at 10s with 5s time to live, times are in milliseconds
+        let header = header(10_000, 5);
+        assert!(!header.is_expired(14_000));
+        assert!(!header.is_expired(14_999));
+        assert!(header.is_expired(15_000));
+        assert!(header.is_expired(16_000));
+    }
+
+    #[test]
+    fn header_without_time_to_live_should_never_expire() {
+        let header = header(10_000, 0);
+        assert!(!header.is_expired(10_000));
+        assert!(!header.is_expired(u64::MAX));
+    }
 }
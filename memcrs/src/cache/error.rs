@@ -1,20 +1,50 @@
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum CacheError {
-    NotFound = 0x01,
-    KeyExists = 0x02,
-    ValueTooLarge = 0x03,
-    InvalidArguments = 0x04,
-    ItemNotStored = 0x05,
-    ArithOnNonNumeric = 0x06,
-    UnkownCommand = 0x81,
-    OutOfMemory = 0x82,
-    NotSupported = 0x83,
-    InternalError = 0x84,
-    Busy = 0x85,
-    TemporaryFailure = 0x86,
+    NotFound,
+    KeyExists,
+    ValueTooLarge,
+    InvalidArguments,
+    ItemNotStored,
+    ArithOnNonNumeric,
+    UnkownCommand,
+    OutOfMemory,
+    NotSupported,
+    InternalError,
+    Busy,
+    TemporaryFailure,
+    /// record exists, but its CAS is different than expected one,
+    /// binary protocol reports it as KeyExists
+    CasMismatch,
+    /// key is too long or contains characters forbidden by a key policy,
+    /// binary protocol reports it as InvalidArguments
+    InvalidKey,
+    /// some items of Cache::atomic_multi_set failed, so nothing is stored,
+    /// holds indexes of the items and their errors
+    TransactionFailed(Vec<(usize, CacheError)>),
 }
 
 impl CacheError {
+    /// Status code of an error, binary protocol responses use it as a status
+    pub const fn code(&self) -> u16 {
+        match self {
+            CacheError::NotFound => 0x01,
+            CacheError::KeyExists => 0x02,
+            CacheError::ValueTooLarge => 0x03,
+            CacheError::InvalidArguments => 0x04,
+            CacheError::ItemNotStored => 0x05,
+            CacheError::ArithOnNonNumeric => 0x06,
+            CacheError::UnkownCommand => 0x81,
+            CacheError::OutOfMemory => 0x82,
+            CacheError::NotSupported => 0x83,
+            CacheError::InternalError => 0x84,
+            CacheError::Busy => 0x85,
+            CacheError::TemporaryFailure => 0x86,
+            CacheError::CasMismatch => 0x87,
+            CacheError::InvalidKey => 0x88,
+            CacheError::TransactionFailed(_) => 0x89,
+        }
+    }
+
     pub fn to_static_string(&self) -> &'static str {
         static NOT_FOUND: &str = "Not found";
         static KEY_EXISTS: &str = "Key exists";
@@ -32,6 +62,9 @@ impl CacheError {
             CacheError::InternalError => "Internal error",
             CacheError::Busy => "Busy",
             CacheError::TemporaryFailure => "Temporary failure",
+            CacheError::CasMismatch => "CAS mismatch",
+            CacheError::InvalidKey => "Invalid key",
+            CacheError::TransactionFailed(_) => "Transaction failed",
         }
     }
 }
@@ -0,0 +1,101 @@
+use super::error::{CacheError, Result};
+
+/// Memcached maximum key length
+pub const MAX_KEY_LENGTH: usize = 250;
+/// Longest key which fits into a binary protocol header
+pub const MAX_BINARY_KEY_LENGTH: usize = u16::MAX as usize;
+
+/// Rules a key has to satisfy to be stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyPolicy {
+    pub max_len: usize,
+    /// if false keys with control characters are rejected
+    pub allow_binary: bool,
+    pub forbidden_bytes: &'static [u8],
+}
+
+impl KeyPolicy {
+    /// Accepts any non empty key up to binary protocol key length limit,
+    /// used when a store is embedded and keys don't come from a text protocol
+    pub const fn permissive() -> KeyPolicy {
+        KeyPolicy {
+            max_len: MAX_BINARY_KEY_LENGTH,
+            allow_binary: true,
+            forbidden_bytes: &[],
+        }
+    }
+}
+
+impl Default for KeyPolicy {
+    /// Same rules as Memcached: up to 250 bytes without whitespace
+    /// and control characters
+    fn default() -> KeyPolicy {
+        KeyPolicy {
+            max_len: MAX_KEY_LENGTH,
+            allow_binary: false,
+            forbidden_bytes: b" ",
+        }
+    }
+}
+
+/// Returns InvalidKey if a key doesn't satisfy a policy
+pub fn validate(key: &[u8], policy: &KeyPolicy) -> Result<()> {
+    if key.is_empty() || key.len() > policy.max_len {
+        return Err(CacheError::InvalidKey);
+    }
+    let invalid_byte = key.iter().any(|byte| {
+        (!policy.allow_binary && byte.is_ascii_control()) || policy.forbidden_bytes.contains(byte)
+    });
+    if invalid_byte {
+        return Err(CacheError::InvalidKey);
+    }
+    Ok(())
+}
+
+/// Returns InvalidKey if a key breaks Memcached rules, see KeyPolicy::default
+pub fn validate_key(key: &[u8]) -> Result<()> {
+    validate(key, &KeyPolicy::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_policy_should_reject_memcached_invalid_keys() {
+        let policy = KeyPolicy::default();
+        assert_eq!(validate(b"foo:bar", &policy), Ok(()));
+        assert_eq!(validate(&[b'a'; MAX_KEY_LENGTH], &policy), Ok(()));
+        for key in [
+            &b""[..],
+            &[b'a'; MAX_KEY_LENGTH + 1],
+            b"foo bar",
+            b"foo\r\n",
+            b"\0",
+        ] {
+            assert_eq!(validate(key, &policy), Err(CacheError::InvalidKey));
+        }
+    }
+
+    #[test]
+    fn validate_key_should_check_memcached_boundaries() {
+        assert_eq!(validate_key(&[b'a'; 250]), Ok(()));
+        assert_eq!(validate_key(&[b'a'; 251]), Err(CacheError::InvalidKey));
+        assert_eq!(validate_key(b"foo bar"), Err(CacheError::InvalidKey));
+        assert_eq!(validate_key(b"foo\x7f"), Err(CacheError::InvalidKey));
+        assert_eq!(validate_key(b"foo\t"), Err(CacheError::InvalidKey));
+        assert_eq!(validate_key(b"~foo!"), Ok(()));
+    }
+
+    #[test]
+    fn permissive_policy_should_accept_binary_keys() {
+        let policy = KeyPolicy::permissive();
+        assert_eq!(validate(b"foo bar\0\r\n", &policy), Ok(()));
+        assert_eq!(validate(&[0xff; MAX_BINARY_KEY_LENGTH], &policy), Ok(()));
+        assert_eq!(
+            validate(&[0xff; MAX_BINARY_KEY_LENGTH + 1], &policy),
+            Err(CacheError::InvalidKey)
+        );
+        assert_eq!(validate(b"", &policy), Err(CacheError::InvalidKey));
+    }
+}
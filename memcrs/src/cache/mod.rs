@@ -1,2 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod cache;
 pub mod error;
+pub mod key_validation;
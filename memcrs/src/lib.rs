@@ -1,3 +1,19 @@
+//! Memcached compatible key value store and server.
+//!
+//! The crate denies `unsafe_code`. Unsafe code is allowed only in modules
+//! named `unsafe_impls`, and each block documents the invariants it relies
+//! on with a SAFETY comment. Code using unsafe under the lint doesn't compile:
+//!
+//! ```compile_fail
+//! #![deny(unsafe_code)]
+//!
+//! fn first_byte(bytes: &[u8]) -> u8 {
+//!     unsafe { *bytes.get_unchecked(0) }
+//! }
+//! # fn main() { first_byte(b"a"); }
+//! ```
+#![deny(unsafe_code)]
+
 #[macro_use]
 extern crate log;
 
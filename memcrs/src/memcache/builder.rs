@@ -1,13 +1,30 @@
+#[cfg(feature = "compression")]
+use super::compressed_cache::CompressedCache;
 use super::eviction_policy::EvictionPolicy;
+use super::lfu_policy::LfuPolicy;
+use super::lru_policy::{LruPolicy, DEFAULT_LRU_SAMPLE_SIZE};
 use super::random_policy::RandomPolicy;
+#[cfg(feature = "opentelemetry")]
+use super::traced_cache::TracedCache;
 use crate::cache::cache::Cache;
-use crate::memory_store::store::MemoryStore;
+use crate::cache::key_validation::KeyPolicy;
+use crate::memory_store::store::{MemoryStore, DEFAULT_MAX_VALUE_SIZE};
 use crate::server::timer;
 use std::sync::Arc;
 
 pub struct MemcacheStoreConfig {
     policy: EvictionPolicy,
     memory_limit: u64,
+    avg_item_size: Option<usize>,
+    key_policy: KeyPolicy,
+    max_value_size: usize,
+    prefix_index: bool,
+    shard_count: Option<usize>,
+    lru_sample_size: usize,
+    #[cfg(feature = "opentelemetry")]
+    tracing: bool,
+    #[cfg(feature = "compression")]
+    compression_min_size: Option<usize>,
 }
 
 impl MemcacheStoreConfig {
@@ -15,8 +32,76 @@ impl MemcacheStoreConfig {
         MemcacheStoreConfig {
             policy: EvictionPolicy::None,
             memory_limit,
+            avg_item_size: None,
+            key_policy: KeyPolicy::default(),
+            max_value_size: DEFAULT_MAX_VALUE_SIZE,
+            prefix_index: false,
+            shard_count: None,
+            lru_sample_size: DEFAULT_LRU_SAMPLE_SIZE,
+            #[cfg(feature = "opentelemetry")]
+            tracing: false,
+            #[cfg(feature = "compression")]
+            compression_min_size: None,
         }
     }
+
+    pub fn with_policy(mut self, policy: EvictionPolicy) -> MemcacheStoreConfig {
+        self.policy = policy;
+        self
+    }
+
+    /// Pre-sizes a store for memory_limit / avg_item_size records
+    pub fn with_avg_item_size(mut self, avg_item_size: usize) -> MemcacheStoreConfig {
+        self.avg_item_size = Some(avg_item_size);
+        self
+    }
+
+    /// Keeps a sorted key index used by prefix scans, see MemoryStore::with_prefix_index
+    pub fn with_prefix_index(mut self, prefix_index: bool) -> MemcacheStoreConfig {
+        self.prefix_index = prefix_index;
+        self
+    }
+
+    /// Number of store shards, see MemoryStore::with_shard_count,
+    /// DashMap default is used if None
+    pub fn with_shard_count(mut self, shard_count: Option<usize>) -> MemcacheStoreConfig {
+        self.shard_count = shard_count;
+        self
+    }
+
+    /// Number of records, least recently read of which is evicted
+    /// by Lru policy, see LruPolicy::with_sample_size
+    pub fn with_lru_sample_size(mut self, lru_sample_size: usize) -> MemcacheStoreConfig {
+        self.lru_sample_size = lru_sample_size;
+        self
+    }
+
+    /// Rules which keys of stored records have to satisfy, see KeyPolicy
+    pub fn with_key_policy(mut self, key_policy: KeyPolicy) -> MemcacheStoreConfig {
+        self.key_policy = key_policy;
+        self
+    }
+
+    /// Longest value a store accepts, see MemoryStore::with_max_value_size
+    pub fn with_max_value_size(mut self, max_value_size: usize) -> MemcacheStoreConfig {
+        self.max_value_size = max_value_size;
+        self
+    }
+
+    /// Creates a span for every store operation, see TracedCache
+    #[cfg(feature = "opentelemetry")]
+    pub fn with_tracing(mut self, tracing: bool) -> MemcacheStoreConfig {
+        self.tracing = tracing;
+        self
+    }
+
+    /// Compresses values of at least min_size bytes, values are stored
+    /// as they are if None, see CompressedCache
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self, min_size: Option<usize>) -> MemcacheStoreConfig {
+        self.compression_min_size = min_size;
+        self
+    }
 }
 
 #[derive(Default)]
@@ -31,13 +116,58 @@ impl MemcacheStoreBuilder {
         config: MemcacheStoreConfig,
         timer: Arc<dyn timer::Timer + Send + Sync>,
     ) -> Arc<dyn Cache + Send + Sync> {
-        let store_engine = Arc::new(MemoryStore::new(timer));
+        let store_engine = match config.avg_item_size {
+            Some(avg_item_size) => {
+                MemoryStore::with_capacity(timer, config.memory_limit, avg_item_size)
+            }
+            None => MemoryStore::new(timer),
+        }
+        .with_key_policy(config.key_policy)
+        .with_max_value_size(config.max_value_size);
+        let store_engine = match config.shard_count {
+            Some(shard_count) => store_engine.with_shard_count(shard_count),
+            None => store_engine,
+        };
+        let store_engine = if config.prefix_index {
+            store_engine.with_prefix_index()
+        } else {
+            store_engine
+        };
+        // without eviction a store itself rejects writes over a limit
+        let store_engine = if config.policy == EvictionPolicy::None {
+            store_engine.with_memory_limit(config.memory_limit)
+        } else {
+            store_engine
+        };
+        // lru policy orders records by a time of a last access
+        let store_engine = Arc::new(if config.policy == EvictionPolicy::Lru {
+            store_engine.with_access_tracking()
+        } else {
+            store_engine
+        });
         let store: Arc<dyn Cache + Send + Sync> = match config.policy {
             EvictionPolicy::Random => {
                 Arc::new(RandomPolicy::new(store_engine, config.memory_limit))
             }
+            EvictionPolicy::Lfu => Arc::new(LfuPolicy::new(store_engine, config.memory_limit)),
+            EvictionPolicy::Lru => Arc::new(
+                LruPolicy::new(store_engine, config.memory_limit)
+                    .with_sample_size(config.lru_sample_size),
+            ),
             EvictionPolicy::None => store_engine,
         };
+        // above eviction policies, so they count compressed sizes
+        #[cfg(feature = "compression")]
+        let store: Arc<dyn Cache + Send + Sync> = match config.compression_min_size {
+            Some(min_size) => Arc::new(CompressedCache::new(store, min_size)),
+            None => store,
+        };
+        #[cfg(feature = "opentelemetry")]
+        let store: Arc<dyn Cache + Send + Sync> = if config.tracing {
+            Arc::new(TracedCache::new(store))
+        } else {
+            store
+        };
         store
     }
 }
@@ -0,0 +1,247 @@
+//! TOML config file passed with --config, its keys are options of
+//! --print-config-template. Options given on a command line override
+//! options of a file, so a file can be shared by a few servers.
+
+use super::config_map::{error_message, option_args, PROGRAM_NAME};
+use super::parser::MemcrsArgs;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, Parser};
+use serde_derive::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use toml::Spanned;
+
+// options which are not read from a config file
+const SKIPPED_OPTIONS: [&str; 2] = ["config", "print_config_template"];
+
+/// Options of a config file, keys are names of MemcrsArgs fields, i.e.
+/// listen_address of --listen-address. Spans of values are kept, so
+/// errors point at lines of a file.
+#[derive(Deserialize, Debug)]
+#[serde(transparent)]
+pub struct MemcrsConfig {
+    options: BTreeMap<String, Spanned<toml::Value>>,
+}
+
+/// Config file and command line it was passed on, it is loaded again
+/// when a config is reloaded
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    path: PathBuf,
+    command_line: Vec<String>,
+}
+
+// 1-based line of a byte offset
+fn line_of(content: &str, offset: usize) -> usize {
+    content[..offset.min(content.len())].matches('\n').count() + 1
+}
+
+// values are passed as on a command line, so strings are not quoted
+fn option_value(name: &str, value: &toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(value) => Ok(value.clone()),
+        toml::Value::Integer(value) => Ok(value.to_string()),
+        toml::Value::Float(value) => Ok(value.to_string()),
+        toml::Value::Boolean(value) => Ok(value.to_string()),
+        _ => Err(format!(
+            "invalid value of '{}', expected string, number or boolean",
+            name
+        )),
+    }
+}
+
+impl MemcrsConfig {
+    /// Parses a TOML document, options are validated by into_args
+    pub fn from_toml(content: &str) -> Result<MemcrsConfig, String> {
+        toml::from_str(content).map_err(|err| match err.span() {
+            Some(span) => format!("line {}: {}", line_of(content, span.start), err.message()),
+            None => err.message().to_string(),
+        })
+    }
+
+    /// Returns long options of a command line, options present in matches
+    /// of a command line are skipped, so they override values of a file.
+    /// Content is a document a config was parsed from.
+    fn into_args(self, content: &str, matches: &ArgMatches) -> Result<Vec<String>, Vec<String>> {
+        let command = MemcrsArgs::command();
+        let mut args = Vec::new();
+        let mut errors = Vec::new();
+        for (name, value) in self.options {
+            let line = line_of(content, value.span().start);
+            let arg = command.get_arguments().find(|arg| {
+                arg.get_id() == name.as_str() && !SKIPPED_OPTIONS.contains(&name.as_str())
+            });
+            let long = match arg.and_then(|arg| arg.get_long()) {
+                Some(long) => long,
+                None => {
+                    errors.push(format!("line {}: unexpected option '{}'", line, name));
+                    continue;
+                }
+            };
+            if matches.value_source(&name) == Some(ValueSource::CommandLine) {
+                continue;
+            }
+            // options are validated one by one, so an error has a line
+            let option_args = option_value(&name, value.get_ref())
+                .map_err(|err| vec![err])
+                .and_then(|value| option_args(vec![(long.to_string(), value)]));
+            match option_args {
+                Ok(option_args) => args.extend(option_args),
+                Err(option_errors) => errors.extend(
+                    option_errors
+                        .into_iter()
+                        .map(|err| format!("line {}: {}", line, err)),
+                ),
+            }
+        }
+        if errors.is_empty() {
+            Ok(args)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl ConfigFile {
+    pub fn new(path: PathBuf, command_line: Vec<String>) -> ConfigFile {
+        ConfigFile { path, command_line }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads a file and returns its options merged with a command line,
+    /// defaults are overridden by a file, which is overridden by a command line
+    pub fn load(&self) -> Result<MemcrsArgs, String> {
+        self.read_args().map_err(|errors| {
+            format!(
+                "Invalid config file {}: {}",
+                self.path.display(),
+                errors.join("; ")
+            )
+        })
+    }
+
+    fn read_args(&self) -> Result<MemcrsArgs, Vec<String>> {
+        let content = std::fs::read_to_string(&self.path).map_err(|err| vec![err.to_string()])?;
+        let config = MemcrsConfig::from_toml(&content).map_err(|err| vec![err])?;
+        let matches = MemcrsArgs::command()
+            .try_get_matches_from(&self.command_line)
+            .map_err(|err| vec![error_message(err)])?;
+
+        let mut args = vec![PROGRAM_NAME.to_string()];
+        args.extend(config.into_args(&content, &matches)?);
+        args.extend(self.command_line.iter().skip(1).cloned());
+        // checks relations between options, i.e. profile-duration requires profile-cpu
+        let mut memcrs_args =
+            MemcrsArgs::try_parse_from(args).map_err(|err| vec![error_message(err)])?;
+        memcrs_args.config_file = Some(self.clone());
+        Ok(memcrs_args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memcache::cli::parser::RuntimeType;
+    use crate::memcache::eviction_policy::EvictionPolicy;
+
+    fn config_file(name: &str, content: &str, command_line: &[&str]) -> ConfigFile {
+        let path = std::env::temp_dir().join(format!(
+            "memcrs-config-{}-{}.toml",
+            name,
+            std::process::id()
+        ));
+        std::fs::write(&path, content).unwrap();
+        let mut args = vec![PROGRAM_NAME.to_string()];
+        args.extend(command_line.iter().map(|arg| arg.to_string()));
+        ConfigFile::new(path, args)
+    }
+
+    #[test]
+    fn command_line_should_override_config_file() {
+        let file = config_file(
+            "override",
+            "port = 11300\nmemory_limit = \"128MiB\"\nverbose = 3\nenable_udp = true\n",
+            &["--port", "11400", "-v"],
+        );
+        let args = file.load().unwrap();
+        assert_eq!(args.port, 11400);
+        assert_eq!(args.memory_limit, 128 * 1024 * 1024);
+        assert_eq!(args.verbose, 1);
+        assert!(args.enable_udp);
+        assert_eq!(
+            args.connection_limit,
+            MemcrsArgs::defaults().connection_limit
+        );
+        assert_eq!(args.config_file.unwrap().path(), file.path());
+        std::fs::remove_file(file.path()).unwrap();
+    }
+
+    #[test]
+    fn example_config_should_load_defaults() {
+        let file = config_file("example", &MemcrsArgs::example_config_toml(), &[]);
+        let args = file.load().unwrap();
+        let defaults = MemcrsArgs::defaults();
+        assert_eq!(args.port, defaults.port);
+        assert_eq!(args.item_size_limit, defaults.item_size_limit);
+        assert_eq!(args.socket_mode, defaults.socket_mode);
+        assert_eq!(args.verbose, defaults.verbose);
+        std::fs::remove_file(file.path()).unwrap();
+    }
+
+    #[test]
+    fn example_file_should_be_loaded() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("memcrs.toml");
+        let args = ConfigFile::new(path, vec![PROGRAM_NAME.to_string()])
+            .load()
+            .unwrap();
+        assert_eq!(
+            args.listen_address,
+            "0.0.0.0".parse::<std::net::IpAddr>().unwrap()
+        );
+        assert_eq!(args.port, 11211);
+        assert_eq!(args.connection_limit, 4096);
+        assert_eq!(args.memory_limit, 1024 * 1024 * 1024);
+        assert_eq!(args.item_size_limit.get_bytes(), 2 * 1024 * 1024);
+        assert_eq!(args.eviction_policy, EvictionPolicy::Lru);
+        assert_eq!(args.runtime_type, RuntimeType::MultiThread);
+        assert_eq!(args.threads, 4);
+        assert!(!args.enable_udp);
+        assert_eq!(args.conn_idle_timeout_secs, 300);
+        assert_eq!(args.verbose, 2);
+        assert_eq!(args.sweep_interval_ms, 1000);
+    }
+
+    #[test]
+    fn invalid_options_should_be_reported_with_lines() {
+        let file = config_file(
+            "invalid",
+            "port = 0\nunknown = 1\nenable_udp = \"yes\"\nshard_count = [1]\n",
+            &[],
+        );
+        let err = file.load().unwrap_err();
+        let prefix = format!("Invalid config file {}: ", file.path().display());
+        let errors: Vec<&str> = err.strip_prefix(&prefix).unwrap().split("; ").collect();
+        assert_eq!(errors.len(), 4);
+        assert!(errors[0].starts_with("line 3: "));
+        assert!(errors[0].contains("--enable-udp"));
+        assert!(errors[1].starts_with("line 1: "));
+        assert!(errors[1].contains("port not in range 1-65535"));
+        assert_eq!(
+            errors[2],
+            "line 4: invalid value of 'shard_count', expected string, number or boolean"
+        );
+        assert_eq!(errors[3], "line 2: unexpected option 'unknown'");
+
+        std::fs::write(file.path(), "port = 11211\nmemory_limit = \n").unwrap();
+        assert!(file
+            .load()
+            .unwrap_err()
+            .starts_with(&format!("{}line 2: ", prefix)));
+        std::fs::remove_file(file.path()).unwrap();
+
+        assert!(file.load().is_err());
+    }
+}
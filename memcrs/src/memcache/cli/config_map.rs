@@ -0,0 +1,237 @@
+use super::parser::MemcrsArgs;
+use clap::error::ErrorKind;
+use clap::{ArgAction, CommandFactory, Parser};
+use std::collections::HashMap;
+
+pub(crate) const PROGRAM_NAME: &str = "memcrsd";
+
+// clap error starts with "error: " and is followed by usage and
+// a hint to run --help, only a first paragraph is kept
+pub(crate) fn error_message(err: clap::Error) -> String {
+    let message = err.to_string();
+    let paragraph: Vec<&str> = message
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .map(str::trim)
+        .collect();
+    let message = paragraph.join(" ");
+    message
+        .strip_prefix("error: ")
+        .unwrap_or(&message)
+        .to_string()
+}
+
+/// Builds command line of long options from pairs of option names and values,
+/// each value is validated on its own, so all invalid values are reported,
+/// not only the first one
+pub(crate) fn option_args(options: Vec<(String, String)>) -> Result<Vec<String>, Vec<String>> {
+    let command = MemcrsArgs::command();
+    let mut args = Vec::new();
+    let mut errors = Vec::new();
+    for (name, value) in options {
+        let arg = match command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(name.as_str()))
+        {
+            Some(arg) => arg,
+            None => {
+                errors.push(format!("unexpected option '{}'", name));
+                continue;
+            }
+        };
+        let option = format!("--{}", name);
+        match arg.get_action() {
+            ArgAction::SetTrue => match value.parse::<bool>() {
+                Ok(true) => args.push(option),
+                Ok(false) => {}
+                Err(_) => errors.push(format!("invalid value '{}' for '{}'", value, option)),
+            },
+            ArgAction::Count => match value.parse::<u8>() {
+                Ok(count) => args.extend((0..count).map(|_| option.clone())),
+                Err(_) => errors.push(format!("invalid value '{}' for '{}'", value, option)),
+            },
+            _ => {
+                // missing required options are checked once all are set
+                match MemcrsArgs::try_parse_from([PROGRAM_NAME, &option, &value]) {
+                    Err(err) if err.kind() != ErrorKind::MissingRequiredArgument => {
+                        errors.push(error_message(err))
+                    }
+                    _ => {}
+                }
+                args.push(option);
+                args.push(value);
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(args)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Builds arguments from a map of long option names to values, as passed
+/// on a command line, i.e. when config is loaded from a KV store.
+/// Flags take "true" or "false", verbose takes a number of repetitions.
+/// Options missing in a map have their default values.
+impl TryFrom<HashMap<String, String>> for MemcrsArgs {
+    type Error = Vec<String>;
+
+    fn try_from(options: HashMap<String, String>) -> Result<Self, Self::Error> {
+        let mut options: Vec<(String, String)> = options.into_iter().collect();
+        options.sort();
+        let mut args = vec![PROGRAM_NAME.to_string()];
+        args.extend(option_args(options)?);
+        // checks relations between options, i.e. profile-duration requires profile-cpu
+        MemcrsArgs::try_parse_from(args).map_err(|err| vec![error_message(err)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memcache::cli::parser::RuntimeType;
+    use crate::memcache::eviction_policy::EvictionPolicy;
+    use crate::memcache_server::memc_tcp::Protocol;
+    use std::net::IpAddr;
+
+    fn options(options: &[(&str, &str)]) -> HashMap<String, String> {
+        options
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn full_map_should_set_all_options() {
+        let args = MemcrsArgs::try_from(options(&[
+            ("port", "11300"),
+            ("connection-limit", "10"),
+            ("backlog-limit", "20"),
+            ("memory-limit", "128MiB"),
+            ("item-size-limit", "2MiB"),
+            ("avg-item-size", "512"),
+            ("enable-prefix-index", "true"),
+            ("enable-compression", "true"),
+            ("compression-min-size", "4096"),
+            ("shard-count", "16"),
+            ("eviction-policy", "lfu"),
+            ("sweep-interval-ms", "100"),
+            ("lru-sample-size", "10"),
+            ("key-stats-capacity", "100"),
+            ("max-pipeline-depth", "16"),
+            ("shutdown-timeout", "1"),
+            ("conn-idle-timeout-secs", "300"),
+            ("slow-log-threshold-us", "1000"),
+            ("tcp-nodelay", "false"),
+            ("tcp-recv-buf", "65536"),
+            ("tcp-send-buf", "32768"),
+            ("protocol", "binary"),
+            ("enable-udp", "true"),
+            ("udp-fragment-timeout-ms", "500"),
+            ("tls-cert", "/etc/memcrs/cert.pem"),
+            ("tls-key", "/etc/memcrs/key.pem"),
+            ("tls-ca", "/etc/memcrs/ca.pem"),
+            ("socket-path", "/run/memcrs.sock"),
+            ("socket-mode", "0660"),
+            ("no-tcp", "false"),
+            ("sasl-users-file", "/etc/memcrs/users"),
+            ("allow-unauthenticated-text", "true"),
+            ("threads", "2"),
+            ("verbose", "3"),
+            ("listen-address", "0.0.0.0"),
+            ("runtime-type", "multi-thread"),
+            ("systemd-socket", "true"),
+            ("health-port", "8080"),
+            ("admin-port", "8081"),
+            ("admin-token", "secret"),
+            ("metrics-port", "9200"),
+            ("grpc-port", "50051"),
+            ("otlp-endpoint", "http://localhost:4317"),
+            ("profile-cpu", "/tmp/memcrs.pb"),
+            ("profile-duration", "30"),
+        ]))
+        .unwrap();
+        assert_eq!(args.port, 11300);
+        assert_eq!(args.connection_limit, 10);
+        assert_eq!(args.backlog_limit, 20);
+        assert_eq!(args.memory_limit, 128 * 1024 * 1024);
+        assert_eq!(args.item_size_limit.get_bytes(), 2 * 1024 * 1024);
+        assert_eq!(args.avg_item_size, 512);
+        assert!(args.enable_prefix_index);
+        assert!(args.enable_compression);
+        assert_eq!(args.compression_min_size, 4096);
+        assert_eq!(args.shard_count, Some(16));
+        assert_eq!(args.eviction_policy, EvictionPolicy::Lfu);
+        assert_eq!(args.sweep_interval_ms, 100);
+        assert_eq!(args.lru_sample_size, 10);
+        assert_eq!(args.key_stats_capacity, 100);
+        assert_eq!(args.max_pipeline_depth, 16);
+        assert_eq!(args.shutdown_timeout, 1);
+        assert_eq!(args.conn_idle_timeout_secs, 300);
+        assert_eq!(args.slow_log_threshold_us, 1000);
+        assert!(!args.tcp_nodelay);
+        assert_eq!(args.tcp_recv_buf, Some(65536));
+        assert_eq!(args.tcp_send_buf, Some(32768));
+        assert_eq!(args.protocol, Protocol::Binary);
+        assert!(args.enable_udp);
+        assert_eq!(args.udp_fragment_timeout_ms, 500);
+        assert_eq!(args.tls_cert, Some("/etc/memcrs/cert.pem".into()));
+        assert_eq!(args.tls_key, Some("/etc/memcrs/key.pem".into()));
+        assert_eq!(args.tls_ca, Some("/etc/memcrs/ca.pem".into()));
+        assert_eq!(args.socket_path, Some("/run/memcrs.sock".into()));
+        assert_eq!(args.socket_mode, 0o660);
+        assert!(!args.no_tcp);
+        assert_eq!(args.sasl_users_file, Some("/etc/memcrs/users".into()));
+        assert!(args.allow_unauthenticated_text);
+        assert_eq!(args.threads, 2);
+        assert_eq!(args.verbose, 3);
+        assert_eq!(args.listen_address, "0.0.0.0".parse::<IpAddr>().unwrap());
+        assert_eq!(args.runtime_type, RuntimeType::MultiThread);
+        assert!(args.systemd_socket);
+        assert_eq!(args.health_port, Some(8080));
+        assert_eq!(args.admin_port, Some(8081));
+        assert_eq!(args.admin_token, Some("secret".to_string()));
+        assert_eq!(args.metrics_port, Some(9200));
+        assert_eq!(args.grpc_port, Some(50051));
+        assert_eq!(
+            args.otlp_endpoint,
+            Some("http://localhost:4317".to_string())
+        );
+        assert_eq!(args.profile_cpu, Some("/tmp/memcrs.pb".into()));
+        assert_eq!(args.profile_duration, Some(30));
+    }
+
+    #[test]
+    fn partial_map_should_use_defaults_of_missing_options() {
+        let defaults = MemcrsArgs::defaults();
+        let args = MemcrsArgs::try_from(options(&[("port", "11300")])).unwrap();
+        assert_eq!(args.port, 11300);
+        assert_eq!(args.memory_limit, defaults.memory_limit);
+        assert_eq!(args.connection_limit, defaults.connection_limit);
+        assert_eq!(args.tcp_nodelay, defaults.tcp_nodelay);
+        assert_eq!(args.verbose, defaults.verbose);
+        assert_eq!(args.runtime_type, defaults.runtime_type);
+        assert_eq!(args.health_port, None);
+    }
+
+    #[test]
+    fn invalid_values_should_be_reported() {
+        let errors = MemcrsArgs::try_from(options(&[
+            ("port", "0"),
+            ("memory-limit", "lots"),
+            ("enable-prefix-index", "yes"),
+            ("unknown", "1"),
+        ]))
+        .unwrap_err();
+        assert_eq!(errors.len(), 4);
+        assert!(errors[0].contains("--enable-prefix-index"));
+        assert!(errors[1].contains("--memory-limit"));
+        assert!(errors[2].contains("port not in range 1-65535"));
+        assert_eq!(errors[3], "unexpected option 'unknown'");
+
+        let errors = MemcrsArgs::try_from(options(&[("profile-duration", "30")])).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("--profile-cpu"));
+    }
+}
@@ -0,0 +1,240 @@
+use super::parser::MemcrsArgs;
+use clap::{CommandFactory, Parser};
+use serde::{de, Deserialize, Deserializer, Serializer};
+use std::fmt::Display;
+use std::str::FromStr;
+
+const BYTE_UNITS: [(&str, u128); 3] = [("GiB", 1 << 30), ("MiB", 1 << 20), ("KiB", 1 << 10)];
+
+// formats size using the biggest unit which represents it exactly,
+// so it is read back to the same number of bytes
+fn format_bytes(bytes: u128) -> String {
+    BYTE_UNITS
+        .iter()
+        .find(|(_unit, multiple)| bytes > 0 && bytes.is_multiple_of(*multiple))
+        .map(|(unit, multiple)| format!("{}{}", bytes / multiple, unit))
+        .unwrap_or_else(|| format!("{}B", bytes))
+}
+
+fn parse_bytes<E: de::Error>(value: &str) -> Result<byte_unit::Byte, E> {
+    byte_unit::Byte::from_str(value).map_err(E::custom)
+}
+
+/// Serializes value using its Display and FromStr implementations, i.e. IpAddr
+pub(crate) mod as_string {
+    use super::*;
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Serializes byte_unit::Byte as a human readable size, i.e. "1MiB"
+pub(crate) mod byte_size {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        value: &byte_unit::Byte,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format_bytes(value.get_bytes()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<byte_unit::Byte, D::Error> {
+        parse_bytes(&String::deserialize(deserializer)?)
+    }
+}
+
+/// Serializes number of bytes as a human readable size, i.e. "64MiB"
+pub(crate) mod memory_size {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format_bytes(*value as u128))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let bytes = parse_bytes::<D::Error>(&String::deserialize(deserializer)?)?;
+        u64::try_from(bytes.get_bytes()).map_err(de::Error::custom)
+    }
+}
+
+/// Serializes enums, i.e. RuntimeType, using the same names as on a command line
+pub(crate) mod value_enum {
+    use super::*;
+    use clap::ValueEnum;
+
+    pub fn serialize<T: ValueEnum, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value.to_possible_value() {
+            Some(name) => serializer.serialize_str(name.get_name()),
+            None => Err(serde::ser::Error::custom("value cannot be serialized")),
+        }
+    }
+
+    pub fn deserialize<'de, T: ValueEnum, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        T::from_str(&value, true).map_err(de::Error::custom)
+    }
+}
+
+/// Serializes file permissions in octal, i.e. "0600"
+pub(crate) mod socket_mode {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:04o}", value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+        super::super::parser::parse_socket_mode(&String::deserialize(deserializer)?)
+            .map_err(de::Error::custom)
+    }
+}
+
+impl MemcrsArgs {
+    /// Returns TOML config with default values, each option is preceded
+    /// by its command line help
+    pub fn example_config_toml() -> String {
+        let defaults = MemcrsArgs::defaults();
+        let values = toml::Table::try_from(&defaults).expect("Default config is serializable");
+        let command = MemcrsArgs::command();
+        let mut config = String::new();
+        for arg in command.get_arguments() {
+            let name = arg.get_id().as_str();
+            if name == "print_config_template" || name == "config" {
+                continue;
+            }
+            if let Some(help) = arg.get_help() {
+                for line in help.to_string().lines() {
+                    config.push_str(&format!("# {}\n", line));
+                }
+            }
+            match values.get(name) {
+                Some(value) => config.push_str(&format!("{} = {}\n\n", name, value)),
+                None => {
+                    // options without default value are left commented out
+                    let value_name = arg
+                        .get_value_names()
+                        .and_then(|names| names.first())
+                        .map(|name| name.to_string())
+                        .unwrap_or_default();
+                    config.push_str(&format!("# {} = <{}>\n\n", name, value_name));
+                }
+            }
+        }
+        config
+    }
+
+    /// Arguments used when memcrsd is started without any command line options
+    pub fn defaults() -> MemcrsArgs {
+        MemcrsArgs::parse_from(["memcrsd"])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_bytes_should_use_biggest_exact_unit() {
+        assert_eq!(format_bytes(64 * 1024 * 1024), "64MiB");
+        assert_eq!(format_bytes(1536), "1536B");
+        assert_eq!(format_bytes(2048), "2KiB");
+        assert_eq!(format_bytes(0), "0B");
+    }
+
+    #[test]
+    fn example_config_should_contain_help_of_each_option() {
+        let config = MemcrsArgs::example_config_toml();
+        assert!(config.contains("# TCP port to listen on\nport = 11211\n"));
+        assert!(config.contains("memory_limit = \"64MiB\"\n"));
+        assert!(config.contains("runtime_type = \"current-thread\"\n"));
+        assert!(config.contains("eviction_policy = \"none\"\n"));
+        assert!(config.contains("protocol = \"text\"\n"));
+        assert!(config.contains("socket_mode = \"0600\"\n"));
+        assert!(config.contains("# health_port = <PORT>\n"));
+        assert!(!config.contains("print_config_template"));
+        assert!(!config.contains("config ="));
+    }
+
+    #[test]
+    fn example_config_should_round_trip_to_defaults() {
+        let defaults = MemcrsArgs::defaults();
+        let parsed: MemcrsArgs = toml::from_str(&MemcrsArgs::example_config_toml()).unwrap();
+
+        assert_eq!(parsed.port, defaults.port);
+        assert_eq!(parsed.connection_limit, defaults.connection_limit);
+        assert_eq!(parsed.backlog_limit, defaults.backlog_limit);
+        assert_eq!(parsed.memory_limit, defaults.memory_limit);
+        assert_eq!(parsed.item_size_limit, defaults.item_size_limit);
+        assert_eq!(parsed.avg_item_size, defaults.avg_item_size);
+        assert_eq!(parsed.enable_prefix_index, defaults.enable_prefix_index);
+        assert_eq!(parsed.shard_count, defaults.shard_count);
+        assert_eq!(parsed.eviction_policy, defaults.eviction_policy);
+        assert_eq!(parsed.sweep_interval_ms, defaults.sweep_interval_ms);
+        assert_eq!(parsed.lru_sample_size, defaults.lru_sample_size);
+        assert_eq!(parsed.enable_compression, defaults.enable_compression);
+        assert_eq!(parsed.compression_min_size, defaults.compression_min_size);
+        assert_eq!(parsed.key_stats_capacity, defaults.key_stats_capacity);
+        assert_eq!(parsed.max_pipeline_depth, defaults.max_pipeline_depth);
+        assert_eq!(parsed.shutdown_timeout, defaults.shutdown_timeout);
+        assert_eq!(
+            parsed.conn_idle_timeout_secs,
+            defaults.conn_idle_timeout_secs
+        );
+        assert_eq!(parsed.slow_log_threshold_us, defaults.slow_log_threshold_us);
+        assert_eq!(parsed.tcp_nodelay, defaults.tcp_nodelay);
+        assert_eq!(parsed.tcp_recv_buf, defaults.tcp_recv_buf);
+        assert_eq!(parsed.tcp_send_buf, defaults.tcp_send_buf);
+        assert_eq!(parsed.protocol, defaults.protocol);
+        assert_eq!(parsed.enable_udp, defaults.enable_udp);
+        assert_eq!(
+            parsed.udp_fragment_timeout_ms,
+            defaults.udp_fragment_timeout_ms
+        );
+        assert_eq!(parsed.threads, defaults.threads);
+        assert_eq!(parsed.verbose, defaults.verbose);
+        assert_eq!(parsed.listen_address, defaults.listen_address);
+        assert_eq!(parsed.runtime_type, defaults.runtime_type);
+        assert_eq!(parsed.systemd_socket, defaults.systemd_socket);
+        assert_eq!(parsed.health_port, defaults.health_port);
+        assert_eq!(parsed.admin_port, defaults.admin_port);
+        assert_eq!(parsed.admin_token, defaults.admin_token);
+        assert_eq!(parsed.metrics_port, defaults.metrics_port);
+        assert_eq!(parsed.grpc_port, defaults.grpc_port);
+        assert_eq!(parsed.otlp_endpoint, defaults.otlp_endpoint);
+        assert_eq!(parsed.profile_cpu, defaults.profile_cpu);
+        assert_eq!(parsed.tls_cert, defaults.tls_cert);
+        assert_eq!(parsed.tls_key, defaults.tls_key);
+        assert_eq!(parsed.tls_ca, defaults.tls_ca);
+        assert_eq!(parsed.socket_path, defaults.socket_path);
+        assert_eq!(parsed.socket_mode, defaults.socket_mode);
+        assert_eq!(parsed.no_tcp, defaults.no_tcp);
+        assert_eq!(parsed.sasl_users_file, defaults.sasl_users_file);
+        assert_eq!(
+            parsed.allow_unauthenticated_text,
+            defaults.allow_unauthenticated_text
+        );
+        assert_eq!(parsed.profile_duration, defaults.profile_duration);
+    }
+}
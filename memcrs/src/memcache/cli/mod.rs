@@ -1 +1,4 @@
+pub mod config_file;
+pub mod config_map;
+pub mod config_template;
 pub mod parser;
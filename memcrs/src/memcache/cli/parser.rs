@@ -1,6 +1,16 @@
 use byte_unit::{Byte};
-use clap::{command, Parser, ValueEnum};
-use std::{net::IpAddr, ops::RangeInclusive, fmt::Debug};
+use clap::{Parser, ValueEnum};
+use serde_derive::{Deserialize, Serialize};
+use super::config_file::ConfigFile;
+use crate::memcache::eviction_policy::EvictionPolicy;
+use crate::memcache::key_stats::DEFAULT_KEY_STATS_CAPACITY;
+use crate::memcache::lru_policy::DEFAULT_LRU_SAMPLE_SIZE;
+use crate::memcache_server::memc_tcp::Protocol;
+use crate::server::config::{
+    CompressionConfig, LogConfig, NetworkConfig, ProfilingConfig, RuntimeConfig, ServerConfig,
+    StorageConfig, TelemetryConfig, TlsConfig, UnixSocketConfig,
+};
+use std::{net::IpAddr, ops::RangeInclusive, fmt::Debug, path::PathBuf};
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
 pub enum RuntimeType {
@@ -25,12 +35,22 @@ const CONNECTION_LIMIT: u32 = 1024;
 const LISTEN_BACKLOG: u32 = 1024;
 const MEMORY_LIMIT: &str = "64MiB";
 const MAX_ITEM_SIZE: &str = "1MiB";
+const AVG_ITEM_SIZE: usize = 256;
+const MAX_PIPELINE_DEPTH: u32 = 128;
+const SHUTDOWN_TIMEOUT_SECS: u32 = 5;
+const SWEEP_INTERVAL_MS: u64 = 1000;
+const COMPRESSION_MIN_SIZE: usize = 1024;
+// lz4_flex has no compression levels
+const COMPRESSION_LEVEL: u32 = 1;
+const UDP_FRAGMENT_TIMEOUT_MS: u64 = 1000;
+const SOCKET_MODE: &str = "0600";
+const METRICS_PORT: &str = "9150";
 
 fn get_default_threads_number() -> usize {
     num_cpus::get_physical().to_string().parse().unwrap()
 }
 
-#[derive(Parser, Debug, Clone)]
+#[derive(Parser, Serialize, Deserialize, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 /// memcached compatible server implementation in Rust
 pub struct MemcrsArgs {
@@ -48,12 +68,131 @@ pub struct MemcrsArgs {
 
     #[arg(short, long, value_name = "MEMORY-LIMIT", value_parser = parse_memory_mb, default_value = MEMORY_LIMIT)]
     /// memory limit in megabytes
+    #[serde(with = "super::config_template::memory_size")]
     pub memory_limit: u64,
 
-    #[arg(short, long, value_name = "MAX-ITEM-SIZE", default_value_t = Byte::from_str(MAX_ITEM_SIZE).unwrap())]
+    // -I as in memcached, -i is kept for existing scripts
+    #[arg(short = 'I', short_alias = 'i', long, value_name = "MAX-ITEM-SIZE", default_value_t = Byte::from_str(MAX_ITEM_SIZE).unwrap())]
     ///  adjusts max item size (min: 1k, max: 1024m)
+    #[serde(with = "super::config_template::byte_size")]
     pub item_size_limit: Byte,
 
+    #[arg(long, value_name = "BYTES", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), default_value_t = AVG_ITEM_SIZE)]
+    /// expected average item size, used to pre-size a store for memory-limit / avg-item-size items
+    pub avg_item_size: usize,
+
+    #[arg(long)]
+    /// keep a sorted index of keys, so prefix scans don't iterate over a whole store,
+    /// slows down inserts and removes
+    pub enable_prefix_index: bool,
+
+    #[arg(long, value_name = "N", value_parser = parse_shard_count)]
+    /// split a store into N independently locked shards, a power of two,
+    /// by default 4 times the number of CPUs rounded up to a power of two
+    pub shard_count: Option<usize>,
+
+    #[arg(long, value_name = "POLICY", default_value_t = EvictionPolicy::None, value_enum)]
+    /// which records are evicted once memory-limit is reached
+    #[serde(with = "super::config_template::value_enum")]
+    pub eviction_policy: EvictionPolicy,
+
+    #[arg(long, value_name = "MS", default_value_t = SWEEP_INTERVAL_MS)]
+    /// remove expired records every MS milliseconds, 0 removes them only when they are read
+    pub sweep_interval_ms: u64,
+
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), default_value_t = DEFAULT_LRU_SAMPLE_SIZE)]
+    /// lru eviction evicts least recently read record of N randomly sampled records
+    pub lru_sample_size: usize,
+
+    #[arg(long)]
+    /// store values compressed with lz4, saves memory of compressible values,
+    /// i.e. JSON, at a cost of latency, requires compression feature
+    pub enable_compression: bool,
+
+    #[arg(long, value_name = "BYTES", default_value_t = COMPRESSION_MIN_SIZE)]
+    /// values shorter than BYTES are stored uncompressed
+    pub compression_min_size: usize,
+
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_KEY_STATS_CAPACITY)]
+    /// count hits and misses of up to N keys for stats hotkeys, reads of other keys are not counted
+    pub key_stats_capacity: usize,
+
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), default_value_t = MAX_PIPELINE_DEPTH)]
+    /// flush responses after N pipelined requests, so a single client cannot delay other clients
+    pub max_pipeline_depth: u32,
+
+    #[arg(long, alias = "shutdown-drain-secs", value_name = "SECS", default_value_t = SHUTDOWN_TIMEOUT_SECS)]
+    /// on shutdown, i.e. on SIGTERM, wait up to SECS for connected clients to finish requests, then abort them
+    pub shutdown_timeout: u32,
+
+    #[arg(long, value_name = "SECS", default_value_t = 0)]
+    /// disconnect clients which don't send any data for SECS, 0 disables it
+    pub conn_idle_timeout_secs: u32,
+
+    #[arg(long, value_name = "US", default_value_t = 0)]
+    /// log requests which take longer than US microseconds to handle, 0 disables it
+    pub slow_log_threshold_us: u64,
+
+    #[arg(long, value_name = "BOOL", default_value_t = true, action = clap::ArgAction::Set)]
+    /// disable Nagle's algorithm on client connections
+    pub tcp_nodelay: bool,
+
+    #[arg(long, value_name = "BYTES")]
+    /// client socket receive buffer size (SO_RCVBUF), OS default if not set
+    pub tcp_recv_buf: Option<usize>,
+
+    #[arg(long, value_name = "BYTES")]
+    /// client socket send buffer size (SO_SNDBUF), OS default if not set
+    pub tcp_send_buf: Option<usize>,
+
+    #[arg(long, value_name = "PROTOCOL", default_value_t = Protocol::Text, value_enum)]
+    /// protocol served to clients, binary requires binary-protocol feature
+    #[serde(with = "super::config_template::value_enum")]
+    pub protocol: Protocol,
+
+    #[arg(long)]
+    /// serve text protocol over UDP on the same port, besides TCP
+    pub enable_udp: bool,
+
+    #[arg(long, value_name = "MS", default_value_t = UDP_FRAGMENT_TIMEOUT_MS)]
+    /// discard UDP requests split into multiple datagrams if all of them don't arrive within MS milliseconds
+    pub udp_fragment_timeout_ms: u64,
+
+    #[arg(long, value_name = "PEM-PATH", requires = "tls_key")]
+    /// serve clients over TLS with a certificate chain from a given PEM file,
+    /// requires tls feature
+    pub tls_cert: Option<PathBuf>,
+
+    #[arg(long, value_name = "PEM-PATH", requires = "tls_cert")]
+    /// private key of tls-cert certificate
+    pub tls_key: Option<PathBuf>,
+
+    #[arg(long, value_name = "PEM-PATH", requires = "tls_cert")]
+    /// require clients to present a certificate signed by a CA from a given PEM file
+    pub tls_ca: Option<PathBuf>,
+
+    #[arg(long, value_name = "PATH")]
+    /// accept connections on a Unix domain socket at a given path, besides TCP
+    pub socket_path: Option<PathBuf>,
+
+    #[arg(long, value_name = "MODE", value_parser = parse_socket_mode, default_value = SOCKET_MODE)]
+    /// permissions of socket-path file, in octal
+    #[serde(with = "super::config_template::socket_mode")]
+    pub socket_mode: u32,
+
+    #[arg(long, requires = "socket_path", conflicts_with = "systemd_socket")]
+    /// don't listen on TCP, clients connect to socket-path only
+    pub no_tcp: bool,
+
+    #[arg(long, value_name = "PATH")]
+    /// require binary protocol clients to authenticate with SASL PLAIN, credentials
+    /// are read from a file of username:password lines and reloaded on SIGHUP
+    pub sasl_users_file: Option<PathBuf>,
+
+    #[arg(long, requires = "sasl_users_file")]
+    /// serve text protocol clients, which cannot authenticate, if sasl-users-file is set
+    pub allow_unauthenticated_text: bool,
+
     #[arg(short, long, value_name = "THREADS", default_value_t = get_default_threads_number())]
     /// number of threads to use
     pub threads: usize,
@@ -64,11 +203,71 @@ pub struct MemcrsArgs {
 
     #[arg(short, long, value_name = "listen", default_value_t = String::from(DEFAULT_ADDRESS).parse::<IpAddr>().unwrap())]
     /// interface to listen on
+    #[serde(with = "super::config_template::as_string")]
     pub listen_address: IpAddr,
 
     #[arg(short, long, value_name = "RUNTIME-TYPE", default_value_t = RuntimeType::CurrentThread, value_enum)]
     ///  runtime type to use
+    #[serde(with = "super::config_template::value_enum")]
     pub runtime_type: RuntimeType,
+
+    #[arg(long)]
+    /// accept connections on a socket passed by systemd socket activation
+    /// instead of binding to listen-address and port, and notify systemd
+    /// once started
+    pub systemd_socket: bool,
+
+    #[arg(long, value_name = "PORT", value_parser = port_in_range)]
+    /// serve HTTP health check endpoints (/health/live, /health/ready, /health/startup) on a given port
+    pub health_port: Option<u16>,
+
+    #[arg(long, value_name = "PORT", value_parser = port_in_range)]
+    /// serve HTTP admin API (/api/v1/stats, /api/v1/flush, /api/v1/key/{key},
+    /// /api/v1/config/memory-limit) on a given port
+    pub admin_port: Option<u16>,
+
+    #[arg(long, value_name = "TOKEN", requires = "admin_port")]
+    /// require admin API requests to carry "Authorization: Bearer TOKEN" header
+    pub admin_token: Option<String>,
+
+    #[arg(long, value_name = "PORT", value_parser = port_in_range, num_args = 0..=1, default_missing_value = METRICS_PORT)]
+    /// serve Prometheus metrics on /metrics on a given port, 9150 if a port is not given
+    pub metrics_port: Option<u16>,
+
+    #[arg(long, value_name = "PORT", value_parser = port_in_range)]
+    /// serve gRPC admin API (MemcacheAdmin service of memcrs.proto) on a given port,
+    /// requires grpc feature
+    pub grpc_port: Option<u16>,
+
+    #[arg(long, value_name = "OUTPUT-PATH")]
+    /// write pprof CPU profile to a given path on SIGTERM or after profile duration,
+    /// requires cpu-profiling feature
+    pub profile_cpu: Option<PathBuf>,
+
+    #[arg(long, value_name = "SECS", requires = "profile_cpu")]
+    /// stop CPU profiling after given number of seconds
+    pub profile_duration: Option<u64>,
+
+    #[arg(long, value_name = "PATH")]
+    #[serde(skip)]
+    /// read options from a TOML config file, see print-config-template,
+    /// options given on a command line take precedence, log level and
+    /// sweep interval are read again on SIGHUP
+    pub config: Option<PathBuf>,
+
+    #[arg(skip)]
+    #[serde(skip)]
+    pub config_file: Option<ConfigFile>,
+
+    #[arg(long, value_name = "URL")]
+    /// export spans of store operations to an OTLP/gRPC collector,
+    /// i.e. http://localhost:4317, requires opentelemetry feature
+    pub otlp_endpoint: Option<String>,
+
+    #[arg(long)]
+    #[serde(skip)]
+    /// print config template with default values and exit
+    pub print_config_template: bool,
 }
 
 const PORT_RANGE: RangeInclusive<usize> = 1..=65535;
@@ -102,15 +301,107 @@ fn parse_memory_mb(s: &str) -> Result<u64, String> {
     }
 }
 
+fn parse_shard_count(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(shard_count) if shard_count > 1 && shard_count.is_power_of_two() => Ok(shard_count),
+        _ => Err(format!("{} is not a power of two greater than 1", s)),
+    }
+}
+
+/// Parses file permissions, i.e. 0600 or 600
+pub(crate) fn parse_socket_mode(s: &str) -> Result<u32, String> {
+    match u32::from_str_radix(s, 8) {
+        Ok(mode) if mode <= 0o777 => Ok(mode),
+        _ => Err(format!("{} is not an octal file mode", s)),
+    }
+}
+
 impl MemcrsArgs {
+    /// Splits command line arguments into configs of server parts
+    pub fn into_server_config(self) -> ServerConfig {
+        ServerConfig {
+            network: NetworkConfig {
+                port: self.port,
+                listen_address: self.listen_address,
+                backlog_limit: self.backlog_limit,
+                connection_limit: self.connection_limit,
+                max_pipeline_depth: self.max_pipeline_depth,
+                shutdown_timeout_secs: self.shutdown_timeout,
+                idle_timeout_secs: self.conn_idle_timeout_secs,
+                slow_log_threshold_us: self.slow_log_threshold_us,
+                protocol: self.protocol,
+                enable_udp: self.enable_udp,
+                udp_fragment_timeout_ms: self.udp_fragment_timeout_ms,
+                tls: self.tls_cert.zip(self.tls_key).map(|(cert_path, key_path)| TlsConfig {
+                    cert_path,
+                    key_path,
+                    ca_path: self.tls_ca,
+                }),
+                unix_socket: self.socket_path.map(|path| UnixSocketConfig {
+                    path,
+                    mode: self.socket_mode,
+                }),
+                no_tcp: self.no_tcp,
+                sasl_users_file: self.sasl_users_file,
+                allow_unauthenticated_text: self.allow_unauthenticated_text,
+                tcp_nodelay: self.tcp_nodelay,
+                tcp_recv_buffer_size: self.tcp_recv_buf,
+                tcp_send_buffer_size: self.tcp_send_buf,
+                systemd_socket: self.systemd_socket,
+                health_port: self.health_port,
+                admin_port: self.admin_port,
+                admin_token: self.admin_token,
+                metrics_port: self.metrics_port,
+                grpc_port: self.grpc_port,
+            },
+            storage: StorageConfig {
+                memory_limit: self.memory_limit,
+                item_size_limit: self.item_size_limit,
+                eviction_policy: self.eviction_policy,
+                avg_item_size: self.avg_item_size,
+                enable_prefix_index: self.enable_prefix_index,
+                shard_count: self.shard_count,
+                lru_sample_size: self.lru_sample_size,
+                key_stats_capacity: self.key_stats_capacity,
+                sweep_interval_ms: self.sweep_interval_ms,
+                compression: CompressionConfig {
+                    enabled: self.enable_compression,
+                    min_size: self.compression_min_size,
+                    level: COMPRESSION_LEVEL,
+                },
+            },
+            runtime: RuntimeConfig {
+                runtime_type: self.runtime_type,
+                threads: self.threads,
+            },
+            logging: LogConfig {
+                verbose: self.verbose,
+            },
+            profiling: ProfilingConfig {
+                cpu_profile: self.profile_cpu,
+                duration_secs: self.profile_duration,
+            },
+            telemetry: TelemetryConfig {
+                otlp_endpoint: self.otlp_endpoint,
+            },
+            config_file: self.config_file,
+        }
+    }
+
     fn from_args(args: Vec<String>) -> Result<MemcrsArgs, String> {
         let memcrs_args = MemcrsArgs::parse_from(args.iter());
         Ok(memcrs_args)
     }
 }
 
+/// Parses command line arguments, options of a config file passed
+/// with --config are used unless they are set on a command line
 pub fn parse(args: Vec<String>) -> Result<MemcrsArgs, String> {
-    MemcrsArgs::from_args(args)
+    let memcrs_args = MemcrsArgs::from_args(args.clone())?;
+    match memcrs_args.config.clone() {
+        Some(path) => ConfigFile::new(path, args).load(),
+        None => Ok(memcrs_args),
+    }
 }
 
 #[cfg(test)]
@@ -121,4 +412,126 @@ mod tests {
     fn verify_cli() {
         MemcrsArgs::command().debug_assert()
     }
+
+    // short options shared with memcached have the same meaning,
+    // see command line compatibility in README
+    #[test]
+    fn short_options_should_match_memcached() {
+        let args = parse(
+            [
+                "memcrsd", "-p", "11311", "-l", "0.0.0.0", "-m", "128MiB", "-c", "10", "-t",
+                "2", "-b", "20", "-I", "2MiB", "-vv",
+            ]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect(),
+        )
+        .unwrap();
+        assert_eq!(args.port, 11311);
+        assert_eq!(args.listen_address, "0.0.0.0".parse::<IpAddr>().unwrap());
+        assert_eq!(args.memory_limit, 128 * 1024 * 1024);
+        assert_eq!(args.connection_limit, 10);
+        assert_eq!(args.threads, 2);
+        assert_eq!(args.backlog_limit, 20);
+        assert_eq!(args.item_size_limit.get_bytes(), 2 * 1024 * 1024);
+        assert_eq!(args.verbose, 2);
+
+        let args = parse(["memcrsd", "-i", "2MiB"].iter().map(|arg| arg.to_string()).collect())
+            .unwrap();
+        assert_eq!(args.item_size_limit.get_bytes(), 2 * 1024 * 1024);
+    }
+
+    #[test]
+    fn into_server_config_should_group_arguments() {
+        let args = parse(
+            [
+                "memcrsd",
+                "--port",
+                "11311",
+                "--memory-limit",
+                "128MiB",
+                "--threads",
+                "3",
+                "--health-port",
+                "8080",
+                "--eviction-policy",
+                "lru",
+                "--sweep-interval-ms",
+                "250",
+                "--enable-compression",
+                "--compression-min-size",
+                "4096",
+                "--protocol",
+                "auto",
+                "--enable-udp",
+                "--udp-fragment-timeout-ms",
+                "500",
+                "--conn-idle-timeout-secs",
+                "300",
+                "--shutdown-drain-secs",
+                "10",
+                "--tls-cert",
+                "/etc/memcrs/cert.pem",
+                "--tls-key",
+                "/etc/memcrs/key.pem",
+                "--socket-path",
+                "/run/memcrs.sock",
+                "--socket-mode",
+                "660",
+                "--sasl-users-file",
+                "/etc/memcrs/users",
+                "--metrics-port",
+                "--grpc-port",
+                "50051",
+                "-vv",
+            ]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect(),
+        )
+        .unwrap();
+        let config = args.into_server_config();
+        assert_eq!(config.network.port, 11311);
+        assert_eq!(config.network.health_port, Some(8080));
+        assert_eq!(config.network.admin_port, None);
+        assert_eq!(config.network.metrics_port, Some(9150));
+        assert_eq!(config.network.grpc_port, Some(50051));
+        assert!(!config.network.systemd_socket);
+        assert_eq!(config.network.connection_limit, CONNECTION_LIMIT);
+        assert_eq!(config.network.protocol, Protocol::Auto);
+        assert!(config.network.enable_udp);
+        assert_eq!(config.network.udp_fragment_timeout_ms, 500);
+        assert_eq!(config.network.idle_timeout_secs, 300);
+        assert_eq!(config.network.slow_log_threshold_us, 0);
+        assert_eq!(config.network.shutdown_timeout_secs, 10);
+        let tls = config.network.tls.unwrap();
+        assert_eq!(tls.cert_path, PathBuf::from("/etc/memcrs/cert.pem"));
+        assert_eq!(tls.key_path, PathBuf::from("/etc/memcrs/key.pem"));
+        assert_eq!(tls.ca_path, None);
+        let unix_socket = config.network.unix_socket.unwrap();
+        assert_eq!(unix_socket.path, PathBuf::from("/run/memcrs.sock"));
+        assert_eq!(unix_socket.mode, 0o660);
+        assert!(!config.network.no_tcp);
+        assert_eq!(
+            config.network.sasl_users_file,
+            Some(PathBuf::from("/etc/memcrs/users"))
+        );
+        assert!(!config.network.allow_unauthenticated_text);
+        assert!(config.network.tcp_nodelay);
+        assert_eq!(config.network.tcp_recv_buffer_size, None);
+        assert_eq!(config.storage.memory_limit, 128 * 1024 * 1024);
+        assert_eq!(config.storage.avg_item_size, AVG_ITEM_SIZE);
+        assert_eq!(config.storage.shard_count, None);
+        assert_eq!(config.storage.lru_sample_size, DEFAULT_LRU_SAMPLE_SIZE);
+        assert_eq!(config.storage.key_stats_capacity, DEFAULT_KEY_STATS_CAPACITY);
+        assert_eq!(config.storage.eviction_policy, EvictionPolicy::Lru);
+        assert_eq!(config.storage.sweep_interval_ms, 250);
+        assert!(config.storage.compression.enabled);
+        assert_eq!(config.storage.compression.min_size, 4096);
+        assert_eq!(config.runtime.threads, 3);
+        assert_eq!(config.runtime.runtime_type, RuntimeType::CurrentThread);
+        assert_eq!(config.logging.verbose, 2);
+        assert_eq!(config.profiling.cpu_profile, None);
+        assert_eq!(config.telemetry.otlp_endpoint, None);
+    }
 }
@@ -0,0 +1,404 @@
+#[cfg(any(test, feature = "debug"))]
+use crate::cache::cache::DebugDump;
+use crate::cache::cache::{
+    impl_details::CacheImplDetails, Cache, CacheMetaData, CachePredicate, CacheReadOnlyView,
+    KeyType, Record, RecordPredicate, RemoveIfResult, SetCondition, SetStatus, ShardStats,
+    StorageSnapshot, ValueType,
+};
+use crate::cache::error::{CacheError, Result};
+use bytes::Bytes;
+use std::sync::Arc;
+
+/// Compresses values of at least min_size bytes with lz4 before they are
+/// stored and decompresses them when they are read, so reads and writes
+/// passing through it see values as clients sent them. A compressed
+/// record is marked in its header, not in flags, which are returned to
+/// clients unchanged. Values which lz4 cannot shrink are stored as they are.
+///
+/// Memory usage and limits of a wrapped store count compressed sizes.
+/// Read only views, snapshots, predicates of remove_if and records
+/// returned by flush_by_tag see values as they are stored.
+pub struct CompressedCache {
+    store: Arc<dyn Cache + Send + Sync>,
+    min_size: usize,
+}
+
+fn decompress(mut record: Record) -> Result<Record> {
+    if !record.header.compressed {
+        return Ok(record);
+    }
+    // a store only holds values compressed by compress, so a value
+    // which does not decompress means a store is broken
+    let value = lz4_flex::decompress_size_prepended(&record.value)
+        .map_err(|_| CacheError::InternalError)?;
+    record.value = Bytes::from(value);
+    record.header.compressed = false;
+    Ok(record)
+}
+
+impl CompressedCache {
+    pub fn new(store: Arc<dyn Cache + Send + Sync>, min_size: usize) -> CompressedCache {
+        CompressedCache { store, min_size }
+    }
+
+    fn compress(&self, mut record: Record) -> Record {
+        // values over a limit are left for a store to reject, so a limit
+        // holds for values clients send, not for compressed ones
+        if record.value.len() < self.min_size || record.value.len() > self.store.max_value_size() {
+            return record;
+        }
+        let value = lz4_flex::compress_prepend_size(&record.value);
+        if value.len() < record.value.len() {
+            record.value = Bytes::from(value);
+            record.header.compressed = true;
+        }
+        record
+    }
+
+    fn compress_all(&self, items: Vec<(KeyType, Record)>) -> Vec<(KeyType, Record)> {
+        items
+            .into_iter()
+            .map(|(key, record)| (key, self.compress(record)))
+            .collect()
+    }
+}
+
+impl CacheImplDetails for CompressedCache {
+    //
+    fn get_by_key(&self, key: &KeyType) -> Result<Record> {
+        self.store.get_by_key(key).and_then(decompress)
+    }
+
+    //
+    fn check_if_expired(&self, key: &KeyType, record: &Record) -> bool {
+        self.store.check_if_expired(key, record)
+    }
+}
+
+impl Cache for CompressedCache {
+    fn get(&self, key: &KeyType) -> Result<Record> {
+        self.store.get(key).and_then(decompress)
+    }
+
+    fn get_many(&self, keys: &[KeyType]) -> Vec<Result<Record>> {
+        self.store
+            .get_many(keys)
+            .into_iter()
+            .map(|result| result.and_then(decompress))
+            .collect()
+    }
+
+    fn try_get(&self, key: &KeyType) -> Option<Result<Record>> {
+        self.store
+            .try_get(key)
+            .map(|result| result.and_then(decompress))
+    }
+
+    fn get_with_metadata(&self, key: &KeyType) -> Result<(Record, u64)> {
+        let (record, metadata) = self.store.get_with_metadata(key)?;
+        Ok((decompress(record)?, metadata))
+    }
+
+    fn get_cas(&self, key: &KeyType) -> Result<u64> {
+        self.store.get_cas(key)
+    }
+
+    fn get_flags(&self, key: &KeyType) -> Result<u32> {
+        self.store.get_flags(key)
+    }
+
+    // a header is needed to tell if a value is compressed
+    fn get_value_bytes(&self, key: &KeyType) -> Result<ValueType> {
+        self.get(key).map(|record| record.value)
+    }
+
+    fn touch(&self, key: &KeyType, expiration: u32) -> Result<()> {
+        self.store.touch(key, expiration)
+    }
+
+    fn get_and_touch(&self, key: &KeyType, expiration: u32) -> Result<Record> {
+        self.store
+            .get_and_touch(key, expiration)
+            .and_then(decompress)
+    }
+
+    fn set(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.store.set(key, self.compress(record))
+    }
+
+    fn set_many(&self, items: Vec<(KeyType, Record)>) -> Vec<Result<SetStatus>> {
+        self.store.set_many(self.compress_all(items))
+    }
+
+    fn atomic_multi_set(&self, items: Vec<(KeyType, Record)>) -> Result<Vec<SetStatus>> {
+        self.store.atomic_multi_set(self.compress_all(items))
+    }
+
+    fn conditional_set(
+        &self,
+        key: KeyType,
+        record: Record,
+        condition: SetCondition,
+    ) -> Result<SetStatus> {
+        self.store
+            .conditional_set(key, self.compress(record), condition)
+    }
+
+    fn replace_if(
+        &self,
+        key: KeyType,
+        new_record: Record,
+        predicate: &RecordPredicate<'_>,
+    ) -> Result<SetStatus> {
+        // a record which does not decompress is not replaced
+        let predicate = |stored: &Record| match decompress(stored.clone()) {
+            Ok(stored) => predicate(&stored),
+            Err(_err) => false,
+        };
+        self.store
+            .replace_if(key, self.compress(new_record), &predicate)
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: KeyType,
+        expected_cas: u64,
+        new_record: Record,
+    ) -> Result<(SetStatus, Option<Record>)> {
+        let (status, replaced) =
+            self.store
+                .compare_and_swap(key, expected_cas, self.compress(new_record))?;
+        Ok((status, replaced.map(decompress).transpose()?))
+    }
+
+    fn delete(&self, key: KeyType, header: CacheMetaData) -> Result<Record> {
+        self.store.delete(key, header).and_then(decompress)
+    }
+
+    // Removes key value and returns as an option, a record which does
+    // not decompress is returned as it was stored
+    fn remove(&self, key: &KeyType) -> Option<(KeyType, Record)> {
+        self.store
+            .remove(key)
+            .map(|(key, record)| (key, decompress(record.clone()).unwrap_or(record)))
+    }
+
+    fn flush(&self, header: CacheMetaData) -> usize {
+        self.store.flush(header)
+    }
+
+    fn flush_by_tag(&self, tag: u64) -> Vec<(KeyType, Record)> {
+        self.store.flush_by_tag(tag)
+    }
+
+    fn as_read_only(&self) -> Box<dyn CacheReadOnlyView<'_>> {
+        self.store.as_read_only()
+    }
+
+    fn remove_if(&self, f: &mut CachePredicate) -> RemoveIfResult {
+        self.store.remove_if(f)
+    }
+
+    fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+
+    fn memory_used(&self) -> u64 {
+        self.store.memory_used()
+    }
+
+    fn max_value_size(&self) -> usize {
+        self.store.max_value_size()
+    }
+
+    fn set_memory_limit(&self, memory_limit: u64) -> Result<()> {
+        self.store.set_memory_limit(memory_limit)
+    }
+
+    fn oldest_item_age_secs(&self) -> Option<u64> {
+        self.store.oldest_item_age_secs()
+    }
+
+    fn newest_item_age_secs(&self) -> Option<u64> {
+        self.store.newest_item_age_secs()
+    }
+
+    fn count_expired(&self) -> usize {
+        self.store.count_expired()
+    }
+
+    fn remove_expired_items(&self) -> RemoveIfResult {
+        self.store.remove_expired_items()
+    }
+
+    fn expired_unfetched(&self) -> u64 {
+        self.store.expired_unfetched()
+    }
+
+    fn flush_affected_items(&self) -> u64 {
+        self.store.flush_affected_items()
+    }
+
+    fn evictions(&self) -> u64 {
+        self.store.evictions()
+    }
+
+    fn start_time(&self) -> u64 {
+        self.store.start_time()
+    }
+
+    fn current_time(&self) -> u64 {
+        self.store.current_time()
+    }
+
+    fn current_instant(&self) -> std::time::Instant {
+        self.store.current_instant()
+    }
+
+    fn current_millis(&self) -> u64 {
+        self.store.current_millis()
+    }
+
+    fn per_shard_stats(&self) -> Vec<ShardStats> {
+        self.store.per_shard_stats()
+    }
+
+    fn shard_for_key(&self, key: &[u8]) -> usize {
+        self.store.shard_for_key(key)
+    }
+
+    fn get_random_sample(&self, n: usize) -> Vec<(KeyType, u64)> {
+        self.store.get_random_sample(n)
+    }
+
+    fn value_sizes(&self) -> Vec<u64> {
+        self.store.value_sizes()
+    }
+
+    fn keys_by_expiry(&self) -> Vec<(u64, KeyType)> {
+        self.store.keys_by_expiry()
+    }
+
+    fn prefix_scan(&self, prefix: &[u8]) -> Vec<KeyType> {
+        self.store.prefix_scan(prefix)
+    }
+
+    fn take_snapshot(&self) -> StorageSnapshot {
+        self.store.take_snapshot()
+    }
+
+    fn reset_to_snapshot(&self, snapshot: &StorageSnapshot) {
+        self.store.reset_to_snapshot(snapshot)
+    }
+
+    #[cfg(any(test, feature = "debug"))]
+    fn debug_dump(&self) -> DebugDump {
+        let mut dump = self.store.debug_dump();
+        for (key, (value, _flags, _expiration, _cas)) in dump.iter_mut() {
+            if let Ok(record) = self.get_by_key(key) {
+                *value = record.value;
+            }
+        }
+        dump
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_store::store::MemoryStore;
+    use crate::mock::mock_server::MockSystemTimer;
+
+    const MIN_SIZE: usize = 64;
+
+    fn create_cache() -> (Arc<MemoryStore>, CompressedCache) {
+        let store = Arc::new(MemoryStore::new(Arc::new(MockSystemTimer::new())));
+        let cache = CompressedCache::new(store.clone(), MIN_SIZE);
+        (store, cache)
+    }
+
+    fn json_value(len: usize) -> Bytes {
+        let item = "{\"id\":1,\"name\":\"memcrs\",\"tags\":[\"cache\",\"lz4\"]},";
+        Bytes::from(item.repeat(len / item.len() + 1)[..len].to_string())
+    }
+
+    #[test]
+    fn large_values_should_be_stored_compressed_and_read_as_sent() {
+        let (store, cache) = create_cache();
+        let key = Bytes::from("json");
+        let value = json_value(10 * 1024);
+        cache
+            .set(key.clone(), Record::new(value.clone(), 0, u32::MAX, 0))
+            .unwrap();
+
+        let stored = store.get(&key).unwrap();
+        assert!(stored.header.compressed);
+        assert!(stored.value.len() < value.len());
+        assert_eq!(stored.header.flags, u32::MAX);
+
+        let record = cache.get(&key).unwrap();
+        assert!(!record.header.compressed);
+        assert_eq!(record.value, value);
+        assert_eq!(record.header.flags, u32::MAX);
+        assert_eq!(cache.get_value_bytes(&key).unwrap(), value);
+        assert_eq!(
+            cache.get_many(std::slice::from_ref(&key))[0]
+                .as_ref()
+                .unwrap()
+                .value,
+            value
+        );
+        assert_eq!(
+            cache
+                .delete(key, CacheMetaData::new(0, 0, 0))
+                .unwrap()
+                .value,
+            value
+        );
+    }
+
+    #[test]
+    fn small_and_incompressible_values_should_be_stored_as_they_are() {
+        let (store, cache) = create_cache();
+        let small = Bytes::from("small");
+        let random: Bytes = (0..1024).map(|_| rand::random::<u8>()).collect();
+        cache
+            .set(Bytes::from("small"), Record::new(small.clone(), 0, 0, 0))
+            .unwrap();
+        cache
+            .set(Bytes::from("random"), Record::new(random.clone(), 0, 0, 0))
+            .unwrap();
+
+        let stored = store.get(&Bytes::from("small")).unwrap();
+        assert!(!stored.header.compressed);
+        assert_eq!(stored.value, small);
+        let stored = store.get(&Bytes::from("random")).unwrap();
+        assert!(!stored.header.compressed);
+        assert_eq!(stored.value, random);
+    }
+
+    #[test]
+    fn replace_if_predicate_should_see_decompressed_value() {
+        let (_store, cache) = create_cache();
+        let key = Bytes::from("json");
+        let value = json_value(1024);
+        cache
+            .set(key.clone(), Record::new(value.clone(), 0, 0, 0))
+            .unwrap();
+
+        let predicate = |stored: &Record| stored.value == value;
+        let replaced = json_value(2048);
+        cache
+            .replace_if(
+                key.clone(),
+                Record::new(replaced.clone(), 0, 0, 0),
+                &predicate,
+            )
+            .unwrap();
+        assert_eq!(cache.get(&key).unwrap().value, replaced);
+    }
+}
@@ -1,5 +1,13 @@
-#[derive(Debug)]
+/// Which records are evicted once a memory limit is reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
 pub enum EvictionPolicy {
+    /// records are not evicted, writes over a memory limit fail
+    /// with OutOfMemory
     None,
+    /// evicts randomly picked records
     Random,
+    /// evicts least frequently read records
+    Lfu,
+    /// evicts least recently read record of a random sample
+    Lru,
 }
@@ -0,0 +1,127 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU16, AtomicU64, Ordering};
+
+const ROWS: usize = 4;
+const WIDTH: usize = 2048;
+/// Counters are halved after this many increments by default
+pub const DEFAULT_RESET_INTERVAL: u64 = 10 * WIDTH as u64;
+
+/// Count-min sketch estimating how often keys are accessed, it never
+/// underestimates a frequency, but keys sharing counters in all rows
+/// are overestimated. Counters are halved every reset interval, so old
+/// accesses count less than recent ones.
+pub struct FrequencySketch {
+    counters: Box<[AtomicU16]>,
+    // independent hash function of each row
+    hashers: [RandomState; ROWS],
+    increments: AtomicU64,
+    reset_interval: u64,
+}
+
+impl FrequencySketch {
+    pub fn new(reset_interval: u64) -> FrequencySketch {
+        FrequencySketch {
+            counters: (0..ROWS * WIDTH).map(|_| AtomicU16::new(0)).collect(),
+            hashers: std::array::from_fn(|_| RandomState::new()),
+            increments: AtomicU64::new(0),
+            reset_interval: reset_interval.max(1),
+        }
+    }
+
+    fn counter(&self, row: usize, key: &[u8]) -> &AtomicU16 {
+        let column = self.hashers[row].hash_one(key) as usize % WIDTH;
+        &self.counters[row * WIDTH + column]
+    }
+
+    /// Records a single access of a key
+    pub fn increment(&self, key: &[u8]) {
+        for row in 0..ROWS {
+            let _ = self.counter(row, key).fetch_update(
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+                |count| count.checked_add(1),
+            );
+        }
+        let increments = self.increments.fetch_add(1, Ordering::Relaxed) + 1;
+        if increments.is_multiple_of(self.reset_interval) {
+            self.decay();
+        }
+    }
+
+    /// Estimated number of accesses of a key, minimum of its counters
+    pub fn frequency(&self, key: &[u8]) -> u64 {
+        (0..ROWS)
+            .map(|row| self.counter(row, key).load(Ordering::Relaxed) as u64)
+            .min()
+            .unwrap_or(0)
+    }
+
+    // concurrent increments may be lost while counters are halved,
+    // estimates are approximate anyway
+    fn decay(&self) {
+        for counter in self.counters.iter() {
+            let _ = counter.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                Some(count >> 1)
+            });
+        }
+    }
+}
+
+impl Default for FrequencySketch {
+    fn default() -> Self {
+        FrequencySketch::new(DEFAULT_RESET_INTERVAL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(id: u64) -> Vec<u8> {
+        format!("key_{}", id).into_bytes()
+    }
+
+    #[test]
+    fn frequency_should_not_underestimate_and_stay_close_to_true_frequency() {
+        const KEYS: u64 = 1000;
+        let sketch = FrequencySketch::new(u64::MAX);
+        let true_frequency = |id: u64| id % 50;
+        let mut total = 0;
+        for id in 0..KEYS {
+            for _ in 0..true_frequency(id) {
+                sketch.increment(&key(id));
+                total += 1;
+            }
+        }
+        // count-min error is at most e / WIDTH * total with probability
+        // 1 - e^-ROWS for each key
+        let max_error = total * 3 / WIDTH as u64;
+        let mut within_error = 0;
+        for id in 0..KEYS {
+            let estimate = sketch.frequency(&key(id));
+            assert!(estimate >= true_frequency(id));
+            if estimate - true_frequency(id) <= max_error {
+                within_error += 1;
+            }
+        }
+        assert!(within_error >= KEYS * 95 / 100, "{} keys", within_error);
+    }
+
+    #[test]
+    fn counters_should_be_halved_every_reset_interval() {
+        let sketch = FrequencySketch::new(100);
+        for _ in 0..60 {
+            sketch.increment(b"hot");
+        }
+        for _ in 0..39 {
+            sketch.increment(b"cold");
+        }
+        let hot = sketch.frequency(b"hot");
+        assert!(hot >= 60);
+        // 100th increment halves all counters
+        sketch.increment(b"cold");
+        assert_eq!(sketch.frequency(b"hot"), hot / 2);
+        assert!(sketch.frequency(b"cold") >= 20);
+    }
+}
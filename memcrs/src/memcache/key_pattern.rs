@@ -0,0 +1,95 @@
+use crate::cache::error::{CacheError, Result};
+use regex::bytes::{Regex, RegexBuilder};
+
+/// Glob pattern matched against whole keys: `*` matches any sequence
+/// of bytes, `?` matches a single byte and `\` escapes a following
+/// character, so `\*` matches `*`. All other characters match themselves.
+pub struct KeyPattern {
+    // literal part of a pattern before a first wildcard
+    prefix: Vec<u8>,
+    regex: Regex,
+}
+
+impl KeyPattern {
+    /// Returns InvalidArguments if a pattern ends with an unfinished escape
+    pub fn new(pattern: &str) -> Result<KeyPattern> {
+        let mut prefix = Vec::new();
+        let mut in_prefix = true;
+        let mut regex = String::from("^");
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            let literal = match c {
+                '*' | '?' => {
+                    in_prefix = false;
+                    regex.push_str(if c == '*' { ".*" } else { "." });
+                    continue;
+                }
+                '\\' => chars.next().ok_or(CacheError::InvalidArguments)?,
+                c => c,
+            };
+            let literal = literal.to_string();
+            if in_prefix {
+                prefix.extend_from_slice(literal.as_bytes());
+            }
+            if literal.is_ascii() {
+                regex.push_str(&regex::escape(&literal));
+            } else {
+                // regex without unicode support accepts only ASCII characters,
+                // bytes of other UTF-8 characters are matched by value
+                for byte in literal.bytes() {
+                    regex.push_str(&format!("\\x{:02x}", byte));
+                }
+            }
+        }
+        regex.push('$');
+        // keys are bytes, not necessarily UTF-8 strings
+        let regex = RegexBuilder::new(&regex)
+            .unicode(false)
+            .dot_matches_new_line(true)
+            .build()
+            .map_err(|_err| CacheError::InvalidArguments)?;
+        Ok(KeyPattern { prefix, regex })
+    }
+
+    /// Every key matching a pattern starts with this prefix,
+    /// it is empty if a pattern starts with a wildcard
+    pub fn literal_prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    pub fn is_match(&self, key: &[u8]) -> bool {
+        self.regex.is_match(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_should_match_whole_keys() {
+        let pattern = KeyPattern::new("user:*:name?").unwrap();
+        assert_eq!(pattern.literal_prefix(), b"user:");
+        assert!(pattern.is_match(b"user:1:name1"));
+        assert!(pattern.is_match(b"user::name\n"));
+        assert!(pattern.is_match(b"user:\xff:name\xfe"));
+        assert!(!pattern.is_match(b"user:1:name"));
+        assert!(!pattern.is_match(b"user:1:name12"));
+        assert!(!pattern.is_match(b"xuser:1:name1"));
+
+        let pattern = KeyPattern::new("*").unwrap();
+        assert!(pattern.literal_prefix().is_empty());
+        assert!(pattern.is_match(b""));
+    }
+
+    #[test]
+    fn escaped_and_regex_characters_should_match_literally() {
+        let pattern = KeyPattern::new(r"a.b[1]\*\?\\ключ").unwrap();
+        assert_eq!(pattern.literal_prefix(), r"a.b[1]*?\ключ".as_bytes());
+        assert!(pattern.is_match(r"a.b[1]*?\ключ".as_bytes()));
+        assert!(!pattern.is_match(r"axb[1]*?\ключ".as_bytes()));
+        assert!(!pattern.is_match(r"a.b1x?\ключ".as_bytes()));
+
+        assert!(KeyPattern::new("key\\").is_err());
+    }
+}
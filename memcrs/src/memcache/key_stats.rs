@@ -0,0 +1,163 @@
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Default number of keys counted by KeyStats
+pub const DEFAULT_KEY_STATS_CAPACITY: usize = 10_000;
+
+/// Number of keys returned by stats hotkeys command
+pub const HOT_KEYS_COUNT: usize = 20;
+
+/// Read counters of a single key
+#[derive(Debug, Default)]
+pub struct KeyCounter {
+    pub hits: AtomicU64,
+    pub misses: AtomicU64,
+    /// timer seconds of a last read
+    pub last_access: AtomicU64,
+}
+
+impl KeyCounter {
+    fn record(&self, found: bool, now: u64) {
+        let counter = if found { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.last_access.store(now, Ordering::Relaxed);
+    }
+}
+
+/// Hits and misses of reads counted per key. Up to capacity keys are
+/// counted, reads of other keys are skipped once it is reached, so a
+/// number of distinct keys read by clients doesn't grow memory use.
+#[derive(Debug)]
+pub struct KeyStats {
+    counters: DashMap<Vec<u8>, KeyCounter>,
+    // keys inserted or reserved for insertion, DashMap::len locks all shards
+    len: AtomicUsize,
+    capacity: usize,
+}
+
+impl Default for KeyStats {
+    fn default() -> Self {
+        KeyStats::new(DEFAULT_KEY_STATS_CAPACITY)
+    }
+}
+
+impl KeyStats {
+    /// Counts up to capacity keys, none if it is 0
+    pub fn new(capacity: usize) -> KeyStats {
+        KeyStats {
+            counters: DashMap::new(),
+            len: AtomicUsize::new(0),
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of counted keys
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Counts a hit if a record was found, a miss otherwise
+    pub fn record(&self, key: &[u8], found: bool, now: u64) {
+        if let Some(counter) = self.counters.get(key) {
+            counter.record(found, now);
+            return;
+        }
+        if !self.reserve() {
+            return;
+        }
+        match self.counters.entry(key.to_vec()) {
+            // inserted by another thread since it was looked up
+            Entry::Occupied(entry) => {
+                self.len.fetch_sub(1, Ordering::Relaxed);
+                entry.get().record(found, now);
+            }
+            Entry::Vacant(entry) => entry.insert(KeyCounter::default()).record(found, now),
+        }
+    }
+
+    fn reserve(&self) -> bool {
+        self.len
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |len| {
+                (len < self.capacity).then_some(len + 1)
+            })
+            .is_ok()
+    }
+
+    /// Returns up to count keys with most hits and their hits, most hits first,
+    /// keys with the same hits are sorted by key
+    pub fn hot_keys(&self, count: usize) -> Vec<(Vec<u8>, u64)> {
+        let mut keys: Vec<(Vec<u8>, u64)> = self
+            .counters
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.hits.load(Ordering::Relaxed)))
+            .collect();
+        keys.sort_unstable_by(|(key, hits), (other_key, other_hits)| {
+            other_hits.cmp(hits).then_with(|| key.cmp(other_key))
+        });
+        keys.truncate(count);
+        keys
+    }
+
+    /// Removes counters of all keys
+    pub fn clear(&self) {
+        // each removed key is released, so keys reserved by concurrent
+        // reads are still counted
+        self.counters.retain(|_key, _counter| {
+            self.len.fetch_sub(1, Ordering::Relaxed);
+            false
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hot_keys_should_be_sorted_by_hits() {
+        let stats = KeyStats::new(10);
+        for _ in 0..3 {
+            stats.record(b"b", true, 1);
+        }
+        stats.record(b"a", true, 1);
+        stats.record(b"c", true, 1);
+        stats.record(b"c", false, 2);
+        stats.record(b"d", false, 2);
+        assert_eq!(
+            stats.hot_keys(3),
+            vec![(b"b".to_vec(), 3), (b"a".to_vec(), 1), (b"c".to_vec(), 1)]
+        );
+        let counter = stats.counters.get(b"c".as_slice()).unwrap();
+        assert_eq!(counter.misses.load(Ordering::Relaxed), 1);
+        assert_eq!(counter.last_access.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn new_keys_should_be_skipped_once_capacity_is_reached() {
+        let stats = KeyStats::new(2);
+        stats.record(b"a", true, 1);
+        stats.record(b"b", true, 1);
+        stats.record(b"c", true, 1);
+        stats.record(b"a", true, 1);
+        assert_eq!(stats.len(), 2);
+        assert_eq!(
+            stats.hot_keys(HOT_KEYS_COUNT),
+            vec![(b"a".to_vec(), 2), (b"b".to_vec(), 1)]
+        );
+
+        stats.clear();
+        assert!(stats.is_empty());
+        assert!(stats.hot_keys(HOT_KEYS_COUNT).is_empty());
+        stats.record(b"c", true, 1);
+        assert_eq!(stats.hot_keys(HOT_KEYS_COUNT), vec![(b"c".to_vec(), 1)]);
+    }
+}
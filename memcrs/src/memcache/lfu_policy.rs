@@ -0,0 +1,354 @@
+#[cfg(any(test, feature = "debug"))]
+use crate::cache::cache::DebugDump;
+use crate::cache::cache::{
+    impl_details::CacheImplDetails, Cache, CacheMetaData, CachePredicate, CacheReadOnlyView,
+    KeyType, Record, RecordPredicate, RemoveIfResult, SetCondition, SetStatus, ShardStats,
+    StorageSnapshot, ValueType,
+};
+use crate::cache::error::Result;
+use crate::memcache::frequency_sketch::FrequencySketch;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Evicts least frequently used records once memory limit is reached,
+/// frequency of reads is estimated with a count-min sketch
+pub struct LfuPolicy {
+    store: Arc<dyn Cache + Send + Sync>,
+    memory_limit: AtomicU64,
+    evictions: AtomicU64,
+    sketch: Arc<FrequencySketch>,
+}
+
+impl LfuPolicy {
+    pub fn new(store: Arc<dyn Cache + Send + Sync>, memory_limit: u64) -> LfuPolicy {
+        LfuPolicy {
+            store,
+            memory_limit: AtomicU64::new(memory_limit),
+            evictions: AtomicU64::new(0),
+            sketch: Arc::new(FrequencySketch::default()),
+        }
+    }
+
+    /// Estimated number of recent reads of a key
+    pub fn get_approximate_frequency(&self, key: &[u8]) -> u64 {
+        self.sketch.frequency(key)
+    }
+
+    // evicts records until a new record of a given size fits in memory limit
+    fn make_room(&self, len: u64) {
+        let memory_limit = self.memory_limit.load(Ordering::Relaxed);
+        while self.store.memory_used() + len > memory_limit {
+            let victim = match self.least_frequent_key() {
+                Some(key) => key,
+                None => break,
+            };
+            if let Some((_key, record)) = self.store.remove(&victim) {
+                debug!("Evicted: {} bytes from storage", record.size_estimate());
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn least_frequent_key(&self) -> Option<KeyType> {
+        // remove_if takes a 'static predicate, so it can't borrow self
+        let sketch = Arc::clone(&self.sketch);
+        let victim: Rc<RefCell<Option<(u64, KeyType)>>> = Rc::new(RefCell::new(None));
+        let min = Rc::clone(&victim);
+        // predicate never matches, so remove_if only visits every record
+        self.store
+            .remove_if(&mut move |key: &KeyType, _record: &Record| -> bool {
+                let frequency = sketch.frequency(key);
+                let mut min = min.borrow_mut();
+                if min.as_ref().is_none_or(|(min, _key)| frequency < *min) {
+                    *min = Some((frequency, key.clone()));
+                }
+                false
+            });
+        let victim = victim.borrow_mut().take();
+        victim.map(|(_frequency, key)| key)
+    }
+}
+
+impl CacheImplDetails for LfuPolicy {
+    //
+    fn get_by_key(&self, key: &KeyType) -> Result<Record> {
+        self.sketch.increment(key);
+        self.store.get_by_key(key)
+    }
+
+    //
+    fn check_if_expired(&self, key: &KeyType, record: &Record) -> bool {
+        self.store.check_if_expired(key, record)
+    }
+}
+
+impl Cache for LfuPolicy {
+    fn get(&self, key: &KeyType) -> Result<Record> {
+        self.sketch.increment(key);
+        self.store.get(key)
+    }
+
+    fn get_many(&self, keys: &[KeyType]) -> Vec<Result<Record>> {
+        keys.iter().for_each(|key| self.sketch.increment(key));
+        self.store.get_many(keys)
+    }
+
+    fn try_get(&self, key: &KeyType) -> Option<Result<Record>> {
+        self.sketch.increment(key);
+        self.store.try_get(key)
+    }
+
+    fn get_with_metadata(&self, key: &KeyType) -> Result<(Record, u64)> {
+        self.sketch.increment(key);
+        self.store.get_with_metadata(key)
+    }
+
+    fn get_cas(&self, key: &KeyType) -> Result<u64> {
+        self.sketch.increment(key);
+        self.store.get_cas(key)
+    }
+
+    fn get_flags(&self, key: &KeyType) -> Result<u32> {
+        self.sketch.increment(key);
+        self.store.get_flags(key)
+    }
+
+    fn get_value_bytes(&self, key: &KeyType) -> Result<ValueType> {
+        self.sketch.increment(key);
+        self.store.get_value_bytes(key)
+    }
+
+    fn touch(&self, key: &KeyType, expiration: u32) -> Result<()> {
+        self.store.touch(key, expiration)
+    }
+
+    fn get_and_touch(&self, key: &KeyType, expiration: u32) -> Result<Record> {
+        self.sketch.increment(key);
+        self.store.get_and_touch(key, expiration)
+    }
+
+    fn set(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        let len = record.size_estimate() as u64;
+        self.make_room(len);
+        self.store.set(key, record)
+    }
+
+    fn set_many(&self, items: Vec<(KeyType, Record)>) -> Vec<Result<SetStatus>> {
+        let len: u64 = items
+            .iter()
+            .map(|(_key, record)| record.size_estimate() as u64)
+            .sum();
+        self.make_room(len);
+        self.store.set_many(items)
+    }
+
+    fn atomic_multi_set(&self, items: Vec<(KeyType, Record)>) -> Result<Vec<SetStatus>> {
+        let len: u64 = items
+            .iter()
+            .map(|(_key, record)| record.size_estimate() as u64)
+            .sum();
+        self.make_room(len);
+        self.store.atomic_multi_set(items)
+    }
+
+    fn conditional_set(
+        &self,
+        key: KeyType,
+        record: Record,
+        condition: SetCondition,
+    ) -> Result<SetStatus> {
+        let len = record.size_estimate() as u64;
+        self.make_room(len);
+        self.store.conditional_set(key, record, condition)
+    }
+
+    fn replace_if(
+        &self,
+        key: KeyType,
+        new_record: Record,
+        predicate: &RecordPredicate<'_>,
+    ) -> Result<SetStatus> {
+        let len = new_record.size_estimate() as u64;
+        self.make_room(len);
+        self.store.replace_if(key, new_record, predicate)
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: KeyType,
+        expected_cas: u64,
+        new_record: Record,
+    ) -> Result<(SetStatus, Option<Record>)> {
+        let len = new_record.size_estimate() as u64;
+        self.make_room(len);
+        self.store.compare_and_swap(key, expected_cas, new_record)
+    }
+
+    fn delete(&self, key: KeyType, header: CacheMetaData) -> Result<Record> {
+        self.store.delete(key, header)
+    }
+
+    // Removes key value and returns as an option
+    fn remove(&self, key: &KeyType) -> Option<(KeyType, Record)> {
+        self.store.remove(key)
+    }
+
+    fn flush(&self, header: CacheMetaData) -> usize {
+        self.store.flush(header)
+    }
+
+    fn flush_by_tag(&self, tag: u64) -> Vec<(KeyType, Record)> {
+        self.store.flush_by_tag(tag)
+    }
+
+    fn as_read_only(&self) -> Box<dyn CacheReadOnlyView<'_>> {
+        self.store.as_read_only()
+    }
+
+    fn remove_if(&self, f: &mut CachePredicate) -> RemoveIfResult {
+        self.store.remove_if(f)
+    }
+
+    fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+
+    fn memory_used(&self) -> u64 {
+        self.store.memory_used()
+    }
+
+    fn max_value_size(&self) -> usize {
+        self.store.max_value_size()
+    }
+
+    fn set_memory_limit(&self, memory_limit: u64) -> Result<()> {
+        self.memory_limit.store(memory_limit, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn oldest_item_age_secs(&self) -> Option<u64> {
+        self.store.oldest_item_age_secs()
+    }
+
+    fn newest_item_age_secs(&self) -> Option<u64> {
+        self.store.newest_item_age_secs()
+    }
+
+    fn count_expired(&self) -> usize {
+        self.store.count_expired()
+    }
+
+    fn remove_expired_items(&self) -> RemoveIfResult {
+        self.store.remove_expired_items()
+    }
+
+    fn expired_unfetched(&self) -> u64 {
+        self.store.expired_unfetched()
+    }
+
+    fn flush_affected_items(&self) -> u64 {
+        self.store.flush_affected_items()
+    }
+
+    fn evictions(&self) -> u64 {
+        self.evictions.load(Ordering::Relaxed)
+    }
+
+    fn start_time(&self) -> u64 {
+        self.store.start_time()
+    }
+
+    fn current_time(&self) -> u64 {
+        self.store.current_time()
+    }
+
+    fn current_instant(&self) -> std::time::Instant {
+        self.store.current_instant()
+    }
+
+    fn current_millis(&self) -> u64 {
+        self.store.current_millis()
+    }
+
+    fn per_shard_stats(&self) -> Vec<ShardStats> {
+        self.store.per_shard_stats()
+    }
+
+    fn shard_for_key(&self, key: &[u8]) -> usize {
+        self.store.shard_for_key(key)
+    }
+
+    fn get_random_sample(&self, n: usize) -> Vec<(KeyType, u64)> {
+        self.store.get_random_sample(n)
+    }
+
+    fn value_sizes(&self) -> Vec<u64> {
+        self.store.value_sizes()
+    }
+
+    fn keys_by_expiry(&self) -> Vec<(u64, KeyType)> {
+        self.store.keys_by_expiry()
+    }
+
+    fn prefix_scan(&self, prefix: &[u8]) -> Vec<KeyType> {
+        self.store.prefix_scan(prefix)
+    }
+
+    fn take_snapshot(&self) -> StorageSnapshot {
+        self.store.take_snapshot()
+    }
+
+    fn reset_to_snapshot(&self, snapshot: &StorageSnapshot) {
+        self.store.reset_to_snapshot(snapshot)
+    }
+
+    #[cfg(any(test, feature = "debug"))]
+    fn debug_dump(&self) -> DebugDump {
+        self.store.debug_dump()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_store::store::MemoryStore;
+    use crate::mock::mock_server::MockSystemTimer;
+    use bytes::Bytes;
+
+    fn key(id: u32) -> KeyType {
+        Bytes::from(format!("key_{}", id))
+    }
+
+    fn record() -> Record {
+        Record::new(Bytes::from_static(b"0123456789"), 0, 0, 0)
+    }
+
+    #[test]
+    fn least_frequently_read_record_should_be_evicted() {
+        let store = MemoryStore::new_shared(Arc::new(MockSystemTimer::new()));
+        let entry_size = key(0).len() as u64 + record().size_estimate() as u64;
+        let policy = LfuPolicy::new(store, entry_size * 3);
+        for id in 0..3 {
+            policy.set(key(id), record()).unwrap();
+        }
+        for _ in 0..5 {
+            policy.get(&key(0)).unwrap();
+            policy.get(&key(2)).unwrap();
+        }
+        policy.get(&key(1)).unwrap();
+        assert_eq!(policy.get_approximate_frequency(&key(0)), 5);
+
+        policy.set(key(3), record()).unwrap();
+        assert_eq!(policy.len(), 3);
+        assert!(policy.get(&key(1)).is_err());
+        for id in [0, 2, 3] {
+            assert!(policy.get(&key(id)).is_ok());
+        }
+    }
+}
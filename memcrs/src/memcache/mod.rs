@@ -1,5 +1,16 @@
 pub mod builder;
 pub mod cli;
+#[cfg(feature = "compression")]
+pub mod compressed_cache;
 pub mod eviction_policy;
+pub mod frequency_sketch;
+#[cfg(feature = "key-pattern")]
+pub mod key_pattern;
+pub mod key_stats;
+pub mod lfu_policy;
+pub mod lru_policy;
 pub mod random_policy;
+pub mod stats;
 pub mod store;
+#[cfg(feature = "opentelemetry")]
+pub mod traced_cache;
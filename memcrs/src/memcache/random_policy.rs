@@ -1,6 +1,9 @@
+#[cfg(any(test, feature = "debug"))]
+use crate::cache::cache::DebugDump;
 use crate::cache::cache::{
     impl_details::CacheImplDetails, Cache, CacheMetaData, CachePredicate, CacheReadOnlyView,
-    KeyType, Record, RemoveIfResult, SetStatus,
+    KeyType, Record, RecordPredicate, RemoveIfResult, SetCondition, SetStatus, ShardStats,
+    StorageSnapshot, ValueType,
 };
 use crate::cache::error::Result;
 use rand::rngs::SmallRng;
@@ -10,16 +13,18 @@ use std::sync::Arc;
 
 pub struct RandomPolicy {
     store: Arc<dyn Cache + Send + Sync>,
-    memory_limit: u64,
+    memory_limit: atomic::AtomicU64,
     memory_usage: atomic::AtomicU64,
+    evictions: atomic::AtomicU64,
 }
 
 impl RandomPolicy {
     pub fn new(store: Arc<dyn Cache + Send + Sync>, memory_limit: u64) -> RandomPolicy {
         RandomPolicy {
             store,
-            memory_limit,
+            memory_limit: atomic::AtomicU64::new(memory_limit),
             memory_usage: atomic::AtomicU64::new(0),
+            evictions: atomic::AtomicU64::new(0),
         }
     }
 
@@ -29,9 +34,10 @@ impl RandomPolicy {
             .fetch_add(value, atomic::Ordering::Release);
 
         let mut small_rng = SmallRng::from_entropy();
-        while usage > self.memory_limit {
+        let memory_limit = self.memory_limit.load(atomic::Ordering::Relaxed);
+        while usage > memory_limit {
             debug!("Current memory usage: {}", usage);
-            debug!("Memory limit: {}", self.memory_limit);
+            debug!("Memory limit: {}", memory_limit);
 
             let max = self.store.len();
             if max == 0 {
@@ -51,13 +57,11 @@ impl RandomPolicy {
                     true
                 });
 
-            res.iter().for_each(|record| match record {
-                Some(val) => {
-                    let len = val.1.len();
-                    debug!("Evicted: {} bytes from storage", len);
-                    usage = self.decr_mem_usage(len as u64);
-                }
-                None => {}
+            res.iter().flatten().for_each(|val| {
+                let len = val.1.size_estimate();
+                debug!("Evicted: {} bytes from storage", len);
+                self.evictions.fetch_add(1, atomic::Ordering::Relaxed);
+                usage = self.decr_mem_usage(len as u64);
             });
         }
         usage
@@ -67,6 +71,22 @@ impl RandomPolicy {
         self.memory_usage
             .fetch_sub(value, atomic::Ordering::Release)
     }
+
+    // size of a record a write of key would replace
+    fn stored_size(&self, key: &KeyType) -> u64 {
+        self.store
+            .get_by_key(key)
+            .map_or(0, |record| record.size_estimate() as u64)
+    }
+
+    // usage is counted before a write, so records are evicted before
+    // it is stored, and given back if a record wasn't stored
+    fn account_write(&self, len: u64, replaced: u64, result: &Result<SetStatus>) {
+        match result {
+            Ok(_) => self.decr_mem_usage(replaced),
+            Err(_) => self.decr_mem_usage(len),
+        };
+    }
 }
 
 impl CacheImplDetails for RandomPolicy {
@@ -86,16 +106,112 @@ impl Cache for RandomPolicy {
         self.store.get(key)
     }
 
+    fn get_many(&self, keys: &[KeyType]) -> Vec<Result<Record>> {
+        self.store.get_many(keys)
+    }
+
+    fn try_get(&self, key: &KeyType) -> Option<Result<Record>> {
+        self.store.try_get(key)
+    }
+
+    fn get_with_metadata(&self, key: &KeyType) -> Result<(Record, u64)> {
+        self.store.get_with_metadata(key)
+    }
+
+    fn get_cas(&self, key: &KeyType) -> Result<u64> {
+        self.store.get_cas(key)
+    }
+
+    fn get_flags(&self, key: &KeyType) -> Result<u32> {
+        self.store.get_flags(key)
+    }
+
+    fn get_value_bytes(&self, key: &KeyType) -> Result<ValueType> {
+        self.store.get_value_bytes(key)
+    }
+
+    fn touch(&self, key: &KeyType, expiration: u32) -> Result<()> {
+        self.store.touch(key, expiration)
+    }
+
+    fn get_and_touch(&self, key: &KeyType, expiration: u32) -> Result<Record> {
+        self.store.get_and_touch(key, expiration)
+    }
+
     fn set(&self, key: KeyType, record: Record) -> Result<SetStatus> {
-        let len = record.len() as u64;
+        let len = record.size_estimate() as u64;
         self.incr_mem_usage(len);
         self.store.set(key, record)
     }
 
+    fn set_many(&self, items: Vec<(KeyType, Record)>) -> Vec<Result<SetStatus>> {
+        let len: u64 = items
+            .iter()
+            .map(|(_key, record)| record.size_estimate() as u64)
+            .sum();
+        self.incr_mem_usage(len);
+        self.store.set_many(items)
+    }
+
+    fn atomic_multi_set(&self, items: Vec<(KeyType, Record)>) -> Result<Vec<SetStatus>> {
+        let len: u64 = items
+            .iter()
+            .map(|(_key, record)| record.size_estimate() as u64)
+            .sum();
+        self.incr_mem_usage(len);
+        self.store.atomic_multi_set(items)
+    }
+
+    fn conditional_set(
+        &self,
+        key: KeyType,
+        record: Record,
+        condition: SetCondition,
+    ) -> Result<SetStatus> {
+        let len = record.size_estimate() as u64;
+        self.incr_mem_usage(len);
+        let replaced = match condition {
+            SetCondition::IfNotExists => 0,
+            _ => self.stored_size(&key),
+        };
+        let result = self.store.conditional_set(key, record, condition);
+        self.account_write(len, replaced, &result);
+        result
+    }
+
+    fn replace_if(
+        &self,
+        key: KeyType,
+        new_record: Record,
+        predicate: &RecordPredicate<'_>,
+    ) -> Result<SetStatus> {
+        let len = new_record.size_estimate() as u64;
+        self.incr_mem_usage(len);
+        let replaced = self.stored_size(&key);
+        let result = self.store.replace_if(key, new_record, predicate);
+        self.account_write(len, replaced, &result);
+        result
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: KeyType,
+        expected_cas: u64,
+        new_record: Record,
+    ) -> Result<(SetStatus, Option<Record>)> {
+        let len = new_record.size_estimate() as u64;
+        self.incr_mem_usage(len);
+        let result = self.store.compare_and_swap(key, expected_cas, new_record);
+        if let Ok((_, Some(old_record))) = &result {
+            self.decr_mem_usage(old_record.size_estimate() as u64);
+        }
+        result
+    }
+
     fn delete(&self, key: KeyType, header: CacheMetaData) -> Result<Record> {
         let result = self.store.delete(key, header);
         if let Ok(record) = &result {
-            self.decr_mem_usage(record.len() as u64);
+            self.decr_mem_usage(record.size_estimate() as u64);
         }
         result
     }
@@ -104,16 +220,24 @@ impl Cache for RandomPolicy {
     fn remove(&self, key: &KeyType) -> Option<(KeyType, Record)> {
         let result = self.store.remove(key);
         if let Some(key_value) = &result {
-            self.decr_mem_usage(key_value.1.len() as u64);
+            self.decr_mem_usage(key_value.1.size_estimate() as u64);
         }
         result
     }
 
-    fn flush(&self, header: CacheMetaData) {
+    fn flush(&self, header: CacheMetaData) -> usize {
         self.store.flush(header)
     }
 
-    fn as_read_only(&self) -> Box<dyn CacheReadOnlyView> {
+    fn flush_by_tag(&self, tag: u64) -> Vec<(KeyType, Record)> {
+        let removed = self.store.flush_by_tag(tag);
+        removed.iter().for_each(|(_key, record)| {
+            self.decr_mem_usage(record.size_estimate() as u64);
+        });
+        removed
+    }
+
+    fn as_read_only(&self) -> Box<dyn CacheReadOnlyView<'_>> {
         self.store.as_read_only()
     }
 
@@ -128,6 +252,174 @@ impl Cache for RandomPolicy {
     fn is_empty(&self) -> bool {
         self.store.is_empty()
     }
+
+    fn memory_used(&self) -> u64 {
+        self.store.memory_used()
+    }
+
+    fn max_value_size(&self) -> usize {
+        self.store.max_value_size()
+    }
+
+    fn set_memory_limit(&self, memory_limit: u64) -> Result<()> {
+        self.memory_limit
+            .store(memory_limit, atomic::Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn oldest_item_age_secs(&self) -> Option<u64> {
+        self.store.oldest_item_age_secs()
+    }
+
+    fn newest_item_age_secs(&self) -> Option<u64> {
+        self.store.newest_item_age_secs()
+    }
+
+    fn count_expired(&self) -> usize {
+        self.store.count_expired()
+    }
+
+    fn remove_expired_items(&self) -> RemoveIfResult {
+        let result = self.store.remove_expired_items();
+        result.iter().flatten().for_each(|(_key, record)| {
+            self.decr_mem_usage(record.size_estimate() as u64);
+        });
+        result
+    }
+
+    fn expired_unfetched(&self) -> u64 {
+        self.store.expired_unfetched()
+    }
+
+    fn flush_affected_items(&self) -> u64 {
+        self.store.flush_affected_items()
+    }
+
+    fn evictions(&self) -> u64 {
+        self.evictions.load(atomic::Ordering::Relaxed)
+    }
+
+    fn start_time(&self) -> u64 {
+        self.store.start_time()
+    }
+
+    fn current_time(&self) -> u64 {
+        self.store.current_time()
+    }
+
+    fn current_instant(&self) -> std::time::Instant {
+        self.store.current_instant()
+    }
+
+    fn current_millis(&self) -> u64 {
+        self.store.current_millis()
+    }
+
+    fn per_shard_stats(&self) -> Vec<ShardStats> {
+        self.store.per_shard_stats()
+    }
+
+    fn shard_for_key(&self, key: &[u8]) -> usize {
+        self.store.shard_for_key(key)
+    }
+
+    fn get_random_sample(&self, n: usize) -> Vec<(KeyType, u64)> {
+        self.store.get_random_sample(n)
+    }
+
+    fn value_sizes(&self) -> Vec<u64> {
+        self.store.value_sizes()
+    }
+
+    fn keys_by_expiry(&self) -> Vec<(u64, KeyType)> {
+        self.store.keys_by_expiry()
+    }
+
+    fn prefix_scan(&self, prefix: &[u8]) -> Vec<KeyType> {
+        self.store.prefix_scan(prefix)
+    }
+
+    fn take_snapshot(&self) -> StorageSnapshot {
+        self.store.take_snapshot()
+    }
+
+    fn reset_to_snapshot(&self, snapshot: &StorageSnapshot) {
+        self.store.reset_to_snapshot(snapshot);
+        let usage: usize = snapshot
+            .records
+            .iter()
+            .map(|(_key, record)| record.size_estimate())
+            .sum();
+        self.memory_usage
+            .store(usage as u64, atomic::Ordering::Release);
+    }
+
+    #[cfg(any(test, feature = "debug"))]
+    fn debug_dump(&self) -> DebugDump {
+        self.store.debug_dump()
+    }
 }
 
-mod tests {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::error::CacheError;
+    use crate::memory_store::store::MemoryStore;
+    use crate::mock::mock_server::MockSystemTimer;
+    use bytes::Bytes;
+
+    fn record() -> Record {
+        Record::new(Bytes::from_static(b"0123456789"), 0, 0, 0)
+    }
+
+    fn usage(policy: &RandomPolicy) -> u64 {
+        policy.memory_usage.load(atomic::Ordering::Acquire)
+    }
+
+    #[test]
+    fn failed_conditional_writes_should_not_count_memory_usage() {
+        let store = Arc::new(MemoryStore::new(Arc::new(MockSystemTimer::new())));
+        let size = record().size_estimate() as u64;
+        let policy = RandomPolicy::new(store, size * 3);
+        let key = Bytes::from("key");
+        policy.set(key.clone(), record()).unwrap();
+
+        for _ in 0..10 {
+            let result = policy.conditional_set(key.clone(), record(), SetCondition::IfNotExists);
+            assert_eq!(result, Err(CacheError::KeyExists));
+            let result = policy.replace_if(Bytes::from("other"), record(), &|_record| true);
+            assert_eq!(result, Err(CacheError::NotFound));
+        }
+        assert_eq!(usage(&policy), size);
+
+        // replaced records are subtracted
+        for _ in 0..10 {
+            policy
+                .conditional_set(key.clone(), record(), SetCondition::IfExists)
+                .unwrap();
+            policy
+                .replace_if(key.clone(), record(), &|_record| true)
+                .unwrap();
+        }
+        assert_eq!(usage(&policy), size);
+        assert_eq!(policy.evictions(), 0);
+        assert!(policy.get(&key).is_ok());
+    }
+
+    #[test]
+    fn flush_by_tag_should_give_back_memory_usage() {
+        let store = Arc::new(MemoryStore::new(Arc::new(MockSystemTimer::new())));
+        let size = record().size_estimate() as u64;
+        let policy = RandomPolicy::new(store, size * 10);
+        for id in 0..5 {
+            let record = Record::new_with_tag(Bytes::from_static(b"0123456789"), 0, 0, 0, 1);
+            policy
+                .set(Bytes::from(format!("tagged_{}", id)), record)
+                .unwrap();
+        }
+        policy.set(Bytes::from("untagged"), record()).unwrap();
+
+        assert_eq!(policy.flush_by_tag(1).len(), 5);
+        assert_eq!(usage(&policy), size);
+    }
+}
@@ -0,0 +1,111 @@
+use super::key_stats::KeyStats;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters reported by stats command, named as memcached counters.
+/// Commands are counted by MemcStore, which may share counters with
+/// other stores of the same cache, i.e. of TCP and UDP listeners.
+/// Gauges: curr_items, bytes, evictions, expired_unfetched and uptime
+/// are refreshed from a cache by MemcStore::counters. Reads are also
+/// counted per key for stats hotkeys command.
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub cmd_get: AtomicU64,
+    pub cmd_set: AtomicU64,
+    pub get_hits: AtomicU64,
+    pub get_misses: AtomicU64,
+    pub delete_hits: AtomicU64,
+    pub delete_misses: AtomicU64,
+    pub incr_hits: AtomicU64,
+    pub incr_misses: AtomicU64,
+    pub decr_hits: AtomicU64,
+    pub decr_misses: AtomicU64,
+    pub cas_hits: AtomicU64,
+    pub cas_misses: AtomicU64,
+    /// CAS of a stored record was different
+    pub cas_badval: AtomicU64,
+    /// bytes received from clients
+    pub bytes_read: AtomicU64,
+    /// bytes sent to clients
+    pub bytes_written: AtomicU64,
+    pub curr_items: AtomicU64,
+    /// records stored since a server was started
+    pub total_items: AtomicU64,
+    /// bytes used by stored keys and records
+    pub bytes: AtomicU64,
+    pub evictions: AtomicU64,
+    pub expired_unfetched: AtomicU64,
+    /// seconds since a store was created
+    pub uptime: AtomicU64,
+    /// setting of a server, seconds after which idle clients
+    /// are disconnected, 0 if they are not
+    pub idle_timeout: AtomicU64,
+    /// requests which took longer than --slow-log-threshold-us to handle
+    pub slow_commands: AtomicU64,
+    /// hits and misses of each read key
+    pub key_stats: KeyStats,
+}
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats::default()
+    }
+
+    /// Counts reads of up to capacity keys, see KeyStats
+    pub fn with_key_stats_capacity(mut self, capacity: usize) -> Self {
+        self.key_stats = KeyStats::new(capacity);
+        self
+    }
+
+    pub(crate) fn incr(counter: &AtomicU64) {
+        Stats::add(counter, 1);
+    }
+
+    pub(crate) fn add(counter: &AtomicU64, value: u64) {
+        counter.fetch_add(value, Ordering::Relaxed);
+    }
+
+    // increments hits if found, misses otherwise
+    pub(crate) fn hit_or_miss(hits: &AtomicU64, misses: &AtomicU64, found: bool) {
+        Stats::incr(if found { hits } else { misses });
+    }
+
+    pub(crate) fn gauge(counter: &AtomicU64, value: u64) {
+        counter.store(value, Ordering::Relaxed);
+    }
+
+    /// Names and values of all counters in order of stats command response
+    pub fn values(&self) -> Vec<(&'static str, u64)> {
+        [
+            ("uptime", &self.uptime),
+            ("cmd_get", &self.cmd_get),
+            ("cmd_set", &self.cmd_set),
+            ("get_hits", &self.get_hits),
+            ("get_misses", &self.get_misses),
+            ("delete_misses", &self.delete_misses),
+            ("delete_hits", &self.delete_hits),
+            ("incr_misses", &self.incr_misses),
+            ("incr_hits", &self.incr_hits),
+            ("decr_misses", &self.decr_misses),
+            ("decr_hits", &self.decr_hits),
+            ("cas_misses", &self.cas_misses),
+            ("cas_hits", &self.cas_hits),
+            ("cas_badval", &self.cas_badval),
+            ("bytes_read", &self.bytes_read),
+            ("bytes_written", &self.bytes_written),
+            ("curr_items", &self.curr_items),
+            ("total_items", &self.total_items),
+            ("expired_unfetched", &self.expired_unfetched),
+            ("evictions", &self.evictions),
+            ("bytes", &self.bytes),
+            ("slow_commands", &self.slow_commands),
+        ]
+        .into_iter()
+        .map(|(name, counter)| (name, counter.load(Ordering::Relaxed)))
+        .collect()
+    }
+
+    /// Names and values of settings in order of stats settings response
+    pub fn settings(&self) -> Vec<(&'static str, u64)> {
+        vec![("idle_timeout", self.idle_timeout.load(Ordering::Relaxed))]
+    }
+}
@@ -1,23 +1,111 @@
 use bytes::{Bytes, BytesMut};
 
+#[cfg(any(test, feature = "debug"))]
+use crate::cache::cache::DebugDump;
 use crate::cache::cache::{
     Cache, CacheMetaData as CacheMeta, KeyType as CacheKeyType, Record as CacheRecord,
-    SetStatus as CacheSetStatus,
+    SetCondition as CacheSetCondition, SetStatus as CacheSetStatus, ShardStats, StorageSnapshot,
+    ValueSizePercentiles, ValueType as CacheValueType,
 };
 use crate::cache::error::{CacheError, Result};
+#[cfg(feature = "key-pattern")]
+use crate::memcache::key_pattern::KeyPattern;
+use crate::memcache::stats::Stats;
+#[cfg(feature = "rayon")]
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
+use serde_derive::Serialize;
+use std::ops::{Add, Sub};
 use std::str;
 use std::sync::Arc;
+use std::time::Instant;
+use utoipa::ToSchema;
 
 pub type Record = CacheRecord;
 pub type Meta = CacheMeta;
 pub type SetStatus = CacheSetStatus;
+pub type SetCondition = CacheSetCondition;
 pub type KeyType = CacheKeyType;
+pub type ValueType = CacheValueType;
 
-#[derive(Clone)]
+/// Batches with at least this many keys are looked up in parallel,
+/// smaller ones are not worth sending to a thread pool
+#[cfg(feature = "rayon")]
+pub const DEFAULT_PARALLEL_GET_THRESHOLD: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DeltaParam {
-    pub(crate) delta: u64,
-    pub(crate) value: u64,
+    pub delta: u64,
+    pub value: u64,
+}
+
+/// Delta with initial value equal to 0
+///
+/// ```
+/// use memcrs::memcache::store::IncrementParam;
+///
+/// let param = IncrementParam::from(5);
+/// assert_eq!(param, IncrementParam { delta: 5, value: 0 });
+/// ```
+impl From<u64> for DeltaParam {
+    fn from(delta: u64) -> DeltaParam {
+        DeltaParam { delta, value: 0 }
+    }
+}
+
+/// Delta and initial value given as a (delta, value) tuple
+///
+/// ```
+/// use memcrs::memcache::store::DecrementParam;
+///
+/// let param = DecrementParam::from((3, 10));
+/// assert_eq!(param, DecrementParam { delta: 3, value: 10 });
+/// ```
+impl From<(u64, u64)> for DeltaParam {
+    fn from(tuple: (u64, u64)) -> DeltaParam {
+        DeltaParam {
+            delta: tuple.0,
+            value: tuple.1,
+        }
+    }
+}
+
+/// Increases the delta, initial value is left untouched
+///
+/// ```
+/// use memcrs::memcache::store::IncrementParam;
+///
+/// let param = IncrementParam::from((3, 10)) + 2;
+/// assert_eq!(param, IncrementParam { delta: 5, value: 10 });
+/// ```
+impl Add<u64> for DeltaParam {
+    type Output = DeltaParam;
+
+    fn add(self, rhs: u64) -> DeltaParam {
+        DeltaParam {
+            delta: self.delta.saturating_add(rhs),
+            value: self.value,
+        }
+    }
+}
+
+/// Decreases the delta, saturating at 0
+///
+/// ```
+/// use memcrs::memcache::store::IncrementParam;
+///
+/// let param = IncrementParam::from(3) - 5;
+/// assert_eq!(param.delta, 0);
+/// ```
+impl Sub<u64> for DeltaParam {
+    type Output = DeltaParam;
+
+    fn sub(self, rhs: u64) -> DeltaParam {
+        DeltaParam {
+            delta: self.delta.saturating_sub(rhs),
+            value: self.value,
+        }
+    }
 }
 pub type IncrementParam = DeltaParam;
 pub type DecrementParam = IncrementParam;
@@ -28,72 +116,461 @@ pub struct DeltaResult {
     pub cas: u64,
     pub value: DeltaResultValueType,
 }
+/// Point in time statistics of a store, reported by stats command
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct StorageStatsSnapshot {
+    pub curr_items: usize,
+    pub oldest_item_age: Option<u64>,
+    pub newest_item_age: Option<u64>,
+    /// expired records which weren't removed yet
+    pub expired_items: usize,
+    pub expired_unfetched: u64,
+    /// records removed or scheduled to expire by flush commands
+    pub cmd_flush_affected_items: u64,
+    /// timer timestamp when a store was created
+    pub start_time: u64,
+    /// timer timestamp when statistics were taken
+    pub time: u64,
+}
+
+impl StorageStatsSnapshot {
+    /// Seconds elapsed since a store was created
+    pub fn uptime_secs(&self, now: u64) -> u64 {
+        now.saturating_sub(self.start_time)
+    }
+}
+
 /**
  * Implements Memcache commands based
  * on Key Value Store
  */
 pub struct MemcStore {
     store: Arc<dyn Cache + Send + Sync>,
+    counters: Arc<Stats>,
+    #[cfg(feature = "rayon")]
+    parallel_get_threshold: usize,
 }
 
 impl MemcStore {
     pub fn new(store: Arc<dyn Cache + Send + Sync>) -> MemcStore {
-        MemcStore { store }
+        MemcStore {
+            store,
+            counters: Arc::new(Stats::new()),
+            #[cfg(feature = "rayon")]
+            parallel_get_threshold: DEFAULT_PARALLEL_GET_THRESHOLD,
+        }
+    }
+
+    /// Smallest batch looked up in parallel by get_many_parallel,
+    /// smaller batches are looked up sequentially
+    #[cfg(feature = "rayon")]
+    pub fn with_parallel_get_threshold(mut self, threshold: usize) -> Self {
+        self.parallel_get_threshold = threshold;
+        self
+    }
+
+    /// Shares counters of stats command, i.e. between listeners
+    pub fn with_stats(mut self, counters: Arc<Stats>) -> Self {
+        self.counters = counters;
+        self
+    }
+
+    // CAS of a record is checked if it is not 0
+    fn count_set(&self, cas: u64, result: &Result<SetStatus>) {
+        let counters = &self.counters;
+        Stats::incr(&counters.cmd_set);
+        if result.is_ok() {
+            Stats::incr(&counters.total_items);
+        }
+        if cas != 0 {
+            self.count_cas(result);
+        }
+    }
+
+    fn count_cas<T>(&self, result: &Result<T>) {
+        let counters = &self.counters;
+        match result {
+            Ok(_) => Stats::incr(&counters.cas_hits),
+            Err(CacheError::KeyExists) | Err(CacheError::CasMismatch) => {
+                Stats::incr(&counters.cas_badval)
+            }
+            Err(CacheError::NotFound) => Stats::incr(&counters.cas_misses),
+            Err(_) => {}
+        }
+    }
+
+    fn count_get<T>(&self, key: &KeyType, result: Result<T>) -> Result<T> {
+        let counters = &self.counters;
+        Stats::incr(&counters.cmd_get);
+        Stats::hit_or_miss(&counters.get_hits, &counters.get_misses, result.is_ok());
+        counters
+            .key_stats
+            .record(key, result.is_ok(), self.store.current_time());
+        result
     }
 
     pub fn set(&self, key: KeyType, record: Record) -> Result<SetStatus> {
-        self.store.set(key, record)
+        let cas = record.header.cas;
+        let result = self.store.set(key, record);
+        self.count_set(cas, &result);
+        result
+    }
+
+    /// Sets all records, results are in the same order as items
+    pub fn set_many(&self, items: Vec<(KeyType, Record)>) -> Vec<Result<SetStatus>> {
+        let cas: Vec<u64> = items
+            .iter()
+            .map(|(_key, record)| record.header.cas)
+            .collect();
+        let results = self.store.set_many(items);
+        cas.into_iter()
+            .zip(results.iter())
+            .for_each(|(cas, result)| self.count_set(cas, result));
+        results
+    }
+
+    /// Sets all records or none of them, see Cache::atomic_multi_set
+    pub fn atomic_multi_set(&self, items: Vec<(KeyType, Record)>) -> Result<Vec<SetStatus>> {
+        let count = items.len() as u64;
+        let result = self.store.atomic_multi_set(items);
+        Stats::add(&self.counters.cmd_set, count);
+        if result.is_ok() {
+            Stats::add(&self.counters.total_items, count);
+        }
+        result
     }
 
     pub fn get(&self, key: &KeyType) -> Result<Record> {
-        self.store.get(key)
+        self.count_get(key, self.store.get(key))
     }
 
-    // fn touch_record(&self, _record: &mut Record) {
-    //     let _timer = self.timer.secs();
-    // }
+    /// Returns None if a key is locked by another thread,
+    /// see Cache::try_get
+    pub fn try_get(&self, key: &KeyType) -> Option<Result<Record>> {
+        self.store
+            .try_get(key)
+            .map(|result| self.count_get(key, result))
+    }
 
-    pub fn add(&self, key: KeyType, record: Record) -> Result<SetStatus> {
-        match self.get(&key) {
-            Ok(_record) => Err(CacheError::KeyExists),
-            Err(_err) => self.set(key, record),
+    /// Returns a record and timer micros at which it was read
+    pub fn get_with_metadata(&self, key: &KeyType) -> Result<(Record, u64)> {
+        self.count_get(key, self.store.get_with_metadata(key))
+    }
+
+    /// Returns result of a get for each key, in the same order as keys
+    pub fn get_many(&self, keys: &[KeyType]) -> Vec<Result<Record>> {
+        keys.iter()
+            .zip(self.store.get_many(keys))
+            .map(|(key, result)| self.count_get(key, result))
+            .collect()
+    }
+
+    /// Returns found records with their keys, in the same order as keys,
+    /// misses are skipped as in a response to a multi-key get
+    pub fn get_batch_hits(&self, keys: &[KeyType]) -> Vec<(KeyType, Record)> {
+        keys.iter()
+            .zip(self.get_many(keys))
+            .filter_map(|(key, result)| result.ok().map(|record| (key.clone(), record)))
+            .collect()
+    }
+
+    /// Same as get_many, but large batches are looked up in parallel
+    /// on rayon thread pool, one key at a time
+    #[cfg(feature = "rayon")]
+    pub fn get_many_parallel(&self, keys: &[KeyType]) -> Vec<Result<Record>> {
+        if keys.len() < self.parallel_get_threshold {
+            return self.get_many(keys);
         }
+        // indexed parallel iterator collects results in keys order
+        keys.into_par_iter().map(|key| self.get(key)).collect()
     }
 
-    pub fn replace(&self, key: KeyType, record: Record) -> Result<SetStatus> {
-        match self.get(&key) {
-            Ok(_record) => self.set(key, record),
-            Err(_err) => Err(CacheError::NotFound),
+    /// Returns CAS of a record without copying its value
+    pub fn get_cas(&self, key: &KeyType) -> Result<u64> {
+        self.count_get(key, self.store.get_cas(key))
+    }
+
+    /// Returns a version of a record, which is its CAS token. CAS values
+    /// come from a single counter shared by all keys, so versions increase
+    /// with every write to any key, not by one per write of a key.
+    pub fn get_version(&self, key: &KeyType) -> Result<u64> {
+        self.store.get_cas(key)
+    }
+
+    /// Replaces a record only if its version, see get_version, is equal
+    /// to expected_version. Unlike set with CAS, a missing key is not
+    /// inserted:
+    ///
+    /// - if key is not found or expired NotFound is returned
+    /// - if stored version is different CasMismatch is returned
+    pub fn set_with_version_check(
+        &self,
+        key: KeyType,
+        record: Record,
+        expected_version: u64,
+    ) -> Result<SetStatus> {
+        let predicate = |stored: &Record| stored.header.cas == expected_version;
+        let result = match self.store.replace_if(key, record, &predicate) {
+            Err(CacheError::KeyExists) => Err(CacheError::CasMismatch),
+            result => result,
+        };
+        self.count_cas(&result);
+        result
+    }
+
+    /// Replaces a record only if its CAS is equal to cas, as cas command
+    /// of text protocol, a missing key is not inserted:
+    ///
+    /// - if key is not found or expired NotFound is returned
+    /// - if stored CAS is different KeyExists is returned
+    pub fn cas(&self, key: KeyType, record: Record, cas: u64) -> Result<SetStatus> {
+        let predicate = |stored: &Record| stored.header.cas == cas;
+        let result = self.store.replace_if(key, record, &predicate);
+        self.count_set(cas, &result);
+        result
+    }
+
+    /// Returns flags of a record without copying its value
+    pub fn get_flags(&self, key: &KeyType) -> Result<u32> {
+        self.count_get(key, self.store.get_flags(key))
+    }
+
+    /// Returns value of a record without copying it or its header
+    pub fn get_value_bytes(&self, key: &KeyType) -> Result<ValueType> {
+        self.count_get(key, self.store.get_value_bytes(key))
+    }
+
+    /// Changes expiration of a record without changing its value,
+    /// see `Cache::touch`
+    pub fn touch(&self, key: &KeyType, expiration: u32) -> Result<()> {
+        self.store.touch(key, expiration)
+    }
+
+    /// Returns a record and changes its expiration, see `Cache::get_and_touch`
+    pub fn get_and_touch(&self, key: &KeyType, expiration: u32) -> Result<Record> {
+        self.count_get(key, self.store.get_and_touch(key, expiration))
+    }
+
+    /// Stores record only if condition is met, see `SetCondition`
+    pub fn conditional_set(
+        &self,
+        key: KeyType,
+        record: Record,
+        condition: SetCondition,
+    ) -> Result<SetStatus> {
+        let cas = match condition {
+            SetCondition::IfCasMatches(cas) => cas,
+            _ => 0,
+        };
+        let result = self.store.conditional_set(key, record, condition);
+        self.count_set(cas, &result);
+        result
+    }
+
+    /// Replaces a record only if a stored one satisfies a predicate,
+    /// see `Cache::replace_if`
+    pub fn replace_if<F>(&self, key: KeyType, new_record: Record, predicate: F) -> Result<SetStatus>
+    where
+        F: Fn(&Record) -> bool + Send + Sync,
+    {
+        let result = self.store.replace_if(key, new_record, &predicate);
+        self.count_set(0, &result);
+        result
+    }
+
+    /// Swaps a record if CAS matches and returns the replaced one,
+    /// see `Cache::compare_and_swap`
+    pub fn compare_and_swap(
+        &self,
+        key: KeyType,
+        expected_cas: u64,
+        new_record: Record,
+    ) -> Result<(SetStatus, Option<Record>)> {
+        let result = self.store.compare_and_swap(key, expected_cas, new_record);
+        Stats::incr(&self.counters.cmd_set);
+        self.count_cas(&result);
+        if result.is_ok() {
+            Stats::incr(&self.counters.total_items);
         }
+        result
     }
 
-    pub fn append(&self, key: KeyType, new_record: Record) -> Result<SetStatus> {
-        match self.get(&key) {
-            Ok(mut record) => {
-                record.header.cas = new_record.header.cas;
-                let mut value =
-                    BytesMut::with_capacity(record.value.len() + new_record.value.len());
-                value.extend_from_slice(&record.value);
-                value.extend_from_slice(&new_record.value);
-                record.value = value.freeze();
-                self.set(key, record)
-            }
-            Err(_err) => Err(CacheError::NotFound),
+    pub fn stats(&self) -> StorageStatsSnapshot {
+        StorageStatsSnapshot {
+            curr_items: self.store.len(),
+            oldest_item_age: self.store.oldest_item_age_secs(),
+            newest_item_age: self.store.newest_item_age_secs(),
+            expired_items: self.store.count_expired(),
+            expired_unfetched: self.store.expired_unfetched(),
+            cmd_flush_affected_items: self.store.flush_affected_items(),
+            start_time: self.store.start_time(),
+            time: self.store.current_time(),
         }
     }
 
+    /// Cache records are stored in, i.e. to create a store sharing it
+    pub(crate) fn cache(&self) -> Arc<dyn Cache + Send + Sync> {
+        Arc::clone(&self.store)
+    }
+
+    /// Monotonic time of a store timer, i.e. to measure time of a request
+    pub(crate) fn current_instant(&self) -> Instant {
+        self.store.current_instant()
+    }
+
+    /// Counters without refreshing gauges, i.e. to count bytes of a connection
+    pub(crate) fn shared_counters(&self) -> Arc<Stats> {
+        Arc::clone(&self.counters)
+    }
+
+    /// Counters of stats command, gauges are refreshed from a store first
+    pub fn counters(&self) -> Arc<Stats> {
+        let counters = &self.counters;
+        Stats::gauge(&counters.curr_items, self.store.len() as u64);
+        Stats::gauge(&counters.bytes, self.store.memory_used());
+        Stats::gauge(&counters.evictions, self.store.evictions());
+        Stats::gauge(&counters.expired_unfetched, self.store.expired_unfetched());
+        let uptime = self
+            .store
+            .current_time()
+            .saturating_sub(self.store.start_time());
+        Stats::gauge(&counters.uptime, uptime);
+        Arc::clone(counters)
+    }
+
+    /// Records and bytes stored in each store shard, used to find hot shards
+    pub fn per_shard_stats(&self) -> Vec<ShardStats> {
+        self.store.per_shard_stats()
+    }
+
+    /// Value size histogram, see Cache::stats_histogram
+    pub fn stats_histogram(&self, bucket_count: usize) -> Vec<(u64, u64)> {
+        self.store.stats_histogram(bucket_count)
+    }
+
+    pub fn value_size_percentiles(&self) -> ValueSizePercentiles {
+        self.store.value_size_percentiles()
+    }
+
+    /// Returns keys soonest expiring first, see Cache::keys_by_expiry
+    pub fn keys_by_expiry(&self) -> Vec<(u64, KeyType)> {
+        self.store.keys_by_expiry()
+    }
+
+    /// Returns sorted keys which start with prefix, see Cache::prefix_scan
+    pub fn prefix_scan(&self, prefix: &[u8]) -> Vec<KeyType> {
+        self.store.prefix_scan(prefix)
+    }
+
+    /// Returns up to limit sorted keys matching a glob pattern, see KeyPattern.
+    /// Only keys starting with a literal prefix of a pattern are scanned,
+    /// so a pattern starting with a wildcard scans a whole store.
+    /// Meant for admin tools and debugging, not for serving requests.
+    #[cfg(feature = "key-pattern")]
+    pub fn get_keys_matching(&self, pattern: &str, limit: usize) -> Result<Vec<KeyType>> {
+        let pattern = KeyPattern::new(pattern)?;
+        Ok(self
+            .store
+            .prefix_scan(pattern.literal_prefix())
+            .into_iter()
+            .filter(|key| pattern.is_match(key))
+            .take(limit)
+            .collect())
+    }
+
+    /// Copies all records and CAS counter, see reset_to_snapshot
+    pub fn take_snapshot(&self) -> StorageSnapshot {
+        self.store.take_snapshot()
+    }
+
+    /// Restores a store to a state from a snapshot
+    pub fn reset_to_snapshot(&self, snapshot: &StorageSnapshot) {
+        self.store.reset_to_snapshot(snapshot)
+    }
+
+    /// Removes all expired records, returns number of removed records
+    pub fn remove_expired_items(&self) -> usize {
+        self.store.remove_expired_items().iter().flatten().count()
+    }
+
+    /// Changes memory limit of an eviction policy, see Cache::set_memory_limit
+    pub fn set_memory_limit(&self, memory_limit: u64) -> Result<()> {
+        self.store.set_memory_limit(memory_limit)
+    }
+
+    /// Number of stored records, including expired ones not removed yet
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+
+    /// Approximate number of bytes used by stored keys and records
+    pub fn memory_used(&self) -> u64 {
+        self.store.memory_used()
+    }
+
+    /// Returns all non-expired records sorted by a key
+    #[cfg(any(test, feature = "debug"))]
+    pub fn debug_dump(&self) -> DebugDump {
+        self.store.debug_dump()
+    }
+
+    pub fn add(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.conditional_set(key, record, SetCondition::IfNotExists)
+    }
+
+    pub fn replace(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.conditional_set(key, record, SetCondition::IfExists)
+    }
+
+    pub fn append(&self, key: KeyType, new_record: Record) -> Result<SetStatus> {
+        self.concat(key, new_record, true)
+    }
+
     pub fn prepend(&self, key: KeyType, new_record: Record) -> Result<SetStatus> {
-        match self.get(&key) {
-            Ok(mut record) => {
-                let mut value =
-                    BytesMut::with_capacity(record.value.len() + new_record.value.len());
+        self.concat(key, new_record, false)
+    }
+
+    // new record with a nonzero CAS is concatenated only if it matches
+    // stored record CAS, set checks CAS of the read record again, so
+    // a concurrent update between get and set is not overwritten, without
+    // a CAS of a client the record is read and concatenated again
+    fn concat(&self, key: KeyType, new_record: Record, append: bool) -> Result<SetStatus> {
+        loop {
+            let mut record = match self.store.get(&key) {
+                Ok(record) => record,
+                Err(CacheError::InvalidKey) => return Err(CacheError::InvalidKey),
+                Err(_err) => return Err(CacheError::NotFound),
+            };
+            if new_record.header.cas != 0 && new_record.header.cas != record.header.cas {
+                return Err(CacheError::CasMismatch);
+            }
+            // checked before a concatenated value is allocated,
+            // a stored record is kept as it is
+            if record.value.len() + new_record.value.len() > self.store.max_value_size() {
+                return Err(CacheError::ValueTooLarge);
+            }
+            let mut value = BytesMut::with_capacity(record.value.len() + new_record.value.len());
+            if append {
+                value.extend_from_slice(&record.value);
+                value.extend_from_slice(&new_record.value);
+            } else {
                 value.extend_from_slice(&new_record.value);
                 value.extend_from_slice(&record.value);
-                record.value = value.freeze();
-                record.header.cas = new_record.header.cas;
-                self.set(key, record)
             }
-            Err(_err) => Err(CacheError::NotFound),
+            record.value = value.freeze();
+            record.header.time_to_live =
+                record.header.time_to_live_left(self.store.current_millis());
+            let result = match self.store.set(key.clone(), record) {
+                Err(CacheError::KeyExists) if new_record.header.cas == 0 => continue,
+                Err(CacheError::KeyExists) => Err(CacheError::CasMismatch),
+                result => result,
+            };
+            self.count_set(new_record.header.cas, &result);
+            return result;
         }
     }
 
@@ -115,6 +592,44 @@ impl MemcStore {
         self.add_delta(header, key, decrement, false)
     }
 
+    /// Increments counters as increment does, results are in the same order
+    /// as ops. Operations are applied shard by shard, so consecutive updates
+    /// lock the same shard, operations of the same key are applied in order.
+    /// A batch is not atomic, other clients can see some counters already
+    /// incremented and others not yet.
+    pub fn incr_many(&self, ops: Vec<(KeyType, Meta, IncrementParam)>) -> Vec<Result<DeltaResult>> {
+        self.add_delta_many(ops, true)
+    }
+
+    /// Decrements counters as decrement does, see incr_many
+    pub fn decr_many(&self, ops: Vec<(KeyType, Meta, DecrementParam)>) -> Vec<Result<DeltaResult>> {
+        self.add_delta_many(ops, false)
+    }
+
+    fn add_delta_many(
+        &self,
+        ops: Vec<(KeyType, Meta, DeltaParam)>,
+        increment: bool,
+    ) -> Vec<Result<DeltaResult>> {
+        let mut ops: Vec<(usize, usize, (KeyType, Meta, DeltaParam))> = ops
+            .into_iter()
+            .enumerate()
+            .map(|(index, op)| (self.store.shard_for_key(&op.0), index, op))
+            .collect();
+        ops.sort_unstable_by_key(|(shard, index, _op)| (*shard, *index));
+        let mut results: Vec<(usize, Result<DeltaResult>)> = ops
+            .into_iter()
+            .map(|(_shard, index, (key, header, delta))| {
+                (index, self.add_delta(header, key, delta, increment))
+            })
+            .collect();
+        results.sort_unstable_by_key(|(index, _result)| *index);
+        results.into_iter().map(|(_index, result)| result).collect()
+    }
+
+    // a counter is stored with CAS of the read record, so a concurrent
+    // update makes set fail with KeyExists, the counter is read and updated
+    // again, so no increment is lost
     fn add_delta(
         &self,
         header: Meta,
@@ -122,64 +637,102 @@ impl MemcStore {
         delta: DeltaParam,
         increment: bool,
     ) -> Result<DeltaResult> {
-        match self.get(&key) {
-            Ok(mut record) => {
-                str::from_utf8(&record.value)
-                    .map(|value: &str| {
-                        value
-                            .parse::<u64>()
-                            .map_err(|_err| CacheError::ArithOnNonNumeric)
-                    })
-                    .map_err(|_err| CacheError::ArithOnNonNumeric)
-                    .and_then(|value: std::result::Result<u64, CacheError>| {
-                        //flatten result
-                        value
-                    })
-                    .map(|mut value: u64| {
-                        if increment {
-                            value += delta.delta;
-                        } else if delta.delta > value {
-                            value = 0;
-                        } else {
-                            value -= delta.delta;
-                        }
-                        record.value = Bytes::from(value.to_string());
-                        record.header = header;
-                        self.set(key, record).map(|result| DeltaResult {
-                            cas: result.cas,
-                            value,
-                        })
-                    })
-                    .and_then(|result: std::result::Result<DeltaResult, CacheError>| {
-                        //flatten result
-                        result
-                    })
-            }
-            Err(_err) => {
-                if header.get_expiration() != 0xffffffff {
+        let mut lookup = self.store.get(&key);
+        let (hits, misses) = if increment {
+            (&self.counters.incr_hits, &self.counters.incr_misses)
+        } else {
+            (&self.counters.decr_hits, &self.counters.decr_misses)
+        };
+        Stats::hit_or_miss(hits, misses, lookup.is_ok());
+        loop {
+            let result = match lookup {
+                Ok(record) => self.store_delta(&header, key.clone(), record, &delta, increment),
+                Err(CacheError::InvalidKey) => return Err(CacheError::InvalidKey),
+                Err(_err) => {
+                    if header.get_expiration() == 0xffffffff {
+                        return Err(CacheError::NotFound);
+                    }
                     let record = Record::new(
                         Bytes::from(delta.value.to_string()),
                         0,
                         0,
                         header.get_expiration(),
                     );
-                    return self.set(key, record).map(|result| DeltaResult {
-                        cas: result.cas,
-                        value: delta.value,
-                    });
+                    // a counter created concurrently is incremented instead
+                    self.store
+                        .conditional_set(key.clone(), record, SetCondition::IfNotExists)
+                        .map(|result| DeltaResult {
+                            cas: result.cas,
+                            value: delta.value,
+                        })
                 }
-                Err(CacheError::NotFound)
+            };
+            match result {
+                Err(CacheError::KeyExists) => lookup = self.store.get(&key),
+                result => return result,
             }
         }
     }
 
+    fn store_delta(
+        &self,
+        header: &Meta,
+        key: KeyType,
+        mut record: Record,
+        delta: &DeltaParam,
+        increment: bool,
+    ) -> Result<DeltaResult> {
+        // binary protocol requests can be conditional on CAS
+        if header.cas != 0 && header.cas != record.header.cas {
+            return Err(CacheError::CasMismatch);
+        }
+        let mut value = str::from_utf8(&record.value)
+            .map_err(|_err| CacheError::ArithOnNonNumeric)?
+            .parse::<u64>()
+            .map_err(|_err| CacheError::ArithOnNonNumeric)?;
+        if increment {
+            value = value.wrapping_add(delta.delta);
+        } else {
+            value = value.saturating_sub(delta.delta);
+        }
+        // flags and expiration of a counter are kept, a header
+        // of a request is used only to create a missing one
+        record.value = Bytes::from(value.to_string());
+        record.header.time_to_live = record.header.time_to_live_left(self.store.current_millis());
+        self.store.set(key, record).map(|result| DeltaResult {
+            cas: result.cas,
+            value,
+        })
+    }
+
     pub fn delete(&self, key: KeyType, header: Meta) -> Result<Record> {
-        self.store.delete(key, header)
+        let result = self.store.delete(key, header);
+        let counters = &self.counters;
+        Stats::hit_or_miss(
+            &counters.delete_hits,
+            &counters.delete_misses,
+            result.is_ok(),
+        );
+        result
     }
 
-    pub fn flush(&self, header: Meta) {
+    /// Removes a record which expired as soon as it was stored or touched,
+    /// a removal isn't counted in delete stats
+    pub fn expire(&self, key: &KeyType) {
+        self.store.remove(key);
+    }
+
+    /// Returns number of affected records, see `Cache::flush`.
+    /// Counters of stats hotkeys are cleared even if records expire later.
+    pub fn flush(&self, header: Meta) -> usize {
+        self.counters.key_stats.clear();
         self.store.flush(header)
     }
+
+    /// Returns number of removed records, see `Cache::flush_by_tag`
+    pub fn flush_by_tag(&self, tag: u64) -> usize {
+        self.store.flush_by_tag(tag).len()
+    }
 }
 
 #[cfg(test)]
@@ -1,5 +1,9 @@
 use super::*;
-use crate::mock::mock_server::{create_server, SetableTimer};
+use crate::cache::cache::DebugDump;
+use crate::memory_store::store::MemoryStore;
+use crate::mock::mock_server::{
+    create_server, create_storage, MockServer, MockSystemTimer, SetableTimer,
+};
 use crate::mock::value::{from_slice, from_string};
 use bytes::{BufMut, BytesMut};
 
@@ -10,7 +14,7 @@ fn if_not_defined_cas_should_be_1() {
     let key = Bytes::from("key");
     let record = Record::new(from_string("Test data"), 0, 0, 0);
     let result = server.storage.set(key.clone(), record.clone());
-    assert!(result.is_ok());
+    assert_eq!(result, Ok(SetStatus { cas: 1 }));
     let found = server.storage.get(&key);
     assert!(found.is_ok());
     match found {
@@ -30,7 +34,7 @@ fn if_cas_defined_it_should_be_returned() {
     let record = Record::new(from_string("test data"), cas, 0, 0);
     info!("Record {:?}", &record.header);
     let result = storage.set(key.clone(), record.clone());
-    assert!(result.is_ok());
+    assert_eq!(result, Ok(SetStatus { cas: cas + 1 }));
     let found = storage.get(&key);
     assert!(found.is_ok());
     match found {
@@ -79,6 +83,171 @@ fn record_should_expire_in_given_time() {
     }
 }
 
+#[test]
+fn touch_should_extend_expiration_without_changing_record() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("test data"), 0, 7, 1);
+    let status = server.storage.set(key.clone(), record).unwrap();
+
+    assert_eq!(server.storage.touch(&key, 60), Ok(()));
+    server.timer.set(30);
+    let found = server.storage.get(&key).unwrap();
+    assert_eq!(found.value, from_string("test data"));
+    assert_eq!(found.header.flags, 7);
+    assert_eq!(found.header.cas, status.cas);
+    // expiration is counted from a touch
+    server.timer.set(59);
+    assert!(server.storage.get(&key).is_ok());
+    server.timer.set(60);
+    assert_eq!(server.storage.get(&key), Err(CacheError::NotFound));
+
+    assert_eq!(
+        server.storage.touch(&Bytes::from("missing"), 60),
+        Err(CacheError::NotFound)
+    );
+    // expired record can't be touched back
+    let record = Record::new(from_string("test data"), 0, 0, 1);
+    server.storage.set(key.clone(), record).unwrap();
+    server.timer.set(61);
+    assert_eq!(server.storage.touch(&key, 60), Err(CacheError::NotFound));
+}
+
+#[test]
+fn get_cas_should_return_stored_cas() {
+    let storage = create_server().storage;
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("test data"), 0, 0, 0);
+    let status = storage.set(key.clone(), record).unwrap();
+    assert_eq!(storage.get_cas(&key), Ok(status.cas));
+
+    let status = storage
+        .set(key.clone(), Record::new(from_string("new data"), 0, 0, 0))
+        .unwrap();
+    assert_eq!(storage.get_cas(&key), Ok(status.cas));
+    assert_eq!(
+        storage.get_cas(&Bytes::from("missing")),
+        Err(CacheError::NotFound)
+    );
+}
+
+#[test]
+fn set_with_version_check_should_distinguish_missing_key_from_conflict() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("v1"), 0, 0, 0);
+    assert_eq!(
+        server
+            .storage
+            .set_with_version_check(key.clone(), record.clone(), 1),
+        Err(CacheError::NotFound)
+    );
+    assert_eq!(server.storage.get_version(&key), Err(CacheError::NotFound));
+
+    server.storage.set(key.clone(), record).unwrap();
+    // versions are global, a write of another key increments them too
+    server
+        .storage
+        .set(Bytes::from("other"), Record::new(from_string("v"), 0, 0, 0))
+        .unwrap();
+    let version = server.storage.get_version(&key).unwrap();
+    let record = Record::new(from_string("v2"), 0, 0, 0);
+    assert_eq!(
+        server
+            .storage
+            .set_with_version_check(key.clone(), record.clone(), version + 1),
+        Err(CacheError::CasMismatch)
+    );
+    let status = server
+        .storage
+        .set_with_version_check(key.clone(), record, version)
+        .unwrap();
+    assert_eq!(server.storage.get_version(&key), Ok(status.cas));
+    assert!(status.cas > version + 1);
+    assert_eq!(server.storage.get(&key).unwrap().value, from_string("v2"));
+}
+
+#[test]
+fn get_flags_should_return_stored_flags() {
+    let storage = create_server().storage;
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("test data"), 0, 0xCAFE, 0);
+    storage.set(key.clone(), record).unwrap();
+    assert_eq!(storage.get_flags(&key), Ok(0xCAFE));
+    assert_eq!(
+        storage.get_flags(&Bytes::from("missing")),
+        Err(CacheError::NotFound)
+    );
+}
+
+#[test]
+fn get_value_bytes_should_share_stored_value() {
+    let storage = create_server().storage;
+    let key = Bytes::from("key");
+    let value = from_string("test data");
+    storage
+        .set(key.clone(), Record::new(value.clone(), 0, 0, 0))
+        .unwrap();
+    let bytes = storage.get_value_bytes(&key).unwrap();
+    assert_eq!(bytes, value);
+    assert_eq!(bytes.as_ptr(), value.as_ptr());
+    assert_eq!(
+        storage.get_value_bytes(&Bytes::from("missing")),
+        Err(CacheError::NotFound)
+    );
+}
+
+#[test]
+fn get_cas_and_flags_should_not_return_expired_record() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("test data"), 0, 0xCAFE, 10);
+    server.storage.set(key.clone(), record).unwrap();
+    assert!(server.storage.get_cas(&key).is_ok());
+
+    server.timer.set(10);
+    assert_eq!(server.storage.get_flags(&key), Err(CacheError::NotFound));
+    assert_eq!(server.storage.get_cas(&key), Err(CacheError::NotFound));
+    assert_eq!(server.storage.get(&key), Err(CacheError::NotFound));
+}
+
+#[test]
+fn get_with_metadata_should_return_time_of_read() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("test data"), 0, 0, 10);
+    server.storage.set(key.clone(), record.clone()).unwrap();
+    server.timer.set(3);
+    let (found, micros) = server.storage.get_with_metadata(&key).unwrap();
+    assert_eq!(found, record);
+    assert_eq!(micros, 3_000_000);
+
+    server.timer.set(10);
+    assert_eq!(
+        server.storage.get_with_metadata(&key),
+        Err(CacheError::NotFound)
+    );
+    assert_eq!(server.storage.stats().curr_items, 0);
+}
+
+#[test]
+fn keys_by_expiry_should_list_soonest_expiring_first() {
+    let server = create_server();
+    for (key, expiration) in [("ten", 10), ("never", 0), ("five", 5), ("expired", 1)] {
+        let record = Record::new(from_string("data"), 0, 0, expiration);
+        server.storage.set(Bytes::from(key), record).unwrap();
+    }
+    server.timer.set(1);
+    assert_eq!(
+        server.storage.keys_by_expiry(),
+        vec![
+            (5, Bytes::from("five")),
+            (10, Bytes::from("ten")),
+            (0, Bytes::from("never"))
+        ]
+    );
+}
+
 #[test]
 fn delete_record() {
     let server = create_server();
@@ -109,7 +278,7 @@ fn delete_should_return_not_exists() {
     let found = server.storage.get(&key);
     assert!(found.is_ok());
     let header = Meta::new(0, 0, 0);
-    let deleted = server.storage.delete(Bytes::from("bad key"), header);
+    let deleted = server.storage.delete(Bytes::from("bad_key"), header);
     match deleted {
         Ok(_) => unreachable!(),
         Err(err) => assert_eq!(err, CacheError::NotFound),
@@ -129,7 +298,7 @@ fn delete_if_cas_doesnt_match_should_not_delete() {
     let deleted = server.storage.delete(Bytes::from("key"), header);
     match deleted {
         Ok(_) => unreachable!(),
-        Err(err) => assert_eq!(err, CacheError::KeyExists),
+        Err(err) => assert_eq!(err, CacheError::CasMismatch),
     }
 }
 
@@ -153,7 +322,7 @@ fn flush_should_remove_all_elements_in_cache() {
     for key_suffix in 1..10 {
         let mut key_str = BytesMut::from("key");
         key_str.reserve(8);
-        key_str.put_slice(&key_suffix.to_string().as_bytes());
+        key_str.put_slice(key_suffix.to_string().as_bytes());
         let key = key_str.freeze();
         let record = Record::new(from_string("test data"), 0, 0, 5);
         let result = server.storage.set(key.clone(), record);
@@ -166,7 +335,7 @@ fn flush_should_remove_all_elements_in_cache() {
     for key_suffix in 1..10 {
         let mut key_str = BytesMut::from("key");
         key_str.reserve(8);
-        key_str.put_slice(&key_suffix.to_string().as_bytes());
+        key_str.put_slice(key_suffix.to_string().as_bytes());
         let result = server.storage.get(&key_str.freeze());
         match result {
             Ok(_) => unreachable!(),
@@ -175,6 +344,559 @@ fn flush_should_remove_all_elements_in_cache() {
     }
 }
 
+#[test]
+fn flush_without_delay_should_remove_records_immediately() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("test data"), 0, 0, 0);
+    server.storage.set(key.clone(), record).unwrap();
+    server.timer.set(10);
+    assert_eq!(server.storage.flush(Meta::new(0, 0, 0)), 1);
+    assert_eq!(server.storage.get(&key).unwrap_err(), CacheError::NotFound);
+}
+
+#[test]
+fn delayed_flush_should_expire_records_after_delay_from_now() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let short_lived = Bytes::from("short-lived");
+    server
+        .storage
+        .set(key.clone(), Record::new(from_string("test data"), 0, 0, 0))
+        .unwrap();
+    server
+        .storage
+        .set(
+            short_lived.clone(),
+            Record::new(from_string("test data"), 0, 0, 12),
+        )
+        .unwrap();
+
+    // records were stored 10 seconds before a flush, so a delay
+    // counted from their insertion would have already elapsed
+    server.timer.set(10);
+    server.storage.flush(Meta::new(0, 0, 5));
+    server.timer.set(11);
+    assert!(server.storage.get(&key).is_ok());
+    assert!(server.storage.get(&short_lived).is_ok());
+
+    // expiration sooner than a flush deadline is kept
+    server.timer.set(12);
+    assert_eq!(
+        server.storage.get(&short_lived).unwrap_err(),
+        CacheError::NotFound
+    );
+    server.timer.set(14);
+    assert!(server.storage.get(&key).is_ok());
+    server.timer.set(15);
+    assert_eq!(server.storage.get(&key).unwrap_err(), CacheError::NotFound);
+}
+
+#[test]
+fn stats_should_report_oldest_and_newest_item_age() {
+    let server = create_server();
+    let stats = server.storage.stats();
+    assert_eq!(stats.oldest_item_age, None);
+    assert_eq!(stats.newest_item_age, None);
+
+    server.timer.set(10);
+    let record = Record::new(from_string("old data"), 0, 0, 0);
+    server.storage.set(Bytes::from("old"), record).unwrap();
+    server.timer.set(20);
+    let record = Record::new(from_string("new data"), 0, 0, 0);
+    server.storage.set(Bytes::from("new"), record).unwrap();
+    server.timer.set(25);
+    let record = Record::new(from_string("expired data"), 0, 0, 5);
+    server.storage.set(Bytes::from("expired"), record).unwrap();
+
+    server.timer.set(30);
+    let stats = server.storage.stats();
+    assert_eq!(stats.curr_items, 3);
+    assert_eq!(stats.oldest_item_age, Some(20));
+    assert_eq!(stats.newest_item_age, Some(10));
+}
+
+fn get_many_keys(server: &MockServer, count: usize) -> Vec<KeyType> {
+    // every other key is stored, so results contain hits and misses
+    let keys: Vec<KeyType> = (0..count)
+        .map(|id| Bytes::from(format!("key_{}", id)))
+        .collect();
+    for key in keys.iter().step_by(2) {
+        let record = Record::new(key.clone(), 0, 0, 0);
+        server.storage.set(key.clone(), record).unwrap();
+    }
+    keys
+}
+
+#[test]
+fn get_many_should_return_results_in_keys_order() {
+    let server = create_server();
+    let keys = get_many_keys(&server, 4);
+    let results = server.storage.get_many(&keys);
+    assert_eq!(results.len(), keys.len());
+    for (id, (key, result)) in keys.iter().zip(results).enumerate() {
+        if id % 2 == 0 {
+            assert_eq!(result.unwrap().value, key);
+        } else {
+            assert_eq!(result.unwrap_err(), CacheError::NotFound);
+        }
+    }
+}
+
+#[test]
+fn set_many_should_assign_distinct_increasing_cas() {
+    let server = create_server();
+    let previous = server
+        .storage
+        .set(
+            Bytes::from("previous"),
+            Record::new(from_string("value"), 0, 0, 0),
+        )
+        .unwrap();
+    let items: Vec<(KeyType, Record)> = (0..1000)
+        .map(|id| {
+            let key = Bytes::from(format!("key_{}", id));
+            (key.clone(), Record::new(key, 0, 0, 0))
+        })
+        .collect();
+    let cas: Vec<u64> = server
+        .storage
+        .set_many(items)
+        .into_iter()
+        .map(|status| status.unwrap().cas)
+        .collect();
+    assert_eq!(cas.len(), 1000);
+    assert!(cas.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(cas[0] > previous.cas);
+    assert_eq!(
+        server.storage.get_cas(&Bytes::from("key_999")),
+        Ok(cas[999])
+    );
+
+    let next = server
+        .storage
+        .set(
+            Bytes::from("next"),
+            Record::new(from_string("value"), 0, 0, 0),
+        )
+        .unwrap();
+    assert!(next.cas > cas[999]);
+}
+
+fn transaction_items(count: usize) -> Vec<(KeyType, Record)> {
+    (0..count)
+        .map(|id| {
+            let key = Bytes::from(format!("key_{}", id));
+            (key.clone(), Record::new(key, 0, 0, 0))
+        })
+        .collect()
+}
+
+#[test]
+fn atomic_multi_set_should_set_all_records() {
+    let server = create_server();
+    let stored = server
+        .storage
+        .set(
+            Bytes::from("key_0"),
+            Record::new(from_string("old"), 0, 0, 0),
+        )
+        .unwrap();
+    let mut items = transaction_items(100);
+    items[0].1 = Record::new(from_string("new"), stored.cas, 0, 0);
+
+    let statuses = server.storage.atomic_multi_set(items).unwrap();
+    assert_eq!(statuses.len(), 100);
+    assert_eq!(statuses[0].cas, stored.cas + 1);
+    assert_eq!(server.storage.stats().curr_items, 100);
+    let record = server.storage.get(&Bytes::from("key_0")).unwrap();
+    assert_eq!(record.value, from_string("new"));
+    let record = server.storage.get(&Bytes::from("key_99")).unwrap();
+    assert_eq!(record.header.cas, statuses[99].cas);
+}
+
+#[test]
+fn atomic_multi_set_should_not_store_anything_if_an_item_fails() {
+    let server = create_server();
+    let stored = server
+        .storage
+        .set(
+            Bytes::from("key_5"),
+            Record::new(from_string("old"), 0, 0, 0),
+        )
+        .unwrap();
+    let memory_used = server.storage.memory_used();
+    let mut items = transaction_items(100);
+    items[5].1 = Record::new(from_string("new"), stored.cas + 10, 0, 0);
+
+    let err = server.storage.atomic_multi_set(items).unwrap_err();
+    assert_eq!(
+        err,
+        CacheError::TransactionFailed(vec![(5, CacheError::KeyExists)])
+    );
+    assert_eq!(server.storage.stats().curr_items, 1);
+    assert_eq!(server.storage.memory_used(), memory_used);
+    let record = server.storage.get(&Bytes::from("key_5")).unwrap();
+    assert_eq!(record.value, from_string("old"));
+
+    let mut items = transaction_items(10);
+    items[3].0 = Bytes::from("foo bar");
+    items[7].0 = Bytes::from("key_1");
+    let err = server.storage.atomic_multi_set(items).unwrap_err();
+    assert_eq!(
+        err,
+        CacheError::TransactionFailed(vec![
+            (3, CacheError::InvalidKey),
+            (7, CacheError::InvalidArguments)
+        ])
+    );
+    assert_eq!(server.storage.stats().curr_items, 1);
+}
+
+#[test]
+fn get_batch_hits_should_skip_misses() {
+    let server = create_server();
+    let keys = get_many_keys(&server, 20);
+    let hits = server.storage.get_batch_hits(&keys);
+    assert_eq!(hits.len(), 10);
+    for ((key, record), expected) in hits.iter().zip(keys.iter().step_by(2)) {
+        assert_eq!(key, expected);
+        assert_eq!(record.value, key);
+    }
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn get_many_parallel_should_return_the_same_results_as_get_many() {
+    let server = create_server();
+    for count in [
+        DEFAULT_PARALLEL_GET_THRESHOLD / 2,
+        DEFAULT_PARALLEL_GET_THRESHOLD * 4,
+    ] {
+        let keys = get_many_keys(&server, count);
+        assert_eq!(
+            server.storage.get_many_parallel(&keys),
+            server.storage.get_many(&keys)
+        );
+    }
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn get_many_parallel_should_use_configured_threshold() {
+    let server = create_server();
+    let keys = get_many_keys(&server, 4);
+    let expected = server.storage.get_many(&keys);
+    // every batch is looked up in parallel
+    let storage = MemcStore::new(server.storage.store.clone()).with_parallel_get_threshold(0);
+    assert_eq!(storage.get_many_parallel(&keys), expected);
+    assert_eq!(storage.get_many_parallel(&[]), Vec::new());
+}
+
+#[test]
+fn memory_used_should_track_stored_keys_and_records() {
+    let server = create_server();
+    assert_eq!(server.storage.memory_used(), 0);
+
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("value"), 0, 0, 0);
+    let record_size = (key.len() + record.size_estimate()) as u64;
+    server.storage.set(key.clone(), record).unwrap();
+    assert_eq!(server.storage.memory_used(), record_size);
+
+    let record = Record::new(from_string("longer value"), 0, 0, 0);
+    let record_size = (key.len() + record.size_estimate()) as u64;
+    server.storage.set(key.clone(), record).unwrap();
+    assert_eq!(server.storage.memory_used(), record_size);
+
+    server
+        .storage
+        .delete(key.clone(), Meta::new(0, 0, 0))
+        .unwrap();
+    assert_eq!(server.storage.memory_used(), 0);
+
+    let record = Record::new(from_string("value"), 0, 0, 0);
+    server.storage.set(key, record.clone()).unwrap();
+    server.storage.set(Bytes::from("other"), record).unwrap();
+    server.storage.flush(Meta::new(0, 0, 0));
+    assert_eq!(server.storage.memory_used(), 0);
+}
+
+#[test]
+fn flush_should_return_number_of_affected_records() {
+    let server = create_server();
+    const RECORDS: usize = 10;
+    for id in 0..RECORDS {
+        let record = Record::new(from_string("data"), 0, 0, 0);
+        server
+            .storage
+            .set(Bytes::from(format!("key{}", id)), record)
+            .unwrap();
+    }
+    // delayed flush counts records which will expire
+    assert_eq!(server.storage.flush(Meta::new(0, 0, 5)), RECORDS);
+    assert_eq!(server.storage.flush(Meta::new(0, 0, 0)), RECORDS);
+    assert_eq!(server.storage.flush(Meta::new(0, 0, 0)), 0);
+    assert_eq!(
+        server.storage.stats().cmd_flush_affected_items,
+        2 * RECORDS as u64
+    );
+}
+
+#[test]
+fn tagged_record_should_keep_its_tag() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new_with_tag(from_string("data"), 0, 3, 0, 42);
+    server.storage.set(key.clone(), record).unwrap();
+    let found = server.storage.get(&key).unwrap();
+    assert_eq!(found.header.tag, Some(42));
+    assert_eq!(found.header.flags, 3);
+
+    // appended value belongs to the same group
+    server
+        .storage
+        .append(key.clone(), Record::new(from_string("1"), 0, 0, 0))
+        .unwrap();
+    assert_eq!(server.storage.get(&key).unwrap().header.tag, Some(42));
+}
+
+#[test]
+fn flush_by_tag_should_remove_only_records_with_tag() {
+    let server = create_server();
+    for id in 0..30 {
+        let key = Bytes::from(format!("key{}", id));
+        let record = match id % 3 {
+            0 => Record::new(from_string("data"), 0, 0, 0),
+            tag => Record::new_with_tag(from_string("data"), 0, 0, 0, tag),
+        };
+        server.storage.set(key, record).unwrap();
+    }
+    let memory_used = server.storage.memory_used();
+
+    assert_eq!(server.storage.flush_by_tag(1), 10);
+    assert_eq!(server.storage.flush_by_tag(1), 0);
+    assert_eq!(server.storage.flush_by_tag(7), 0);
+    assert_eq!(server.storage.stats().curr_items, 20);
+    assert_eq!(server.storage.memory_used(), memory_used * 2 / 3);
+    for id in 0..30 {
+        let found = server.storage.get(&Bytes::from(format!("key{}", id)));
+        match id % 3 {
+            1 => assert_eq!(found.unwrap_err(), CacheError::NotFound),
+            tag => assert_eq!(found.unwrap().header.tag, (tag != 0).then_some(tag)),
+        }
+    }
+
+    // untagged records survive flushes of all tags
+    assert_eq!(server.storage.flush_by_tag(2), 10);
+    assert_eq!(server.storage.stats().curr_items, 10);
+}
+
+#[cfg(feature = "key-pattern")]
+#[test]
+fn get_keys_matching_should_return_only_matching_keys() {
+    let server = create_server();
+    for id in 0..1000 {
+        let key = Bytes::from(format!(
+            "user:{}:{}",
+            id,
+            if id % 10 == 0 { "name" } else { "age" }
+        ));
+        let record = Record::new(from_string("data"), 0, 0, 0);
+        server.storage.set(key, record).unwrap();
+    }
+    let keys = server
+        .storage
+        .get_keys_matching("user:*:name", 1000)
+        .unwrap();
+    assert_eq!(keys.len(), 100);
+    assert!(keys.iter().all(|key| key.ends_with(b":name")));
+
+    let keys = server.storage.get_keys_matching("*:name", 10).unwrap();
+    assert_eq!(keys.len(), 10);
+    assert!(server
+        .storage
+        .get_keys_matching("user\\*", 1000)
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        server
+            .storage
+            .get_keys_matching("user:12?:*", 1000)
+            .unwrap()
+            .len(),
+        10
+    );
+    assert_eq!(
+        server.storage.get_keys_matching("user:\\", 1000),
+        Err(CacheError::InvalidArguments)
+    );
+}
+
+#[test]
+fn count_expired_should_not_remove_expired_records() {
+    let server = create_server();
+    server.timer.set(10);
+    for (key, ttl) in [("live", 0), ("expired", 5), ("expired_read", 5)] {
+        let record = Record::new(from_string("data"), 0, 0, ttl);
+        server.storage.set(Bytes::from(key), record).unwrap();
+    }
+    server.timer.set(20);
+    let stats = server.storage.stats();
+    assert_eq!(stats.curr_items, 3);
+    assert_eq!(stats.expired_items, 2);
+    assert_eq!(stats.expired_unfetched, 0);
+
+    // read removes expired record, so it is not counted as unfetched
+    assert!(server.storage.get(&Bytes::from("expired_read")).is_err());
+    assert_eq!(server.storage.remove_expired_items(), 1);
+
+    let stats = server.storage.stats();
+    assert_eq!(stats.curr_items, 1);
+    assert_eq!(stats.expired_items, 0);
+    assert_eq!(stats.expired_unfetched, 1);
+}
+
+#[test]
+fn per_shard_stats_should_sum_up_to_store_totals() {
+    let server = create_server();
+    for id in 0..100 {
+        let record = Record::new(from_string("data"), 0, 0, 0);
+        server
+            .storage
+            .set(Bytes::from(format!("key_{}", id)), record)
+            .unwrap();
+    }
+    let shards = server.storage.per_shard_stats();
+    assert!(shards.len() > 1);
+    for (id, shard) in shards.iter().enumerate() {
+        assert_eq!(shard.shard_id, id);
+    }
+    let items: usize = shards.iter().map(|shard| shard.item_count).sum();
+    let bytes: u64 = shards.iter().map(|shard| shard.bytes_used).sum();
+    assert_eq!(items, 100);
+    let keys_and_values: u64 = (0..100)
+        .map(|id| (format!("key_{}", id).len() + "data".len()) as u64)
+        .sum();
+    assert_eq!(bytes, keys_and_values);
+    // memory accounting includes record headers
+    assert!(bytes < server.storage.memory_used());
+}
+
+#[test]
+fn stats_histogram_should_count_values_in_power_of_two_buckets() {
+    let server = create_server();
+    const RECORDS: u64 = 10_000;
+    // sizes from 1 to 1000, each used by 10 values
+    for id in 0..RECORDS {
+        let value = Bytes::from(vec![b'x'; (id % 1000 + 1) as usize]);
+        server
+            .storage
+            .set(
+                Bytes::from(format!("key_{}", id)),
+                Record::new(value, 0, 0, 0),
+            )
+            .unwrap();
+    }
+
+    let histogram = server.storage.stats_histogram(11);
+    let bounds: Vec<u64> = histogram.iter().map(|(bound, _count)| *bound).collect();
+    assert_eq!(bounds, vec![1, 2, 4, 8, 16, 32, 64, 128, 256, 512, 1024]);
+    let counts: Vec<u64> = histogram.iter().map(|(_bound, count)| *count).collect();
+    assert_eq!(
+        counts,
+        vec![10, 10, 20, 40, 80, 160, 320, 640, 1280, 2560, 4880]
+    );
+    assert_eq!(counts.iter().sum::<u64>(), RECORDS);
+
+    // values over a bound of a last bucket are counted in it
+    let histogram = server.storage.stats_histogram(4);
+    assert_eq!(histogram[3], (8, RECORDS - 40));
+    assert!(server.storage.stats_histogram(0).is_empty());
+
+    let percentiles = server.storage.value_size_percentiles();
+    assert_eq!(
+        percentiles,
+        ValueSizePercentiles {
+            p50_value_size: 500,
+            p95_value_size: 950,
+            p99_value_size: 990,
+        }
+    );
+    assert_eq!(
+        create_server().storage.value_size_percentiles(),
+        ValueSizePercentiles::default()
+    );
+}
+
+#[test]
+fn reset_to_snapshot_should_restore_records_and_cas() {
+    let server = create_server();
+    let base_cas = server
+        .storage
+        .set(
+            Bytes::from("base"),
+            Record::new(from_string("base"), 0, 0, 0),
+        )
+        .unwrap()
+        .cas;
+    let snapshot = server.storage.take_snapshot();
+    assert_eq!(snapshot.len(), 1);
+    let dump = server.storage.debug_dump();
+    let memory_used = server.storage.memory_used();
+
+    server
+        .storage
+        .set(
+            Bytes::from("base"),
+            Record::new(from_string("changed"), 0, 0, 0),
+        )
+        .unwrap();
+    server
+        .storage
+        .set(
+            Bytes::from("added"),
+            Record::new(from_string("added"), 0, 0, 0),
+        )
+        .unwrap();
+    server.storage.reset_to_snapshot(&snapshot);
+
+    assert_eq!(server.storage.debug_dump(), dump);
+    assert_eq!(server.storage.memory_used(), memory_used);
+    let cas = server
+        .storage
+        .set(Bytes::from("new"), Record::new(from_string("new"), 0, 0, 0))
+        .unwrap()
+        .cas;
+    assert_eq!(cas, base_cas + 1);
+}
+
+#[test]
+fn debug_dump_should_return_live_records_sorted_by_key() {
+    let server = create_server();
+    let cas_b = server
+        .storage
+        .set(Bytes::from("b"), Record::new(from_string("2"), 0, 2, 0))
+        .unwrap()
+        .cas;
+    let cas_a = server
+        .storage
+        .set(Bytes::from("a"), Record::new(from_string("1"), 0, 1, 100))
+        .unwrap()
+        .cas;
+    server
+        .storage
+        .set(Bytes::from("c"), Record::new(from_string("3"), 0, 3, 5))
+        .unwrap();
+    server.timer.set(5);
+
+    let expected = DebugDump::from([
+        (Bytes::from("a"), (from_string("1"), 1, 100, cas_a)),
+        (Bytes::from("b"), (from_string("2"), 2, 0, cas_b)),
+    ]);
+    assert_eq!(server.storage.debug_dump(), expected);
+}
+
 #[test]
 fn add_should_succeed_if_not_already_stored() {
     let server = create_server();
@@ -227,6 +949,239 @@ fn replace_should_succeed_if_stored() {
     }
 }
 
+#[test]
+fn conditional_set_if_not_exists_should_store_only_new_keys() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("test data"), 0, 0, 0);
+    let result =
+        server
+            .storage
+            .conditional_set(key.clone(), record.clone(), SetCondition::IfNotExists);
+    assert!(result.is_ok());
+    let result = server
+        .storage
+        .conditional_set(key, record, SetCondition::IfNotExists);
+    match result {
+        Ok(_) => unreachable!(),
+        Err(err) => assert_eq!(err, CacheError::KeyExists),
+    }
+}
+
+#[test]
+fn conditional_set_if_not_exists_should_overwrite_expired_record() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("test data"), 0, 0, 10);
+    let result = server.storage.set(key.clone(), record);
+    assert!(result.is_ok());
+    server.timer.set(10);
+
+    let record = Record::new(from_string("new data"), 0, 0, 0);
+    let result =
+        server
+            .storage
+            .conditional_set(key.clone(), record.clone(), SetCondition::IfNotExists);
+    assert!(result.is_ok());
+    assert_eq!(server.storage.get(&key).unwrap(), record);
+}
+
+#[test]
+fn conditional_set_if_exists_should_store_only_existing_keys() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("test data"), 0, 0, 0);
+    let result =
+        server
+            .storage
+            .conditional_set(key.clone(), record.clone(), SetCondition::IfExists);
+    match result {
+        Ok(_) => unreachable!(),
+        Err(err) => assert_eq!(err, CacheError::NotFound),
+    }
+
+    let result = server.storage.set(key.clone(), record.clone());
+    assert!(result.is_ok());
+    let result = server
+        .storage
+        .conditional_set(key, record, SetCondition::IfExists);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn conditional_set_if_cas_matches_should_compare_cas() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("test data"), 0, 0, 0);
+    let cas = server.storage.set(key.clone(), record.clone()).unwrap().cas;
+
+    let result = server.storage.conditional_set(
+        key.clone(),
+        record.clone(),
+        SetCondition::IfCasMatches(cas + 100),
+    );
+    match result {
+        Ok(_) => unreachable!(),
+        Err(err) => assert_eq!(err, CacheError::KeyExists),
+    }
+
+    let result = server
+        .storage
+        .conditional_set(key, record, SetCondition::IfCasMatches(cas));
+    assert_eq!(result, Ok(SetStatus { cas: cas + 1 }));
+}
+
+#[test]
+fn conditional_set_unconditional_should_ignore_record_cas() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("test data"), 0, 0, 0);
+    let cas = server.storage.set(key.clone(), record).unwrap().cas;
+
+    let record = Record::new(from_string("new data"), cas + 100, 0, 0);
+    let result = server
+        .storage
+        .conditional_set(key, record, SetCondition::Unconditional);
+    match result {
+        Ok(status) => assert_ne!(status.cas, cas + 101),
+        Err(_) => unreachable!(),
+    }
+}
+
+fn value_below(threshold: u64) -> impl Fn(&Record) -> bool + Send + Sync {
+    move |record: &Record| {
+        std::str::from_utf8(&record.value)
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .is_some_and(|value| value < threshold)
+    }
+}
+
+#[test]
+fn replace_if_should_replace_only_if_predicate_is_met() {
+    let storage = create_server().storage;
+    let key = Bytes::from("key");
+    let result = storage.replace_if(key.clone(), Record::new(from_string("5"), 0, 0, 0), |_| {
+        true
+    });
+    assert_eq!(result, Err(CacheError::NotFound));
+
+    storage
+        .set(key.clone(), Record::new(from_string("10"), 0, 0, 0))
+        .unwrap();
+    let result = storage.replace_if(
+        key.clone(),
+        Record::new(from_string("5"), 0, 0, 0),
+        value_below(10),
+    );
+    assert_eq!(result, Err(CacheError::KeyExists));
+    assert_eq!(storage.get(&key).unwrap().value, from_string("10"));
+
+    let result = storage.replace_if(
+        key.clone(),
+        Record::new(from_string("15"), 0, 0, 0),
+        value_below(11),
+    );
+    assert!(result.is_ok());
+    assert_eq!(storage.get(&key).unwrap().value, from_string("15"));
+}
+
+#[test]
+fn replace_if_should_respect_predicate_with_concurrent_writers() {
+    const WRITERS: u64 = 8;
+    const VALUES_PER_WRITER: u64 = 200;
+    let storage = create_storage();
+    let key = Bytes::from("key");
+    storage
+        .set(key.clone(), Record::new(from_string("0"), 0, 0, 0))
+        .unwrap();
+
+    // every writer tries to store increasing values, a value is stored only
+    // if it is bigger than the current one, so the final value is the maximum
+    let handles: Vec<_> = (0..WRITERS)
+        .map(|writer| {
+            let storage = storage.clone();
+            let key = key.clone();
+            std::thread::spawn(move || {
+                for step in 1..=VALUES_PER_WRITER {
+                    let candidate = step * WRITERS + writer;
+                    let record = Record::new(Bytes::from(candidate.to_string()), 0, 0, 0);
+                    let _ = storage.replace_if(key.clone(), record, value_below(candidate));
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let expected = VALUES_PER_WRITER * WRITERS + WRITERS - 1;
+    assert_eq!(
+        storage.get(&key).unwrap().value,
+        Bytes::from(expected.to_string())
+    );
+}
+
+#[test]
+fn compare_and_swap_should_return_replaced_record() {
+    let storage = create_server().storage;
+    let key = Bytes::from("key");
+    let old_record = Record::new(from_string("old data"), 0, 0, 0);
+    let status = storage.set(key.clone(), old_record.clone()).unwrap();
+
+    let new_record = Record::new(from_string("new data"), 0, 0, 0);
+    let (new_status, replaced) = storage
+        .compare_and_swap(key.clone(), status.cas, new_record.clone())
+        .unwrap();
+    assert_eq!(
+        new_status,
+        SetStatus {
+            cas: status.cas + 1
+        }
+    );
+    assert_eq!(replaced, Some(old_record));
+    assert_eq!(storage.get(&key), Ok(new_record));
+    assert_eq!(storage.get_cas(&key), Ok(new_status.cas));
+}
+
+#[test]
+fn compare_and_swap_should_fail_on_cas_mismatch() {
+    let storage = create_server().storage;
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("old data"), 0, 0, 0);
+    let status = storage.set(key.clone(), record.clone()).unwrap();
+
+    let result = storage.compare_and_swap(
+        key.clone(),
+        status.cas + 100,
+        Record::new(from_string("new data"), 0, 0, 0),
+    );
+    match result {
+        Ok(_) => unreachable!(),
+        Err(err) => assert_eq!(err, CacheError::KeyExists),
+    }
+    assert_eq!(storage.get(&key), Ok(record));
+    assert_eq!(storage.get_cas(&key), Ok(status.cas));
+}
+
+#[test]
+fn compare_and_swap_should_insert_missing_key() {
+    let storage = create_server().storage;
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("new data"), 0, 0, 0);
+    let (status, replaced) = storage
+        .compare_and_swap(key.clone(), 0xDEAD_BEEF, record.clone())
+        .unwrap();
+    assert_eq!(
+        status,
+        SetStatus {
+            cas: 0xDEAD_BEEF + 1
+        }
+    );
+    assert!(replaced.is_none());
+    assert_eq!(storage.get(&key), Ok(record));
+}
+
 #[test]
 fn append_should_fail_if_not_exist() {
     let server = create_server();
@@ -303,15 +1258,119 @@ fn prepend_should_add_at_the_begining() {
     }
 }
 
+#[test]
+fn append_should_keep_expiration_of_record() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("Foo"), 0, 0, 60);
+    server.storage.set(key.clone(), record).unwrap();
+
+    server.timer.set(30);
+    let append_data = Record::new(from_string("bar"), 0, 0, 0);
+    server.storage.append(key.clone(), append_data).unwrap();
+    server.timer.set(59);
+    assert_eq!(
+        server.storage.get(&key).unwrap().value,
+        from_string("Foobar")
+    );
+    server.timer.set(60);
+    assert_eq!(server.storage.get(&key).unwrap_err(), CacheError::NotFound);
+}
+
+#[test]
+fn concurrent_appends_should_not_be_lost() {
+    const THREADS: usize = 8;
+    const APPENDS_PER_THREAD: usize = 500;
+    let storage = create_storage();
+    let key = Bytes::from("key");
+    storage
+        .set(key.clone(), Record::new(from_string(""), 0, 0, 0))
+        .unwrap();
+
+    let handles: Vec<_> = (0..THREADS)
+        .map(|_thread| {
+            let storage = storage.clone();
+            let key = key.clone();
+            std::thread::spawn(move || {
+                for _step in 0..APPENDS_PER_THREAD {
+                    let record = Record::new(from_string("x"), 0, 0, 0);
+                    storage.append(key.clone(), record).unwrap();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(
+        storage.get(&key).unwrap().value.len(),
+        THREADS * APPENDS_PER_THREAD
+    );
+}
+
+#[test]
+fn append_and_prepend_should_fail_if_concatenated_value_is_too_large() {
+    let timer = Arc::new(MockSystemTimer::new());
+    let store = MemoryStore::new(timer).with_max_value_size(10);
+    let storage = MemcStore::new(Arc::new(store));
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("123456789"), 0, 0, 0);
+    let cas = storage.set(key.clone(), record).unwrap().cas;
+
+    let extra = Record::new(from_string("ab"), 0, 0, 0);
+    assert_eq!(
+        storage.append(key.clone(), extra.clone()),
+        Err(CacheError::ValueTooLarge)
+    );
+    assert_eq!(
+        storage.prepend(key.clone(), extra),
+        Err(CacheError::ValueTooLarge)
+    );
+    let stored = storage.get(&key).unwrap();
+    assert_eq!(stored.value, from_string("123456789"));
+    assert_eq!(stored.header.cas, cas);
+
+    // a value of exactly max_value_size bytes is stored
+    let extra = Record::new(from_string("a"), 0, 0, 0);
+    assert!(storage.append(key.clone(), extra).is_ok());
+    assert_eq!(storage.get(&key).unwrap().value, from_string("123456789a"));
+}
+
+#[test]
+fn append_and_prepend_should_fail_if_cas_doesnt_match() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("Foo"), 0, 0, 0);
+    let status = server.storage.set(key.clone(), record).unwrap();
+
+    let append_data = Record::new(from_string("bar"), status.cas + 1, 0, 0);
+    assert_eq!(
+        server.storage.append(key.clone(), append_data),
+        Err(CacheError::CasMismatch)
+    );
+    let prepend_data = Record::new(from_string("bar"), status.cas + 1, 0, 0);
+    assert_eq!(
+        server.storage.prepend(key.clone(), prepend_data),
+        Err(CacheError::CasMismatch)
+    );
+    assert_eq!(server.storage.get(&key).unwrap().value, from_string("Foo"));
+
+    // CAS 0 concatenates regardless of stored CAS
+    let append_data = Record::new(from_string("bar"), 0, 0, 0);
+    assert!(server.storage.append(key.clone(), append_data).is_ok());
+    assert_eq!(
+        server.storage.get(&key).unwrap().value,
+        from_string("Foobar")
+    );
+}
+
 #[test]
 fn increment_if_counter_doesnt_exists_it_should_created() {
     const COUNTER_INITIAL_VALUE: u64 = 5;
     let server = create_server();
     let key = Bytes::from("counter1");
-    let counter = IncrementParam {
-        delta: 0,
-        value: COUNTER_INITIAL_VALUE,
-    };
+    let counter = IncrementParam::from((0, COUNTER_INITIAL_VALUE));
     let header = Meta::new(0, 0, 0);
     let result = server.storage.increment(header, key, counter);
     match result {
@@ -328,7 +1387,7 @@ fn increment_if_counter_doesnt_exists_it_should_created() {
 fn increment_if_expire_equals_ffffffff_counter_should_not_be_created() {
     let server = create_server();
     let key = Bytes::from("counter1");
-    let counter = IncrementParam { delta: 0, value: 0 };
+    let counter = IncrementParam::from(0);
     let header = Meta::new(0, 0, 0xffffffff);
     let result = server.storage.increment(header, key, counter);
     match result {
@@ -352,10 +1411,7 @@ fn increment_value_should_be_incremented() {
     assert!(result.is_ok());
     let cas = result.unwrap().cas;
 
-    let counter = IncrementParam {
-        delta: DELTA,
-        value: 0,
-    };
+    let counter = IncrementParam::from(DELTA);
     let header = Meta::new(0, 0, 0);
     let result = server.storage.increment(header, key, counter);
     match result {
@@ -369,6 +1425,23 @@ fn increment_value_should_be_incremented() {
     }
 }
 
+#[test]
+fn increment_should_wrap_around_on_overflow() {
+    let server = create_server();
+    let key = Bytes::from("counter1");
+    let record = Record::new(Bytes::from(u64::MAX.to_string()), 0, 0, 0);
+    let result = server.storage.set(key.clone(), record);
+    assert!(result.is_ok());
+
+    let counter = IncrementParam::from(2);
+    let header = Meta::new(0, 0, 0);
+    let result = server.storage.increment(header, key, counter);
+    match result {
+        Ok(counter_value) => assert_eq!(counter_value.value, 1),
+        Err(_) => unreachable!(),
+    }
+}
+
 #[test]
 fn increment_if_value_is_not_number_it_should_be_error() {
     const DELTA: u64 = 5;
@@ -378,10 +1451,7 @@ fn increment_if_value_is_not_number_it_should_be_error() {
     let result = server.storage.set(key.clone(), record);
     assert!(result.is_ok());
 
-    let counter = IncrementParam {
-        delta: DELTA,
-        value: 0,
-    };
+    let counter = IncrementParam::from(DELTA);
     let header = Meta::new(0, 0, 0);
     let result = server.storage.increment(header, key, counter);
     match result {
@@ -403,10 +1473,7 @@ fn increment_if_value_cannot_be_parsed_it_should_be_error() {
     let result = server.storage.set(key.clone(), record);
     assert!(result.is_ok());
 
-    let counter = IncrementParam {
-        delta: DELTA,
-        value: 0,
-    };
+    let counter = IncrementParam::from(DELTA);
     let header = Meta::new(0, 0, 0);
     let result = server.storage.increment(header, key, counter);
     match result {
@@ -429,10 +1496,7 @@ fn decrement_should_not_result_in_negative_value() {
     assert!(result.is_ok());
     let cas = result.unwrap().cas;
 
-    let counter = IncrementParam {
-        delta: DELTA,
-        value: 0,
-    };
+    let counter = IncrementParam::from(DELTA);
     let header = Meta::new(0, 0, 0);
     let result = server.storage.decrement(header, key, counter);
     match result {
@@ -457,10 +1521,7 @@ fn decrement_value_should_be_decremented() {
     assert!(result.is_ok());
     let cas = result.unwrap().cas;
 
-    let counter = IncrementParam {
-        delta: DELTA,
-        value: 0,
-    };
+    let counter = IncrementParam::from(DELTA);
     let header = Meta::new(0, 0, 0);
     let result = server.storage.decrement(header, key, counter);
     match result {
@@ -473,3 +1534,264 @@ fn decrement_value_should_be_decremented() {
         }
     }
 }
+
+#[test]
+fn concurrent_increments_should_not_be_lost() {
+    const THREADS: u64 = 8;
+    const INCREMENTS_PER_THREAD: u64 = 5000;
+    let storage = create_storage();
+    let key = Bytes::from("counter");
+    storage
+        .set(key.clone(), Record::new(from_string("0"), 0, 0, 0))
+        .unwrap();
+
+    let handles: Vec<_> = (0..THREADS)
+        .map(|_thread| {
+            let storage = storage.clone();
+            let key = key.clone();
+            std::thread::spawn(move || {
+                for _step in 0..INCREMENTS_PER_THREAD {
+                    storage
+                        .increment(Meta::new(0, 0, 0), key.clone(), IncrementParam::from(1))
+                        .unwrap();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let expected = THREADS * INCREMENTS_PER_THREAD;
+    assert_eq!(
+        storage.get(&key).unwrap().value,
+        Bytes::from(expected.to_string())
+    );
+}
+
+#[test]
+fn increment_should_fail_if_request_cas_doesnt_match() {
+    let server = create_server();
+    let key = Bytes::from("counter1");
+    let cas = server
+        .storage
+        .set(key.clone(), Record::new(from_string("5"), 0, 0, 0))
+        .unwrap()
+        .cas;
+
+    let err = server
+        .storage
+        .increment(
+            Meta::new(cas + 10, 0, 0),
+            key.clone(),
+            IncrementParam::from(1),
+        )
+        .unwrap_err();
+    assert_eq!(err, CacheError::CasMismatch);
+    assert_eq!(server.storage.get(&key).unwrap().value, from_string("5"));
+
+    let result =
+        server
+            .storage
+            .increment(Meta::new(cas, 0, 0), key.clone(), IncrementParam::from(1));
+    assert_eq!(result.unwrap().value, 6);
+}
+
+#[test]
+fn incr_many_and_decr_many_should_return_results_in_order_of_ops() {
+    let server = create_server();
+    for id in 0..50 {
+        let record = Record::new(from_string("10"), 0, 0, 0);
+        server
+            .storage
+            .set(Bytes::from(format!("counter{}", id)), record)
+            .unwrap();
+    }
+    server
+        .storage
+        .set(
+            Bytes::from("text"),
+            Record::new(from_string("abc"), 0, 0, 0),
+        )
+        .unwrap();
+    let op = |key: &str, delta: u64| {
+        (
+            Bytes::from(key.to_string()),
+            Meta::new(0, 0, 0xffffffff),
+            IncrementParam::from(delta),
+        )
+    };
+
+    let mut ops: Vec<_> = (0..50)
+        .map(|id| op(&format!("counter{}", id), id))
+        .collect();
+    ops.push(op("counter0", 5));
+    ops.push(op("missing", 1));
+    ops.push(op("text", 1));
+    let results = server.storage.incr_many(ops);
+    assert_eq!(results.len(), 53);
+    for (id, result) in results.iter().take(50).enumerate() {
+        assert_eq!(result.as_ref().unwrap().value, 10 + id as u64);
+    }
+    // operations of the same key are applied in order
+    assert_eq!(results[50].as_ref().unwrap().value, 15);
+    assert_eq!(results[51].as_ref().unwrap_err(), &CacheError::NotFound);
+    assert_eq!(
+        results[52].as_ref().unwrap_err(),
+        &CacheError::ArithOnNonNumeric
+    );
+
+    let results = server
+        .storage
+        .decr_many(vec![op("counter1", 1), op("counter2", 100)]);
+    let values: Vec<u64> = results
+        .into_iter()
+        .map(|result| result.unwrap().value)
+        .collect();
+    assert_eq!(values, vec![10, 0]);
+}
+
+#[test]
+fn set_should_reject_keys_invalid_for_key_policy() {
+    let server = create_server();
+    let record = Record::new(from_string("data"), 0, 0, 0);
+    for key in ["foo bar", "foo\r\n", ""] {
+        let result = server.storage.set(Bytes::from(key), record.clone());
+        assert_eq!(result, Err(CacheError::InvalidKey));
+    }
+    let key = Bytes::from("a".repeat(251));
+    let result = server.storage.set(key, record.clone());
+    assert_eq!(result, Err(CacheError::InvalidKey));
+    assert_eq!(server.storage.stats().curr_items, 0);
+
+    let key = Bytes::from("a".repeat(250));
+    assert!(server.storage.set(key.clone(), record.clone()).is_ok());
+    assert!(server.storage.get(&key).is_ok());
+}
+
+#[test]
+fn reads_and_deletes_should_reject_keys_invalid_for_key_policy() {
+    let server = create_server();
+    let storage = &server.storage;
+    let key = Bytes::from("foo bar");
+    let header = || Meta::new(0, 0, 0xffffffff);
+    assert_eq!(storage.get(&key), Err(CacheError::InvalidKey));
+    assert_eq!(storage.get_cas(&key), Err(CacheError::InvalidKey));
+    assert_eq!(storage.touch(&key, 60), Err(CacheError::InvalidKey));
+    assert_eq!(
+        storage.delete(key.clone(), Meta::new(0, 0, 0)).unwrap_err(),
+        CacheError::InvalidKey
+    );
+    assert_eq!(
+        storage
+            .increment(header(), key.clone(), IncrementParam::from(1))
+            .unwrap_err(),
+        CacheError::InvalidKey
+    );
+    assert_eq!(
+        storage
+            .decrement(
+                header(),
+                Bytes::from("a".repeat(251)),
+                DecrementParam::from(1)
+            )
+            .unwrap_err(),
+        CacheError::InvalidKey
+    );
+    let record = Record::new(from_string("data"), 0, 0, 0);
+    for result in [
+        storage.add(key.clone(), record.clone()),
+        storage.replace(key.clone(), record.clone()),
+        storage.append(key.clone(), record.clone()),
+        storage.prepend(key.clone(), record.clone()),
+        storage.replace_if(key, record, |_record| true),
+    ] {
+        assert_eq!(result, Err(CacheError::InvalidKey));
+    }
+}
+
+#[test]
+fn stats_should_report_uptime() {
+    let server = create_server();
+    server.timer.set(60);
+    let stats = server.storage.stats();
+    assert_eq!(stats.start_time, 0);
+    assert_eq!(stats.time, 60);
+    assert_eq!(stats.uptime_secs(stats.time), 60);
+}
+
+#[test]
+fn counters_should_count_hits_and_misses_of_commands() {
+    let server = create_server();
+    let storage = &server.storage;
+    let key = Bytes::from("counter");
+    let record = Record::new(from_string("10"), 0, 0, 0);
+    let cas = storage.set(key.clone(), record.clone()).unwrap().cas;
+    assert!(storage.get(&key).is_ok());
+    assert!(storage.get(&Bytes::from("missing")).is_err());
+    assert!(storage
+        .increment(
+            Meta::new(0, 0, 0xffffffff),
+            key.clone(),
+            IncrementParam::from(1)
+        )
+        .is_ok());
+    assert!(storage
+        .decrement(
+            Meta::new(0, 0, 0xffffffff),
+            Bytes::from("missing"),
+            DecrementParam::from(1)
+        )
+        .is_err());
+    assert_eq!(
+        storage.cas(key.clone(), record.clone(), cas),
+        Err(CacheError::KeyExists)
+    );
+    assert!(storage.cas(Bytes::from("missing"), record, cas).is_err());
+    assert!(storage.delete(key.clone(), Meta::new(0, 0, 0)).is_ok());
+    assert!(storage.delete(key, Meta::new(0, 0, 0)).is_err());
+    server.timer.set(5);
+
+    let counters = storage.counters();
+    let value =
+        |counter: &std::sync::atomic::AtomicU64| counter.load(std::sync::atomic::Ordering::Relaxed);
+    assert_eq!(value(&counters.cmd_get), 2);
+    assert_eq!(value(&counters.get_hits), 1);
+    assert_eq!(value(&counters.get_misses), 1);
+    // incr doesn't count as a get or a set
+    assert_eq!(value(&counters.cmd_set), 3);
+    assert_eq!(value(&counters.total_items), 1);
+    assert_eq!(value(&counters.incr_hits), 1);
+    assert_eq!(value(&counters.decr_misses), 1);
+    assert_eq!(value(&counters.cas_badval), 1);
+    assert_eq!(value(&counters.cas_misses), 1);
+    assert_eq!(value(&counters.cas_hits), 0);
+    assert_eq!(value(&counters.delete_hits), 1);
+    assert_eq!(value(&counters.delete_misses), 1);
+    assert_eq!(value(&counters.curr_items), 0);
+    assert_eq!(value(&counters.uptime), 5);
+}
+
+#[test]
+fn values_over_max_value_size_should_be_rejected() {
+    let timer = Arc::new(crate::mock::mock_server::MockSystemTimer::new());
+    let store = crate::memory_store::store::MemoryStore::new(timer).with_max_value_size(64);
+    let storage = MemcStore::new(Arc::new(store));
+    let key = Bytes::from("foo");
+    let too_large = Record::new(Bytes::from(vec![b'a'; 65]), 0, 0, 0);
+    assert_eq!(
+        storage.set(key.clone(), too_large).unwrap_err(),
+        CacheError::ValueTooLarge
+    );
+    assert_eq!(storage.get(&key).unwrap_err(), CacheError::NotFound);
+
+    let largest = Record::new(Bytes::from(vec![b'a'; 64]), 0, 0, 0);
+    storage.set(key.clone(), largest).unwrap();
+
+    // a combined value is checked, a stored one is kept
+    let status = storage.append(key.clone(), Record::new(from_string("b"), 0, 0, 0));
+    assert_eq!(status.unwrap_err(), CacheError::ValueTooLarge);
+    let status = storage.prepend(key.clone(), Record::new(from_string("b"), 0, 0, 0));
+    assert_eq!(status.unwrap_err(), CacheError::ValueTooLarge);
+    assert_eq!(storage.get(&key).unwrap().value.len(), 64);
+}
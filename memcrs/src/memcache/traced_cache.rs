@@ -0,0 +1,462 @@
+#[cfg(any(test, feature = "debug"))]
+use crate::cache::cache::DebugDump;
+use crate::cache::cache::{
+    impl_details::CacheImplDetails, Cache, CacheMetaData, CachePredicate, CacheReadOnlyView,
+    KeyType, Record, RecordPredicate, RemoveIfResult, SetCondition, SetStatus, ShardStats,
+    StorageSnapshot, ValueType,
+};
+use crate::cache::error::{CacheError, Result};
+use opentelemetry::global::{self, BoxedSpan, BoxedTracer};
+use opentelemetry::trace::{Span, SpanKind, Status, Tracer};
+use opentelemetry::KeyValue;
+use std::sync::Arc;
+
+/// Name of a tracer spans are created by
+pub const TRACER_NAME: &str = "memcrs";
+
+// longer keys are truncated, so spans don't carry whole keys
+const MAX_KEY_LEN: usize = 64;
+
+const OPERATION: &str = "cache.operation";
+const KEY: &str = "cache.key";
+const KEY_COUNT: &str = "cache.key_count";
+const HIT: &str = "cache.hit";
+const VALUE_SIZE: &str = "cache.value_size";
+
+/// Creates a span for every read and write of records, stats and
+/// introspection calls are passed to a store as they are. Spans are
+/// children of a current context, so they are nested in a span of
+/// a caller if it has one.
+pub struct TracedCache {
+    store: Arc<dyn Cache + Send + Sync>,
+    tracer: BoxedTracer,
+}
+
+fn truncated_key(key: &[u8]) -> String {
+    String::from_utf8_lossy(&key[..key.len().min(MAX_KEY_LEN)]).into_owned()
+}
+
+fn value_size(records: &[(KeyType, Record)]) -> usize {
+    records
+        .iter()
+        .map(|(_key, record)| record.value.len())
+        .sum()
+}
+
+// a missing key is a miss, not an error of a store
+fn finish<T>(mut span: BoxedSpan, result: &Result<T>, value_size: Option<usize>) {
+    span.set_attribute(KeyValue::new(HIT, result.is_ok()));
+    if let Some(value_size) = value_size {
+        span.set_attribute(KeyValue::new(VALUE_SIZE, value_size as i64));
+    }
+    match result {
+        Ok(_) | Err(CacheError::NotFound) => {}
+        Err(err) => span.set_status(Status::error(err.to_static_string())),
+    }
+    span.end();
+}
+
+impl TracedCache {
+    /// Spans are created by a tracer of a global tracer provider,
+    /// which has to be installed beforehand, otherwise they are dropped
+    pub fn new(store: Arc<dyn Cache + Send + Sync>) -> TracedCache {
+        TracedCache::with_tracer(store, global::tracer(TRACER_NAME))
+    }
+
+    pub fn with_tracer(store: Arc<dyn Cache + Send + Sync>, tracer: BoxedTracer) -> TracedCache {
+        TracedCache { store, tracer }
+    }
+
+    fn start(&self, operation: &'static str, key: Option<&[u8]>) -> BoxedSpan {
+        let mut attributes = vec![KeyValue::new(OPERATION, operation)];
+        if let Some(key) = key {
+            attributes.push(KeyValue::new(KEY, truncated_key(key)));
+        }
+        self.tracer
+            .span_builder(operation)
+            .with_kind(SpanKind::Internal)
+            .with_attributes(attributes)
+            .start(&self.tracer)
+    }
+
+    fn start_many(&self, operation: &'static str, count: usize) -> BoxedSpan {
+        let mut span = self.start(operation, None);
+        span.set_attribute(KeyValue::new(KEY_COUNT, count as i64));
+        span
+    }
+
+    fn traced_get(
+        &self,
+        operation: &'static str,
+        key: &KeyType,
+        get: impl FnOnce() -> Result<Record>,
+    ) -> Result<Record> {
+        let span = self.start(operation, Some(key));
+        let result = get();
+        finish(
+            span,
+            &result,
+            result.as_ref().ok().map(|record| record.value.len()),
+        );
+        result
+    }
+
+    fn traced_set<T>(
+        &self,
+        operation: &'static str,
+        key: &KeyType,
+        value_size: usize,
+        set: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        let span = self.start(operation, Some(key));
+        let result = set();
+        finish(span, &result, Some(value_size));
+        result
+    }
+}
+
+impl CacheImplDetails for TracedCache {
+    //
+    fn get_by_key(&self, key: &KeyType) -> Result<Record> {
+        self.store.get_by_key(key)
+    }
+
+    //
+    fn check_if_expired(&self, key: &KeyType, record: &Record) -> bool {
+        self.store.check_if_expired(key, record)
+    }
+}
+
+impl Cache for TracedCache {
+    fn get(&self, key: &KeyType) -> Result<Record> {
+        self.traced_get("get", key, || self.store.get(key))
+    }
+
+    fn get_many(&self, keys: &[KeyType]) -> Vec<Result<Record>> {
+        let mut span = self.start_many("get_many", keys.len());
+        let results = self.store.get_many(keys);
+        let found = results.iter().filter(|result| result.is_ok()).count();
+        span.set_attribute(KeyValue::new(HIT, found == results.len()));
+        span.end();
+        results
+    }
+
+    fn try_get(&self, key: &KeyType) -> Option<Result<Record>> {
+        let span = self.start("try_get", Some(key));
+        let result = self.store.try_get(key);
+        // a locked shard is neither a hit nor a miss
+        match &result {
+            Some(result) => finish(
+                span,
+                result,
+                result.as_ref().ok().map(|record| record.value.len()),
+            ),
+            None => drop(span),
+        }
+        result
+    }
+
+    fn get_with_metadata(&self, key: &KeyType) -> Result<(Record, u64)> {
+        let span = self.start("get_with_metadata", Some(key));
+        let result = self.store.get_with_metadata(key);
+        finish(
+            span,
+            &result,
+            result.as_ref().ok().map(|(record, _)| record.value.len()),
+        );
+        result
+    }
+
+    fn get_cas(&self, key: &KeyType) -> Result<u64> {
+        let span = self.start("get_cas", Some(key));
+        let result = self.store.get_cas(key);
+        finish(span, &result, None);
+        result
+    }
+
+    fn get_flags(&self, key: &KeyType) -> Result<u32> {
+        let span = self.start("get_flags", Some(key));
+        let result = self.store.get_flags(key);
+        finish(span, &result, None);
+        result
+    }
+
+    fn get_value_bytes(&self, key: &KeyType) -> Result<ValueType> {
+        let span = self.start("get_value_bytes", Some(key));
+        let result = self.store.get_value_bytes(key);
+        finish(span, &result, result.as_ref().ok().map(|value| value.len()));
+        result
+    }
+
+    fn touch(&self, key: &KeyType, expiration: u32) -> Result<()> {
+        let span = self.start("touch", Some(key));
+        let result = self.store.touch(key, expiration);
+        finish(span, &result, None);
+        result
+    }
+
+    fn get_and_touch(&self, key: &KeyType, expiration: u32) -> Result<Record> {
+        self.traced_get("get_and_touch", key, || {
+            self.store.get_and_touch(key, expiration)
+        })
+    }
+
+    fn set(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        let size = record.value.len();
+        self.traced_set("set", &key.clone(), size, || self.store.set(key, record))
+    }
+
+    fn set_many(&self, items: Vec<(KeyType, Record)>) -> Vec<Result<SetStatus>> {
+        let mut span = self.start_many("set_many", items.len());
+        span.set_attribute(KeyValue::new(VALUE_SIZE, value_size(&items) as i64));
+        let results = self.store.set_many(items);
+        let stored = results.iter().filter(|result| result.is_ok()).count();
+        span.set_attribute(KeyValue::new(HIT, stored == results.len()));
+        span.end();
+        results
+    }
+
+    fn atomic_multi_set(&self, items: Vec<(KeyType, Record)>) -> Result<Vec<SetStatus>> {
+        let mut span = self.start_many("atomic_multi_set", items.len());
+        span.set_attribute(KeyValue::new(VALUE_SIZE, value_size(&items) as i64));
+        let result = self.store.atomic_multi_set(items);
+        span.set_attribute(KeyValue::new(HIT, result.is_ok()));
+        span.end();
+        result
+    }
+
+    fn conditional_set(
+        &self,
+        key: KeyType,
+        record: Record,
+        condition: SetCondition,
+    ) -> Result<SetStatus> {
+        let size = record.value.len();
+        self.traced_set("conditional_set", &key.clone(), size, || {
+            self.store.conditional_set(key, record, condition)
+        })
+    }
+
+    fn replace_if(
+        &self,
+        key: KeyType,
+        new_record: Record,
+        predicate: &RecordPredicate<'_>,
+    ) -> Result<SetStatus> {
+        let size = new_record.value.len();
+        self.traced_set("replace_if", &key.clone(), size, || {
+            self.store.replace_if(key, new_record, predicate)
+        })
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: KeyType,
+        expected_cas: u64,
+        new_record: Record,
+    ) -> Result<(SetStatus, Option<Record>)> {
+        let size = new_record.value.len();
+        self.traced_set("compare_and_swap", &key.clone(), size, || {
+            self.store.compare_and_swap(key, expected_cas, new_record)
+        })
+    }
+
+    fn delete(&self, key: KeyType, header: CacheMetaData) -> Result<Record> {
+        let span = self.start("delete", Some(&key));
+        let result = self.store.delete(key, header);
+        finish(span, &result, None);
+        result
+    }
+
+    // Removes key value and returns as an option
+    fn remove(&self, key: &KeyType) -> Option<(KeyType, Record)> {
+        let mut span = self.start("remove", Some(key));
+        let result = self.store.remove(key);
+        span.set_attribute(KeyValue::new(HIT, result.is_some()));
+        span.end();
+        result
+    }
+
+    fn flush(&self, header: CacheMetaData) -> usize {
+        let mut span = self.start("flush", None);
+        let flushed = self.store.flush(header);
+        span.set_attribute(KeyValue::new(KEY_COUNT, flushed as i64));
+        span.end();
+        flushed
+    }
+
+    fn flush_by_tag(&self, tag: u64) -> Vec<(KeyType, Record)> {
+        let mut span = self.start("flush_by_tag", None);
+        let flushed = self.store.flush_by_tag(tag);
+        span.set_attribute(KeyValue::new(KEY_COUNT, flushed.len() as i64));
+        span.end();
+        flushed
+    }
+
+    fn as_read_only(&self) -> Box<dyn CacheReadOnlyView<'_>> {
+        self.store.as_read_only()
+    }
+
+    fn remove_if(&self, f: &mut CachePredicate) -> RemoveIfResult {
+        self.store.remove_if(f)
+    }
+
+    fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+
+    fn memory_used(&self) -> u64 {
+        self.store.memory_used()
+    }
+
+    fn max_value_size(&self) -> usize {
+        self.store.max_value_size()
+    }
+
+    fn set_memory_limit(&self, memory_limit: u64) -> Result<()> {
+        self.store.set_memory_limit(memory_limit)
+    }
+
+    fn oldest_item_age_secs(&self) -> Option<u64> {
+        self.store.oldest_item_age_secs()
+    }
+
+    fn newest_item_age_secs(&self) -> Option<u64> {
+        self.store.newest_item_age_secs()
+    }
+
+    fn count_expired(&self) -> usize {
+        self.store.count_expired()
+    }
+
+    fn remove_expired_items(&self) -> RemoveIfResult {
+        self.store.remove_expired_items()
+    }
+
+    fn expired_unfetched(&self) -> u64 {
+        self.store.expired_unfetched()
+    }
+
+    fn flush_affected_items(&self) -> u64 {
+        self.store.flush_affected_items()
+    }
+
+    fn evictions(&self) -> u64 {
+        self.store.evictions()
+    }
+
+    fn start_time(&self) -> u64 {
+        self.store.start_time()
+    }
+
+    fn current_time(&self) -> u64 {
+        self.store.current_time()
+    }
+
+    fn current_instant(&self) -> std::time::Instant {
+        self.store.current_instant()
+    }
+
+    fn current_millis(&self) -> u64 {
+        self.store.current_millis()
+    }
+
+    fn per_shard_stats(&self) -> Vec<ShardStats> {
+        self.store.per_shard_stats()
+    }
+
+    fn shard_for_key(&self, key: &[u8]) -> usize {
+        self.store.shard_for_key(key)
+    }
+
+    fn get_random_sample(&self, n: usize) -> Vec<(KeyType, u64)> {
+        self.store.get_random_sample(n)
+    }
+
+    fn value_sizes(&self) -> Vec<u64> {
+        self.store.value_sizes()
+    }
+
+    fn keys_by_expiry(&self) -> Vec<(u64, KeyType)> {
+        self.store.keys_by_expiry()
+    }
+
+    fn prefix_scan(&self, prefix: &[u8]) -> Vec<KeyType> {
+        let mut span = self.start("prefix_scan", Some(prefix));
+        let keys = self.store.prefix_scan(prefix);
+        span.set_attribute(KeyValue::new(KEY_COUNT, keys.len() as i64));
+        span.end();
+        keys
+    }
+
+    fn take_snapshot(&self) -> StorageSnapshot {
+        self.store.take_snapshot()
+    }
+
+    fn reset_to_snapshot(&self, snapshot: &StorageSnapshot) {
+        self.store.reset_to_snapshot(snapshot)
+    }
+
+    #[cfg(any(test, feature = "debug"))]
+    fn debug_dump(&self) -> DebugDump {
+        self.store.debug_dump()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_store::store::MemoryStore;
+    use crate::mock::mock_server::MockSystemTimer;
+    use bytes::Bytes;
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry::Value;
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+
+    fn attribute(span: &SpanData, name: &str) -> Option<Value> {
+        span.attributes
+            .iter()
+            .find(|attribute| attribute.key.as_str() == name)
+            .map(|attribute| attribute.value.clone())
+    }
+
+    #[test]
+    fn operations_should_be_traced_with_key_hit_and_value_size() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let store = MemoryStore::new_shared(Arc::new(MockSystemTimer::new()));
+        let cache = TracedCache::with_tracer(
+            store,
+            BoxedTracer::new(Box::new(provider.tracer(TRACER_NAME))),
+        );
+
+        let long_key = Bytes::from("k".repeat(100));
+        cache
+            .set(long_key.clone(), Record::new(Bytes::from("value"), 0, 0, 0))
+            .unwrap();
+        cache.get(&long_key).unwrap();
+        cache.get(&Bytes::from("missing")).unwrap_err();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        assert_eq!(spans.len(), 3);
+        assert_eq!(spans[0].name, "set");
+        assert_eq!(attribute(&spans[0], OPERATION), Some(Value::from("set")));
+        assert_eq!(
+            attribute(&spans[0], KEY),
+            Some(Value::from("k".repeat(MAX_KEY_LEN)))
+        );
+        assert_eq!(attribute(&spans[0], VALUE_SIZE), Some(Value::I64(5)));
+        assert_eq!(spans[1].name, "get");
+        assert_eq!(attribute(&spans[1], HIT), Some(Value::Bool(true)));
+        assert_eq!(attribute(&spans[1], VALUE_SIZE), Some(Value::I64(5)));
+        assert_eq!(attribute(&spans[2], HIT), Some(Value::Bool(false)));
+        assert_eq!(attribute(&spans[2], VALUE_SIZE), None);
+        assert_eq!(spans[2].status, Status::Unset);
+    }
+}
@@ -0,0 +1,444 @@
+use axum::extract::{Path, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bytes::Bytes;
+use serde_derive::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io;
+use tokio::net::TcpListener;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
+
+use crate::cache::error::CacheError;
+use crate::memcache::store::{MemcStore, Meta, StorageStatsSnapshot};
+
+/// Data shared by admin API endpoints
+pub struct AdminState {
+    store: Arc<MemcStore>,
+    // if set, requests have to carry it as a bearer token
+    token: Option<String>,
+}
+
+impl AdminState {
+    pub fn new(store: Arc<MemcStore>) -> AdminState {
+        AdminState { store, token: None }
+    }
+
+    /// Requires `Authorization: Bearer <token>` header on every request
+    pub fn with_token(mut self, token: Option<String>) -> AdminState {
+        self.token = token;
+        self
+    }
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+struct ErrorResponse {
+    error: &'static str,
+}
+
+type ApiError = (StatusCode, Json<ErrorResponse>);
+
+fn api_error(err: CacheError) -> ApiError {
+    let status = match err {
+        CacheError::NotFound => StatusCode::NOT_FOUND,
+        CacheError::NotSupported => StatusCode::CONFLICT,
+        CacheError::InvalidArguments | CacheError::InvalidKey => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (
+        status,
+        Json(ErrorResponse {
+            error: err.to_static_string(),
+        }),
+    )
+}
+
+// compares all bytes, so time of a comparison doesn't tell
+// how long a matching prefix of a guessed token is
+fn tokens_equal(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+async fn authorize(State(state): State<Arc<AdminState>>, request: Request, next: Next) -> Response {
+    if let Some(token) = &state.token {
+        let authorized = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|given| tokens_equal(token, given));
+        if !authorized {
+            let response = ErrorResponse {
+                error: "Unauthorized",
+            };
+            return (StatusCode::UNAUTHORIZED, Json(response)).into_response();
+        }
+    }
+    next.run(request).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/stats",
+    responses((status = 200, description = "Statistics of a store", body = StorageStatsSnapshot))
+)]
+async fn stats(State(state): State<Arc<AdminState>>) -> Json<StorageStatsSnapshot> {
+    Json(state.store.stats())
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+struct FlushRequest {
+    #[serde(default)]
+    delay_secs: u32,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+struct FlushResponse {
+    affected_items: usize,
+}
+
+/// Removes all records, or sets them to expire after delay_secs
+#[utoipa::path(
+    post,
+    path = "/api/v1/flush",
+    request_body = FlushRequest,
+    responses((status = 200, description = "Number of affected records", body = FlushResponse))
+)]
+async fn flush(
+    State(state): State<Arc<AdminState>>,
+    Json(request): Json<FlushRequest>,
+) -> Json<FlushResponse> {
+    let affected_items = state.store.flush(Meta::new(0, 0, request.delay_secs));
+    Json(FlushResponse { affected_items })
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+struct KeyResponse {
+    key: String,
+    /// base64 encoded value
+    value: String,
+    flags: u32,
+    cas: u64,
+    expiration: u32,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/key/{key}",
+    params(("key" = String, Path, description = "Key of a record")),
+    responses(
+        (status = 200, description = "Stored record", body = KeyResponse),
+        (status = 400, description = "Invalid key", body = ErrorResponse),
+        (status = 404, description = "Key not found", body = ErrorResponse)
+    )
+)]
+async fn get_key(
+    State(state): State<Arc<AdminState>>,
+    Path(key): Path<String>,
+) -> Result<Json<KeyResponse>, ApiError> {
+    let record = state
+        .store
+        .get(&Bytes::from(key.clone()))
+        .map_err(api_error)?;
+    Ok(Json(KeyResponse {
+        key,
+        value: BASE64.encode(&record.value),
+        flags: record.header.flags,
+        cas: record.header.cas,
+        expiration: record.header.get_expiration(),
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/key/{key}",
+    params(("key" = String, Path, description = "Key of a record")),
+    responses(
+        (status = 204, description = "Record was removed"),
+        (status = 400, description = "Invalid key", body = ErrorResponse),
+        (status = 404, description = "Key not found", body = ErrorResponse)
+    )
+)]
+async fn delete_key(
+    State(state): State<Arc<AdminState>>,
+    Path(key): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    state
+        .store
+        .delete(Bytes::from(key), Meta::new(0, 0, 0))
+        .map_err(api_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+struct MemoryLimitRequest {
+    bytes: u64,
+}
+
+/// Changes memory limit of a store, 409 is returned
+/// if a store cannot change it
+#[utoipa::path(
+    put,
+    path = "/api/v1/config/memory-limit",
+    request_body = MemoryLimitRequest,
+    responses(
+        (status = 204, description = "Memory limit was changed"),
+        (status = 409, description = "Store cannot change memory limit", body = ErrorResponse)
+    )
+)]
+async fn set_memory_limit(
+    State(state): State<Arc<AdminState>>,
+    Json(request): Json<MemoryLimitRequest>,
+) -> Result<StatusCode, ApiError> {
+    state
+        .store
+        .set_memory_limit(request.bytes)
+        .map_err(api_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// OpenAPI description of admin API, served on /api/v1/openapi.json
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "memcrs admin API"),
+    paths(stats, flush, get_key, delete_key, set_memory_limit),
+    modifiers(&BearerToken),
+    security(("bearer_token" = []))
+)]
+pub struct ApiDoc;
+
+// --admin-token is sent as a bearer token
+struct BearerToken;
+
+impl Modify for BearerToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "bearer_token",
+                SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+            );
+        }
+    }
+}
+
+async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+pub fn router(state: Arc<AdminState>) -> Router {
+    Router::new()
+        .route("/api/v1/stats", get(stats))
+        .route("/api/v1/flush", post(flush))
+        .route("/api/v1/key/:key", get(get_key).delete(delete_key))
+        .route("/api/v1/config/memory-limit", put(set_memory_limit))
+        .route_layer(middleware::from_fn_with_state(state.clone(), authorize))
+        // added after the authorization layer, so a description is public
+        .route("/api/v1/openapi.json", get(openapi))
+        .with_state(state)
+}
+
+/// Serves admin API over HTTP on a given address
+pub async fn serve(addr: SocketAddr, state: Arc<AdminState>) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    axum::serve(listener, router(state)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::cache::Cache;
+    use crate::memcache::random_policy::RandomPolicy;
+    use crate::memcache::store::Record;
+    use crate::memory_store::store::MemoryStore;
+    use crate::mock::mock_server::{create_storage, MockSystemTimer};
+    use axum::body::{to_bytes, Body};
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "secret";
+
+    fn create_state(store: Arc<MemcStore>) -> Arc<AdminState> {
+        Arc::new(AdminState::new(store).with_token(Some(TOKEN.to_string())))
+    }
+
+    async fn call(
+        state: Arc<AdminState>,
+        method: &str,
+        uri: &str,
+        body: Option<serde_json::Value>,
+    ) -> (StatusCode, serde_json::Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", TOKEN))
+            .header(header::CONTENT_TYPE, "application/json");
+        let body = body.map_or_else(Body::empty, |body| Body::from(body.to_string()));
+        let response = router(state)
+            .oneshot(request.body(body).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = if body.is_empty() {
+            serde_json::Value::Null
+        } else {
+            serde_json::from_slice(&body).unwrap()
+        };
+        (status, body)
+    }
+
+    fn set(store: &MemcStore, key: &'static str, value: &'static [u8]) {
+        store
+            .set(Bytes::from(key), Record::new(Bytes::from(value), 0, 3, 100))
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn requests_without_valid_token_should_be_rejected() {
+        let state = create_state(create_storage());
+        for authorization in [None, Some("Bearer wrong"), Some("secret")] {
+            let mut request = Request::get("/api/v1/stats");
+            if let Some(authorization) = authorization {
+                request = request.header(header::AUTHORIZATION, authorization);
+            }
+            let response = router(state.clone())
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        let (status, _body) = call(state, "GET", "/api/v1/stats", None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let state = Arc::new(AdminState::new(create_storage()));
+        let request = Request::get("/api/v1/stats").body(Body::empty()).unwrap();
+        let response = router(state).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn openapi_description_should_list_all_endpoints_without_token() {
+        let state = create_state(create_storage());
+        let request = Request::get("/api/v1/openapi.json")
+            .body(Body::empty())
+            .unwrap();
+        let response = router(state).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let description: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let paths = description["paths"].as_object().unwrap();
+        let mut paths: Vec<&str> = paths.keys().map(String::as_str).collect();
+        paths.sort_unstable();
+        assert_eq!(
+            paths,
+            [
+                "/api/v1/config/memory-limit",
+                "/api/v1/flush",
+                "/api/v1/key/{key}",
+                "/api/v1/stats"
+            ]
+        );
+        assert_eq!(
+            description["components"]["securitySchemes"]["bearer_token"]["scheme"],
+            "bearer"
+        );
+    }
+
+    #[tokio::test]
+    async fn stats_should_return_store_statistics() {
+        let store = create_storage();
+        set(&store, "foo", b"bar");
+        let (status, body) = call(create_state(store), "GET", "/api/v1/stats", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["curr_items"], 1);
+        assert_eq!(body["cmd_flush_affected_items"], 0);
+    }
+
+    #[tokio::test]
+    async fn get_and_delete_key_should_use_store() {
+        let store = create_storage();
+        set(&store, "foo", b"bar\xff");
+        let state = create_state(store.clone());
+
+        let (status, body) = call(state.clone(), "GET", "/api/v1/key/foo", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["key"], "foo");
+        assert_eq!(body["value"], "YmFy/w==");
+        assert_eq!(body["flags"], 3);
+        assert_eq!(body["expiration"], 100);
+        assert_eq!(body["cas"], store.get_cas(&Bytes::from("foo")).unwrap());
+
+        let (status, _body) = call(state.clone(), "DELETE", "/api/v1/key/foo", None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, body) = call(state.clone(), "DELETE", "/api/v1/key/foo", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"], "Not found");
+        let (status, _body) = call(state, "GET", "/api/v1/key/foo", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn flush_should_return_number_of_affected_records() {
+        let store = create_storage();
+        set(&store, "foo", b"bar");
+        set(&store, "baz", b"bar");
+        let state = create_state(store.clone());
+        let flush = serde_json::json!({ "delay_secs": 0 });
+        let (status, body) = call(state.clone(), "POST", "/api/v1/flush", Some(flush)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["affected_items"], 2);
+        assert!(store.get(&Bytes::from("foo")).is_err());
+
+        let (status, _body) =
+            call(state, "POST", "/api/v1/flush", Some(serde_json::json!({}))).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn memory_limit_should_be_changed_at_runtime() {
+        let limit = serde_json::json!({ "bytes": 1024 });
+        let store = create_storage();
+        let (status, _body) = call(
+            create_state(store.clone()),
+            "PUT",
+            "/api/v1/config/memory-limit",
+            Some(limit.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        // a store without eviction rejects records over a new limit
+        let record = Record::new(Bytes::from(vec![b'x'; 1024]), 0, 0, 0);
+        assert_eq!(
+            store.set(Bytes::from("key"), record),
+            Err(CacheError::OutOfMemory)
+        );
+
+        let memory_store = MemoryStore::new_shared(Arc::new(MockSystemTimer::new()));
+        let policy: Arc<dyn Cache + Send + Sync> =
+            Arc::new(RandomPolicy::new(memory_store, 1024 * 1024));
+        let store = Arc::new(MemcStore::new(policy));
+        let state = create_state(store.clone());
+        let (status, _body) = call(state, "PUT", "/api/v1/config/memory-limit", Some(limit)).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        // records over a new limit are evicted by following writes
+        for id in 0..100 {
+            let record = Record::new(Bytes::from(vec![b'x'; 100]), 0, 0, 0);
+            store
+                .set(Bytes::from(format!("key{}", id)), record)
+                .unwrap();
+        }
+        assert!(store.stats().curr_items < 100);
+    }
+}
@@ -0,0 +1,158 @@
+//! SASL authentication of binary protocol clients. Only PLAIN mechanism
+//! is supported, so a password is sent in clear text and a connection
+//! should be encrypted, i.e. with --tls-cert, unless it is local.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// Name of the only supported mechanism
+pub const PLAIN_MECHANISM: &str = "PLAIN";
+
+/// Mechanisms returned to SASL_LIST_MECHS requests
+pub const SASL_MECHANISMS: &str = PLAIN_MECHANISM;
+
+/// Checks credentials of clients against a file of `username:password` lines
+pub struct SaslAuthenticator {
+    path: PathBuf,
+    users: RwLock<HashMap<String, Vec<u8>>>,
+}
+
+// a password is split on a first colon, so it may contain colons,
+// empty lines are skipped
+fn parse_users(content: &str) -> io::Result<HashMap<String, Vec<u8>>> {
+    let mut users = HashMap::new();
+    for (number, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match line.split_once(':') {
+            Some((username, password)) if !username.is_empty() => {
+                users.insert(username.to_string(), password.as_bytes().to_vec());
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {} is not in username:password format", number + 1),
+                ))
+            }
+        }
+    }
+    Ok(users)
+}
+
+// time of a comparison depends on a length of an expected value only,
+// not on a position of a first byte which differs
+fn constant_time_eq(expected: &[u8], actual: &[u8]) -> bool {
+    let mut difference = expected.len() ^ actual.len();
+    for (index, byte) in expected.iter().enumerate() {
+        let other = actual.get(index).copied().unwrap_or(!byte);
+        difference |= (byte ^ other) as usize;
+    }
+    difference == 0
+}
+
+impl SaslAuthenticator {
+    /// Loads credentials from a file, it is read again by reload
+    pub fn from_file(path: &Path) -> io::Result<SaslAuthenticator> {
+        let users = parse_users(&std::fs::read_to_string(path)?)?;
+        Ok(SaslAuthenticator {
+            path: path.to_path_buf(),
+            users: RwLock::new(users),
+        })
+    }
+
+    /// Path of a credentials file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads a credentials file again and returns a number of users,
+    /// if it cannot be read previous credentials are kept
+    pub fn reload(&self) -> io::Result<usize> {
+        let users = parse_users(&std::fs::read_to_string(&self.path)?)?;
+        let count = users.len();
+        *self.users.write().unwrap() = users;
+        Ok(count)
+    }
+
+    /// Checks a PLAIN mechanism message: authorization identity,
+    /// username and password separated by NUL bytes
+    pub fn authenticate_plain(&self, message: &[u8]) -> Option<String> {
+        let mut parts = message.splitn(3, |byte| *byte == 0);
+        let (_authzid, username, password) = (parts.next()?, parts.next()?, parts.next()?);
+        let username = std::str::from_utf8(username).ok()?;
+        let users = self.users.read().unwrap();
+        // unknown users are compared too, so a time of a response doesn't
+        // tell whether a user exists
+        let (expected, known) = match users.get(username) {
+            Some(expected) => (expected.as_slice(), true),
+            None => (password, false),
+        };
+        if constant_time_eq(expected, password) && known {
+            Some(username.to_string())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn users_file(name: &str, content: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("memcrs-users-{}-{}", name, std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn plain_message_should_be_checked_against_users_file() {
+        let path = users_file("plain", "alice:secret\n\nbob:pass:with:colons\n");
+        let authenticator = SaslAuthenticator::from_file(&path).unwrap();
+        assert_eq!(
+            authenticator.authenticate_plain(b"\0alice\0secret"),
+            Some(String::from("alice"))
+        );
+        assert_eq!(
+            authenticator.authenticate_plain(b"alice\0bob\0pass:with:colons"),
+            Some(String::from("bob"))
+        );
+        assert_eq!(authenticator.authenticate_plain(b"\0alice\0secre"), None);
+        assert_eq!(authenticator.authenticate_plain(b"\0alice\0secrets"), None);
+        assert_eq!(authenticator.authenticate_plain(b"\0carol\0secret"), None);
+        assert_eq!(authenticator.authenticate_plain(b"\0alice"), None);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reload_should_keep_users_if_file_is_invalid() {
+        let path = users_file("reload", "alice:secret\n");
+        let authenticator = SaslAuthenticator::from_file(&path).unwrap();
+
+        std::fs::write(&path, "bob:other\ncarol:third\n").unwrap();
+        assert_eq!(authenticator.reload().unwrap(), 2);
+        assert_eq!(authenticator.authenticate_plain(b"\0alice\0secret"), None);
+        assert!(authenticator.authenticate_plain(b"\0bob\0other").is_some());
+
+        std::fs::write(&path, "no-password\n").unwrap();
+        assert_eq!(
+            authenticator.reload().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert!(authenticator.authenticate_plain(b"\0bob\0other").is_some());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn constant_time_eq_should_compare_lengths_and_bytes() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(constant_time_eq(b"", b""));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret1"));
+        assert!(!constant_time_eq(b"secret", b""));
+    }
+}
@@ -1,89 +1,362 @@
-use std::net::SocketAddr;
+use bytes::BytesMut;
+use std::fmt;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::io;
-use tokio::net::TcpStream;
+use tokio::io::{self, AsyncReadExt};
 use tokio::sync::Semaphore;
-use tokio::time::timeout;
 use tracing::{debug, error};
 
 //use tracing_attributes::instrument;
 
+use super::auth::SaslAuthenticator;
+#[cfg(feature = "binary-protocol")]
 use super::handler;
+use super::memc_tcp::Protocol;
+use super::server_state::{ConnectionGuard, ServerState};
+use super::slow_log::SlowLog;
+use super::text_handler;
 use crate::memcache::store as storage;
+#[cfg(feature = "binary-protocol")]
 use crate::protocol::binary_codec::{BinaryRequest, BinaryResponse};
+#[cfg(feature = "binary-protocol")]
 use crate::protocol::binary_connection::MemcacheBinaryConnection;
+use crate::protocol::client_stream::{BoxedClientStream, CountingStream, IdleTimeoutStream};
+use crate::protocol::text_codec::TextRequest;
+use crate::protocol::text_connection::MemcacheTextConnection;
+
+// every binary protocol request starts with this magic byte,
+// text protocol commands are printable characters
+const BINARY_REQUEST_MAGIC: u8 = 0x80;
 
 pub struct ClientConfig {
     pub(crate) item_memory_limit: u32,
-    pub(crate) rx_timeout_secs: u32,
-    pub(crate) _wx_timeout_secs: u32,
+    // clients which don't send anything for this long are disconnected,
+    // 0 disables a timeout
+    pub(crate) idle_timeout_secs: u32,
+    pub(crate) max_pipeline_depth: u32,
+    pub(crate) protocol: Protocol,
+    // binary protocol clients have to authenticate if set
+    pub(crate) authenticator: Option<Arc<SaslAuthenticator>>,
+    // text protocol clients are disconnected if authenticator is set, unless allowed
+    pub(crate) allow_unauthenticated_text: bool,
+    // requests handled longer than this are logged, 0 disables it
+    pub(crate) slow_log_threshold_us: u64,
 }
-pub struct Client {
+
+/// Protocol specific part of a client connection
+trait ClientConnection {
+    type Request: fmt::Debug;
+
+    /// Returns None if client closed a connection
+    async fn read_frame(&mut self) -> io::Result<Option<Self::Request>>;
+
+    /// Handles request and queues its response,
+    /// returns true if client asked to close a connection
+    async fn handle_request(&mut self, request: Self::Request) -> io::Result<bool>;
+
+    fn has_buffered_data(&self) -> bool;
+
+    fn pending_responses(&self) -> usize;
+
+    async fn flush(&mut self) -> io::Result<()>;
+
+    async fn shutdown(&mut self) -> io::Result<()>;
+}
+
+#[cfg(feature = "binary-protocol")]
+struct BinaryClientConnection {
     stream: MemcacheBinaryConnection,
-    addr: SocketAddr,
-    config: ClientConfig,
     handler: handler::BinaryHandler,
+    slow_log: Option<SlowLog>,
+}
+
+#[cfg(feature = "binary-protocol")]
+impl ClientConnection for BinaryClientConnection {
+    type Request = BinaryRequest;
+
+    async fn read_frame(&mut self) -> io::Result<Option<BinaryRequest>> {
+        self.stream.read_frame().await
+    }
+
+    /// Handles single memcached binary request
+    async fn handle_request(&mut self, request: BinaryRequest) -> io::Result<bool> {
+        debug!("Got request {:?}", request.get_header());
+
+        if let BinaryRequest::QuitQuietly(_req) = request {
+            debug!("Closing client socket quit quietly");
+            return Ok(true);
+        }
+
+        let timed = (self.slow_log.as_ref())
+            .map(|slow_log| slow_log.start(request.command_name(), request.key()));
+        let response = self.handler.handle_request(request);
+        if let (Some(slow_log), Some(timed)) = (&self.slow_log, timed) {
+            slow_log.finish(timed);
+        }
+        match response {
+            Some(response) => {
+                debug!("Sending response {:?}", response);
+                self.stream.write(&response).await?;
+                Ok(matches!(response, BinaryResponse::Quit(_)))
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        self.stream.has_buffered_data()
+    }
+
+    fn pending_responses(&self) -> usize {
+        self.stream.pending_responses()
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush().await
+    }
+
+    async fn shutdown(&mut self) -> io::Result<()> {
+        self.stream.shutdown().await
+    }
+}
+
+struct TextClientConnection {
+    stream: MemcacheTextConnection,
+    handler: text_handler::TextHandler,
+    slow_log: Option<SlowLog>,
+}
+
+impl ClientConnection for TextClientConnection {
+    type Request = TextRequest;
+
+    async fn read_frame(&mut self) -> io::Result<Option<TextRequest>> {
+        self.stream.read_frame().await
+    }
+
+    /// Handles single memcached text protocol request
+    async fn handle_request(&mut self, request: TextRequest) -> io::Result<bool> {
+        debug!("Got request {:?}", request);
+        if let TextRequest::Quit = request {
+            return Ok(true);
+        }
+        let timed = (self.slow_log.as_ref())
+            .map(|slow_log| slow_log.start(request.command_name(), request.key()));
+        let response = self.handler.handle_request(request);
+        if let (Some(slow_log), Some(timed)) = (&self.slow_log, timed) {
+            slow_log.finish(timed);
+        }
+        match response {
+            Some(response) => {
+                debug!("Sending response {:?}", response);
+                self.stream.write(&response).await?;
+            }
+            None => debug!("No reply requested"),
+        }
+        Ok(false)
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        self.stream.has_buffered_data()
+    }
+
+    fn pending_responses(&self) -> usize {
+        self.stream.pending_responses()
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush().await
+    }
+
+    async fn shutdown(&mut self) -> io::Result<()> {
+        self.stream.shutdown().await
+    }
+}
+
+pub struct Client {
+    store: Arc<storage::MemcStore>,
+    // peer address of a TCP client or path of a Unix socket
+    addr: String,
+    config: ClientConfig,
     /// Max connection semaphore.
     ///
     /// When the handler is dropped, a permit is returned to this semaphore. If
     /// the listener is waiting for connections to close, it will be notified of
     /// the newly available permit and resume accepting connections.
     limit_connections: Arc<Semaphore>,
+    state: Arc<ServerState>,
+    // counts a client as connected until it is dropped
+    connection: ConnectionGuard,
 }
 
 impl Client {
     pub fn new(
         store: Arc<storage::MemcStore>,
-        socket: TcpStream,
-        addr: SocketAddr,
+        addr: String,
         config: ClientConfig,
         limit_connections: Arc<Semaphore>,
+        state: Arc<ServerState>,
     ) -> Self {
+        let connection = state.connection_opened();
         Client {
-            stream: MemcacheBinaryConnection::new(socket, config.item_memory_limit),
+            store,
             addr,
             config,
-            handler: handler::BinaryHandler::new(store),
             limit_connections,
+            state,
+            connection,
         }
     }
 
-    pub async fn handle(&mut self) {
-        debug!("New client connected: {}", self.addr);
+    /// Detects protocol from a first byte sent by a client and
+    /// handles requests until a connection is closed
+    pub async fn handle(&mut self, socket: BoxedClientStream) {
+        let mut socket: BoxedClientStream =
+            Box::new(CountingStream::new(socket, self.store.shared_counters()));
+        if self.config.idle_timeout_secs > 0 {
+            let idle_timeout = Duration::from_secs(self.config.idle_timeout_secs as u64);
+            socket = Box::new(IdleTimeoutStream::new(socket, idle_timeout));
+        }
+        debug!(
+            "New client connected: {}, connection id: {}",
+            self.addr,
+            self.connection.connection_id()
+        );
+        let received = match self.read_first_bytes(&mut socket).await {
+            Some(received) => received,
+            None => return,
+        };
+        match received[0] {
+            BINARY_REQUEST_MAGIC if self.config.protocol == Protocol::Text => {
+                error!(
+                    "Binary protocol is disabled, disconnecting client: {}",
+                    self.addr
+                );
+            }
+            BINARY_REQUEST_MAGIC => self.handle_binary(socket, received).await,
+            _ if self.config.protocol == Protocol::Binary => {
+                error!(
+                    "Text protocol is disabled, disconnecting client: {}",
+                    self.addr
+                );
+            }
+            // text protocol has no authentication
+            _ if self.config.authenticator.is_some() && !self.config.allow_unauthenticated_text => {
+                error!(
+                    "Text protocol clients cannot authenticate, disconnecting client: {}",
+                    self.addr
+                );
+            }
+            _ => {
+                let connection = TextClientConnection {
+                    stream: MemcacheTextConnection::new(
+                        socket,
+                        received,
+                        self.config.item_memory_limit,
+                    ),
+                    handler: text_handler::TextHandler::new(Arc::clone(&self.store)),
+                    slow_log: self.slow_log(),
+                };
+                self.serve(connection).await
+            }
+        }
+    }
+
+    #[cfg(feature = "binary-protocol")]
+    async fn handle_binary(&mut self, socket: BoxedClientStream, received: BytesMut) {
+        let handler = handler::BinaryHandler::new(Arc::clone(&self.store));
+        let handler = match &self.config.authenticator {
+            Some(authenticator) => handler.with_authenticator(Arc::clone(authenticator)),
+            None => handler,
+        };
+        let connection = BinaryClientConnection {
+            stream: MemcacheBinaryConnection::new(socket, received, self.config.item_memory_limit),
+            handler,
+            slow_log: self.slow_log(),
+        };
+        self.serve(connection).await
+    }
+
+    #[cfg(not(feature = "binary-protocol"))]
+    async fn handle_binary(&mut self, _socket: BoxedClientStream, _received: BytesMut) {
+        error!(
+            "memcrsd was built without binary protocol support, disconnecting client: {}",
+            self.addr
+        );
+    }
 
+    fn slow_log(&self) -> Option<SlowLog> {
+        SlowLog::new(
+            self.config.slow_log_threshold_us,
+            Arc::clone(&self.store),
+            self.addr.clone(),
+        )
+    }
+
+    // a stream may not support peeking, i.e. TLS, so received bytes are
+    // passed to a connection, None is returned if a client disconnected
+    // before sending a request
+    async fn read_first_bytes(&self, socket: &mut BoxedClientStream) -> Option<BytesMut> {
+        let mut received = BytesMut::with_capacity(4096);
+        let read = tokio::select! {
+            read = socket.read_buf(&mut received) => read,
+            _ = self.state.wait_for_shutdown() => {
+                debug!("Server shutdown, disconnecting client: {}", self.addr);
+                return None;
+            }
+        };
+        match read {
+            Ok(0) => {
+                debug!("Connection closed: {}", self.addr);
+                None
+            }
+            Ok(_) => Some(received),
+            Err(err) => {
+                self.log_read_error(err);
+                None
+            }
+        }
+    }
+
+    async fn serve<C: ClientConnection>(&mut self, mut connection: C) {
         // Here for every packet we get back from the `Framed` decoder,
         // we parse the request, and if it's valid we generate a response
         // based on the values in the storage.
+        let mut shutting_down = false;
         loop {
-            match timeout(
-                Duration::from_secs(self.config.rx_timeout_secs as u64),
-                self.stream.read_frame(),
-            )
-            .await
-            {
-                Ok(req_or_none) => {
-                    let client_close = self.handle_frame(req_or_none).await;
+            // on shutdown a client is closed once it is between requests,
+            // so a request which is already being received is completed
+            if shutting_down && !connection.has_buffered_data() {
+                debug!("Server shutdown, disconnecting client: {}", self.addr);
+                if let Err(_e) = connection.shutdown().await.map_err(log_error) {}
+                return;
+            }
+
+            let frame = tokio::select! {
+                frame = connection.read_frame() => Some(frame),
+                _ = self.state.wait_for_shutdown(), if !shutting_down => None,
+            };
+
+            match frame {
+                Some(req_or_none) => {
+                    let client_close = self.handle_frame(&mut connection, req_or_none).await;
                     if client_close {
                         return;
                     }
                 }
-                Err(err) => {
-                    debug!(
-                        "Timeout {}s elapsed, disconecting client: {}, error: {}",
-                        self.config.rx_timeout_secs, self.addr, err
-                    );
-                    return;
-                }
+                None => shutting_down = true,
             }
         }
     }
 
-    async fn handle_frame(&mut self, req: Result<Option<BinaryRequest>, io::Error>) -> bool {
+    async fn handle_frame<C: ClientConnection>(
+        &mut self,
+        connection: &mut C,
+        req: Result<Option<C::Request>, io::Error>,
+    ) -> bool {
         match req {
             Ok(re) => {
                 match re {
-                    Some(request) => self.handle_request(request).await,
+                    Some(request) => self.handle_request(connection, request).await,
                     None => {
                         // The connection will be closed at this point as `lines.next()` has returned `None`.
                         debug!("Connection closed: {}", self.addr);
@@ -92,46 +365,54 @@ impl Client {
                 }
             }
             Err(err) => {
-                error!("Error when reading frame; error = {:?}", err);
+                self.log_read_error(err);
                 true
             }
         }
     }
 
-    /// Handles single memcached binary request
-    /// Returns true if we should leave client receive loop
-    async fn handle_request(&mut self, request: BinaryRequest) -> bool {
-        debug!("Got request {:?}", request.get_header());
-
-        if let BinaryRequest::QuitQuietly(_req) = request {
-            debug!("Closing client socket quit quietly");
-            if let Err(_e) = self.stream.shutdown().await.map_err(log_error) {}
-            return true;
+    fn log_read_error(&self, err: io::Error) {
+        if err.kind() == io::ErrorKind::TimedOut {
+            debug!(
+                "Idle timeout {}s elapsed, disconnecting client: {}",
+                self.config.idle_timeout_secs, self.addr
+            );
+        } else {
+            error!("Error when reading frame; error = {:?}", err);
         }
+    }
 
-        let resp = self.handler.handle_request(request);
-        match resp {
-            Some(response) => {
-                let mut socket_close = false;
-                if let BinaryResponse::Quit(_resp) = &response {
-                    socket_close = true;
-                }
-
-                debug!("Sending response {:?}", response);
-                if let Err(e) = self.stream.write(&response).await {
-                    error!("error on sending response; error = {:?}", e);
-                    return true;
-                }
+    /// Handles single request
+    /// Returns true if we should leave client receive loop
+    async fn handle_request<C: ClientConnection>(
+        &mut self,
+        connection: &mut C,
+        request: C::Request,
+    ) -> bool {
+        let socket_close = match connection.handle_request(request).await {
+            Ok(socket_close) => socket_close,
+            Err(e) => {
+                error!("error on sending response; error = {:?}", e);
+                return true;
+            }
+        };
 
-                if socket_close {
-                    debug!("Closing client socket quit command");
-                    if let Err(_e) = self.stream.shutdown().await.map_err(log_error) {}
-                    return true;
-                }
-                false
+        // don't let a single client queue responses for a whole pipeline
+        // while other clients are waiting
+        if connection.pending_responses() >= self.config.max_pipeline_depth as usize {
+            if let Err(e) = connection.flush().await {
+                error!("error on sending response; error = {:?}", e);
+                return true;
             }
-            None => false,
+            self.state.pipeline_flushed();
+        }
+
+        if socket_close {
+            debug!("Closing client socket quit command");
+            if let Err(_e) = connection.shutdown().await.map_err(log_error) {}
+            return true;
         }
+        false
     }
 }
 
@@ -0,0 +1,182 @@
+//! gRPC admin API, MemcacheAdmin service of proto/memcrs.proto
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+use crate::cache::error::CacheError;
+use crate::memcache::store::{MemcStore, Meta};
+
+/// Messages, client and server of MemcacheAdmin service
+pub mod proto {
+    include!("grpc/memcrs.admin.rs");
+}
+
+use proto::memcache_admin_server::{MemcacheAdmin, MemcacheAdminServer};
+use proto::{
+    DeleteRequest, DeleteResponse, Empty, FlushRequest, GetRequest, GetResponse,
+    MemoryLimitRequest, StatsResponse,
+};
+
+/// MemcacheAdmin service over a store
+pub struct AdminService {
+    store: Arc<MemcStore>,
+}
+
+impl AdminService {
+    /// Stats of a store should be shared with listeners, so records
+    /// removed by flush are counted
+    pub fn new(store: Arc<MemcStore>) -> AdminService {
+        AdminService { store }
+    }
+}
+
+fn status(err: CacheError) -> Status {
+    let message = err.to_static_string();
+    match err {
+        CacheError::NotFound => Status::not_found(message),
+        CacheError::NotSupported => Status::failed_precondition(message),
+        CacheError::InvalidArguments | CacheError::InvalidKey => Status::invalid_argument(message),
+        _ => Status::internal(message),
+    }
+}
+
+#[tonic::async_trait]
+impl MemcacheAdmin for AdminService {
+    async fn get_stats(&self, _request: Request<Empty>) -> Result<Response<StatsResponse>, Status> {
+        let stats = self.store.stats();
+        Ok(Response::new(StatsResponse {
+            curr_items: stats.curr_items as u64,
+            oldest_item_age: stats.oldest_item_age,
+            newest_item_age: stats.newest_item_age,
+            expired_items: stats.expired_items as u64,
+            expired_unfetched: stats.expired_unfetched,
+            cmd_flush_affected_items: stats.cmd_flush_affected_items,
+            start_time: stats.start_time,
+            time: stats.time,
+            bytes: self.store.memory_used(),
+        }))
+    }
+
+    async fn flush_all(&self, request: Request<FlushRequest>) -> Result<Response<Empty>, Status> {
+        self.store
+            .flush(Meta::new(0, 0, request.into_inner().delay_secs));
+        Ok(Response::new(Empty {}))
+    }
+
+    async fn get_key(&self, request: Request<GetRequest>) -> Result<Response<GetResponse>, Status> {
+        let record = self.store.get(&request.into_inner().key).map_err(status)?;
+        Ok(Response::new(GetResponse {
+            value: record.value,
+            flags: record.header.flags,
+            cas: record.header.cas,
+            expiration: record.header.get_expiration(),
+        }))
+    }
+
+    async fn delete_key(
+        &self,
+        request: Request<DeleteRequest>,
+    ) -> Result<Response<DeleteResponse>, Status> {
+        let deleted = match self
+            .store
+            .delete(request.into_inner().key, Meta::new(0, 0, 0))
+        {
+            Ok(_record) => true,
+            Err(CacheError::NotFound) => false,
+            Err(err) => return Err(status(err)),
+        };
+        Ok(Response::new(DeleteResponse { deleted }))
+    }
+
+    async fn set_memory_limit(
+        &self,
+        request: Request<MemoryLimitRequest>,
+    ) -> Result<Response<Empty>, Status> {
+        self.store
+            .set_memory_limit(request.into_inner().bytes)
+            .map_err(status)?;
+        Ok(Response::new(Empty {}))
+    }
+}
+
+/// Serves MemcacheAdmin service on a given address
+pub async fn serve(addr: SocketAddr, service: AdminService) -> Result<(), tonic::transport::Error> {
+    Server::builder()
+        .add_service(MemcacheAdminServer::new(service))
+        .serve(addr)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memcache::store::Record;
+    use crate::mock::mock_server::create_storage;
+    use bytes::Bytes;
+
+    fn set(store: &MemcStore, key: &'static str, value: &'static [u8]) {
+        store
+            .set(Bytes::from(key), Record::new(Bytes::from(value), 0, 3, 100))
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn get_and_delete_key_should_use_store() {
+        let store = create_storage();
+        set(&store, "foo", b"bar\xff");
+        let service = AdminService::new(store.clone());
+
+        let key = Bytes::from("foo");
+        let response = service
+            .get_key(Request::new(GetRequest { key: key.clone() }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.value, Bytes::from_static(b"bar\xff"));
+        assert_eq!(response.flags, 3);
+        assert_eq!(response.expiration, 100);
+        assert_eq!(response.cas, store.get_cas(&key).unwrap());
+
+        for deleted in [true, false] {
+            let response = service
+                .delete_key(Request::new(DeleteRequest { key: key.clone() }))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(response.deleted, deleted);
+        }
+        let err = service
+            .get_key(Request::new(GetRequest { key }))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn flush_all_should_be_counted_in_stats() {
+        let store = create_storage();
+        set(&store, "foo", b"bar");
+        set(&store, "baz", b"bar");
+        let service = AdminService::new(store);
+        let stats = service
+            .get_stats(Request::new(Empty {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(stats.curr_items, 2);
+
+        service
+            .flush_all(Request::new(FlushRequest { delay_secs: 0 }))
+            .await
+            .unwrap();
+        let stats = service
+            .get_stats(Request::new(Empty {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(stats.curr_items, 0);
+        assert_eq!(stats.cmd_flush_affected_items, 2);
+    }
+}
@@ -1,20 +1,26 @@
+use super::auth::{SaslAuthenticator, PLAIN_MECHANISM, SASL_MECHANISMS};
 use crate::cache::error::CacheError;
 use crate::memcache::store;
-use crate::protocol::binary_codec::storage_error_to_response;
+use crate::protocol::binary_codec::{authentication_error_response, storage_error_to_response};
 use crate::protocol::{binary, binary_codec};
 use crate::version::MEMCRS_VERSION;
 use bytes::Bytes;
+use log::debug;
 use std::sync::Arc;
 
 const EXTRAS_LENGTH: u8 = 4;
 
+const AUTHENTICATION_REQUIRED: &str = "Authentication required";
+const AUTHENTICATION_FAILED: &str = "Authentication failed";
+const AUTHENTICATED: &str = "Authenticated";
+
 fn into_record_meta(request_header: &binary::RequestHeader, expiration: u32) -> store::Meta {
     store::Meta::new(request_header.cas, request_header.opaque, expiration)
 }
 
 fn into_quiet_get(response: binary_codec::BinaryResponse) -> Option<binary_codec::BinaryResponse> {
     if let binary_codec::BinaryResponse::Error(response) = &response {
-        if response.header.status == CacheError::NotFound as u16 {
+        if response.header.status == CacheError::NotFound.code() {
             return None;
         }
     }
@@ -30,23 +36,53 @@ fn into_quiet_mutation(
     None
 }
 
+fn is_sasl_request(request: &binary_codec::BinaryRequest) -> bool {
+    matches!(
+        request,
+        binary_codec::BinaryRequest::SaslListMechs(_)
+            | binary_codec::BinaryRequest::SaslAuth(_)
+            | binary_codec::BinaryRequest::SaslStep(_)
+    )
+}
+
+/// Handles binary protocol requests of a single connection
 pub struct BinaryHandler {
     storage: Arc<store::MemcStore>,
+    authenticator: Option<Arc<SaslAuthenticator>>,
+    authenticated: bool,
 }
 
 impl BinaryHandler {
     pub fn new(store: Arc<store::MemcStore>) -> BinaryHandler {
-        BinaryHandler { storage: store }
+        BinaryHandler {
+            storage: store,
+            authenticator: None,
+            authenticated: false,
+        }
+    }
+
+    /// Requests other than SASL ones are refused until a client
+    /// authenticates with SASL_AUTH
+    pub fn with_authenticator(mut self, authenticator: Arc<SaslAuthenticator>) -> Self {
+        self.authenticator = Some(authenticator);
+        self
     }
 
     pub fn handle_request(
-        &self,
+        &mut self,
         req: binary_codec::BinaryRequest,
     ) -> Option<binary_codec::BinaryResponse> {
         let request_header = req.get_header();
         let mut response_header =
             binary::ResponseHeader::new(request_header.opcode, request_header.opaque);
 
+        if self.authenticator.is_some() && !self.authenticated && !is_sasl_request(&req) {
+            return Some(authentication_error_response(
+                AUTHENTICATION_REQUIRED,
+                &mut response_header,
+            ));
+        }
+
         match req {
             binary_codec::BinaryRequest::Delete(delete_request) => {
                 Some(self.delete(delete_request, &mut response_header))
@@ -134,9 +170,77 @@ impl BinaryHandler {
                     },
                 ))
             }
+            binary_codec::BinaryRequest::Touch(touch_request) => {
+                Some(self.touch(touch_request, &mut response_header))
+            }
+            binary_codec::BinaryRequest::GetAndTouch(gat_request)
+            | binary_codec::BinaryRequest::GetAndTouchKey(gat_request) => {
+                Some(self.get_and_touch(gat_request, &mut response_header))
+            }
+            binary_codec::BinaryRequest::GetAndTouchQuietly(gat_request)
+            | binary_codec::BinaryRequest::GetAndTouchKeyQuietly(gat_request) => {
+                into_quiet_get(self.get_and_touch(gat_request, &mut response_header))
+            }
             binary_codec::BinaryRequest::ItemTooLarge(_set_request) => Some(
                 storage_error_to_response(CacheError::ValueTooLarge, &mut response_header),
             ),
+            binary_codec::BinaryRequest::SaslListMechs(_request) => {
+                Some(self.sasl_list_mechanisms(&mut response_header))
+            }
+            binary_codec::BinaryRequest::SaslAuth(auth_request) => {
+                Some(self.sasl_auth(auth_request, &mut response_header))
+            }
+            // PLAIN mechanism completes in a single step
+            binary_codec::BinaryRequest::SaslStep(_step_request) => {
+                Some(match self.authenticator {
+                    Some(_) => {
+                        authentication_error_response(AUTHENTICATION_FAILED, &mut response_header)
+                    }
+                    None => {
+                        storage_error_to_response(CacheError::UnkownCommand, &mut response_header)
+                    }
+                })
+            }
+        }
+    }
+
+    fn sasl_list_mechanisms(
+        &self,
+        response_header: &mut binary::ResponseHeader,
+    ) -> binary_codec::BinaryResponse {
+        if self.authenticator.is_none() {
+            return storage_error_to_response(CacheError::UnkownCommand, response_header);
+        }
+        response_header.body_length = SASL_MECHANISMS.len() as u32;
+        binary_codec::BinaryResponse::SaslListMechs(binary::SaslListMechsResponse {
+            header: *response_header,
+            mechanisms: SASL_MECHANISMS,
+        })
+    }
+
+    fn sasl_auth(
+        &mut self,
+        request: binary::SaslAuthRequest,
+        response_header: &mut binary::ResponseHeader,
+    ) -> binary_codec::BinaryResponse {
+        let authenticator = match &self.authenticator {
+            Some(authenticator) => authenticator,
+            None => return storage_error_to_response(CacheError::UnkownCommand, response_header),
+        };
+        if request.mechanism != PLAIN_MECHANISM.as_bytes() {
+            return authentication_error_response(AUTHENTICATION_FAILED, response_header);
+        }
+        match authenticator.authenticate_plain(&request.data) {
+            Some(username) => {
+                debug!("Client authenticated as {}", username);
+                self.authenticated = true;
+                response_header.body_length = AUTHENTICATED.len() as u32;
+                binary_codec::BinaryResponse::SaslAuth(binary::SaslAuthResponse {
+                    header: *response_header,
+                    message: AUTHENTICATED,
+                })
+            }
+            None => authentication_error_response(AUTHENTICATION_FAILED, response_header),
         }
     }
 
@@ -244,33 +348,81 @@ impl BinaryHandler {
         get_request: binary::GetRequest,
         response_header: &mut binary::ResponseHeader,
     ) -> binary_codec::BinaryResponse {
-        let result = self.storage.get(&get_request.key);
-
-        match result {
+        match self.storage.get(&get_request.key) {
             Ok(record) => {
-                let include_key = self.is_get_key_command(get_request.header.opcode);
-                let mut key: Bytes = Bytes::new();
-                if include_key {
-                    key = get_request.key
-                }
-                response_header.body_length =
-                    record.value.len() as u32 + EXTRAS_LENGTH as u32 + key.len() as u32;
-                response_header.key_length = key.len() as u16;
-                response_header.extras_length = EXTRAS_LENGTH;
-                response_header.cas = record.header.cas;
-                binary_codec::BinaryResponse::Get(binary::GetResponse {
-                    header: *response_header,
-                    flags: record.header.flags,
-                    key,
-                    value: record.value,
-                })
+                let response = self.record_response(get_request, record, response_header);
+                binary_codec::BinaryResponse::Get(response)
             }
             Err(err) => storage_error_to_response(err, response_header),
         }
     }
 
+    fn record_response(
+        &self,
+        get_request: binary::GetRequest,
+        record: store::Record,
+        response_header: &mut binary::ResponseHeader,
+    ) -> binary::GetResponse {
+        let include_key = self.is_get_key_command(get_request.header.opcode);
+        let mut key: Bytes = Bytes::new();
+        if include_key {
+            key = get_request.key
+        }
+        response_header.body_length =
+            record.value.len() as u32 + EXTRAS_LENGTH as u32 + key.len() as u32;
+        response_header.key_length = key.len() as u16;
+        response_header.extras_length = EXTRAS_LENGTH;
+        response_header.cas = record.header.cas;
+        binary::GetResponse {
+            header: *response_header,
+            flags: record.header.flags,
+            key,
+            value: record.value,
+        }
+    }
+
     fn is_get_key_command(&self, opcode: u8) -> bool {
-        opcode == binary::Command::GetKey as u8 || opcode == binary::Command::GetKeyQuiet as u8
+        opcode == binary::Command::GetKey as u8
+            || opcode == binary::Command::GetKeyQuiet as u8
+            || opcode == binary::Command::GetAndTouchKey as u8
+            || opcode == binary::Command::GetAndTouchKeyQuiet as u8
+    }
+
+    fn touch(
+        &self,
+        touch_request: binary::TouchRequest,
+        response_header: &mut binary::ResponseHeader,
+    ) -> binary_codec::BinaryResponse {
+        match self
+            .storage
+            .touch(&touch_request.key, touch_request.expiration)
+        {
+            Ok(()) => binary_codec::BinaryResponse::Touch(binary::TouchResponse {
+                header: *response_header,
+            }),
+            Err(err) => storage_error_to_response(err, response_header),
+        }
+    }
+
+    fn get_and_touch(
+        &self,
+        gat_request: binary::GetAndTouchRequest,
+        response_header: &mut binary::ResponseHeader,
+    ) -> binary_codec::BinaryResponse {
+        match self
+            .storage
+            .get_and_touch(&gat_request.key, gat_request.expiration)
+        {
+            Ok(record) => {
+                let get_request = binary::GetRequest {
+                    header: gat_request.header,
+                    key: gat_request.key,
+                };
+                let response = self.record_response(get_request, record, response_header);
+                binary_codec::BinaryResponse::GetAndTouch(response)
+            }
+            Err(err) => storage_error_to_response(err, response_header),
+        }
     }
 
     fn flush(
@@ -279,7 +431,8 @@ impl BinaryHandler {
         response_header: &mut binary::ResponseHeader,
     ) -> binary_codec::BinaryResponse {
         let meta: store::Meta = store::Meta::new(0, 0, flush_request.expiration);
-        self.storage.flush(meta);
+        let affected = self.storage.flush(meta);
+        debug!("Flush affected {} records", affected);
         binary_codec::BinaryResponse::Flush(binary::FlushResponse {
             header: *response_header,
         })
@@ -290,10 +443,7 @@ impl BinaryHandler {
         inc_request: binary::IncrementRequest,
         response_header: &mut binary::ResponseHeader,
     ) -> binary_codec::BinaryResponse {
-        let delta = store::IncrementParam {
-            delta: inc_request.delta,
-            value: inc_request.initial,
-        };
+        let delta = store::IncrementParam::from((inc_request.delta, inc_request.initial));
 
         let result = self.storage.increment(
             into_record_meta(&inc_request.header, inc_request.expiration),
@@ -319,10 +469,7 @@ impl BinaryHandler {
         dec_request: binary::IncrementRequest,
         response_header: &mut binary::ResponseHeader,
     ) -> binary_codec::BinaryResponse {
-        let delta = store::IncrementParam {
-            delta: dec_request.delta,
-            value: dec_request.initial,
-        };
+        let delta = store::IncrementParam::from((dec_request.delta, dec_request.initial));
 
         let result = self.storage.decrement(
             into_record_meta(&dec_request.header, dec_request.expiration),
@@ -374,7 +521,7 @@ mod tests {
         }
     }
 
-    fn get_value(handler: &BinaryHandler, key: Bytes) -> Bytes {
+    fn get_value(handler: &mut BinaryHandler, key: Bytes) -> Bytes {
         let header = create_header(binary::Command::Get, &key);
         let request = binary_codec::BinaryRequest::Get(binary::GetRequest { header, key });
 
@@ -383,7 +530,7 @@ mod tests {
             Some(resp) => {
                 if let binary_codec::BinaryResponse::Get(response) = resp {
                     assert_ne!(response.header.cas, 0);
-                    return response.value;
+                    response.value
                 } else {
                     unreachable!();
                 }
@@ -392,7 +539,7 @@ mod tests {
         }
     }
 
-    fn insert_value(handler: &BinaryHandler, key: Bytes, value: Bytes) {
+    fn insert_value(handler: &mut BinaryHandler, key: Bytes, value: Bytes) {
         let header = create_header(binary::Command::Set, &key);
         const FLAGS: u32 = 0xDEAD_BEEF;
         let request = binary_codec::BinaryRequest::SetQuietly(binary::SetRequest {
@@ -429,7 +576,7 @@ mod tests {
 
     #[test]
     fn get_request_should_return_not_found_when_not_exists() {
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = Bytes::from("key");
         let header = create_header(binary::Command::Get, &key);
 
@@ -439,7 +586,7 @@ mod tests {
         match result {
             Some(resp) => {
                 if let binary_codec::BinaryResponse::Error(response) = resp {
-                    assert_eq!(response.header.status, error::CacheError::NotFound as u16);
+                    assert_eq!(response.header.status, error::CacheError::NotFound.code());
                     assert_eq!(response.error, "Not found");
                     assert_eq!(response.header.body_length, response.error.len() as u32);
                 } else {
@@ -452,7 +599,7 @@ mod tests {
 
     #[test]
     fn get_quiet_request_should_return_none_when_not_exists() {
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = Bytes::from("key");
         let header = create_header(binary::Command::GetQuiet, &key);
 
@@ -465,7 +612,7 @@ mod tests {
 
     #[test]
     fn get_quiet_key_request_should_return_none_when_not_exists() {
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = Bytes::from("key");
         let header = create_header(binary::Command::GetQuiet, &key);
 
@@ -478,11 +625,11 @@ mod tests {
 
     #[test]
     fn get_key_request_should_return_key_and_record() {
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = Bytes::from("test_key");
         let value = from_string("test value");
 
-        insert_value(&handler, key.clone(), value.clone());
+        insert_value(&mut handler, key.clone(), value.clone());
 
         let header = create_header(binary::Command::GetKey, &key);
         let request = binary_codec::BinaryRequest::GetKey(binary::GetKeyRequest {
@@ -516,11 +663,11 @@ mod tests {
 
     #[test]
     fn get_quiet_key_request_should_return_key_and_record() {
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = Bytes::from("test_key");
         let value = from_string("test value");
 
-        insert_value(&handler, key.clone(), value.clone());
+        insert_value(&mut handler, key.clone(), value.clone());
 
         let header = create_header(binary::Command::GetKeyQuiet, &key);
         let request = binary_codec::BinaryRequest::GetKeyQuietly(binary::GetKeyQuietRequest {
@@ -554,7 +701,7 @@ mod tests {
 
     #[test]
     fn get_request_should_return_record() {
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = Bytes::from("key");
         let header = create_header(binary::Command::Get, &key);
         const FLAGS: u32 = 0xDEAD_BEEF;
@@ -591,7 +738,7 @@ mod tests {
 
     #[test]
     fn set_request_should_succeed() {
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = Bytes::from("key");
         let header = create_header(binary::Command::Set, &key);
         const FLAGS: u32 = 0xDEAD_BEEF;
@@ -619,7 +766,7 @@ mod tests {
 
     #[test]
     fn set_request_should_return_item_too_large_() {
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = Bytes::from("key");
         let header = create_header(binary::Command::Set, &key);
         const FLAGS: u32 = 0xDEAD_BEEF;
@@ -642,7 +789,7 @@ mod tests {
                         0,
                         0,
                         0,
-                        error::CacheError::ValueTooLarge as u16,
+                        error::CacheError::ValueTooLarge.code(),
                         response.error.len() as u32,
                     );
                 } else {
@@ -655,7 +802,7 @@ mod tests {
 
     #[test]
     fn set_request_on_cas_mismatch_should_return_key_exists() {
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = Bytes::from("key");
         let mut header = create_header(binary::Command::Set, &key);
         const FLAGS: u32 = 0xDEAD_BEEF;
@@ -702,7 +849,7 @@ mod tests {
                         0,
                         0,
                         0,
-                        error::CacheError::KeyExists as u16,
+                        error::CacheError::KeyExists.code(),
                         response.error.len() as u32,
                     );
                 } else {
@@ -715,7 +862,7 @@ mod tests {
 
     #[test]
     fn version_request_should_return_version() {
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = String::from("").into_bytes();
         let header = create_header(binary::Command::Version, &key);
         let request = binary_codec::BinaryRequest::Version(binary::VersionRequest { header });
@@ -744,7 +891,7 @@ mod tests {
     #[test]
     fn increment_request_should_return_cas() {
         const EXPECTED_VALUE: u64 = 1;
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = Bytes::from("counter");
         let header = create_header(binary::Command::Increment, &key);
         let request = binary_codec::BinaryRequest::Increment(binary::IncrementRequest {
@@ -781,10 +928,10 @@ mod tests {
     #[test]
     fn increment_request_should_increment_value() {
         const EXPECTED_VALUE: u64 = 101;
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = Bytes::from("counter");
         let value = from_string("100");
-        insert_value(&handler, key.clone(), value);
+        insert_value(&mut handler, key.clone(), value);
 
         let header = create_header(binary::Command::Increment, &key);
         let request = binary_codec::BinaryRequest::Increment(binary::IncrementRequest {
@@ -820,10 +967,10 @@ mod tests {
 
     #[test]
     fn increment_quiet_should_increment_value() {
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = Bytes::from("counter");
         let value = from_string("100");
-        insert_value(&handler, key.clone(), value);
+        insert_value(&mut handler, key.clone(), value);
 
         let header = create_header(binary::Command::IncrementQuiet, &key);
         let request = binary_codec::BinaryRequest::IncrementQuiet(binary::IncrementRequest {
@@ -835,11 +982,10 @@ mod tests {
         });
 
         let result = handler.handle_request(request);
-        match result {
-            Some(_resp) => unreachable!(),
-            None => {}
+        if let Some(_resp) = result {
+            unreachable!()
         }
-        let incremented_value = get_value(&handler, key.clone());
+        let incremented_value = get_value(&mut handler, key.clone());
         let expected_value = from_string("101");
         assert_eq!(incremented_value[..], expected_value[..]);
     }
@@ -847,7 +993,7 @@ mod tests {
     #[test]
     fn decrement_request_should_return_cas() {
         const EXPECTED_VALUE: u64 = 1;
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = Bytes::from("counter");
         let header = create_header(binary::Command::Decrement, &key);
         let request = binary_codec::BinaryRequest::Decrement(binary::DecrementRequest {
@@ -884,10 +1030,10 @@ mod tests {
     #[test]
     fn decrement_request_should_decrement_value() {
         const EXPECTED_VALUE: u64 = 99;
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = Bytes::from("counter");
         let value = from_string("100");
-        insert_value(&handler, key.clone(), value);
+        insert_value(&mut handler, key.clone(), value);
 
         let header = create_header(binary::Command::Decrement, &key);
         let request = binary_codec::BinaryRequest::Decrement(binary::DecrementRequest {
@@ -923,10 +1069,10 @@ mod tests {
 
     #[test]
     fn decrement_quiet_should_increment_value() {
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = Bytes::from("counter");
         let value = from_string("100");
-        insert_value(&handler, key.clone(), value);
+        insert_value(&mut handler, key.clone(), value);
 
         let header = create_header(binary::Command::DecrementQuiet, &key);
         let request = binary_codec::BinaryRequest::DecrementQuiet(binary::DecrementRequest {
@@ -938,18 +1084,17 @@ mod tests {
         });
 
         let result = handler.handle_request(request);
-        match result {
-            Some(_resp) => unreachable!(),
-            None => {}
+        if let Some(_resp) = result {
+            unreachable!()
         }
-        let dec_value = get_value(&handler, key.clone());
+        let dec_value = get_value(&mut handler, key.clone());
         let expected_value = from_string("99");
         assert_eq!(dec_value[..], expected_value[..]);
     }
 
     #[test]
     fn increment_request_should_error_when_expiration_is_ffffffff() {
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = Bytes::from("counter");
         let header = create_header(binary::Command::Increment, &key);
         let request = binary_codec::BinaryRequest::Increment(binary::IncrementRequest {
@@ -984,7 +1129,7 @@ mod tests {
 
     #[test]
     fn decrement_request_should_error_when_expiration_is_ffffffff() {
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = Bytes::from("counter");
         let header = create_header(binary::Command::Decrement, &key);
         let request = binary_codec::BinaryRequest::Decrement(binary::DecrementRequest {
@@ -1019,11 +1164,11 @@ mod tests {
 
     #[test]
     fn flush_should_remove_all() {
-        let handler = create_handler();
+        let mut handler = create_handler();
         let value = from_string("test value");
         for key_suffix in 0..100 {
             let key = Bytes::from(String::from("test_key") + &key_suffix.to_string());
-            insert_value(&handler, key.clone(), value.clone());
+            insert_value(&mut handler, key.clone(), value.clone());
         }
 
         let key = String::from("").into_bytes();
@@ -1048,10 +1193,10 @@ mod tests {
 
     #[test]
     fn delete_should_remove_from_store() {
-        let handler = create_handler();
+        let mut handler = create_handler();
         let value = from_string("test value");
         let key = Bytes::from("test_key");
-        insert_value(&handler, key.clone(), value.clone());
+        insert_value(&mut handler, key.clone(), value.clone());
 
         let header = create_header(binary::Command::Delete, &key);
         let request = binary_codec::BinaryRequest::Delete(binary::DeleteRequest {
@@ -1071,9 +1216,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn delete_should_return_key_exists_if_cas_doesnt_match() {
+        let mut handler = create_handler();
+        let key = Bytes::from("test_key");
+        insert_value(&mut handler, key.clone(), from_string("test value"));
+
+        let mut header = create_header(binary::Command::Delete, &key);
+        header.cas = 0xDEAD;
+        let request = binary_codec::BinaryRequest::Delete(binary::DeleteRequest {
+            header,
+            key: key.clone(),
+        });
+        match handler.handle_request(request) {
+            Some(binary_codec::BinaryResponse::Error(response)) => {
+                assert_eq!(
+                    response.header.status,
+                    binary::ResponseStatus::KeyExists as u16
+                );
+            }
+            _ => unreachable!(),
+        }
+    }
+
     #[test]
     fn delete_should_return_error_if_not_exists() {
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = Bytes::from("test_key");
 
         let header = create_header(binary::Command::DeleteQuiet, &key);
@@ -1102,9 +1270,202 @@ mod tests {
         }
     }
 
+    fn create_authenticated_handler(name: &str) -> (BinaryHandler, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!(
+            "memcrs-handler-users-{}-{}",
+            name,
+            std::process::id()
+        ));
+        std::fs::write(&path, "alice:secret\n").unwrap();
+        let authenticator = SaslAuthenticator::from_file(&path).unwrap();
+        let handler = create_handler().with_authenticator(Arc::new(authenticator));
+        (handler, path)
+    }
+
+    fn sasl_auth_request(data: &'static [u8]) -> binary_codec::BinaryRequest {
+        let mechanism = Bytes::from(PLAIN_MECHANISM);
+        let mut header = create_header(binary::Command::SaslAuth, &mechanism);
+        header.body_length = (mechanism.len() + data.len()) as u32;
+        binary_codec::BinaryRequest::SaslAuth(binary::SaslAuthRequest {
+            header,
+            mechanism,
+            data: Bytes::from(data),
+        })
+    }
+
+    fn create_noop_request() -> binary_codec::BinaryRequest {
+        let header = create_header(binary::Command::Noop, &[]);
+        binary_codec::BinaryRequest::Noop(binary::NoopRequest { header })
+    }
+
+    fn response_status(response: Option<binary_codec::BinaryResponse>) -> u16 {
+        response.unwrap().get_header().status
+    }
+
+    #[test]
+    fn requests_should_require_authentication_if_authenticator_is_set() {
+        let (mut handler, path) = create_authenticated_handler("required");
+        assert_eq!(
+            response_status(handler.handle_request(create_noop_request())),
+            binary::ResponseStatus::AuthenticationError as u16
+        );
+
+        let header = create_header(binary::Command::SaslListMechs, &[]);
+        let request =
+            binary_codec::BinaryRequest::SaslListMechs(binary::SaslListMechsRequest { header });
+        match handler.handle_request(request) {
+            Some(binary_codec::BinaryResponse::SaslListMechs(response)) => {
+                assert_eq!(response.mechanisms, "PLAIN");
+                assert_eq!(response.header.body_length, 5);
+            }
+            _ => unreachable!(),
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn sasl_auth_should_authenticate_connection_with_valid_credentials() {
+        let (mut handler, path) = create_authenticated_handler("auth");
+        assert_eq!(
+            response_status(handler.handle_request(sasl_auth_request(b"\0alice\0wrong"))),
+            binary::ResponseStatus::AuthenticationError as u16
+        );
+        assert_eq!(
+            response_status(handler.handle_request(create_noop_request())),
+            binary::ResponseStatus::AuthenticationError as u16
+        );
+
+        match handler.handle_request(sasl_auth_request(b"\0alice\0secret")) {
+            Some(binary_codec::BinaryResponse::SaslAuth(response)) => {
+                assert_eq!(
+                    response.header.status,
+                    binary::ResponseStatus::Success as u16
+                );
+                assert_eq!(response.message, "Authenticated");
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(
+            response_status(handler.handle_request(create_noop_request())),
+            binary::ResponseStatus::Success as u16
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn sasl_requests_should_be_unknown_without_authenticator() {
+        let mut handler = create_handler();
+        assert_eq!(
+            response_status(handler.handle_request(sasl_auth_request(b"\0alice\0secret"))),
+            CacheError::UnkownCommand.code()
+        );
+        assert_eq!(
+            response_status(handler.handle_request(create_noop_request())),
+            binary::ResponseStatus::Success as u16
+        );
+    }
+
+    #[test]
+    fn touch_request_should_return_not_found_when_not_exists() {
+        let mut handler = create_handler();
+        let key = Bytes::from("test_key");
+        let header = create_header(binary::Command::Touch, &key);
+        let request = binary_codec::BinaryRequest::Touch(binary::TouchRequest {
+            header,
+            expiration: 100,
+            key,
+        });
+
+        let result = handler.handle_request(request);
+        match result {
+            Some(binary_codec::BinaryResponse::Error(response)) => {
+                assert_eq!(
+                    response.header.status,
+                    binary::ResponseStatus::KeyNotExists as u16
+                );
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn touch_request_should_return_header_only_response() {
+        let mut handler = create_handler();
+        let key = Bytes::from("test_key");
+        insert_value(&mut handler, key.clone(), from_string("test value"));
+
+        let header = create_header(binary::Command::Touch, &key);
+        let request = binary_codec::BinaryRequest::Touch(binary::TouchRequest {
+            header,
+            expiration: 100,
+            key: key.clone(),
+        });
+
+        let result = handler.handle_request(request);
+        match result {
+            Some(binary_codec::BinaryResponse::Touch(response)) => {
+                check_header(&response.header, binary::Command::Touch, 0, 0, 0, 0, 0);
+            }
+            _ => unreachable!(),
+        }
+        let record = handler.storage.get(&key).unwrap();
+        assert_eq!(record.header.time_to_live, 100_000);
+    }
+
+    #[test]
+    fn get_and_touch_key_request_should_return_key_and_record() {
+        let mut handler = create_handler();
+        let key = Bytes::from("test_key");
+        let value = from_string("test value");
+        insert_value(&mut handler, key.clone(), value.clone());
+
+        let header = create_header(binary::Command::GetAndTouchKey, &key);
+        let request = binary_codec::BinaryRequest::GetAndTouchKey(binary::GetAndTouchRequest {
+            header,
+            expiration: 100,
+            key: key.clone(),
+        });
+
+        let result = handler.handle_request(request);
+        match result {
+            Some(binary_codec::BinaryResponse::GetAndTouch(response)) => {
+                assert_ne!(response.header.cas, 0);
+                check_header(
+                    &response.header,
+                    binary::Command::GetAndTouchKey,
+                    key.len() as u16,
+                    EXTRAS_LENGTH,
+                    0,
+                    0,
+                    key.len() as u32 + value.len() as u32 + EXTRAS_LENGTH as u32,
+                );
+                assert_eq!(response.key[..], key[..]);
+                assert_eq!(response.value[..], value[..]);
+            }
+            _ => unreachable!(),
+        }
+        let record = handler.storage.get(&key).unwrap();
+        assert_eq!(record.header.time_to_live, 100_000);
+    }
+
+    #[test]
+    fn get_and_touch_quiet_request_should_return_none_when_not_exists() {
+        let mut handler = create_handler();
+        let key = Bytes::from("test_key");
+        let header = create_header(binary::Command::GetAndTouchQuiet, &key);
+        let request = binary_codec::BinaryRequest::GetAndTouchQuietly(binary::GetAndTouchRequest {
+            header,
+            expiration: 100,
+            key,
+        });
+
+        let result = handler.handle_request(request);
+        assert!(result.is_none());
+    }
+
     #[test]
     fn noop_request() {
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = String::from("").into_bytes();
 
         let header = create_header(binary::Command::Noop, &key);
@@ -1124,7 +1485,7 @@ mod tests {
 
     #[test]
     fn quit_request() {
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = String::from("").into_bytes();
 
         let header = create_header(binary::Command::Quit, &key);
@@ -1144,15 +1505,14 @@ mod tests {
 
     #[test]
     fn quit_quiet_request() {
-        let handler = create_handler();
+        let mut handler = create_handler();
         let key = String::from("").into_bytes();
 
         let header = create_header(binary::Command::QuitQuiet, &key);
         let request = binary_codec::BinaryRequest::QuitQuietly(binary::QuitRequest { header });
         let result = handler.handle_request(request);
-        match result {
-            Some(_resp) => unreachable!(),
-            None => {}
+        if let Some(_resp) = result {
+            unreachable!()
         }
     }
 }
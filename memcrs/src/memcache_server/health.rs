@@ -0,0 +1,254 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde_derive::Serialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io;
+use tokio::net::TcpListener;
+
+use super::server_state::ServerState;
+use crate::cache::cache::{Cache, ShardStats, ValueSizePercentiles};
+
+// buckets from 1 byte up to 1MiB, default item size limit
+const DEFAULT_SIZE_BUCKETS: usize = 21;
+
+/// Data exposed by health check endpoints
+pub struct HealthState {
+    server: Arc<ServerState>,
+    store: Arc<dyn Cache + Send + Sync>,
+    // number of power of two buckets of a value size histogram
+    size_buckets: usize,
+}
+
+impl HealthState {
+    pub fn new(server: Arc<ServerState>, store: Arc<dyn Cache + Send + Sync>) -> HealthState {
+        HealthState {
+            server,
+            store,
+            size_buckets: DEFAULT_SIZE_BUCKETS,
+        }
+    }
+
+    /// Value size histogram ends with a bucket holding item_size_limit
+    pub fn with_item_size_limit(mut self, item_size_limit: u64) -> HealthState {
+        self.size_buckets =
+            item_size_limit.max(1).next_power_of_two().trailing_zeros() as usize + 1;
+        self
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct HealthResponse {
+    status: &'static str,
+    connections: u32,
+    memory_used_bytes: u64,
+}
+
+type HealthResult = (StatusCode, Json<HealthResponse>);
+
+fn health_response(state: &HealthState, healthy: bool) -> HealthResult {
+    let (status_code, status) = if healthy {
+        (StatusCode::OK, "ok")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    };
+    let response = HealthResponse {
+        status,
+        connections: state.server.current_connections(),
+        memory_used_bytes: state.store.memory_used(),
+    };
+    (status_code, Json(response))
+}
+
+/// Process is running
+async fn live(State(state): State<Arc<HealthState>>) -> HealthResult {
+    health_response(&state, true)
+}
+
+/// Server accepts connections, connection limit is not reached
+/// and server is not shutting down
+async fn ready(State(state): State<Arc<HealthState>>) -> HealthResult {
+    let ready = state.server.is_started()
+        && !state.server.is_connection_limit_reached()
+        && !state.server.is_shutting_down();
+    health_response(&state, ready)
+}
+
+/// Server finished starting up
+async fn startup(State(state): State<Arc<HealthState>>) -> HealthResult {
+    health_response(&state, state.server.is_started())
+}
+
+/// Records and bytes stored in each store shard, used to find hot shards
+async fn shard_distribution(State(state): State<Arc<HealthState>>) -> Json<Vec<ShardStats>> {
+    Json(state.store.per_shard_stats())
+}
+
+#[derive(Serialize, Debug)]
+struct SizeBucket {
+    size_upper_bound: u64,
+    count: u64,
+}
+
+#[derive(Serialize, Debug)]
+struct SizesResponse {
+    buckets: Vec<SizeBucket>,
+    #[serde(flatten)]
+    percentiles: ValueSizePercentiles,
+}
+
+/// Histogram and percentiles of stored value sizes
+async fn sizes(State(state): State<Arc<HealthState>>) -> Json<SizesResponse> {
+    let buckets = state
+        .store
+        .stats_histogram(state.size_buckets)
+        .into_iter()
+        .map(|(size_upper_bound, count)| SizeBucket {
+            size_upper_bound,
+            count,
+        })
+        .collect();
+    Json(SizesResponse {
+        buckets,
+        percentiles: state.store.value_size_percentiles(),
+    })
+}
+
+pub fn router(state: Arc<HealthState>) -> Router {
+    Router::new()
+        .route("/health/live", get(live))
+        .route("/health/ready", get(ready))
+        .route("/health/startup", get(startup))
+        .route("/stats/shard-distribution", get(shard_distribution))
+        .route("/stats/sizes", get(sizes))
+        .with_state(state)
+}
+
+/// Serves health check endpoints over HTTP on a given address
+pub async fn serve(addr: SocketAddr, state: Arc<HealthState>) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    axum::serve(listener, router(state)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::cache::Record;
+    use crate::memory_store::store::MemoryStore;
+    use crate::mock::mock_server::MockSystemTimer;
+    use axum::body::{to_bytes, Body};
+    use axum::http::Request;
+    use bytes::Bytes;
+    use tower::ServiceExt;
+
+    fn create_state(connection_limit: u32) -> (Arc<ServerState>, Arc<HealthState>) {
+        let server = Arc::new(ServerState::new(connection_limit));
+        let store = MemoryStore::new_shared(Arc::new(MockSystemTimer::new()));
+        let health = Arc::new(HealthState::new(server.clone(), store));
+        (server, health)
+    }
+
+    async fn get_health(state: Arc<HealthState>, uri: &str) -> (StatusCode, serde_json::Value) {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let response = router(state).oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn live_should_always_return_ok() {
+        let (_server, state) = create_state(1);
+        let (status, body) = get_health(state, "/health/live").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            serde_json::json!({"status": "ok", "connections": 0, "memory_used_bytes": 0})
+        );
+    }
+
+    #[tokio::test]
+    async fn ready_should_return_unavailable_when_connection_limit_is_reached() {
+        let (server, state) = create_state(1);
+        let (status, _body) = get_health(state.clone(), "/health/ready").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+        server.set_started();
+        let (status, _body) = get_health(state.clone(), "/health/ready").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let _connection = server.connection_opened();
+        let (status, body) = get_health(state, "/health/ready").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "unavailable");
+        assert_eq!(body["connections"], 1);
+    }
+
+    #[tokio::test]
+    async fn shard_distribution_should_return_stats_of_each_shard() {
+        let (_server, state) = create_state(1);
+        let record = Record::new(Bytes::from("value"), 0, 0, 0);
+        state.store.set(Bytes::from("key"), record).unwrap();
+
+        let (status, body) = get_health(state.clone(), "/stats/shard-distribution").await;
+        assert_eq!(status, StatusCode::OK);
+        let shards = body.as_array().unwrap();
+        assert_eq!(shards.len(), state.store.per_shard_stats().len());
+        let items: u64 = shards
+            .iter()
+            .map(|shard| shard["item_count"].as_u64().unwrap())
+            .sum();
+        assert_eq!(items, 1);
+    }
+
+    #[tokio::test]
+    async fn sizes_should_return_histogram_up_to_item_size_limit() {
+        let server = Arc::new(ServerState::new(1));
+        let store = MemoryStore::new_shared(Arc::new(MockSystemTimer::new()));
+        let state = Arc::new(HealthState::new(server, store).with_item_size_limit(1000));
+        for size in [0, 3, 4, 1000] {
+            let record = Record::new(Bytes::from(vec![b'x'; size]), 0, 0, 0);
+            state
+                .store
+                .set(Bytes::from(format!("key{}", size)), record)
+                .unwrap();
+        }
+
+        let (status, body) = get_health(state, "/stats/sizes").await;
+        assert_eq!(status, StatusCode::OK);
+        let buckets = body["buckets"].as_array().unwrap();
+        assert_eq!(buckets.len(), 11);
+        assert_eq!(buckets[10]["size_upper_bound"], 1024);
+        let counts: Vec<u64> = buckets
+            .iter()
+            .map(|bucket| bucket["count"].as_u64().unwrap())
+            .collect();
+        assert_eq!(counts, vec![1, 0, 2, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(body["p50_value_size"], 3);
+        assert_eq!(body["p99_value_size"], 4);
+    }
+
+    #[tokio::test]
+    async fn ready_should_return_unavailable_during_shutdown() {
+        let (server, state) = create_state(1);
+        server.set_started();
+        server.shutdown();
+        let (status, _body) = get_health(state.clone(), "/health/ready").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        let (status, _body) = get_health(state, "/health/live").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn startup_should_return_ok_once_server_is_started() {
+        let (server, state) = create_state(1);
+        let (status, _body) = get_health(state.clone(), "/health/startup").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+        server.set_started();
+        let (status, _body) = get_health(state, "/health/startup").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
@@ -1,27 +1,65 @@
-use socket2::{Domain, SockAddr, Socket, Type};
-use std::net::ToSocketAddrs;
+use futures::future::BoxFuture;
+use socket2::{Domain, SockAddr, SockRef, Socket, Type};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use tokio::io;
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, TcpStream, UnixListener};
 use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 
-use tracing::{debug, error};
+use tracing::{debug, error, info, warn};
 
 //use tracing_attributes::instrument;
 
+use super::auth::SaslAuthenticator;
 use super::client_handler;
+use super::server_state::ServerState;
+#[cfg(feature = "tls")]
+use super::tls::TlsAcceptor;
 use crate::cache::cache::Cache;
+use crate::memcache::stats::Stats;
 use crate::memcache::store as storage;
+use crate::protocol::client_stream::BoxedClientStream;
+
+/// Protocols served to clients
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Protocol {
+    /// text protocol only
+    Text,
+    /// binary protocol only, requires binary-protocol feature
+    Binary,
+    /// protocol of each connection is detected by its first byte
+    Auto,
+}
 
 #[derive(Clone, Copy)]
 pub struct MemcacheServerConfig {
+    // TLS handshake has to be completed in this time
+    #[cfg_attr(not(feature = "tls"), allow(dead_code))]
     timeout_secs: u32,
+    idle_timeout_secs: u32,
     connection_limit: u32,
     item_memory_limit: u32,
     listen_backlog: u32,
+    max_pipeline_depth: u32,
+    shutdown_timeout_secs: u32,
+    protocol: Protocol,
+    tcp_nodelay: bool,
+    tcp_recv_buffer_size: Option<usize>,
+    tcp_send_buffer_size: Option<usize>,
+    allow_unauthenticated_text: bool,
+    slow_log_threshold_us: u64,
 }
 
+/// Default number of pipelined responses queued before they are flushed
+pub const DEFAULT_MAX_PIPELINE_DEPTH: u32 = 128;
+
+/// Default time connected clients have to finish requests on shutdown
+pub const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u32 = 5;
+
 impl MemcacheServerConfig {
     pub fn new(
         timeout_secs: u32,
@@ -31,17 +69,248 @@ impl MemcacheServerConfig {
     ) -> Self {
         MemcacheServerConfig {
             timeout_secs,
+            idle_timeout_secs: 0,
             connection_limit,
             item_memory_limit,
             listen_backlog,
+            max_pipeline_depth: DEFAULT_MAX_PIPELINE_DEPTH,
+            shutdown_timeout_secs: DEFAULT_SHUTDOWN_TIMEOUT_SECS,
+            protocol: Protocol::Text,
+            tcp_nodelay: true,
+            tcp_recv_buffer_size: None,
+            tcp_send_buffer_size: None,
+            allow_unauthenticated_text: false,
+            slow_log_threshold_us: 0,
+        }
+    }
+
+    /// On shutdown connections which are still handling requests after
+    /// shutdown_timeout_secs are aborted
+    pub fn with_shutdown_timeout_secs(mut self, shutdown_timeout_secs: u32) -> Self {
+        self.shutdown_timeout_secs = shutdown_timeout_secs;
+        self
+    }
+
+    /// Clients which send commands of other protocols are disconnected,
+    /// only text protocol is served by default
+    pub fn with_protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Serves text protocol clients even if binary protocol clients
+    /// have to authenticate, they are disconnected by default
+    pub fn with_unauthenticated_text(mut self, allow_unauthenticated_text: bool) -> Self {
+        self.allow_unauthenticated_text = allow_unauthenticated_text;
+        self
+    }
+
+    /// Disables Nagle's algorithm on client sockets, enabled by default
+    pub fn with_tcp_nodelay(mut self, tcp_nodelay: bool) -> Self {
+        self.tcp_nodelay = tcp_nodelay;
+        self
+    }
+
+    /// Sets SO_RCVBUF and SO_SNDBUF of client sockets, OS defaults are used if None
+    pub fn with_tcp_buffer_sizes(
+        mut self,
+        recv_buffer_size: Option<usize>,
+        send_buffer_size: Option<usize>,
+    ) -> Self {
+        self.tcp_recv_buffer_size = recv_buffer_size;
+        self.tcp_send_buffer_size = send_buffer_size;
+        self
+    }
+
+    /// Disconnects clients which don't send any data for idle_timeout_secs,
+    /// the time is counted from a last read, 0 disables it and is the default
+    pub fn with_idle_timeout_secs(mut self, idle_timeout_secs: u32) -> Self {
+        self.idle_timeout_secs = idle_timeout_secs;
+        self
+    }
+
+    /// Logs requests which take longer than slow_log_threshold_us to handle,
+    /// 0 disables it and is the default
+    pub fn with_slow_log_threshold_us(mut self, slow_log_threshold_us: u64) -> Self {
+        self.slow_log_threshold_us = slow_log_threshold_us;
+        self
+    }
+
+    /// Responses are flushed after max_pipeline_depth pipelined requests
+    pub fn with_max_pipeline_depth(mut self, max_pipeline_depth: u32) -> Self {
+        self.max_pipeline_depth = max_pipeline_depth;
+        self
+    }
+}
+fn bind_listener<A: ToSocketAddrs>(addr: A, backlog: u32) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::IPV4, Type::STREAM, None)?;
+    socket.set_reuse_address(true)?;
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    let addrs_iter = addr.to_socket_addrs()?;
+    for socket_addr in addrs_iter {
+        debug!("Binding to addr: {:?}", socket_addr);
+        let sock_addr = SockAddr::from(socket_addr);
+        let res = socket.bind(&sock_addr);
+        if let Err(err) = res {
+            error!("Can't bind to: {:?}, err {:?}", sock_addr, err);
+            return Err(err);
+        }
+    }
+
+    // listen takes a signed backlog, values which don't fit are capped
+    let backlog = i32::try_from(backlog).unwrap_or(i32::MAX);
+    if let Err(err) = socket.listen(backlog) {
+        error!("Listen error: {:?}", err);
+        return Err(err);
+    }
+    log_effective_backlog(backlog);
+
+    let std_listener: std::net::TcpListener = socket.into();
+    TcpListener::from_std(std_listener)
+}
+
+// there is no getsockopt for a listen backlog, on Linux kernel silently
+// caps it to net.core.somaxconn
+#[cfg(target_os = "linux")]
+fn log_effective_backlog(backlog: i32) {
+    let somaxconn = std::fs::read_to_string("/proc/sys/net/core/somaxconn")
+        .ok()
+        .and_then(|value| value.trim().parse::<i32>().ok());
+    match somaxconn {
+        Some(somaxconn) if somaxconn < backlog => warn!(
+            "Listen backlog {} requested, but it is capped by net.core.somaxconn to {}",
+            backlog, somaxconn
+        ),
+        _ => info!("Listen backlog: {}", backlog),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn log_effective_backlog(backlog: i32) {
+    info!("Listen backlog: {}", backlog);
+}
+
+/// Unix domain socket listener, its file is removed once it is dropped,
+/// so clients don't connect to a path of a stopped server
+struct UnixSocket {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            warn!("Cannot remove socket file {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+fn bind_unix_listener(path: &Path, mode: u32) -> io::Result<UnixSocket> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    // a file left by a server which wasn't stopped cleanly is replaced,
+    // a socket of a running server isn't
+    let is_socket = std::fs::symlink_metadata(path)
+        .map(|metadata| metadata.file_type().is_socket())
+        .unwrap_or(false);
+    if is_socket {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is used by a running server", path.display()),
+            ));
+        }
+        debug!("Removing stale socket file: {}", path.display());
+        std::fs::remove_file(path)?;
+    }
+    let socket = UnixSocket {
+        listener: UnixListener::bind(path)?,
+        path: path.to_path_buf(),
+    };
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(socket)
+}
+
+// listener of connections served by the same protocol handlers
+enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixSocket),
+}
+
+// applies socket options of a config to an accepted client socket
+fn configure_socket(config: &MemcacheServerConfig, socket: &TcpStream) -> io::Result<()> {
+    socket.set_nodelay(config.tcp_nodelay)?;
+    let socket = SockRef::from(socket);
+    if let Some(size) = config.tcp_recv_buffer_size {
+        socket.set_recv_buffer_size(size)?;
+        warn_if_not_honored("receive", size, socket.recv_buffer_size()?);
+    }
+    if let Some(size) = config.tcp_send_buffer_size {
+        socket.set_send_buffer_size(size)?;
+        warn_if_not_honored("send", size, socket.send_buffer_size()?);
+    }
+    Ok(())
+}
+
+// Linux doubles requested size for bookkeeping overhead, so only a smaller
+// buffer, i.e. capped by net.core.rmem_max, means the size wasn't honored
+fn warn_if_not_honored(buffer: &str, requested: usize, actual: usize) {
+    if actual < requested {
+        warn!(
+            "TCP {} buffer size {} requested, but OS set {}",
+            buffer, requested, actual
+        );
+    } else if actual != requested {
+        debug!(
+            "TCP {} buffer size {} requested, OS set {}",
+            buffer, requested, actual
+        );
+    }
+}
+
+// handshake errors are caused by clients, i.e. by a certificate
+// which isn't trusted, so they don't stop a server
+#[cfg(feature = "tls")]
+async fn tls_handshake(
+    acceptor: Arc<TlsAcceptor>,
+    socket: TcpStream,
+    peer_addr: SocketAddr,
+    timeout: Duration,
+) -> Option<BoxedClientStream> {
+    match tokio::time::timeout(timeout, acceptor.accept(socket)).await {
+        Ok(Ok(stream)) => Some(Box::new(stream)),
+        Ok(Err(err)) => {
+            warn!("TLS handshake with {} failed: {}", peer_addr, err);
+            None
+        }
+        Err(_) => {
+            warn!("TLS handshake with {} timed out", peer_addr);
+            None
         }
     }
 }
+
+// settings of stats settings command are kept with counters
+fn settings_store(
+    store: Arc<dyn Cache + Send + Sync>,
+    config: &MemcacheServerConfig,
+    counters: Arc<Stats>,
+) -> storage::MemcStore {
+    Stats::gauge(&counters.idle_timeout, config.idle_timeout_secs as u64);
+    storage::MemcStore::new(store).with_stats(counters)
+}
+
 #[derive(Clone)]
 pub struct MemcacheTcpServer {
     storage: Arc<storage::MemcStore>,
     limit_connections: Arc<Semaphore>,
+    state: Arc<ServerState>,
     config: MemcacheServerConfig,
+    // socket bound by another process, i.e. passed by systemd
+    inherited_listener: Option<Arc<std::net::TcpListener>>,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<TlsAcceptor>>,
+    authenticator: Option<Arc<SaslAuthenticator>>,
 }
 
 impl MemcacheTcpServer {
@@ -50,78 +319,321 @@ impl MemcacheTcpServer {
         store: Arc<dyn Cache + Send + Sync>,
     ) -> MemcacheTcpServer {
         MemcacheTcpServer {
-            storage: Arc::new(storage::MemcStore::new(store)),
+            storage: Arc::new(settings_store(store, &config, Arc::new(Stats::new()))),
             limit_connections: Arc::new(Semaphore::new(config.connection_limit as usize)),
+            state: Arc::new(ServerState::new(config.connection_limit)),
             config,
+            inherited_listener: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+            authenticator: None,
+        }
+    }
+
+    /// Shares counters of stats command, i.e. between listeners
+    pub fn with_stats(mut self, counters: Arc<Stats>) -> Self {
+        self.storage = Arc::new(settings_store(self.storage.cache(), &self.config, counters));
+        self
+    }
+
+    /// Shares server state i.e. between listeners and health check endpoint
+    pub fn with_state(mut self, state: Arc<ServerState>) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// Accepts connections on an already listening socket instead of binding
+    /// to an address passed to run, listeners of all threads may share it
+    pub fn with_listener(mut self, listener: Arc<std::net::TcpListener>) -> Self {
+        self.inherited_listener = Some(listener);
+        self
+    }
+
+    /// Clients have to complete a TLS handshake before sending requests
+    #[cfg(feature = "tls")]
+    pub fn with_tls(mut self, acceptor: Arc<TlsAcceptor>) -> Self {
+        self.tls = Some(acceptor);
+        self
+    }
+
+    /// Binary protocol clients have to authenticate with SASL PLAIN
+    pub fn with_authenticator(mut self, authenticator: Arc<SaslAuthenticator>) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    // a TLS handshake is completed in a client task,
+    // so a slow client doesn't delay accepting other connections
+    #[cfg_attr(not(feature = "tls"), allow(unused_variables))]
+    fn client_stream(
+        &self,
+        socket: TcpStream,
+        peer_addr: SocketAddr,
+    ) -> BoxFuture<'static, Option<BoxedClientStream>> {
+        #[cfg(feature = "tls")]
+        if let Some(acceptor) = &self.tls {
+            let timeout = Duration::from_secs(self.config.timeout_secs as u64);
+            return Box::pin(tls_handshake(
+                Arc::clone(acceptor),
+                socket,
+                peer_addr,
+                timeout,
+            ));
+        }
+        Box::pin(async move { Some(Box::new(socket) as BoxedClientStream) })
+    }
+
+    // returns a stream of an accepted client, which is ready once
+    // a TLS handshake is completed, and an address of a client
+    async fn accept(
+        &self,
+        listener: &Listener,
+    ) -> io::Result<(BoxFuture<'static, Option<BoxedClientStream>>, String)> {
+        match listener {
+            Listener::Tcp(listener) => {
+                let (socket, peer_addr) = listener.accept().await?;
+                configure_socket(&self.config, &socket)?;
+                SockRef::from(&socket).set_linger(None)?;
+                Ok((self.client_stream(socket, peer_addr), peer_addr.to_string()))
+            }
+            // local clients are trusted, so they aren't served over TLS
+            Listener::Unix(unix_socket) => {
+                let (socket, _) = unix_socket.listener.accept().await?;
+                let client_stream: BoxedClientStream = Box::new(socket);
+                Ok((
+                    Box::pin(async move { Some(client_stream) }),
+                    unix_socket.path.display().to_string(),
+                ))
+            }
         }
     }
 
+    /// Accepts connections until shutdown is started in server state,
+    /// then waits for connected clients to finish their requests
     pub async fn run<A: ToSocketAddrs>(&mut self, addr: A) -> io::Result<()> {
-        let listener = self.get_tcp_listener(addr)?;
+        let listener = Listener::Tcp(self.get_tcp_listener(addr)?);
+        self.serve(listener).await
+    }
+
+    /// Accepts connections on a Unix domain socket created at a given path
+    /// with given permissions, the same way as run, a socket file is
+    /// removed once shutdown is started
+    pub async fn run_unix<P: AsRef<Path>>(&mut self, path: P, mode: u32) -> io::Result<()> {
+        let listener = Listener::Unix(bind_unix_listener(path.as_ref(), mode)?);
+        self.serve(listener).await
+    }
+
+    async fn serve(&mut self, listener: Listener) -> io::Result<()> {
+        let state = Arc::clone(&self.state);
+        let mut clients = JoinSet::new();
+        self.state.set_started();
         loop {
+            // a connection is accepted once a client slot is free, so
+            // clients over the limit wait in a listen backlog, shutdown
+            // and finished clients are handled while waiting for a slot
+            let permit = tokio::select! {
+                permit = Arc::clone(&self.limit_connections).acquire_owned() => permit.unwrap(),
+                Some(_) = clients.join_next(), if !clients.is_empty() => continue,
+                _ = state.wait_for_shutdown() => break,
+            };
             tokio::select! {
-                connection = listener.accept() => {
+                connection = self.accept(&listener) => {
                     match connection {
-                        Ok((socket, addr)) => {
-                            let peer_addr = addr;
-                            socket.set_nodelay(true)?;
-                            socket.set_linger(None)?;
+                        Ok((client_stream, peer_addr)) => {
                             let mut client = client_handler::Client::new(
                                 Arc::clone(&self.storage),
-                                socket,
                                 peer_addr,
                                 self.get_client_config(),
-                                Arc::clone(&self.limit_connections)
+                                Arc::clone(&self.limit_connections),
+                                Arc::clone(&self.state),
                             );
 
-                            self.limit_connections.acquire().await.unwrap().forget();
+                            // a client gives its slot back when it is dropped
+                            permit.forget();
                             // Like with other small servers, we'll `spawn` this client to ensure it
                             // runs concurrently with all other clients. The `move` keyword is used
                             // here to move ownership of our store handle into the async closure.
-                            tokio::spawn(async move { client.handle().await });
+                            clients.spawn(async move {
+                                if let Some(socket) = client_stream.await {
+                                    client.handle(socket).await
+                                }
+                            });
                         },
                         Err(err) => {
                             error!("Accept error: {}", err);
                         }
                     }
                 }
+                Some(_) = clients.join_next(), if !clients.is_empty() => {}
+                _ = state.wait_for_shutdown() => break,
             }
         }
+        // stop accepting connections while clients are drained,
+        // a file of a Unix socket is removed
+        drop(listener);
+        self.drain(clients).await;
+        Ok(())
+    }
+
+    async fn drain(&self, mut clients: JoinSet<()>) {
+        let timeout = Duration::from_secs(self.config.shutdown_timeout_secs as u64);
+        debug!("Waiting {:?} for {} clients", timeout, clients.len());
+        let drained = tokio::time::timeout(timeout, async {
+            while clients.join_next().await.is_some() {}
+        })
+        .await;
+        if drained.is_err() {
+            warn!(
+                "{} clients didn't finish within {:?}, aborting",
+                clients.len(),
+                timeout
+            );
+            clients.shutdown().await;
+        }
     }
 
     fn get_tcp_listener<A: ToSocketAddrs>(
         &mut self,
         addr: A,
     ) -> Result<TcpListener, std::io::Error> {
-        let socket = Socket::new(Domain::IPV4, Type::STREAM, None)?;
-        socket.set_reuse_address(true)?;
-        socket.set_reuse_port(true)?;
-        socket.set_nonblocking(true)?;
-        let addrs_iter = addr.to_socket_addrs()?;
-        for socket_addr in addrs_iter {
-            debug!("Binding to addr: {:?}", socket_addr);
-            let sock_addr = SockAddr::from(socket_addr);
-            let res = socket.bind(&sock_addr);
-            if let Err(err) = res {
-                error!("Can't bind to: {:?}, err {:?}", sock_addr, err);
-                return Err(err);
+        match &self.inherited_listener {
+            Some(listener) => {
+                let listener = listener.try_clone()?;
+                listener.set_nonblocking(true)?;
+                TcpListener::from_std(listener)
             }
+            None => bind_listener(addr, self.config.listen_backlog),
         }
-
-        if let Err(err) = socket.listen(self.config.listen_backlog as i32) {
-            error!("Listen error: {:?}", err);
-            return Err(err);
-        }
-
-        let std_listener: std::net::TcpListener = socket.into();
-        TcpListener::from_std(std_listener)
     }
 
     fn get_client_config(&self) -> client_handler::ClientConfig {
         client_handler::ClientConfig {
             item_memory_limit: self.config.item_memory_limit,
-            rx_timeout_secs: self.config.timeout_secs,
-            _wx_timeout_secs: self.config.timeout_secs,
+            idle_timeout_secs: self.config.idle_timeout_secs,
+            max_pipeline_depth: self.config.max_pipeline_depth,
+            protocol: self.config.protocol,
+            authenticator: self.authenticator.clone(),
+            allow_unauthenticated_text: self.config.allow_unauthenticated_text,
+            slow_log_threshold_us: self.config.slow_log_threshold_us,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn accept_configured(config: MemcacheServerConfig) -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (socket, _addr) = listener.accept().await.unwrap();
+        configure_socket(&config, &socket).unwrap();
+        (client, socket)
+    }
+
+    #[tokio::test]
+    async fn listener_should_queue_or_refuse_connections_over_backlog() {
+        let listener = bind_listener("127.0.0.1:0", 1).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connect = || tokio::time::timeout(Duration::from_millis(500), TcpStream::connect(addr));
+        // nothing is accepted, so connections are left in the accept queue
+        let first = connect().await;
+        let second = connect().await;
+        let third = connect().await;
+        assert!(matches!(first, Ok(Ok(_))));
+        for connection in [second, third] {
+            match connection {
+                // queued, Linux keeps backlog + 1 connections
+                Ok(Ok(_)) => {}
+                // refused or SYN dropped until the queue has room
+                Ok(Err(err)) => assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused),
+                Err(_elapsed) => {}
+            }
         }
     }
+
+    #[tokio::test]
+    async fn accepted_socket_should_use_configured_nodelay() {
+        let config = MemcacheServerConfig::new(60, 10, 1024, 10);
+        let (_client, socket) = accept_configured(config).await;
+        assert!(socket.nodelay().unwrap());
+
+        let (_client, socket) = accept_configured(config.with_tcp_nodelay(false)).await;
+        assert!(!socket.nodelay().unwrap());
+    }
+
+    #[tokio::test]
+    async fn accepted_socket_should_use_configured_buffer_sizes() {
+        const BUFFER_SIZE: usize = 64 * 1024;
+        let config = MemcacheServerConfig::new(60, 10, 1024, 10)
+            .with_tcp_buffer_sizes(Some(BUFFER_SIZE), Some(BUFFER_SIZE));
+        let (_client, socket) = accept_configured(config).await;
+        let socket = SockRef::from(&socket);
+        // OS may round a size up, i.e. Linux doubles it
+        assert!(socket.recv_buffer_size().unwrap() >= BUFFER_SIZE);
+        assert!(socket.send_buffer_size().unwrap() >= BUFFER_SIZE);
+    }
+
+    #[tokio::test]
+    async fn unix_listener_should_not_replace_socket_of_running_server() {
+        let path = std::env::temp_dir().join(format!("memcrs-unix-{}.sock", std::process::id()));
+        let running = bind_unix_listener(&path, 0o600).unwrap();
+        let err = bind_unix_listener(&path, 0o600).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        assert!(path.exists());
+        drop(running);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn server_should_accept_connections_on_inherited_listener() {
+        let inherited = Arc::new(std::net::TcpListener::bind("127.0.0.1:0").unwrap());
+        let addr = inherited.local_addr().unwrap();
+        let store = crate::memory_store::store::MemoryStore::new_shared(Arc::new(
+            crate::mock::mock_server::MockSystemTimer::new(),
+        ));
+        let mut server = MemcacheTcpServer::new(MemcacheServerConfig::new(60, 10, 1024, 10), store)
+            .with_listener(inherited);
+        // an address passed to run is ignored
+        let listener = server.get_tcp_listener("127.0.0.1:1").unwrap();
+        let accept = async { listener.accept().await.unwrap() };
+        let (client, (_socket, peer_addr)) = tokio::join!(TcpStream::connect(addr), accept);
+        assert_eq!(client.unwrap().local_addr().unwrap(), peer_addr);
+    }
+
+    #[tokio::test]
+    async fn shutdown_should_not_wait_for_connection_slot() {
+        let inherited = Arc::new(std::net::TcpListener::bind("127.0.0.1:0").unwrap());
+        let addr = inherited.local_addr().unwrap();
+        let store = crate::memory_store::store::MemoryStore::new_shared(Arc::new(
+            crate::mock::mock_server::MockSystemTimer::new(),
+        ));
+        let config = MemcacheServerConfig::new(60, 1, 1024, 10).with_shutdown_timeout_secs(1);
+        let state = Arc::new(ServerState::new(1));
+        let mut server = MemcacheTcpServer::new(config, store)
+            .with_state(Arc::clone(&state))
+            .with_listener(inherited);
+        let server = tokio::spawn(async move { server.run(addr).await });
+
+        // the only slot is taken by a client in the middle of a request,
+        // so it isn't closed on shutdown, a second client waits for a slot
+        let mut first = TcpStream::connect(addr).await.unwrap();
+        first.write_all(b"get foo\r\n").await.unwrap();
+        let mut response = [0u8; 5];
+        first.read_exact(&mut response).await.unwrap();
+        assert_eq!(&response, b"END\r\n");
+        first.write_all(b"get fo").await.unwrap();
+        let _second = TcpStream::connect(addr).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        state.shutdown();
+        tokio::time::timeout(Duration::from_secs(3), server)
+            .await
+            .expect("server should stop after shutdown timeout")
+            .unwrap()
+            .unwrap();
+    }
 }
@@ -0,0 +1,439 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use std::collections::HashMap;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io;
+use tokio::net::UdpSocket;
+use tokio_util::codec::{Decoder, Encoder};
+use tracing::{debug, error, warn};
+
+use super::server_state::ServerState;
+use super::text_handler::TextHandler;
+use crate::cache::cache::Cache;
+use crate::memcache::stats::Stats;
+use crate::memcache::store as storage;
+use crate::protocol::text_codec::{MemcacheTextCodec, TextRequest};
+
+/// Length of a frame header which precedes a payload of each datagram
+const UDP_HEADER_LEN: usize = 8;
+
+/// Largest datagram sent to a client, same as in memcached
+const MAX_DATAGRAM_SIZE: usize = 1400;
+
+/// Default time all datagrams of a request have to arrive in
+pub const DEFAULT_FRAGMENT_TIMEOUT: Duration = Duration::from_secs(1);
+
+// bounds memory held by clients which never send remaining datagrams
+const MAX_PENDING_REQUESTS: usize = 1024;
+
+/// Frame header of memcached UDP protocol, all fields are big endian
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct UdpHeader {
+    request_id: u16,
+    sequence: u16,
+    total: u16,
+}
+
+impl UdpHeader {
+    /// Returns a header and a payload of a datagram,
+    /// None if it is too short or its sequence is out of range
+    fn parse(datagram: &[u8]) -> Option<(UdpHeader, &[u8])> {
+        if datagram.len() < UDP_HEADER_LEN {
+            return None;
+        }
+        let field = |index: usize| u16::from_be_bytes([datagram[index], datagram[index + 1]]);
+        let header = UdpHeader {
+            request_id: field(0),
+            sequence: field(2),
+            total: field(4),
+        };
+        if header.sequence >= header.total {
+            return None;
+        }
+        Some((header, &datagram[UDP_HEADER_LEN..]))
+    }
+
+    fn write(&self, dst: &mut BytesMut) {
+        dst.put_u16(self.request_id);
+        dst.put_u16(self.sequence);
+        dst.put_u16(self.total);
+        // reserved
+        dst.put_u16(0);
+    }
+}
+
+struct PendingRequest {
+    fragments: Vec<Option<Bytes>>,
+    received: usize,
+    started_at: Instant,
+}
+
+/// Collects datagrams of requests split into multiple datagrams,
+/// they may arrive in any order
+struct Reassembler {
+    pending: HashMap<(SocketAddr, u16), PendingRequest>,
+    timeout: Duration,
+}
+
+impl Reassembler {
+    fn new(timeout: Duration) -> Self {
+        Reassembler {
+            pending: HashMap::new(),
+            timeout,
+        }
+    }
+
+    /// Returns a payload of a request once all its datagrams arrived
+    fn add(
+        &mut self,
+        peer: SocketAddr,
+        header: UdpHeader,
+        payload: &[u8],
+        now: Instant,
+    ) -> Option<BytesMut> {
+        if header.total == 1 {
+            return Some(BytesMut::from(payload));
+        }
+        if self.pending.len() >= MAX_PENDING_REQUESTS
+            && !self.pending.contains_key(&(peer, header.request_id))
+        {
+            warn!(
+                "Too many incomplete UDP requests, dropping datagram from {}",
+                peer
+            );
+            return None;
+        }
+        let request = self
+            .pending
+            .entry((peer, header.request_id))
+            .or_insert_with(|| PendingRequest {
+                fragments: vec![None; header.total as usize],
+                received: 0,
+                started_at: now,
+            });
+        // a client reused request id for a request with different length
+        if request.fragments.len() != header.total as usize {
+            self.pending.remove(&(peer, header.request_id));
+            return None;
+        }
+        let fragment = &mut request.fragments[header.sequence as usize];
+        if fragment.is_none() {
+            *fragment = Some(Bytes::copy_from_slice(payload));
+            request.received += 1;
+        }
+        if request.received < request.fragments.len() {
+            return None;
+        }
+        let request = self.pending.remove(&(peer, header.request_id))?;
+        let mut buffer = BytesMut::new();
+        request
+            .fragments
+            .iter()
+            .flatten()
+            .for_each(|fragment| buffer.put_slice(fragment));
+        Some(buffer)
+    }
+
+    /// Drops requests which didn't receive all datagrams within a timeout
+    fn discard_expired(&mut self, now: Instant) -> usize {
+        let before = self.pending.len();
+        let timeout = self.timeout;
+        self.pending
+            .retain(|_, request| now.duration_since(request.started_at) < timeout);
+        before - self.pending.len()
+    }
+}
+
+/// Splits a response into datagrams, each with a frame header
+/// of a request it answers
+fn into_datagrams(request_id: u16, response: &[u8]) -> Option<Vec<Bytes>> {
+    let chunks: Vec<&[u8]> = response
+        .chunks(MAX_DATAGRAM_SIZE - UDP_HEADER_LEN)
+        .collect();
+    let total = u16::try_from(chunks.len()).ok()?;
+    let datagrams = chunks
+        .into_iter()
+        .enumerate()
+        .map(|(sequence, chunk)| {
+            let mut datagram = BytesMut::with_capacity(UDP_HEADER_LEN + chunk.len());
+            UdpHeader {
+                request_id,
+                sequence: sequence as u16,
+                total,
+            }
+            .write(&mut datagram);
+            datagram.put_slice(chunk);
+            datagram.freeze()
+        })
+        .collect();
+    Some(datagrams)
+}
+
+/// Serves memcached text protocol over UDP, each datagram starts
+/// with a frame header, see memcached protocol.txt
+pub struct MemcacheUdpServer {
+    storage: Arc<storage::MemcStore>,
+    handler: TextHandler,
+    state: Arc<ServerState>,
+    item_size_limit: u32,
+    fragment_timeout: Duration,
+}
+
+impl MemcacheUdpServer {
+    pub fn new(store: Arc<dyn Cache + Send + Sync>, item_size_limit: u32) -> Self {
+        let storage = Arc::new(storage::MemcStore::new(store));
+        MemcacheUdpServer {
+            handler: TextHandler::new(Arc::clone(&storage)),
+            storage,
+            state: Arc::new(ServerState::new(0)),
+            item_size_limit,
+            fragment_timeout: DEFAULT_FRAGMENT_TIMEOUT,
+        }
+    }
+
+    /// Shares counters of stats command, i.e. with TCP listeners
+    pub fn with_stats(mut self, counters: Arc<Stats>) -> Self {
+        self.storage = Arc::new(storage::MemcStore::new(self.storage.cache()).with_stats(counters));
+        self.handler = TextHandler::new(Arc::clone(&self.storage));
+        self
+    }
+
+    /// Shares server state, so a listener is stopped on shutdown
+    pub fn with_state(mut self, state: Arc<ServerState>) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// Requests split into multiple datagrams are discarded if all
+    /// of them don't arrive within fragment_timeout
+    pub fn with_fragment_timeout(mut self, fragment_timeout: Duration) -> Self {
+        self.fragment_timeout = fragment_timeout;
+        self
+    }
+
+    /// Handles datagrams until shutdown is started in server state
+    pub async fn run<A: ToSocketAddrs>(&self, addr: A) -> io::Result<()> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::AddrNotAvailable))?;
+        let socket = UdpSocket::bind(addr).await?;
+        debug!("UDP listener bound to: {:?}", socket.local_addr()?);
+        self.serve(socket).await
+    }
+
+    /// Handles datagrams received on an already bound socket
+    pub async fn serve(&self, socket: UdpSocket) -> io::Result<()> {
+        let mut reassembler = Reassembler::new(self.fragment_timeout);
+        let counters = self.storage.shared_counters();
+        // a datagram can't be larger than 64KiB
+        let mut datagram = vec![0u8; 64 * 1024];
+        loop {
+            let (len, peer) = tokio::select! {
+                received = socket.recv_from(&mut datagram) => match received {
+                    Ok(received) => received,
+                    Err(err) => {
+                        error!("UDP receive error: {}", err);
+                        continue;
+                    }
+                },
+                _ = self.state.wait_for_shutdown() => break,
+            };
+            Stats::add(&counters.bytes_read, len as u64);
+            let discarded = reassembler.discard_expired(Instant::now());
+            if discarded > 0 {
+                debug!("Discarded {} incomplete UDP requests", discarded);
+            }
+            let (header, payload) = match UdpHeader::parse(&datagram[..len]) {
+                Some(parsed) => parsed,
+                None => {
+                    debug!("Invalid UDP frame header from {}", peer);
+                    continue;
+                }
+            };
+            let request = match reassembler.add(peer, header, payload, Instant::now()) {
+                Some(request) => request,
+                None => continue,
+            };
+            let response = self.handle_requests(request);
+            if response.is_empty() {
+                continue;
+            }
+            match into_datagrams(header.request_id, &response) {
+                Some(datagrams) => {
+                    for datagram in datagrams {
+                        match socket.send_to(&datagram, peer).await {
+                            Ok(sent) => Stats::add(&counters.bytes_written, sent as u64),
+                            Err(err) => {
+                                error!("UDP send error to {}: {}", peer, err);
+                                break;
+                            }
+                        }
+                    }
+                }
+                None => warn!("UDP response to {} doesn't fit in 65535 datagrams", peer),
+            }
+        }
+        Ok(())
+    }
+
+    // handles all requests of a payload, a request truncated
+    // at its end is ignored, there is no more data to come
+    fn handle_requests(&self, mut payload: BytesMut) -> BytesMut {
+        let mut codec = MemcacheTextCodec::new(self.item_size_limit);
+        let mut response = BytesMut::new();
+        loop {
+            let request = match codec.decode(&mut payload) {
+                Ok(Some(request)) => request,
+                Ok(None) => break,
+                Err(err) => {
+                    debug!("Invalid UDP request: {}", err);
+                    break;
+                }
+            };
+            if let TextRequest::Quit = request {
+                break;
+            }
+            if let Some(reply) = self.handler.handle_request(request) {
+                if let Err(err) = codec.encode(&reply, &mut response) {
+                    error!("Cannot encode UDP response: {}", err);
+                    break;
+                }
+            }
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_store::store::MemoryStore;
+    use crate::mock::mock_server::MockSystemTimer;
+
+    fn datagram(request_id: u16, sequence: u16, total: u16, payload: &[u8]) -> Vec<u8> {
+        let mut buffer = BytesMut::new();
+        UdpHeader {
+            request_id,
+            sequence,
+            total,
+        }
+        .write(&mut buffer);
+        buffer.put_slice(payload);
+        buffer.to_vec()
+    }
+
+    fn peer() -> SocketAddr {
+        "127.0.0.1:5000".parse().unwrap()
+    }
+
+    #[test]
+    fn header_should_be_parsed() {
+        let packet = datagram(7, 1, 2, b"get");
+        let (header, payload) = UdpHeader::parse(&packet).unwrap();
+        assert_eq!(
+            header,
+            UdpHeader {
+                request_id: 7,
+                sequence: 1,
+                total: 2
+            }
+        );
+        assert_eq!(payload, b"get");
+        assert_eq!(UdpHeader::parse(&packet[..7]), None);
+        assert_eq!(UdpHeader::parse(&datagram(7, 2, 2, b"")), None);
+    }
+
+    #[test]
+    fn fragments_should_be_reassembled_in_any_order() {
+        let mut reassembler = Reassembler::new(DEFAULT_FRAGMENT_TIMEOUT);
+        let now = Instant::now();
+        let header = |sequence| UdpHeader {
+            request_id: 1,
+            sequence,
+            total: 3,
+        };
+        assert!(reassembler
+            .add(peer(), header(2), b"bar\r\n", now)
+            .is_none());
+        assert!(reassembler
+            .add(peer(), header(0), b"set foo 0 0 3", now)
+            .is_none());
+        // duplicated datagram is ignored
+        assert!(reassembler
+            .add(peer(), header(2), b"bar\r\n", now)
+            .is_none());
+        let request = reassembler.add(peer(), header(1), b"\r\n", now).unwrap();
+        assert_eq!(&request[..], b"set foo 0 0 3\r\nbar\r\n");
+        assert!(reassembler.pending.is_empty());
+    }
+
+    #[test]
+    fn incomplete_requests_should_be_discarded_after_timeout() {
+        let mut reassembler = Reassembler::new(Duration::from_millis(100));
+        let now = Instant::now();
+        let header = UdpHeader {
+            request_id: 1,
+            sequence: 0,
+            total: 2,
+        };
+        assert!(reassembler.add(peer(), header, b"get", now).is_none());
+        assert_eq!(
+            reassembler.discard_expired(now + Duration::from_millis(50)),
+            0
+        );
+        assert_eq!(
+            reassembler.discard_expired(now + Duration::from_millis(100)),
+            1
+        );
+        assert!(reassembler.pending.is_empty());
+    }
+
+    #[test]
+    fn large_response_should_be_split_into_datagrams() {
+        let response = vec![b'a'; 3000];
+        let datagrams = into_datagrams(9, &response).unwrap();
+        assert_eq!(datagrams.len(), 3);
+        for (sequence, datagram) in datagrams.iter().enumerate() {
+            assert!(datagram.len() <= MAX_DATAGRAM_SIZE);
+            let (header, _) = UdpHeader::parse(datagram).unwrap();
+            assert_eq!(header.request_id, 9);
+            assert_eq!(header.sequence, sequence as u16);
+            assert_eq!(header.total, 3);
+        }
+        let payload: usize = datagrams.iter().map(|d| d.len() - UDP_HEADER_LEN).sum();
+        assert_eq!(payload, 3000);
+    }
+
+    #[tokio::test]
+    async fn server_should_answer_requests_with_request_id() {
+        let store = MemoryStore::new_shared(Arc::new(MockSystemTimer::new()));
+        let state = Arc::new(ServerState::new(1));
+        let server = MemcacheUdpServer::new(store, 1024 * 1024).with_state(Arc::clone(&state));
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let server = tokio::spawn(async move { server.serve(socket).await });
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client
+            .send_to(
+                &datagram(42, 0, 1, b"set foo 0 0 3\r\nbar\r\nget foo\r\n"),
+                addr,
+            )
+            .await
+            .unwrap();
+        let mut buffer = [0u8; 1500];
+        let len = tokio::time::timeout(Duration::from_secs(5), client.recv(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        let (header, payload) = UdpHeader::parse(&buffer[..len]).unwrap();
+        assert_eq!(header.request_id, 42);
+        assert_eq!(header.total, 1);
+        assert_eq!(payload, b"STORED\r\nVALUE foo 0 3\r\nbar\r\nEND\r\n");
+
+        state.shutdown();
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+//! Prometheus metrics endpoint, counters of stats command are exposed as
+//! memcrs_<name>_total and gauges as memcrs_<name>, i.e. memcrs_cmd_get_total
+//! and memcrs_curr_items.
+
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use prometheus::{Encoder, IntCounter, IntGauge, Registry, TextEncoder};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io;
+use tokio::net::TcpListener;
+
+use crate::memcache::stats::Stats;
+use crate::memcache::store::MemcStore;
+
+const NAMESPACE: &str = "memcrs";
+
+// stats which may decrease, other stats are counters
+const GAUGES: [&str; 3] = ["uptime", "curr_items", "bytes"];
+
+/// Data exposed by metrics endpoint
+pub struct MetricsState {
+    store: Arc<MemcStore>,
+}
+
+impl MetricsState {
+    /// Stats of a store should be shared with listeners, so commands
+    /// they handle are counted
+    pub fn new(store: Arc<MemcStore>) -> MetricsState {
+        MetricsState { store }
+    }
+}
+
+// a registry is created for each scrape, so values are copied from
+// stats as they are and counters don't have to be incremented by a difference
+fn registry(stats: &Stats) -> prometheus::Result<Registry> {
+    let registry = Registry::new_custom(Some(NAMESPACE.to_string()), None)?;
+    for (name, value) in stats.values() {
+        let help = format!("{} of stats command", name);
+        if GAUGES.contains(&name) {
+            let gauge = IntGauge::new(name, help)?;
+            gauge.set(value as i64);
+            registry.register(Box::new(gauge))?;
+        } else {
+            let counter = IntCounter::new(format!("{}_total", name), help)?;
+            counter.inc_by(value);
+            registry.register(Box::new(counter))?;
+        }
+    }
+    Ok(registry)
+}
+
+/// Stats in Prometheus text format
+async fn metrics(State(state): State<Arc<MetricsState>>) -> Response {
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    match registry(&state.store.counters())
+        .and_then(|registry| encoder.encode(&registry.gather(), &mut body))
+    {
+        Ok(()) => (
+            [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+            body,
+        )
+            .into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+pub fn router(state: Arc<MetricsState>) -> Router {
+    Router::new()
+        .route("/metrics", get(metrics))
+        .with_state(state)
+}
+
+pub async fn serve(addr: SocketAddr, state: Arc<MetricsState>) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    axum::serve(listener, router(state)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memcache::store::Record;
+    use crate::mock::mock_server::create_storage;
+    use axum::body::{to_bytes, Body};
+    use axum::http::Request;
+    use bytes::Bytes;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn metrics_should_expose_stats() {
+        let store = create_storage();
+        store
+            .set(Bytes::from("foo"), Record::new(Bytes::from("bar"), 0, 0, 0))
+            .unwrap();
+        store.get(&Bytes::from("foo")).unwrap();
+        store.get(&Bytes::from("missing")).unwrap_err();
+
+        let request = Request::get("/metrics").body(Body::empty()).unwrap();
+        let response = router(Arc::new(MetricsState::new(store)))
+            .oneshot(request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/plain; version=0.0.4"
+        );
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("# TYPE memcrs_cmd_get_total counter\n"));
+        assert!(body.contains("memcrs_cmd_get_total 2\n"));
+        assert!(body.contains("memcrs_get_hits_total 1\n"));
+        assert!(body.contains("memcrs_get_misses_total 1\n"));
+        assert!(body.contains("# TYPE memcrs_curr_items gauge\n"));
+        assert!(body.contains("memcrs_curr_items 1\n"));
+    }
+}
@@ -1,4 +1,19 @@
+pub mod admin;
+pub mod auth;
 pub mod client_handler;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "binary-protocol")]
 pub mod handler;
+pub mod health;
 pub mod memc_tcp;
+pub mod memc_udp;
+pub mod metrics;
 pub mod runtime_builder;
+pub mod server_state;
+pub mod signals;
+pub mod slow_log;
+pub mod sweeper;
+pub mod text_handler;
+#[cfg(feature = "tls")]
+pub mod tls;
@@ -1,16 +1,29 @@
 extern crate core_affinity;
 use crate::memcache;
+use crate::memcache::stats::Stats;
 use crate::memcache_server;
+use crate::memcache_server::admin;
+use crate::memcache_server::auth::SaslAuthenticator;
+use crate::memcache_server::health;
+use crate::memcache_server::memc_udp;
+use crate::memcache_server::metrics;
+use crate::memcache_server::server_state::ServerState;
+use crate::memcache_server::signals::{self, ConfigReloader};
+use crate::memcache_server::sweeper;
 use crate::server;
+use crate::server::systemd;
 use crate::{cache::cache::Cache, memcache::cli::parser::RuntimeType};
 use std::net::SocketAddr;
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Arc,
 };
+use std::time::Duration;
 use tokio::runtime::Builder;
+use tokio::sync::watch;
 
-use crate::memcache::cli::parser::MemcrsArgs;
+use crate::memcache_server::memc_tcp::MemcacheServerConfig;
+use crate::server::config::ServerConfig;
 
 fn get_worker_thread_name() -> String {
     static ATOMIC_ID: AtomicUsize = AtomicUsize::new(0);
@@ -40,31 +53,123 @@ fn create_current_thread_runtime() -> tokio::runtime::Runtime {
     runtime
 }
 
+fn create_memcache_server_config(config: &ServerConfig) -> MemcacheServerConfig {
+    let memc_config = MemcacheServerConfig::new(
+        60,
+        config.network.connection_limit,
+        config.storage.item_size_limit.get_bytes() as u32,
+        config.network.backlog_limit,
+    )
+    .with_max_pipeline_depth(config.network.max_pipeline_depth)
+    .with_shutdown_timeout_secs(config.network.shutdown_timeout_secs)
+    .with_idle_timeout_secs(config.network.idle_timeout_secs)
+    .with_slow_log_threshold_us(config.network.slow_log_threshold_us)
+    .with_tcp_nodelay(config.network.tcp_nodelay)
+    .with_tcp_buffer_sizes(
+        config.network.tcp_recv_buffer_size,
+        config.network.tcp_send_buffer_size,
+    )
+    .with_protocol(config.network.protocol)
+    .with_unauthenticated_text(config.network.allow_unauthenticated_text);
+    #[cfg(not(feature = "binary-protocol"))]
+    if config.network.protocol != memcache_server::memc_tcp::Protocol::Text {
+        warn!("memcrsd was built without binary-protocol feature, binary protocol clients are disconnected");
+    }
+    memc_config
+}
+
+// shared by TCP servers of all runtime threads
+#[derive(Clone)]
+struct SharedListener {
+    // socket passed by systemd
+    listener: Option<Arc<std::net::TcpListener>>,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<memcache_server::tls::TlsAcceptor>>,
+    authenticator: Option<Arc<SaslAuthenticator>>,
+    // counters of stats command
+    stats: Arc<Stats>,
+}
+
+fn create_tcp_server(
+    memc_config: MemcacheServerConfig,
+    store: Arc<dyn Cache + Send + Sync>,
+    state: Arc<ServerState>,
+    shared: SharedListener,
+) -> memcache_server::memc_tcp::MemcacheTcpServer {
+    let tcp_server = memcache_server::memc_tcp::MemcacheTcpServer::new(memc_config, store)
+        .with_state(state)
+        .with_stats(shared.stats);
+    let tcp_server = match shared.listener {
+        Some(listener) => tcp_server.with_listener(listener),
+        None => tcp_server,
+    };
+    #[cfg(feature = "tls")]
+    let tcp_server = match shared.tls {
+        Some(acceptor) => tcp_server.with_tls(acceptor),
+        None => tcp_server,
+    };
+    match shared.authenticator {
+        Some(authenticator) => tcp_server.with_authenticator(authenticator),
+        None => tcp_server,
+    }
+}
+
+#[cfg(feature = "tls")]
+fn create_tls_acceptor(config: &ServerConfig) -> Option<Arc<memcache_server::tls::TlsAcceptor>> {
+    let tls = config.network.tls.as_ref()?;
+    let acceptor = memcache_server::tls::TlsAcceptor::from_pem_files(
+        &tls.cert_path,
+        &tls.key_path,
+        tls.ca_path.as_deref(),
+    )
+    .unwrap_or_else(|err| panic!("Cannot load TLS certificate: {}", err));
+    Some(Arc::new(acceptor))
+}
+
+fn create_authenticator(config: &ServerConfig) -> Option<Arc<SaslAuthenticator>> {
+    let path = config.network.sasl_users_file.as_ref()?;
+    let authenticator = SaslAuthenticator::from_file(path)
+        .unwrap_or_else(|err| panic!("Cannot load SASL users from {}: {}", path.display(), err));
+    Some(Arc::new(authenticator))
+}
+
+// systemd passes sockets in order of ListenStream= entries
+fn take_systemd_listener() -> Arc<std::net::TcpListener> {
+    let mut listeners = systemd::listen_fds().expect("Cannot take sockets passed by systemd");
+    if listeners.is_empty() {
+        panic!("--systemd-socket is set, but systemd didn't pass any socket");
+    }
+    if listeners.len() > 1 {
+        warn!(
+            "systemd passed {} sockets, only the first one is used",
+            listeners.len()
+        );
+    }
+    Arc::new(listeners.remove(0))
+}
+
 fn create_current_thread_server(
-    config: MemcrsArgs,
+    addr: SocketAddr,
+    memc_config: MemcacheServerConfig,
+    threads: usize,
     store: Arc<dyn Cache + Send + Sync>,
+    state: Arc<ServerState>,
+    shared: SharedListener,
 ) -> tokio::runtime::Runtime {
-    let addr = SocketAddr::new(config.listen_address, config.port);
-    let memc_config = memcache_server::memc_tcp::MemcacheServerConfig::new(
-        60,
-        config.connection_limit,
-        config.item_size_limit.get_bytes() as u32,
-        config.backlog_limit,
-    );
-
     let core_ids = core_affinity::get_core_ids().unwrap();
 
-    for i in 0..config.threads {
+    for i in 0..threads {
         let store_rc = Arc::clone(&store);
+        let state_rc = Arc::clone(&state);
         let core_ids_clone = core_ids.clone();
+        let shared_rc = shared.clone();
         std::thread::spawn(move || {
             debug!("Creating runtime {}", i);
             let core_id = core_ids_clone[i % core_ids_clone.len()];
             let res = core_affinity::set_for_current(core_id);
             let create_runtime = || {
                 let child_runtime = create_current_thread_runtime();
-                let mut tcp_server =
-                    memcache_server::memc_tcp::MemcacheTcpServer::new(memc_config, store_rc);
+                let mut tcp_server = create_tcp_server(memc_config, store_rc, state_rc, shared_rc);
                 child_runtime.block_on(tcp_server.run(addr)).unwrap()
             };
             if res {
@@ -84,33 +189,282 @@ fn create_current_thread_server(
 }
 
 fn create_threadpool_server(
-    config: MemcrsArgs,
+    addr: SocketAddr,
+    memc_config: MemcacheServerConfig,
+    threads: usize,
     store: Arc<dyn Cache + Send + Sync>,
+    state: Arc<ServerState>,
+    shared: SharedListener,
 ) -> tokio::runtime::Runtime {
-    let addr = SocketAddr::new(config.listen_address, config.port);
-    let memc_config = memcache_server::memc_tcp::MemcacheServerConfig::new(
-        60,
-        config.connection_limit,
-        config.item_size_limit.get_bytes() as u32,
-        config.backlog_limit,
-    );
-    let runtime = create_multi_thread_runtime(config.threads);
-    let store_rc = Arc::clone(&store);
-    let mut tcp_server = memcache_server::memc_tcp::MemcacheTcpServer::new(memc_config, store_rc);
+    let runtime = create_multi_thread_runtime(threads);
+    let mut tcp_server = create_tcp_server(memc_config, store, state, shared);
     runtime.spawn(async move { tcp_server.run(addr).await });
     runtime
 }
 
+// spans are exported by a runtime of an exporter, so it is started
+// before a store is created
+#[cfg(feature = "opentelemetry")]
+fn with_tracing(
+    store_config: memcache::builder::MemcacheStoreConfig,
+    config: &ServerConfig,
+) -> memcache::builder::MemcacheStoreConfig {
+    let endpoint = match &config.telemetry.otlp_endpoint {
+        Some(endpoint) => endpoint,
+        None => return store_config,
+    };
+    match server::telemetry::init(endpoint) {
+        Ok(()) => {
+            info!("Exporting spans to {}", endpoint);
+            store_config.with_tracing(true)
+        }
+        Err(err) => {
+            error!("Cannot export spans to {}: {}", endpoint, err);
+            store_config
+        }
+    }
+}
+
+#[cfg(not(feature = "opentelemetry"))]
+fn with_tracing(
+    store_config: memcache::builder::MemcacheStoreConfig,
+    config: &ServerConfig,
+) -> memcache::builder::MemcacheStoreConfig {
+    if config.telemetry.otlp_endpoint.is_some() {
+        warn!("memcrsd was built without opentelemetry feature, --otlp-endpoint is ignored");
+    }
+    store_config
+}
+
+#[cfg(feature = "compression")]
+fn with_compression(
+    store_config: memcache::builder::MemcacheStoreConfig,
+    config: &ServerConfig,
+) -> memcache::builder::MemcacheStoreConfig {
+    let compression = &config.storage.compression;
+    store_config.with_compression(compression.enabled.then_some(compression.min_size))
+}
+
+#[cfg(not(feature = "compression"))]
+fn with_compression(
+    store_config: memcache::builder::MemcacheStoreConfig,
+    config: &ServerConfig,
+) -> memcache::builder::MemcacheStoreConfig {
+    if config.storage.compression.enabled {
+        warn!("memcrsd was built without compression feature, --enable-compression is ignored");
+    }
+    store_config
+}
+
+/// Starts listeners and returns a runtime a timer should be run on and
+/// a state of listeners, their shutdown is started on SIGTERM or SIGINT
 pub fn create_memcrs_server(
-    config: MemcrsArgs,
+    config: ServerConfig,
     system_timer: std::sync::Arc<server::timer::SystemTimer>,
-) -> tokio::runtime::Runtime {
-    let store_config = memcache::builder::MemcacheStoreConfig::new(config.memory_limit);
+) -> (tokio::runtime::Runtime, Arc<ServerState>) {
+    let store_config = memcache::builder::MemcacheStoreConfig::new(config.storage.memory_limit)
+        .with_policy(config.storage.eviction_policy)
+        .with_avg_item_size(config.storage.avg_item_size)
+        .with_prefix_index(config.storage.enable_prefix_index)
+        .with_shard_count(config.storage.shard_count)
+        .with_lru_sample_size(config.storage.lru_sample_size)
+        .with_max_value_size(config.storage.item_size_limit.get_bytes() as usize);
+    let store_config = with_tracing(store_config, &config);
+    let store_config = with_compression(store_config, &config);
     let memcache_store =
         memcache::builder::MemcacheStoreBuilder::from_config(store_config, system_timer);
 
-    match config.runtime_type {
-        RuntimeType::CurrentThread => create_current_thread_server(config, memcache_store),
-        RuntimeType::MultiThread => create_threadpool_server(config, memcache_store),
+    let addr = SocketAddr::new(config.network.listen_address, config.network.port);
+    let memc_config = create_memcache_server_config(&config);
+    let threads = config.runtime.threads;
+    let state = Arc::new(ServerState::new(config.network.connection_limit));
+    let listener = if config.network.systemd_socket {
+        Some(take_systemd_listener())
+    } else {
+        None
+    };
+    // clients must not be served unencrypted if TLS was requested
+    #[cfg(not(feature = "tls"))]
+    if config.network.tls.is_some() {
+        panic!("--tls-cert is set, but memcrsd was built without tls feature");
+    }
+    // metrics are not served on memcache port, even if a port is taken by a proxy
+    if config.network.metrics_port == Some(config.network.port) {
+        panic!("--metrics-port must differ from --port");
+    }
+    let authenticator = create_authenticator(&config);
+    // UDP clients cannot authenticate, so they are not served unless
+    // text protocol clients are allowed to skip authentication
+    if authenticator.is_some()
+        && config.network.enable_udp
+        && !config.network.allow_unauthenticated_text
+    {
+        panic!("--enable-udp requires --allow-unauthenticated-text if --sasl-users-file is set");
+    }
+    let shared = SharedListener {
+        listener,
+        #[cfg(feature = "tls")]
+        tls: create_tls_acceptor(&config),
+        authenticator: authenticator.clone(),
+        stats: Arc::new(Stats::new().with_key_stats_capacity(config.storage.key_stats_capacity)),
+    };
+    let runtime = match config.runtime.runtime_type {
+        // clients are served by a Unix socket listener only
+        RuntimeType::CurrentThread if config.network.no_tcp => create_current_thread_runtime(),
+        RuntimeType::MultiThread if config.network.no_tcp => create_multi_thread_runtime(threads),
+        RuntimeType::CurrentThread => create_current_thread_server(
+            addr,
+            memc_config,
+            threads,
+            Arc::clone(&memcache_store),
+            Arc::clone(&state),
+            shared.clone(),
+        ),
+        RuntimeType::MultiThread => create_threadpool_server(
+            addr,
+            memc_config,
+            threads,
+            Arc::clone(&memcache_store),
+            Arc::clone(&state),
+            shared.clone(),
+        ),
+    };
+
+    if let Some(unix_socket) = config.network.unix_socket.clone() {
+        let unix_server = memcache_server::memc_tcp::MemcacheTcpServer::new(
+            memc_config,
+            Arc::clone(&memcache_store),
+        )
+        .with_state(Arc::clone(&state))
+        .with_stats(Arc::clone(&shared.stats));
+        let mut unix_server = match authenticator.clone() {
+            Some(authenticator) => unix_server.with_authenticator(authenticator),
+            None => unix_server,
+        };
+        runtime.spawn(async move {
+            if let Err(err) = unix_server
+                .run_unix(&unix_socket.path, unix_socket.mode)
+                .await
+            {
+                error!("Unix socket listener error: {}", err);
+            }
+        });
+    }
+
+    if config.network.enable_udp {
+        let udp_server = memc_udp::MemcacheUdpServer::new(
+            Arc::clone(&memcache_store),
+            config.storage.item_size_limit.get_bytes() as u32,
+        )
+        .with_state(Arc::clone(&state))
+        .with_stats(Arc::clone(&shared.stats))
+        .with_fragment_timeout(Duration::from_millis(
+            config.network.udp_fragment_timeout_ms,
+        ));
+        runtime.spawn(async move {
+            if let Err(err) = udp_server.run(addr).await {
+                error!("UDP listener error: {}", err);
+            }
+        });
+    }
+
+    // a sweeper waits while an interval is zero, it may be changed on SIGHUP
+    let (sweep_interval, sweep_receiver) =
+        watch::channel(Duration::from_millis(config.storage.sweep_interval_ms));
+    runtime.spawn(sweeper::expiration_sweeper(
+        Arc::clone(&memcache_store),
+        sweep_receiver,
+        Arc::clone(&state),
+    ));
+
+    let mut reloader = ConfigReloader::new(sweep_interval);
+    if let Some(config_file) = config.config_file.clone() {
+        reloader = reloader.with_config_file(config_file);
+    }
+    if let Some(authenticator) = authenticator {
+        reloader = reloader.with_authenticator(authenticator);
+    }
+    let signal_state = Arc::clone(&state);
+    runtime.spawn(async move {
+        if let Err(err) = signals::handle_signals(signal_state, reloader).await {
+            error!("Cannot handle signals: {}", err);
+        }
+    });
+
+    if let Some(admin_port) = config.network.admin_port {
+        let addr = SocketAddr::new(config.network.listen_address, admin_port);
+        if config.network.admin_token.is_none() {
+            warn!(
+                "Admin API on port {} is not protected by a token",
+                admin_port
+            );
+        }
+        let store = Arc::new(
+            memcache::store::MemcStore::new(Arc::clone(&memcache_store))
+                .with_stats(Arc::clone(&shared.stats)),
+        );
+        let admin_state =
+            Arc::new(admin::AdminState::new(store).with_token(config.network.admin_token.clone()));
+        runtime.spawn(async move {
+            if let Err(err) = admin::serve(addr, admin_state).await {
+                error!("Admin API endpoint error: {}", err);
+            }
+        });
+    }
+
+    if let Some(metrics_port) = config.network.metrics_port {
+        let addr = SocketAddr::new(config.network.listen_address, metrics_port);
+        let store = Arc::new(
+            memcache::store::MemcStore::new(Arc::clone(&memcache_store))
+                .with_stats(Arc::clone(&shared.stats)),
+        );
+        let metrics_state = Arc::new(metrics::MetricsState::new(store));
+        runtime.spawn(async move {
+            if let Err(err) = metrics::serve(addr, metrics_state).await {
+                error!("Metrics endpoint error: {}", err);
+            }
+        });
+    }
+
+    #[cfg(feature = "grpc")]
+    if let Some(grpc_port) = config.network.grpc_port {
+        let addr = SocketAddr::new(config.network.listen_address, grpc_port);
+        let store = Arc::new(
+            memcache::store::MemcStore::new(Arc::clone(&memcache_store))
+                .with_stats(Arc::clone(&shared.stats)),
+        );
+        runtime.spawn(async move {
+            let service = memcache_server::grpc::AdminService::new(store);
+            if let Err(err) = memcache_server::grpc::serve(addr, service).await {
+                error!("gRPC admin API endpoint error: {}", err);
+            }
+        });
+    }
+    #[cfg(not(feature = "grpc"))]
+    if config.network.grpc_port.is_some() {
+        warn!("memcrsd was built without grpc feature, --grpc-port is ignored");
+    }
+
+    if let Some(health_port) = config.network.health_port {
+        let addr = SocketAddr::new(config.network.listen_address, health_port);
+        let health_state = Arc::new(
+            health::HealthState::new(Arc::clone(&state), memcache_store)
+                .with_item_size_limit(config.storage.item_size_limit.get_bytes() as u64),
+        );
+        runtime.spawn(async move {
+            if let Err(err) = health::serve(addr, health_state).await {
+                error!("Health check endpoint error: {}", err);
+            }
+        });
+    }
+    // connections are queued by a kernel on a socket held by systemd,
+    // so a service is ready once a store is created
+    if config.network.systemd_socket {
+        match systemd::notify_ready() {
+            Ok(true) => debug!("Notified systemd of readiness"),
+            Ok(false) => {}
+            Err(err) => warn!("Cannot notify systemd of readiness: {}", err),
+        }
     }
+    (runtime, state)
 }
@@ -0,0 +1,179 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+// how often wait_for_drain checks a number of connections
+const DRAIN_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// State shared by all listeners of a single memcrsd process
+pub struct ServerState {
+    connection_limit: u32,
+    current_connections: AtomicU32,
+    next_connection_id: AtomicU64,
+    started: AtomicBool,
+    pipeline_flushes: AtomicU64,
+    shutdown_in_progress: AtomicBool,
+    shutdown_notify: Notify,
+}
+
+impl ServerState {
+    pub fn new(connection_limit: u32) -> ServerState {
+        ServerState {
+            connection_limit,
+            current_connections: AtomicU32::new(0),
+            next_connection_id: AtomicU64::new(0),
+            started: AtomicBool::new(false),
+            pipeline_flushes: AtomicU64::new(0),
+            shutdown_in_progress: AtomicBool::new(false),
+            shutdown_notify: Notify::new(),
+        }
+    }
+
+    pub fn connection_limit(&self) -> u32 {
+        self.connection_limit
+    }
+
+    /// Number of currently connected clients
+    pub fn current_connections(&self) -> u32 {
+        self.current_connections.load(Ordering::Relaxed)
+    }
+
+    pub fn is_connection_limit_reached(&self) -> bool {
+        self.current_connections() >= self.connection_limit
+    }
+
+    /// True once a listener is bound and accepts connections
+    pub fn is_started(&self) -> bool {
+        self.started.load(Ordering::Acquire)
+    }
+
+    /// True once shutdown started, listeners don't accept new connections
+    /// and connected clients are drained
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown_in_progress.load(Ordering::Acquire)
+    }
+
+    /// Starts graceful shutdown of all listeners sharing this state
+    pub fn shutdown(&self) {
+        self.shutdown_in_progress.store(true, Ordering::Release);
+        self.shutdown_notify.notify_waiters();
+    }
+
+    /// Completes once shutdown is started
+    pub(crate) async fn wait_for_shutdown(&self) {
+        let notified = self.shutdown_notify.notified();
+        tokio::pin!(notified);
+        // register as a waiter before checking a flag, so a notification
+        // sent in between is not lost
+        notified.as_mut().enable();
+        if self.is_shutting_down() {
+            return;
+        }
+        notified.await;
+    }
+
+    /// Completes once shutdown is started and all clients disconnected,
+    /// or once timeout elapsed since shutdown was started
+    pub async fn wait_for_drain(&self, timeout: Duration) {
+        self.wait_for_shutdown().await;
+        let _ = tokio::time::timeout(timeout, async {
+            while self.current_connections() > 0 {
+                tokio::time::sleep(DRAIN_CHECK_INTERVAL).await;
+            }
+        })
+        .await;
+    }
+
+    /// Number of times responses were flushed because a client pipelined
+    /// more than max pipeline depth requests
+    pub fn pipeline_flushes(&self) -> u64 {
+        self.pipeline_flushes.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_started(&self) {
+        self.started.store(true, Ordering::Release)
+    }
+
+    /// Counts a connection as open until returned guard is dropped
+    pub(crate) fn connection_opened(self: &Arc<Self>) -> ConnectionGuard {
+        self.current_connections.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard {
+            state: Arc::clone(self),
+            connection_id: self.next_connection_id.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn pipeline_flushed(&self) {
+        self.pipeline_flushes.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Open connection counted in a server state, connection is counted
+/// as closed when a guard is dropped, also when a client task panics
+pub struct ConnectionGuard {
+    state: Arc<ServerState>,
+    connection_id: u64,
+}
+
+impl ConnectionGuard {
+    /// Unique id of a connection within a server state
+    pub fn connection_id(&self) -> u64 {
+        self.connection_id
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.state
+            .current_connections
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::seq::SliceRandom;
+    use std::collections::HashSet;
+
+    #[test]
+    fn connection_guard_should_count_open_connections() {
+        let state = Arc::new(ServerState::new(1000));
+        let mut connections: Vec<ConnectionGuard> =
+            (0..100).map(|_| state.connection_opened()).collect();
+        let ids: HashSet<u64> = connections.iter().map(|c| c.connection_id()).collect();
+        assert_eq!(ids.len(), 100);
+        assert_eq!(state.current_connections(), 100);
+
+        connections.shuffle(&mut rand::thread_rng());
+        connections.truncate(50);
+        assert_eq!(state.current_connections(), 50);
+        drop(connections);
+        assert_eq!(state.current_connections(), 0);
+    }
+
+    #[tokio::test]
+    async fn wait_for_drain_should_complete_once_connections_are_closed() {
+        let state = Arc::new(ServerState::new(10));
+        let connection = state.connection_opened();
+        let drain_state = Arc::clone(&state);
+        let drain = tokio::spawn(async move {
+            drain_state.wait_for_drain(Duration::from_secs(30)).await;
+        });
+        state.shutdown();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!drain.is_finished());
+        drop(connection);
+        tokio::time::timeout(Duration::from_secs(5), drain)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // clients which don't disconnect are waited for up to a timeout
+        let state = Arc::new(ServerState::new(10));
+        let _connection = state.connection_opened();
+        state.shutdown();
+        state.wait_for_drain(Duration::from_millis(50)).await;
+    }
+}
@@ -0,0 +1,114 @@
+//! Signals handled by memcrsd. On SIGTERM or CTRL-C listeners stop
+//! accepting connections and connected clients finish requests they are
+//! receiving, see MemcacheServerConfig::with_shutdown_timeout_secs.
+//! On SIGHUP a config file and SASL users are read again.
+
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use super::auth::SaslAuthenticator;
+use super::server_state::ServerState;
+use crate::memcache::cli::config_file::ConfigFile;
+use crate::server::logging;
+
+/// Applies a config file and SASL users changed while a server is running,
+/// only a log level and a sweep interval of a config file are applied,
+/// other options require a restart
+pub struct ConfigReloader {
+    config_file: Option<ConfigFile>,
+    authenticator: Option<Arc<SaslAuthenticator>>,
+    sweep_interval: watch::Sender<Duration>,
+}
+
+impl ConfigReloader {
+    /// Sweep interval is sent to a running expiration sweeper
+    pub fn new(sweep_interval: watch::Sender<Duration>) -> ConfigReloader {
+        ConfigReloader {
+            config_file: None,
+            authenticator: None,
+            sweep_interval,
+        }
+    }
+
+    pub fn with_config_file(mut self, config_file: ConfigFile) -> Self {
+        self.config_file = Some(config_file);
+        self
+    }
+
+    pub fn with_authenticator(mut self, authenticator: Arc<SaslAuthenticator>) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    /// Reads a config file and SASL users again, if one cannot be read
+    /// its previous values are kept
+    pub fn reload(&self) {
+        if let Some(config_file) = &self.config_file {
+            self.reload_config(config_file);
+        }
+        if let Some(authenticator) = &self.authenticator {
+            match authenticator.reload() {
+                Ok(count) => info!("Reloaded {} SASL users", count),
+                Err(err) => warn!(
+                    "Cannot reload SASL users from {}, previous users are kept: {}",
+                    authenticator.path().display(),
+                    err
+                ),
+            }
+        }
+    }
+
+    fn reload_config(&self, config_file: &ConfigFile) {
+        let config = match config_file.load() {
+            Ok(args) => args.into_server_config(),
+            Err(err) => {
+                warn!("Cannot reload config, previous options are kept: {}", err);
+                return;
+            }
+        };
+        let level = logging::log_level(config.logging.verbose);
+        if logging::set_level(level) {
+            info!("Log level changed to {}", level);
+        }
+        let interval = Duration::from_millis(config.storage.sweep_interval_ms);
+        let changed = self.sweep_interval.send_if_modified(|current| {
+            let changed = *current != interval;
+            *current = interval;
+            changed
+        });
+        if changed {
+            info!("Sweep interval changed to {}ms", interval.as_millis());
+        }
+    }
+}
+
+/// Handles signals until shutdown is started, SIGTERM and SIGINT start
+/// shutdown of listeners sharing a state, SIGHUP reloads a config
+pub async fn handle_signals(state: Arc<ServerState>, reloader: ConfigReloader) -> io::Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut hangup = signal(SignalKind::hangup())?;
+    loop {
+        tokio::select! {
+            _ = terminate.recv() => {
+                info!("SIGTERM received, shutting down");
+                break;
+            }
+            interrupt = tokio::signal::ctrl_c() => {
+                interrupt?;
+                info!("SIGINT received, shutting down");
+                break;
+            }
+            _ = hangup.recv() => {
+                info!("SIGHUP received, reloading config");
+                reloader.reload();
+            }
+            _ = state.wait_for_shutdown() => return Ok(()),
+        }
+    }
+    state.shutdown();
+    Ok(())
+}
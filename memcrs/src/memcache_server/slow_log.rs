@@ -0,0 +1,103 @@
+use crate::memcache::stats::Stats;
+use crate::memcache::store::MemcStore;
+use bytes::Bytes;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+// longer keys are truncated, so a log line doesn't carry a whole key
+const MAX_KEY_LEN: usize = 64;
+
+/// Logs requests of a client which take longer than a threshold to handle
+/// and counts them in Stats::slow_commands. Time is measured with a timer
+/// of a store, so it can be controlled by a mock timer.
+pub(crate) struct SlowLog {
+    threshold: Duration,
+    store: Arc<MemcStore>,
+    // peer address of a TCP client or path of a Unix socket
+    addr: String,
+}
+
+/// Request timed by SlowLog, see SlowLog::start
+pub(crate) struct TimedRequest {
+    command: &'static str,
+    key: Option<Bytes>,
+    started: Instant,
+}
+
+impl SlowLog {
+    /// Returns None if threshold_us is 0, requests are not timed then
+    pub(crate) fn new(threshold_us: u64, store: Arc<MemcStore>, addr: String) -> Option<SlowLog> {
+        (threshold_us > 0).then(|| SlowLog {
+            threshold: Duration::from_micros(threshold_us),
+            store,
+            addr,
+        })
+    }
+
+    /// Starts timing of a request before it is handled
+    pub(crate) fn start(&self, command: &'static str, key: Option<&Bytes>) -> TimedRequest {
+        TimedRequest {
+            command,
+            key: key.cloned(),
+            started: self.store.current_instant(),
+        }
+    }
+
+    /// Logs a request once it is handled if it took longer than a threshold,
+    /// returns true if it did
+    pub(crate) fn finish(&self, request: TimedRequest) -> bool {
+        let elapsed = self
+            .store
+            .current_instant()
+            .saturating_duration_since(request.started);
+        if elapsed <= self.threshold {
+            return false;
+        }
+        Stats::incr(&self.store.shared_counters().slow_commands);
+        let key = request
+            .key
+            .as_ref()
+            .map(|key| String::from_utf8_lossy(&key[..key.len().min(MAX_KEY_LEN)]).into_owned())
+            .unwrap_or_default();
+        warn!(
+            "Slow request: command: {}, key: {}, elapsed: {}us, client: {}",
+            request.command,
+            key,
+            elapsed.as_micros(),
+            self.addr
+        );
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_store::store::MemoryStore;
+    use crate::mock::mock_server::{MockSystemTimer, SetableTimer};
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn request_slower_than_threshold_should_be_counted() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = Arc::new(MemcStore::new(MemoryStore::new_shared(timer.clone())));
+        let slow_log = SlowLog::new(500_000, Arc::clone(&store), "127.0.0.1:1234".into()).unwrap();
+        let key = Bytes::from("foo");
+        let slow_commands = || store.counters().slow_commands.load(Ordering::Relaxed);
+
+        let request = slow_log.start("get", Some(&key));
+        assert!(store.get(&key).is_err());
+        assert!(!slow_log.finish(request));
+        assert_eq!(slow_commands(), 0);
+
+        // mock timer is moved while a request is handled, so it takes a second
+        let request = slow_log.start("get", Some(&key));
+        timer.set(1);
+        assert!(store.get(&key).is_err());
+        assert!(slow_log.finish(request));
+        assert_eq!(slow_commands(), 1);
+
+        assert!(SlowLog::new(0, store, String::new()).is_none());
+    }
+}
@@ -0,0 +1,151 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::{self, Interval, MissedTickBehavior};
+
+use super::server_state::ServerState;
+use crate::cache::cache::Cache;
+
+/// Removes expired records every interval until shutdown is started,
+/// so memory of records which are never read again is reclaimed.
+/// A store is scanned on a blocking thread, a scan doesn't delay
+/// clients handled by the same runtime thread. An interval may be
+/// changed while a sweeper is running, records are not removed while
+/// it is zero.
+pub async fn expiration_sweeper(
+    store: Arc<dyn Cache + Send + Sync>,
+    mut interval: watch::Receiver<Duration>,
+    state: Arc<ServerState>,
+) {
+    let mut can_change = true;
+    loop {
+        let period = *interval.borrow_and_update();
+        let mut ticker = (!period.is_zero()).then(|| {
+            let mut ticker = time::interval(period);
+            // a slow scan doesn't cause a burst of scans after it
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            ticker
+        });
+        loop {
+            tokio::select! {
+                _ = next_tick(&mut ticker) => remove_expired(&store).await,
+                changed = interval.changed(), if can_change => match changed {
+                    Ok(()) => break,
+                    // a sender was dropped, a current interval is kept
+                    Err(_) => can_change = false,
+                },
+                _ = state.wait_for_shutdown() => return,
+            }
+        }
+    }
+}
+
+async fn next_tick(ticker: &mut Option<Interval>) {
+    match ticker {
+        Some(ticker) => {
+            ticker.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+async fn remove_expired(store: &Arc<dyn Cache + Send + Sync>) {
+    let store = Arc::clone(store);
+    match tokio::task::spawn_blocking(move || store.remove_expired_items()).await {
+        Ok(removed) => {
+            let removed = removed.iter().flatten().count();
+            if removed > 0 {
+                debug!("Removed {} expired records", removed);
+            }
+        }
+        Err(err) => error!("Expiration sweeper failed: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::cache::Record;
+    use crate::memory_store::store::MemoryStore;
+    use crate::mock::mock_server::{MockSystemTimer, SetableTimer};
+    use bytes::Bytes;
+
+    #[tokio::test]
+    async fn sweeper_should_remove_expired_records_until_shutdown() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = MemoryStore::new_shared(timer.clone());
+        for id in 0..10 {
+            let record = Record::new(Bytes::from("value"), 0, 0, 5);
+            store
+                .set(Bytes::from(format!("key{}", id)), record)
+                .unwrap();
+        }
+        store
+            .set(
+                Bytes::from("live"),
+                Record::new(Bytes::from("value"), 0, 0, 0),
+            )
+            .unwrap();
+        timer.set(5);
+
+        let state = Arc::new(ServerState::new(1));
+        let (_interval, receiver) = watch::channel(Duration::from_millis(10));
+        let sweeper = tokio::spawn(expiration_sweeper(
+            store.clone(),
+            receiver,
+            Arc::clone(&state),
+        ));
+        time::timeout(Duration::from_secs(5), async {
+            while store.len() > 1 {
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(store.get(&Bytes::from("live")).is_ok());
+
+        state.shutdown();
+        time::timeout(Duration::from_secs(5), sweeper)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn sweeper_should_start_once_interval_is_changed_from_zero() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = MemoryStore::new_shared(timer.clone());
+        store
+            .set(
+                Bytes::from("key"),
+                Record::new(Bytes::from("value"), 0, 0, 5),
+            )
+            .unwrap();
+        timer.set(5);
+
+        let state = Arc::new(ServerState::new(1));
+        let (interval, receiver) = watch::channel(Duration::ZERO);
+        let sweeper = tokio::spawn(expiration_sweeper(
+            store.clone(),
+            receiver,
+            Arc::clone(&state),
+        ));
+        time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(store.len(), 1);
+
+        interval.send(Duration::from_millis(10)).unwrap();
+        time::timeout(Duration::from_secs(5), async {
+            while store.len() > 0 {
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        state.shutdown();
+        time::timeout(Duration::from_secs(5), sweeper)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
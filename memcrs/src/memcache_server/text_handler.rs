@@ -0,0 +1,584 @@
+use crate::cache::error::CacheError;
+use crate::memcache::key_stats::HOT_KEYS_COUNT;
+use crate::memcache::store;
+use crate::protocol::text;
+use crate::protocol::text_codec::{storage_error_to_response, TextRequest, TextResponse};
+use crate::version::MEMCRS_VERSION;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+// incr and decr don't create missing records in text protocol
+const NO_INITIAL_VALUE: u32 = 0xffffffff;
+
+pub struct TextHandler {
+    storage: Arc<store::MemcStore>,
+}
+
+impl TextHandler {
+    pub fn new(store: Arc<store::MemcStore>) -> TextHandler {
+        TextHandler { storage: store }
+    }
+
+    /// Returns None if a request doesn't have a response, i.e. quit,
+    /// or if a client asked for no reply and a request didn't fail
+    /// with an error
+    pub fn handle_request(&self, req: TextRequest) -> Option<TextResponse> {
+        let noreply = req.is_noreply();
+        let response = match req {
+            TextRequest::Get(get_request) => Some(self.get(get_request)),
+            TextRequest::GetAndTouch(gat_request) => Some(self.get_and_touch(gat_request)),
+            TextRequest::Store(store_request) => Some(self.store(store_request)),
+            TextRequest::Delete(delete_request) => Some(self.delete(delete_request)),
+            TextRequest::Touch(touch_request) => Some(self.touch(touch_request)),
+            TextRequest::Increment(delta_request) => Some(self.delta(delta_request, true)),
+            TextRequest::Decrement(delta_request) => Some(self.delta(delta_request, false)),
+            TextRequest::FlushAll(flush_request) => Some(self.flush(flush_request)),
+            TextRequest::Stats(group) => Some(self.stats(group)),
+            TextRequest::Version => Some(TextResponse::Version(MEMCRS_VERSION)),
+            TextRequest::Quit => None,
+            TextRequest::ItemTooLarge => Some(storage_error_to_response(CacheError::ValueTooLarge)),
+            TextRequest::UnknownCommand => Some(TextResponse::Error),
+            TextRequest::BadFormat => Some(TextResponse::ClientError("bad command line format")),
+            TextRequest::BadDataChunk => Some(TextResponse::ClientError("bad data chunk")),
+        }?;
+        if noreply && !response.is_error() {
+            return None;
+        }
+        Some(response)
+    }
+
+    fn get(&self, get_request: text::GetRequest) -> TextResponse {
+        let values = self
+            .storage
+            .get_batch_hits(&get_request.keys)
+            .into_iter()
+            .map(|(key, record)| text::Value {
+                key,
+                flags: record.header.flags,
+                cas: get_request.with_cas.then_some(record.header.cas),
+                data: record.value,
+            })
+            .collect();
+        TextResponse::Values(values)
+    }
+
+    fn get_and_touch(&self, gat_request: text::GetAndTouchRequest) -> TextResponse {
+        let values = gat_request
+            .keys
+            .into_iter()
+            .filter_map(|key| {
+                let record = self
+                    .storage
+                    .get_and_touch(&key, time_to_live(gat_request.expiration))
+                    .ok()?;
+                if gat_request.expiration < 0 {
+                    self.storage.expire(&key);
+                }
+                Some(text::Value {
+                    key,
+                    flags: record.header.flags,
+                    cas: gat_request.with_cas.then_some(record.header.cas),
+                    data: record.value,
+                })
+            })
+            .collect();
+        TextResponse::Values(values)
+    }
+
+    fn store(&self, store_request: text::StoreRequest) -> TextResponse {
+        let key = store_request.key;
+        let record = store::Record::new(
+            store_request.value,
+            0,
+            store_request.flags,
+            time_to_live(store_request.expiration),
+        );
+        // append and prepend keep expiration of a stored record
+        let expired = store_request.expiration < 0
+            && !matches!(
+                store_request.command,
+                text::StoreCommand::Append | text::StoreCommand::Prepend
+            );
+        let result = match store_request.command {
+            text::StoreCommand::Set => self.storage.set(key.clone(), record),
+            text::StoreCommand::Add => self.storage.add(key.clone(), record),
+            text::StoreCommand::Replace => self.storage.replace(key.clone(), record),
+            text::StoreCommand::Append => self.storage.append(key.clone(), record),
+            text::StoreCommand::Prepend => self.storage.prepend(key.clone(), record),
+            text::StoreCommand::Cas(cas) => self.storage.cas(key.clone(), record, cas),
+        };
+        if expired && result.is_ok() {
+            self.storage.expire(&key);
+        }
+        if let text::StoreCommand::Cas(_cas) = store_request.command {
+            return match result {
+                Ok(_status) => TextResponse::Stored,
+                Err(err) => storage_error_to_response(err),
+            };
+        }
+        match result {
+            Ok(_status) => TextResponse::Stored,
+            // add, replace, append and prepend report failed condition
+            // as not stored
+            Err(CacheError::KeyExists) | Err(CacheError::NotFound) => TextResponse::NotStored,
+            Err(err) => storage_error_to_response(err),
+        }
+    }
+
+    fn delete(&self, delete_request: text::DeleteRequest) -> TextResponse {
+        match self
+            .storage
+            .delete(delete_request.key, store::Meta::new(0, 0, 0))
+        {
+            Ok(_record) => TextResponse::Deleted,
+            Err(err) => storage_error_to_response(err),
+        }
+    }
+
+    fn touch(&self, touch_request: text::TouchRequest) -> TextResponse {
+        match self
+            .storage
+            .touch(&touch_request.key, time_to_live(touch_request.expiration))
+        {
+            Ok(()) => {
+                if touch_request.expiration < 0 {
+                    self.storage.expire(&touch_request.key);
+                }
+                TextResponse::Touched
+            }
+            Err(err) => storage_error_to_response(err),
+        }
+    }
+
+    fn delta(&self, delta_request: text::DeltaRequest, increment: bool) -> TextResponse {
+        let header = store::Meta::new(0, 0, NO_INITIAL_VALUE);
+        let delta = store::IncrementParam::from(delta_request.delta);
+        let result = if increment {
+            self.storage.increment(header, delta_request.key, delta)
+        } else {
+            self.storage.decrement(header, delta_request.key, delta)
+        };
+        match result {
+            Ok(delta_result) => TextResponse::Number(delta_result.value),
+            Err(err) => storage_error_to_response(err),
+        }
+    }
+
+    fn flush(&self, flush_request: text::FlushRequest) -> TextResponse {
+        let affected = self
+            .storage
+            .flush(store::Meta::new(0, 0, flush_request.delay));
+        debug!("Flush affected {} records", affected);
+        TextResponse::Ok
+    }
+
+    // records are not kept in slabs, so all of them are reported
+    // as items of slab class 1
+    fn stats(&self, group: text::StatsGroup) -> TextResponse {
+        let counters = self.storage.counters();
+        let stats = match group {
+            text::StatsGroup::General => counters
+                .values()
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+            text::StatsGroup::Items => {
+                let snapshot = self.storage.stats();
+                vec![
+                    ("number", snapshot.curr_items as u64),
+                    ("age", snapshot.oldest_item_age.unwrap_or(0)),
+                    ("evicted", counters.evictions.load(Ordering::Relaxed)),
+                    ("expired_unfetched", snapshot.expired_unfetched),
+                ]
+                .into_iter()
+                .map(|(name, value)| (format!("items:1:{}", name), value))
+                .collect()
+            }
+            text::StatsGroup::Settings => counters
+                .settings()
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+            // keys are used as stat names, so binary keys are not escaped
+            text::StatsGroup::HotKeys => counters
+                .key_stats
+                .hot_keys(HOT_KEYS_COUNT)
+                .into_iter()
+                .map(|(key, hits)| (String::from_utf8_lossy(&key).into_owned(), hits))
+                .collect(),
+        };
+        TextResponse::Stats(stats)
+    }
+}
+
+// memcached treats a negative expiration as already expired, a request
+// is handled as if a record never expired and the record is removed once
+// it is
+fn time_to_live(expiration: i64) -> u32 {
+    expiration.max(0) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_store::store::MemoryStore;
+    use crate::mock::mock_server::{create_storage, MockSystemTimer, SetableTimer};
+    use bytes::Bytes;
+
+    fn create_handler() -> TextHandler {
+        TextHandler::new(create_storage())
+    }
+
+    fn store_request(command: text::StoreCommand, key: &str, value: &str) -> TextRequest {
+        TextRequest::Store(text::StoreRequest {
+            command,
+            key: Bytes::from(key.to_string()),
+            flags: 5,
+            expiration: 0,
+            value: Bytes::from(value.to_string()),
+            noreply: false,
+        })
+    }
+
+    fn get_request(keys: &[&str], with_cas: bool) -> TextRequest {
+        TextRequest::Get(text::GetRequest {
+            keys: keys
+                .iter()
+                .map(|key| Bytes::from(key.to_string()))
+                .collect(),
+            with_cas,
+        })
+    }
+
+    fn get_values(handler: &TextHandler, keys: &[&str], with_cas: bool) -> Vec<text::Value> {
+        match handler.handle_request(get_request(keys, with_cas)) {
+            Some(TextResponse::Values(values)) => values,
+            response => panic!("Unexpected response {:?}", response),
+        }
+    }
+
+    #[test]
+    fn get_should_return_only_found_values() {
+        let handler = create_handler();
+        let response = handler.handle_request(store_request(text::StoreCommand::Set, "foo", "bar"));
+        assert_eq!(response, Some(TextResponse::Stored));
+
+        let values = get_values(&handler, &["missing", "foo"], false);
+        assert_eq!(
+            values,
+            vec![text::Value {
+                key: Bytes::from("foo"),
+                flags: 5,
+                cas: None,
+                data: Bytes::from("bar"),
+            }]
+        );
+        assert!(get_values(&handler, &["foo"], true)[0].cas.is_some());
+    }
+
+    #[test]
+    fn conditional_store_should_return_not_stored() {
+        let handler = create_handler();
+        for command in [
+            text::StoreCommand::Replace,
+            text::StoreCommand::Append,
+            text::StoreCommand::Prepend,
+        ] {
+            let response = handler.handle_request(store_request(command, "foo", "bar"));
+            assert_eq!(response, Some(TextResponse::NotStored));
+        }
+        handler.handle_request(store_request(text::StoreCommand::Add, "foo", "bar"));
+        let response = handler.handle_request(store_request(text::StoreCommand::Add, "foo", "baz"));
+        assert_eq!(response, Some(TextResponse::NotStored));
+
+        handler.handle_request(store_request(text::StoreCommand::Append, "foo", "2"));
+        handler.handle_request(store_request(text::StoreCommand::Prepend, "foo", "1"));
+        assert_eq!(
+            get_values(&handler, &["foo"], false)[0].data,
+            Bytes::from("1bar2")
+        );
+    }
+
+    #[test]
+    fn cas_should_check_stored_cas() {
+        let handler = create_handler();
+        let response =
+            handler.handle_request(store_request(text::StoreCommand::Cas(1), "foo", "bar"));
+        assert_eq!(response, Some(TextResponse::NotFound));
+
+        handler.handle_request(store_request(text::StoreCommand::Set, "foo", "bar"));
+        let cas = get_values(&handler, &["foo"], true)[0].cas.unwrap();
+        let response = handler.handle_request(store_request(
+            text::StoreCommand::Cas(cas + 1),
+            "foo",
+            "baz",
+        ));
+        assert_eq!(response, Some(TextResponse::Exists));
+        let response =
+            handler.handle_request(store_request(text::StoreCommand::Cas(cas), "foo", "baz"));
+        assert_eq!(response, Some(TextResponse::Stored));
+    }
+
+    #[test]
+    fn incr_decr_should_not_create_missing_counter() {
+        let handler = create_handler();
+        let increment = |delta| {
+            handler.handle_request(TextRequest::Increment(text::DeltaRequest {
+                key: Bytes::from("counter"),
+                delta,
+                noreply: false,
+            }))
+        };
+        assert_eq!(increment(1), Some(TextResponse::NotFound));
+
+        handler.handle_request(store_request(text::StoreCommand::Set, "counter", "10"));
+        assert_eq!(increment(5), Some(TextResponse::Number(15)));
+        let response = handler.handle_request(TextRequest::Decrement(text::DeltaRequest {
+            key: Bytes::from("counter"),
+            delta: 20,
+            noreply: false,
+        }));
+        assert_eq!(response, Some(TextResponse::Number(0)));
+    }
+
+    #[test]
+    fn incr_should_keep_flags_and_expiration_of_counter() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let handler = TextHandler::new(Arc::new(store::MemcStore::new(MemoryStore::new_shared(
+            timer.clone(),
+        ))));
+        let mut request = store_request(text::StoreCommand::Set, "counter", "10");
+        if let TextRequest::Store(store_request) = &mut request {
+            store_request.expiration = 2;
+        }
+        handler.handle_request(request);
+
+        timer.set(1);
+        let response = handler.handle_request(TextRequest::Increment(text::DeltaRequest {
+            key: Bytes::from("counter"),
+            delta: 5,
+            noreply: false,
+        }));
+        assert_eq!(response, Some(TextResponse::Number(15)));
+        let values = get_values(&handler, &["counter"], false);
+        assert_eq!(values[0].flags, 5);
+        assert_eq!(values[0].data, Bytes::from("15"));
+
+        // expires 2 seconds after it was set, not after incr
+        timer.set(2);
+        assert!(get_values(&handler, &["counter"], false).is_empty());
+    }
+
+    #[test]
+    fn negative_expiration_should_expire_record_at_once() {
+        let handler = create_handler();
+        let store = |command, key, expiration| {
+            let mut request = store_request(command, key, "10");
+            if let TextRequest::Store(store_request) = &mut request {
+                store_request.expiration = expiration;
+            }
+            handler.handle_request(request)
+        };
+        store(text::StoreCommand::Set, "foo", 0);
+        assert_eq!(
+            store(text::StoreCommand::Add, "foo", -1),
+            Some(TextResponse::NotStored)
+        );
+        assert_eq!(get_values(&handler, &["foo"], false).len(), 1);
+
+        // a stored record is replaced by an expired one
+        assert_eq!(
+            store(text::StoreCommand::Set, "foo", -1),
+            Some(TextResponse::Stored)
+        );
+        assert!(get_values(&handler, &["foo"], false).is_empty());
+
+        store(text::StoreCommand::Set, "foo", 0);
+        let touch = |expiration| {
+            handler.handle_request(TextRequest::Touch(text::TouchRequest {
+                key: Bytes::from("foo"),
+                expiration,
+                noreply: false,
+            }))
+        };
+        assert_eq!(touch(-1), Some(TextResponse::Touched));
+        assert!(get_values(&handler, &["foo"], false).is_empty());
+        assert_eq!(touch(-1), Some(TextResponse::NotFound));
+    }
+
+    #[test]
+    fn gat_should_return_only_found_values() {
+        let handler = create_handler();
+        handler.handle_request(store_request(text::StoreCommand::Set, "foo", "bar"));
+        let cas = get_values(&handler, &["foo"], true)[0].cas;
+        let gat = |with_cas| {
+            handler.handle_request(TextRequest::GetAndTouch(text::GetAndTouchRequest {
+                keys: vec![Bytes::from("missing"), Bytes::from("foo")],
+                expiration: 60,
+                with_cas,
+            }))
+        };
+        let value = text::Value {
+            key: Bytes::from("foo"),
+            flags: 5,
+            cas: None,
+            data: Bytes::from("bar"),
+        };
+        assert_eq!(gat(false), Some(TextResponse::Values(vec![value.clone()])));
+        // touch doesn't change CAS
+        assert_eq!(
+            gat(true),
+            Some(TextResponse::Values(vec![text::Value { cas, ..value }]))
+        );
+    }
+
+    #[test]
+    fn touch_should_report_missing_key() {
+        let handler = create_handler();
+        let touch = || {
+            handler.handle_request(TextRequest::Touch(text::TouchRequest {
+                key: Bytes::from("foo"),
+                expiration: 60,
+                noreply: false,
+            }))
+        };
+        assert_eq!(touch(), Some(TextResponse::NotFound));
+        handler.handle_request(store_request(text::StoreCommand::Set, "foo", "bar"));
+        assert_eq!(touch(), Some(TextResponse::Touched));
+    }
+
+    #[test]
+    fn delete_and_flush_should_remove_records() {
+        let handler = create_handler();
+        handler.handle_request(store_request(text::StoreCommand::Set, "foo", "bar"));
+        handler.handle_request(store_request(text::StoreCommand::Set, "baz", "bar"));
+        let delete = || {
+            handler.handle_request(TextRequest::Delete(text::DeleteRequest {
+                key: Bytes::from("foo"),
+                noreply: false,
+            }))
+        };
+        assert_eq!(delete(), Some(TextResponse::Deleted));
+        assert_eq!(delete(), Some(TextResponse::NotFound));
+
+        let response = handler.handle_request(TextRequest::FlushAll(text::FlushRequest {
+            delay: 0,
+            noreply: false,
+        }));
+        assert_eq!(response, Some(TextResponse::Ok));
+        assert!(get_values(&handler, &["baz"], false).is_empty());
+    }
+
+    #[test]
+    fn stats_should_report_counters() {
+        let handler = create_handler();
+        handler.handle_request(store_request(text::StoreCommand::Set, "foo", "bar"));
+        get_values(&handler, &["foo", "missing"], false);
+        let stats = match handler.handle_request(TextRequest::Stats(text::StatsGroup::General)) {
+            Some(TextResponse::Stats(stats)) => stats,
+            response => panic!("Unexpected response {:?}", response),
+        };
+        let stat = |name: &str| {
+            stats
+                .iter()
+                .find(|(stat, _value)| stat == name)
+                .map(|(_stat, value)| *value)
+        };
+        assert_eq!(stat("cmd_get"), Some(2));
+        assert_eq!(stat("get_hits"), Some(1));
+        assert_eq!(stat("get_misses"), Some(1));
+        assert_eq!(stat("cmd_set"), Some(1));
+        assert_eq!(stat("curr_items"), Some(1));
+
+        let response = handler.handle_request(TextRequest::Stats(text::StatsGroup::Items));
+        match response {
+            Some(TextResponse::Stats(stats)) => {
+                assert_eq!(stats[0], (String::from("items:1:number"), 1))
+            }
+            response => panic!("Unexpected response {:?}", response),
+        }
+    }
+
+    #[test]
+    fn stats_hotkeys_should_report_keys_with_most_hits() {
+        let handler = create_handler();
+        handler.handle_request(store_request(text::StoreCommand::Set, "foo", "bar"));
+        handler.handle_request(store_request(text::StoreCommand::Set, "baz", "bar"));
+        get_values(&handler, &["foo", "baz", "foo", "missing"], false);
+        let hot_keys = |handler: &TextHandler| match handler
+            .handle_request(TextRequest::Stats(text::StatsGroup::HotKeys))
+        {
+            Some(TextResponse::Stats(stats)) => stats,
+            response => panic!("Unexpected response {:?}", response),
+        };
+        assert_eq!(
+            hot_keys(&handler),
+            vec![
+                (String::from("foo"), 2),
+                (String::from("baz"), 1),
+                (String::from("missing"), 0)
+            ]
+        );
+
+        handler.handle_request(TextRequest::FlushAll(text::FlushRequest {
+            delay: 0,
+            noreply: false,
+        }));
+        assert!(hot_keys(&handler).is_empty());
+    }
+
+    #[test]
+    fn version_should_return_crate_version() {
+        let handler = create_handler();
+        let version = match handler.handle_request(TextRequest::Version) {
+            Some(TextResponse::Version(version)) => version,
+            response => panic!("Unexpected response {:?}", response),
+        };
+        assert_eq!(version, env!("CARGO_PKG_VERSION"));
+        let parts: Vec<u64> = version
+            .split('.')
+            .map(|part| part.parse().unwrap())
+            .collect();
+        assert_eq!(parts.len(), 3);
+    }
+
+    #[test]
+    fn noreply_should_suppress_all_but_error_responses() {
+        let handler = create_handler();
+        let noreply_store =
+            |command, key: &str, value: &str| match store_request(command, key, value) {
+                TextRequest::Store(request) => {
+                    handler.handle_request(TextRequest::Store(text::StoreRequest {
+                        noreply: true,
+                        ..request
+                    }))
+                }
+                request => panic!("Unexpected request {:?}", request),
+            };
+        assert_eq!(noreply_store(text::StoreCommand::Set, "foo", "bar"), None);
+        assert_eq!(noreply_store(text::StoreCommand::Add, "foo", "baz"), None);
+        assert_eq!(noreply_store(text::StoreCommand::Set, "counter", "a"), None);
+        assert_eq!(get_values(&handler, &["foo"], false)[0].data, "bar");
+
+        let delta = |key: &str| {
+            handler.handle_request(TextRequest::Increment(text::DeltaRequest {
+                key: Bytes::from(key.to_string()),
+                delta: 1,
+                noreply: true,
+            }))
+        };
+        assert_eq!(delta("missing"), None);
+        assert_eq!(
+            delta("counter"),
+            Some(TextResponse::ClientError(
+                "cannot increment or decrement non-numeric value"
+            ))
+        );
+        let response = handler.handle_request(TextRequest::Delete(text::DeleteRequest {
+            key: Bytes::from("foo"),
+            noreply: true,
+        }));
+        assert_eq!(response, None);
+        assert!(get_values(&handler, &["foo"], false).is_empty());
+    }
+}
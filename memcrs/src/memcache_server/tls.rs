@@ -0,0 +1,160 @@
+//! TLS of client connections with rustls. A handshake is completed
+//! before a stream is handed to a protocol connection, so protocol
+//! detection and handlers are the same as for plain TCP.
+
+use rustls::crypto::ring;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Stream of a client connected over TLS
+pub type TlsStream<S> = tokio_rustls::server::TlsStream<S>;
+
+/// Accepts TLS connections with a certificate loaded from PEM files
+pub struct TlsAcceptor {
+    acceptor: tokio_rustls::TlsAcceptor,
+}
+
+impl TlsAcceptor {
+    /// Loads a certificate chain and its private key, if ca_path is set
+    /// clients have to present a certificate signed by one of its CAs
+    pub fn from_pem_files(
+        cert_path: &Path,
+        key_path: &Path,
+        ca_path: Option<&Path>,
+    ) -> io::Result<TlsAcceptor> {
+        // a provider is passed explicitly, so a process wide default
+        // doesn't have to be installed
+        let provider = Arc::new(ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?;
+        let builder = match ca_path {
+            Some(ca_path) => builder.with_client_cert_verifier(
+                WebPkiClientVerifier::builder_with_provider(
+                    Arc::new(load_roots(ca_path)?),
+                    provider,
+                )
+                .build()
+                .map_err(io::Error::other)?,
+            ),
+            None => builder.with_no_client_auth(),
+        };
+        // fails if a key doesn't match a certificate
+        let config = builder
+            .with_single_cert(load_certs(cert_path)?, load_key(key_path)?)
+            .map_err(io::Error::other)?;
+        Ok(TlsAcceptor {
+            acceptor: tokio_rustls::TlsAcceptor::from(Arc::new(config)),
+        })
+    }
+
+    /// Completes a server side handshake on a stream
+    pub async fn accept<S>(&self, stream: S) -> io::Result<TlsStream<S>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        self.acceptor.accept(stream).await
+    }
+}
+
+fn load_certs(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .map_err(io::Error::other)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(io::Error::other)?;
+    if certs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no certificate in {}", path.display()),
+        ));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> io::Result<PrivateKeyDer<'static>> {
+    PrivateKeyDer::from_pem_file(path).map_err(io::Error::other)
+}
+
+fn load_roots(path: &Path) -> io::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(cert).map_err(io::Error::other)?;
+    }
+    Ok(roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::pki_types::ServerName;
+    use rustls::ClientConfig;
+    use std::path::PathBuf;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // writes a self-signed certificate for localhost and its key
+    // to a temp directory
+    fn write_certificate(name: &str) -> (PathBuf, PathBuf) {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let dir = std::env::temp_dir();
+        let prefix = format!("memcrs-tls-{}-{}", name, std::process::id());
+        let cert_path = dir.join(format!("{}.crt", prefix));
+        let key_path = dir.join(format!("{}.key", prefix));
+        std::fs::write(&cert_path, certified.cert.pem()).unwrap();
+        std::fs::write(&key_path, certified.signing_key.serialize_pem()).unwrap();
+        (cert_path, key_path)
+    }
+
+    #[tokio::test]
+    async fn tls_stream_should_exchange_data_after_handshake() {
+        let (cert_path, key_path) = write_certificate("stream");
+        let acceptor = TlsAcceptor::from_pem_files(&cert_path, &key_path, None).unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // a client trusts the self-signed certificate of a server
+        let roots = load_roots(&cert_path).unwrap();
+        let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let client = tokio::spawn(async move {
+            let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+            let socket = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let server_name = ServerName::try_from("localhost").unwrap();
+            let mut stream = connector.connect(server_name, socket).await.unwrap();
+            stream.write_all(b"ping").await.unwrap();
+            stream.flush().await.unwrap();
+            let mut response = [0u8; 4];
+            stream.read_exact(&mut response).await.unwrap();
+            response
+        });
+        let (socket, _) = listener.accept().await.unwrap();
+        let mut stream = acceptor.accept(socket).await.unwrap();
+        let mut request = [0u8; 4];
+        stream.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request, b"ping");
+        stream.write_all(b"pong").await.unwrap();
+        stream.flush().await.unwrap();
+        assert_eq!(&client.await.unwrap(), b"pong");
+
+        std::fs::remove_file(cert_path).unwrap();
+        std::fs::remove_file(key_path).unwrap();
+    }
+
+    #[test]
+    fn acceptor_should_fail_if_key_doesnt_match_certificate() {
+        let (cert_path, key_path) = write_certificate("first");
+        let (other_cert_path, other_key_path) = write_certificate("second");
+        assert!(TlsAcceptor::from_pem_files(&cert_path, &other_key_path, None).is_err());
+        for path in [cert_path, key_path, other_cert_path, other_key_path] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! FxHash, the hash function of rustc and rustc-hash crate. It is fast
+//! on short keys, but not DoS resistant, so keys shouldn't be chosen
+//! by untrusted clients.
+
+use std::hash::{BuildHasherDefault, Hasher};
+
+// multiplier of rustc-hash 1.x FxHasher
+const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+/// Hasher mixing input by 8 byte words with a rotate, xor and multiply
+#[derive(Default, Clone, Copy)]
+pub struct FxHasher {
+    hash: u64,
+}
+
+/// Creates FxHasher, same hasher for every store, there is no random seed
+pub type FxBuildHasher = BuildHasherDefault<FxHasher>;
+
+impl FxHasher {
+    #[inline]
+    fn add_to_hash(&mut self, word: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(SEED);
+    }
+}
+
+impl Hasher for FxHasher {
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        let mut words = bytes.chunks_exact(8);
+        for word in &mut words {
+            self.add_to_hash(u64::from_le_bytes(word.try_into().unwrap()));
+        }
+        // slices write their length before bytes, so a zero padded
+        // tail doesn't collide with a longer key
+        let tail = words.remainder();
+        if !tail.is_empty() {
+            let mut word = [0u8; 8];
+            word[..tail.len()].copy_from_slice(tail);
+            self.add_to_hash(u64::from_le_bytes(word));
+        }
+    }
+
+    #[inline]
+    fn write_u8(&mut self, value: u8) {
+        self.add_to_hash(value as u64);
+    }
+
+    #[inline]
+    fn write_u32(&mut self, value: u32) {
+        self.add_to_hash(value as u64);
+    }
+
+    #[inline]
+    fn write_u64(&mut self, value: u64) {
+        self.add_to_hash(value);
+    }
+
+    #[inline]
+    fn write_usize(&mut self, value: usize) {
+        self.add_to_hash(value as u64);
+    }
+
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hash::BuildHasher;
+
+    #[test]
+    fn hash_should_be_same_for_every_hasher() {
+        let key: &[u8] = b"user:1234:session";
+        assert_eq!(
+            FxBuildHasher::default().hash_one(key),
+            FxBuildHasher::default().hash_one(key)
+        );
+    }
+
+    #[test]
+    fn keys_differing_by_trailing_zero_should_have_different_hashes() {
+        let hasher = FxBuildHasher::default();
+        let keys: [&[u8]; 4] = [b"ab", b"ab\0", b"abcdefgh", b"abcdefgh\0"];
+        for (index, key) in keys.iter().enumerate() {
+            for other in &keys[index + 1..] {
+                assert_ne!(hasher.hash_one(key), hasher.hash_one(other));
+            }
+        }
+    }
+}
@@ -1 +1,2 @@
+pub mod fx_hasher;
 pub mod store;
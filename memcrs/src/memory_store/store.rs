@@ -1,24 +1,89 @@
+#[cfg(any(test, feature = "debug"))]
+use crate::cache::cache::DebugDump;
 use crate::cache::cache::{
     impl_details, Cache, CacheMetaData, CachePredicate, CacheReadOnlyView, KeyType, Record,
-    RemoveIfResult, SetStatus,
+    RecordPredicate, RemoveIfResult, SetCondition, SetStatus, ShardStats, StorageSnapshot,
+    ValueType,
 };
 use crate::cache::error::{CacheError, Result};
+use crate::cache::key_validation::{self, KeyPolicy};
+use crate::memory_store::fx_hasher::FxBuildHasher;
 use crate::server::timer;
+use dashmap::mapref::entry::Entry;
 use dashmap::mapref::multiple::RefMulti;
-use dashmap::{DashMap, ReadOnlyView};
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+use dashmap::try_result::TryResult;
+use dashmap::{DashMap, ReadOnlyView, SharedValue};
+use rand::Rng;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+use std::hash::BuildHasher;
+use std::ops::Bound;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
 
-type Storage = DashMap<KeyType, Record>;
-pub struct MemoryStore {
-    memory: Storage,
+type Storage<H> = DashMap<KeyType, Record, H>;
+
+type KeyWatcher = watch::Sender<Option<Arc<Record>>>;
+
+/// Hash function of store keys, a store may be created with a hasher
+/// fitting a key distribution, see MemoryStore::with_hasher
+pub trait StoreHasher: BuildHasher + Default + Clone + Send + Sync + 'static {}
+
+impl<H: BuildHasher + Default + Clone + Send + Sync + 'static> StoreHasher for H {}
+
+/// Store hashing keys with aHash, used unless another hasher is given
+pub type DefaultMemoryStore = MemoryStore<ahash::RandomState>;
+
+/// Store hashing keys with SipHash 1-3 of std, DoS resistant but slower
+pub type SipMemoryStore = MemoryStore<std::collections::hash_map::RandomState>;
+
+/// Store hashing keys with FxHash, fast on short keys, but not DoS resistant
+pub type FxMemoryStore = MemoryStore<FxBuildHasher>;
+
+// get_random_sample picks one of this many first records of a shard
+const SAMPLE_MAX_SKIP: usize = 16;
+
+/// Values over this many bytes are rejected unless with_max_value_size is set,
+/// as memcached default item size limit
+pub const DEFAULT_MAX_VALUE_SIZE: usize = 1024 * 1024;
+pub struct MemoryStore<H: StoreHasher = ahash::RandomState> {
+    memory: Storage<H>,
     timer: Arc<dyn timer::Timer + Send + Sync>,
     cas_id: AtomicU64,
+    memory_used: AtomicU64,
+    // number of stored records, so len doesn't lock every shard
+    item_count: AtomicUsize,
+    // inserts over this many bytes fail with OutOfMemory, 0 is no limit
+    memory_limit: AtomicU64,
+    expired_unfetched: AtomicU64,
+    flush_affected_items: AtomicU64,
+    key_policy: KeyPolicy,
+    // writes of longer values fail with ValueTooLarge
+    max_value_size: usize,
+    // sorted copy of stored keys, kept only if prefix index is enabled
+    prefix_index: Option<Mutex<BTreeSet<KeyType>>>,
+    // reads update accessed_at of records
+    track_access: bool,
+    // timer timestamp when a store was created
+    start_time: u64,
+    // senders of channels returned by watch_key
+    watchers: DashMap<KeyType, KeyWatcher>,
+    // number of watched keys, so writes don't look into watchers
+    // if no key is watched
+    watched_keys: AtomicUsize,
 }
 
-type StorageReadOnlyView = ReadOnlyView<KeyType, Record>;
+// store is shared between server threads, fails to compile
+// if a field which is not thread safe is added
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<MemoryStore>();
+};
+
+type StorageReadOnlyView<H> = ReadOnlyView<KeyType, Record, H>;
 
-impl<'a> CacheReadOnlyView<'a> for StorageReadOnlyView {
+impl<'a, H: StoreHasher> CacheReadOnlyView<'a> for StorageReadOnlyView<H> {
     fn len(&self) -> usize {
         StorageReadOnlyView::len(self)
     }
@@ -35,20 +100,470 @@ impl<'a> CacheReadOnlyView<'a> for StorageReadOnlyView {
 
 impl MemoryStore {
     pub fn new(timer: Arc<dyn timer::Timer + Send + Sync>) -> MemoryStore {
+        MemoryStore::with_hasher(timer, ahash::RandomState::new())
+    }
+
+    /// Creates a store pre-sized for memory_limit / avg_item_size records,
+    /// so a map doesn't have to grow while a cache is warming up
+    pub fn with_capacity(
+        timer: Arc<dyn timer::Timer + Send + Sync>,
+        memory_limit: u64,
+        avg_item_size: usize,
+    ) -> MemoryStore {
+        MemoryStore::with_capacity_and_hasher(
+            timer,
+            memory_limit,
+            avg_item_size,
+            ahash::RandomState::new(),
+        )
+    }
+
+    /// Store is shared by its users, i.e. a memcache policy and
+    /// the admin API, all operations take &self
+    pub fn new_shared(timer: Arc<dyn timer::Timer + Send + Sync>) -> Arc<MemoryStore> {
+        Arc::new(MemoryStore::new(timer))
+    }
+
+    pub fn new_shared_with_capacity(
+        timer: Arc<dyn timer::Timer + Send + Sync>,
+        memory_limit: u64,
+        avg_item_size: usize,
+    ) -> Arc<MemoryStore> {
+        Arc::new(MemoryStore::with_capacity(
+            timer,
+            memory_limit,
+            avg_item_size,
+        ))
+    }
+}
+
+impl<H: StoreHasher> MemoryStore<H> {
+    /// Creates a store hashing keys with a given hasher,
+    /// i.e. MemoryStore::with_hasher(timer, H::default())
+    pub fn with_hasher(timer: Arc<dyn timer::Timer + Send + Sync>, hasher: H) -> Self {
+        MemoryStore::with_capacity_and_hasher(timer, 0, 1, hasher)
+    }
+
+    pub fn with_capacity_and_hasher(
+        timer: Arc<dyn timer::Timer + Send + Sync>,
+        memory_limit: u64,
+        avg_item_size: usize,
+        hasher: H,
+    ) -> Self {
+        let capacity = memory_limit / avg_item_size.max(1) as u64;
         MemoryStore {
-            memory: DashMap::new(),
+            memory: DashMap::with_capacity_and_hasher(capacity as usize, hasher),
+            start_time: timer.timestamp(),
             timer,
             cas_id: AtomicU64::new(1),
+            memory_used: AtomicU64::new(0),
+            item_count: AtomicUsize::new(0),
+            memory_limit: AtomicU64::new(0),
+            expired_unfetched: AtomicU64::new(0),
+            flush_affected_items: AtomicU64::new(0),
+            key_policy: KeyPolicy::default(),
+            max_value_size: DEFAULT_MAX_VALUE_SIZE,
+            prefix_index: None,
+            track_access: false,
+            watchers: DashMap::new(),
+            watched_keys: AtomicUsize::new(0),
         }
     }
 
+    /// Rejects inserts with OutOfMemory once keys and records would take
+    /// more than memory_limit bytes, see Record::size_estimate. Records
+    /// are never evicted, memory is freed only by deletes and removal
+    /// of expired records. The limit is checked against a store wide counter
+    /// while only a shard of a key is locked, so concurrent inserts may
+    /// exceed it by the size of records being inserted at the same time.
+    pub fn with_memory_limit(self, memory_limit: u64) -> Self {
+        self.memory_limit.store(memory_limit, Ordering::Relaxed);
+        self
+    }
+
+    /// Sets rules which keys of stored records have to satisfy,
+    /// by default Memcached key rules are used
+    pub fn with_key_policy(mut self, key_policy: KeyPolicy) -> Self {
+        self.key_policy = key_policy;
+        self
+    }
+
+    /// Rejects writes of values longer than max_value_size bytes with
+    /// ValueTooLarge, by default DEFAULT_MAX_VALUE_SIZE
+    pub fn with_max_value_size(mut self, max_value_size: usize) -> Self {
+        self.max_value_size = max_value_size;
+        self
+    }
+
+    /// Splits a store into shard_count independently locked shards instead
+    /// of DashMap default, more shards mean less contention between threads
+    /// writing different keys. shard_count has to be a power of two greater than 1.
+    pub fn with_shard_count(mut self, shard_count: usize) -> Self {
+        let memory = DashMap::with_capacity_and_hasher_and_shard_amount(
+            self.memory.capacity(),
+            self.memory.hasher().clone(),
+            shard_count,
+        );
+        for (key, record) in self.memory {
+            memory.insert(key, record);
+        }
+        self.memory = memory;
+        self
+    }
+
+    /// Updates access time of a record on every get, so records can be
+    /// ordered by recency of use. A read takes a shard write lock then,
+    /// so concurrent reads of keys in the same shard are serialized.
+    pub fn with_access_tracking(mut self) -> Self {
+        self.track_access = true;
+        self
+    }
+
+    /// Keeps a sorted index of keys, so prefix_scan doesn't have to
+    /// iterate over a whole store. Every insert and remove of a key
+    /// updates the index under a single lock, so it slows down writes.
+    pub fn with_prefix_index(mut self) -> Self {
+        let keys = self.memory.iter().map(|record| record.key().clone());
+        self.prefix_index = Some(Mutex::new(keys.collect()));
+        self
+    }
+
+    fn check_value_size(&self, record: &Record) -> Result<()> {
+        if record.value.len() > self.max_value_size {
+            return Err(CacheError::ValueTooLarge);
+        }
+        Ok(())
+    }
+
     fn get_cas_id(&self) -> u64 {
         self.cas_id.fetch_add(1, Ordering::Release)
     }
+
+    // approximate number of bytes used by a key value pair
+    fn entry_size(key: &KeyType, record: &Record) -> u64 {
+        (key.len() + record.size_estimate()) as u64
+    }
+
+    /// Returns a channel which receives a new record of a key every time
+    /// it is stored and None when it is deleted or an expired record is
+    /// removed. The initial value of a channel is a current record, records
+    /// expire without a notification until they are removed. Receivers
+    /// may skip records if a key is modified faster than they are read.
+    pub fn watch_key(&self, key: KeyType) -> watch::Receiver<Option<Arc<Record>>> {
+        // a shard lock is held while a watcher is registered, so
+        // no write of a key happens between a read and a subscription
+        let shard = self.memory.shards()[self.memory.determine_map(&key)].read();
+        let current_time = self.timer.millis();
+        let record = shard
+            .get(&key)
+            .map(SharedValue::get)
+            .filter(|record| !self.is_expired(record, current_time))
+            .map(|record| Arc::new(record.clone()));
+        match self.watchers.entry(key) {
+            Entry::Occupied(entry) => entry.get().subscribe(),
+            Entry::Vacant(entry) => {
+                let (sender, receiver) = watch::channel(record);
+                entry.insert(sender);
+                self.watched_keys.fetch_add(1, Ordering::Relaxed);
+                receiver
+            }
+        }
+    }
+
+    // called from account_insert and account_remove, so every path
+    // which stores or removes a record notifies watchers
+    fn notify_watchers(&self, key: &KeyType, record: Option<&Record>) {
+        if self.watched_keys.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        if let Entry::Occupied(entry) = self.watchers.entry(key.clone()) {
+            // send fails only if all receivers were dropped
+            if entry.get().send(record.cloned().map(Arc::new)).is_err() {
+                entry.remove();
+                self.watched_keys.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    // account_insert and account_remove are called with a shard lock held,
+    // so the prefix index is updated in the same order as a map
+    fn account_insert(&self, key: &KeyType, new_record: &Record, old_record: Option<&Record>) {
+        self.notify_watchers(key, Some(new_record));
+        match old_record {
+            Some(old_record) => self.account_resize(
+                Self::entry_size(key, old_record),
+                Self::entry_size(key, new_record),
+            ),
+            None => {
+                self.memory_used
+                    .fetch_add(Self::entry_size(key, new_record), Ordering::Relaxed);
+                self.item_count.fetch_add(1, Ordering::Relaxed);
+                if let Some(index) = &self.prefix_index {
+                    index.lock().unwrap().insert(key.clone());
+                }
+            }
+        }
+    }
+
+    fn account_remove(&self, key: &KeyType, record: &Record) {
+        self.notify_watchers(key, None);
+        self.memory_used
+            .fetch_sub(Self::entry_size(key, record), Ordering::Relaxed);
+        self.item_count.fetch_sub(1, Ordering::Relaxed);
+        if let Some(index) = &self.prefix_index {
+            index.lock().unwrap().remove(key);
+        }
+    }
+
+    // called before account_insert, while a shard lock of a key is held
+    fn check_memory_limit(&self, size: u64, replaced_size: u64) -> Result<()> {
+        let memory_limit = self.memory_limit.load(Ordering::Relaxed);
+        if memory_limit == 0 {
+            return Ok(());
+        }
+        let memory_used = self
+            .memory_used
+            .load(Ordering::Relaxed)
+            .saturating_sub(replaced_size);
+        if memory_used + size > memory_limit {
+            return Err(CacheError::OutOfMemory);
+        }
+        Ok(())
+    }
+
+    fn account_resize(&self, old_size: u64, new_size: u64) {
+        self.memory_used.fetch_add(new_size, Ordering::Relaxed);
+        self.memory_used.fetch_sub(old_size, Ordering::Relaxed);
+    }
+
+    // returns number of removed records
+    fn clear(&self) -> usize {
+        // retain holds shard locks, so memory accounting stays exact
+        // even with concurrent inserts
+        let mut removed = 0;
+        self.memory.retain(|key, record| {
+            self.account_remove(key, record);
+            removed += 1;
+            false
+        });
+        removed
+    }
+
+    /// Consumes a store and moves all records which are not expired out
+    /// of it, i.e. to migrate them to another backend. Values are not
+    /// copied, records are returned in no particular order.
+    pub fn drain(self) -> Vec<(KeyType, Record)> {
+        let current_time = self.timer.millis();
+        self.memory
+            .into_iter()
+            .filter(|(_key, record)| !record.header.is_expired(current_time))
+            .collect()
+    }
+
+    /// Returns entry for a key which can be atomically checked and modified,
+    /// entry holds a shard write lock, see StorageEntry
+    pub fn entry(&self, key: KeyType) -> StorageEntry<'_, H> {
+        StorageEntry::new(self, self.memory.entry(key))
+    }
+
+    fn is_expired(&self, record: &Record, current_time: u64) -> bool {
+        record.header.is_expired(current_time)
+    }
+
+    // Record could have been replaced by another thread since it was read,
+    // so it is removed only if it is still expired
+    fn remove_expired(&self, key: &KeyType, current_time: u64) -> Option<(KeyType, Record)> {
+        let result = self
+            .memory
+            .remove_if(key, |_key, record| self.is_expired(record, current_time));
+        if let Some((key, record)) = &result {
+            self.account_remove(key, record);
+        }
+        result
+    }
+
+    // linear scan over a store, used only by stats so it is not on a hot path
+    fn live_timestamps(&self, current_time: u64) -> impl Iterator<Item = u64> + '_ {
+        self.memory
+            .iter()
+            .filter(move |record| !self.is_expired(record.value(), current_time))
+            .map(|record| record.value().header.inserted_at)
+    }
+
+    fn get_record_field<T>(&self, key: &KeyType, field: fn(&Record) -> T) -> Result<T> {
+        key_validation::validate(key, &self.key_policy)?;
+        let expired = match self.memory.get(key) {
+            Some(record) => {
+                if !self.is_expired(&record, self.timer.millis()) {
+                    return Ok(field(&record));
+                }
+                true
+            }
+            None => false,
+        };
+        // read guard has to be released before expired record is removed
+        if expired {
+            self.remove_expired(key, self.timer.millis());
+        }
+        Err(CacheError::NotFound)
+    }
+
+    // updates expiration of a record under a shard write lock and
+    // returns a field of a touched record
+    fn touch_record<T>(
+        &self,
+        key: &KeyType,
+        expiration: u32,
+        field: fn(&Record) -> T,
+    ) -> Result<T> {
+        key_validation::validate(key, &self.key_policy)?;
+        let current_time = self.timer.millis();
+        let expired = match self.memory.get_mut(key) {
+            Some(mut record) => {
+                if !self.is_expired(&record, current_time) {
+                    record.header.time_to_live = expiration as u64 * 1000;
+                    record.header.inserted_at = current_time;
+                    if self.track_access {
+                        record.header.accessed_at = current_time;
+                    }
+                    return Ok(field(&record));
+                }
+                true
+            }
+            None => false,
+        };
+        // write guard has to be released before expired record is removed
+        if expired {
+            self.remove_expired(key, current_time);
+        }
+        Err(CacheError::NotFound)
+    }
+
+    fn stamp_record(&self, record: &mut Record, condition: SetCondition) -> SetStatus {
+        self.stamp_record_with_cas(record, condition, None)
+    }
+
+    // reserved_cas is used instead of a next CAS id if it was already
+    // taken from a counter, i.e. by set_many
+    fn stamp_record_with_cas(
+        &self,
+        record: &mut Record,
+        condition: SetCondition,
+        reserved_cas: Option<u64>,
+    ) -> SetStatus {
+        record.header.cas = match condition {
+            SetCondition::IfCasMatches(cas) => cas + 1,
+            _ => reserved_cas.unwrap_or_else(|| self.get_cas_id()),
+        };
+        record.header.inserted_at = self.timer.millis();
+        record.header.accessed_at = record.header.inserted_at;
+        SetStatus {
+            cas: record.header.cas,
+        }
+    }
+
+    fn store_record(
+        &self,
+        key: KeyType,
+        mut record: Record,
+        condition: SetCondition,
+        reserved_cas: Option<u64>,
+    ) -> Result<SetStatus> {
+        key_validation::validate(&key, &self.key_policy)?;
+        self.check_value_size(&record)?;
+        // entry holds shard write lock, so the check and insert are atomic
+        match self.memory.entry(key) {
+            Entry::Occupied(mut entry) => {
+                let expired = self.is_expired(entry.get(), self.timer.millis());
+                match condition {
+                    SetCondition::IfNotExists if !expired => return Err(CacheError::KeyExists),
+                    SetCondition::IfExists if expired => {
+                        let (key, record) = entry.remove_entry();
+                        self.account_remove(&key, &record);
+                        return Err(CacheError::NotFound);
+                    }
+                    SetCondition::IfCasMatches(cas)
+                        if !expired && entry.get().header.cas != cas =>
+                    {
+                        return Err(CacheError::KeyExists)
+                    }
+                    _ => {}
+                }
+                self.check_memory_limit(
+                    Self::entry_size(entry.key(), &record),
+                    Self::entry_size(entry.key(), entry.get()),
+                )?;
+                let status = self.stamp_record_with_cas(&mut record, condition, reserved_cas);
+                self.account_insert(entry.key(), &record, Some(entry.get()));
+                entry.insert(record);
+                Ok(status)
+            }
+            Entry::Vacant(entry) => {
+                if condition == SetCondition::IfExists {
+                    return Err(CacheError::NotFound);
+                }
+                self.check_memory_limit(Self::entry_size(entry.key(), &record), 0)?;
+                let status = self.stamp_record_with_cas(&mut record, condition, reserved_cas);
+                self.account_insert(entry.key(), &record, None);
+                entry.insert(record);
+                Ok(status)
+            }
+        }
+    }
 }
 
-impl impl_details::CacheImplDetails for MemoryStore {
+const DEBUG_SAMPLE_KEYS: usize = 5;
+const DEBUG_VALUE_PREFIX: usize = 16;
+
+// Implemented manually, because DashMap Debug would lock all shards
+// and print a whole store
+impl<H: StoreHasher> fmt::Debug for MemoryStore<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sample: Vec<(KeyType, String)> = self
+            .memory
+            .iter()
+            .take(DEBUG_SAMPLE_KEYS)
+            .map(|record| {
+                let value = &record.value().value;
+                let prefix = &value[..value.len().min(DEBUG_VALUE_PREFIX)];
+                let mut value = String::from_utf8_lossy(prefix).into_owned();
+                if record.value().value.len() > DEBUG_VALUE_PREFIX {
+                    value.push_str("...");
+                }
+                (record.key().clone(), value)
+            })
+            .collect();
+        // shard locks are released before anything is written to a formatter
+        f.debug_struct("MemoryStore")
+            .field("len", &self.memory.len())
+            .field("memory_used", &self.memory_used.load(Ordering::Relaxed))
+            .field("sample", &DebugSample(&sample))
+            .finish()
+    }
+}
+
+struct DebugSample<'a>(&'a [(KeyType, String)]);
+
+impl fmt::Debug for DebugSample<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.0.iter().map(|(key, value)| (key, value)))
+            .finish()
+    }
+}
+
+impl<H: StoreHasher> impl_details::CacheImplDetails for MemoryStore<H> {
     fn get_by_key(&self, key: &KeyType) -> Result<Record> {
+        key_validation::validate(key, &self.key_policy)?;
+        if self.track_access {
+            return match self.memory.get_mut(key) {
+                Some(mut record) => {
+                    record.header.accessed_at = self.timer.millis();
+                    Ok(record.clone())
+                }
+                None => Err(CacheError::NotFound),
+            };
+        }
         match self.memory.get(key) {
             Some(record) => Ok(record.clone()),
             None => Err(CacheError::NotFound),
@@ -56,87 +571,391 @@ impl impl_details::CacheImplDetails for MemoryStore {
     }
 
     fn check_if_expired(&self, key: &KeyType, record: &Record) -> bool {
-        let current_time = self.timer.timestamp();
-
-        if record.header.time_to_live == 0 {
-            return false;
-        }
-
-        if record.header.timestamp + (record.header.time_to_live as u64) > current_time {
+        let current_time = self.timer.millis();
+        if !record.header.is_expired(current_time) {
             return false;
         }
-        match self.remove(key) {
-            Some(_) => true,
-            None => true,
-        }
+        self.remove_expired(key, current_time);
+        true
     }
 }
 
-impl Cache for MemoryStore {
+impl<H: StoreHasher> Cache for MemoryStore<H> {
     // Removes key value and returns as an option
     fn remove(&self, key: &KeyType) -> Option<(KeyType, Record)> {
-        self.memory.remove(key)
+        let result = self.memory.remove(key);
+        if let Some((key, record)) = &result {
+            self.account_remove(key, record);
+        }
+        result
     }
 
-    fn set(&self, key: KeyType, mut record: Record) -> Result<SetStatus> {
-        //trace!("Set: {:?}", &record.header);
-        if record.header.cas > 0 {
-            match self.memory.get_mut(&key) {
-                Some(mut key_value) => {
-                    if key_value.header.cas != record.header.cas {
-                        Err(CacheError::KeyExists)
-                    } else {
-                        record.header.cas += 1;
-                        record.header.timestamp = self.timer.timestamp();
-                        let cas = record.header.cas;
-                        *key_value = record;
-                        Ok(SetStatus { cas })
+    fn get_many(&self, keys: &[KeyType]) -> Vec<Result<Record>> {
+        // indexes of keys grouped by a shard, so each shard is locked once
+        let mut results = vec![Err(CacheError::NotFound); keys.len()];
+        let mut shard_keys: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (index, key) in keys.iter().enumerate() {
+            match key_validation::validate(key, &self.key_policy) {
+                Ok(()) => shard_keys
+                    .entry(self.memory.determine_map(key))
+                    .or_default()
+                    .push(index),
+                Err(err) => results[index] = Err(err),
+            }
+        }
+        let current_time = self.timer.millis();
+        let shards = self.memory.shards();
+        let mut expired = Vec::new();
+        for (shard_id, indexes) in shard_keys {
+            if self.track_access {
+                let mut shard = shards[shard_id].write();
+                for index in indexes {
+                    if let Some(record) = shard.get_mut(&keys[index]).map(SharedValue::get_mut) {
+                        if self.is_expired(record, current_time) {
+                            expired.push(index);
+                        } else {
+                            record.header.accessed_at = current_time;
+                            results[index] = Ok(record.clone());
+                        }
                     }
                 }
-                None => {
-                    record.header.cas += 1;
-                    record.header.timestamp = self.timer.timestamp();
-                    let cas = record.header.cas;
-                    self.memory.insert(key, record);
-                    Ok(SetStatus { cas })
+            } else {
+                let shard = shards[shard_id].read();
+                for index in indexes {
+                    if let Some(record) = shard.get(&keys[index]).map(SharedValue::get) {
+                        if self.is_expired(record, current_time) {
+                            expired.push(index);
+                        } else {
+                            results[index] = Ok(record.clone());
+                        }
+                    }
                 }
             }
+        }
+        // shard locks are released before expired records are removed
+        for index in expired {
+            self.remove_expired(&keys[index], current_time);
+        }
+        results
+    }
+
+    fn try_get(&self, key: &KeyType) -> Option<Result<Record>> {
+        if let Err(err) = key_validation::validate(key, &self.key_policy) {
+            return Some(Err(err));
+        }
+        let current_time = self.timer.millis();
+        let read = if self.track_access {
+            match self.memory.try_get_mut(key) {
+                TryResult::Present(mut record) => {
+                    record.header.accessed_at = current_time;
+                    TryResult::Present(record.clone())
+                }
+                TryResult::Absent => TryResult::Absent,
+                TryResult::Locked => TryResult::Locked,
+            }
         } else {
-            let cas = self.get_cas_id();
-            record.header.cas = cas;
-            record.header.timestamp = self.timer.timestamp();
-            self.memory.insert(key, record);
-            Ok(SetStatus { cas })
+            match self.memory.try_get(key) {
+                TryResult::Present(record) => TryResult::Present(record.clone()),
+                TryResult::Absent => TryResult::Absent,
+                TryResult::Locked => TryResult::Locked,
+            }
+        };
+        match read {
+            TryResult::Present(record) if !self.is_expired(&record, current_time) => {
+                Some(Ok(record))
+            }
+            TryResult::Present(_) | TryResult::Absent => Some(Err(CacheError::NotFound)),
+            TryResult::Locked => None,
+        }
+    }
+
+    fn get_with_metadata(&self, key: &KeyType) -> Result<(Record, u64)> {
+        key_validation::validate(key, &self.key_policy)?;
+        let read = if self.track_access {
+            self.memory.get_mut(key).map(|mut record| {
+                record.header.accessed_at = self.timer.millis();
+                (record.clone(), self.timer.micros())
+            })
+        } else {
+            self.memory
+                .get(key)
+                .map(|record| (record.clone(), self.timer.micros()))
+        };
+        // shard lock is released before an expired record is removed
+        let (record, micros) = read.ok_or(CacheError::NotFound)?;
+        if impl_details::CacheImplDetails::check_if_expired(self, key, &record) {
+            return Err(CacheError::NotFound);
+        }
+        Ok((record, micros))
+    }
+
+    fn get_cas(&self, key: &KeyType) -> Result<u64> {
+        self.get_record_field(key, |record| record.header.cas)
+    }
+
+    fn get_flags(&self, key: &KeyType) -> Result<u32> {
+        self.get_record_field(key, |record| record.header.flags)
+    }
+
+    fn get_value_bytes(&self, key: &KeyType) -> Result<ValueType> {
+        self.get_record_field(key, |record| record.value.clone())
+    }
+
+    fn touch(&self, key: &KeyType, expiration: u32) -> Result<()> {
+        self.touch_record(key, expiration, |_record| ())
+    }
+
+    fn get_and_touch(&self, key: &KeyType, expiration: u32) -> Result<Record> {
+        self.touch_record(key, expiration, Record::clone)
+    }
+
+    fn set(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        //trace!("Set: {:?}", &record.header);
+        let condition = if record.header.cas > 0 {
+            SetCondition::IfCasMatches(record.header.cas)
+        } else {
+            SetCondition::Unconditional
+        };
+        self.conditional_set(key, record, condition)
+    }
+
+    fn set_many(&self, items: Vec<(KeyType, Record)>) -> Vec<Result<SetStatus>> {
+        // CAS values of all unconditional sets are reserved with a single
+        // increment, sets with CAS are compared and stamped as in set
+        let unconditional = items
+            .iter()
+            .filter(|(_key, record)| record.header.cas == 0)
+            .count() as u64;
+        let mut next_cas = self.cas_id.fetch_add(unconditional, Ordering::Release);
+        items
+            .into_iter()
+            .map(|(key, record)| {
+                if record.header.cas > 0 {
+                    return self.set(key, record);
+                }
+                let cas = next_cas;
+                next_cas += 1;
+                self.store_record(key, record, SetCondition::Unconditional, Some(cas))
+            })
+            .collect()
+    }
+
+    fn conditional_set(
+        &self,
+        key: KeyType,
+        record: Record,
+        condition: SetCondition,
+    ) -> Result<SetStatus> {
+        self.store_record(key, record, condition, None)
+    }
+
+    fn atomic_multi_set(&self, items: Vec<(KeyType, Record)>) -> Result<Vec<SetStatus>> {
+        let mut errors = Vec::new();
+        let mut keys = HashSet::new();
+        for (index, (key, record)) in items.iter().enumerate() {
+            if let Err(err) = key_validation::validate(key, &self.key_policy) {
+                errors.push((index, err));
+            } else if let Err(err) = self.check_value_size(record) {
+                errors.push((index, err));
+            } else if !keys.insert(key) {
+                errors.push((index, CacheError::InvalidArguments));
+            }
+        }
+        if !errors.is_empty() {
+            return Err(CacheError::TransactionFailed(errors));
+        }
+
+        // shards are locked in ascending order, so transactions
+        // sharing shards can't deadlock
+        let shard_ids: BTreeSet<usize> = items
+            .iter()
+            .map(|(key, _record)| self.memory.determine_map(key))
+            .collect();
+        let shards = self.memory.shards();
+        let mut guards: BTreeMap<usize, _> = shard_ids
+            .into_iter()
+            .map(|shard_id| (shard_id, shards[shard_id].write()))
+            .collect();
+
+        let current_time = self.timer.millis();
+        for (index, (key, record)) in items.iter().enumerate() {
+            let shard = &guards[&self.memory.determine_map(key)];
+            let cas = record.header.cas;
+            // CAS is checked as in set, missing or expired record is stored
+            let stored = shard.get(key).map(SharedValue::get);
+            if let Some(stored) = stored {
+                if cas > 0 && !self.is_expired(stored, current_time) && stored.header.cas != cas {
+                    errors.push((index, CacheError::KeyExists));
+                }
+            }
+        }
+        if !errors.is_empty() {
+            return Err(CacheError::TransactionFailed(errors));
+        }
+
+        // memory limit fails at a first item which doesn't fit together
+        // with all items before it
+        let (mut size, mut replaced_size) = (0, 0);
+        for (index, (key, record)) in items.iter().enumerate() {
+            let shard = &guards[&self.memory.determine_map(key)];
+            size += Self::entry_size(key, record);
+            replaced_size += shard
+                .get(key)
+                .map_or(0, |stored| Self::entry_size(key, stored.get()));
+            if let Err(err) = self.check_memory_limit(size, replaced_size) {
+                return Err(CacheError::TransactionFailed(vec![(index, err)]));
+            }
+        }
+
+        let unconditional = items
+            .iter()
+            .filter(|(_key, record)| record.header.cas == 0)
+            .count() as u64;
+        let mut next_cas = self.cas_id.fetch_add(unconditional, Ordering::Release);
+        let statuses = items
+            .into_iter()
+            .map(|(key, mut record)| {
+                let status = match record.header.cas {
+                    0 => {
+                        next_cas += 1;
+                        self.stamp_record_with_cas(
+                            &mut record,
+                            SetCondition::Unconditional,
+                            Some(next_cas - 1),
+                        )
+                    }
+                    cas => self.stamp_record(&mut record, SetCondition::IfCasMatches(cas)),
+                };
+                let shard = guards
+                    .get_mut(&self.memory.determine_map(&key))
+                    .expect("shard of every key is locked");
+                self.account_insert(&key, &record, shard.get(&key).map(SharedValue::get));
+                shard.insert(key, SharedValue::new(record));
+                status
+            })
+            .collect();
+        Ok(statuses)
+    }
+
+    fn replace_if(
+        &self,
+        key: KeyType,
+        mut new_record: Record,
+        predicate: &RecordPredicate<'_>,
+    ) -> Result<SetStatus> {
+        key_validation::validate(&key, &self.key_policy)?;
+        self.check_value_size(&new_record)?;
+        match self.memory.entry(key) {
+            Entry::Occupied(mut entry) => {
+                if self.is_expired(entry.get(), self.timer.millis()) {
+                    let (key, record) = entry.remove_entry();
+                    self.account_remove(&key, &record);
+                    return Err(CacheError::NotFound);
+                }
+                if !predicate(entry.get()) {
+                    return Err(CacheError::KeyExists);
+                }
+                self.check_memory_limit(
+                    Self::entry_size(entry.key(), &new_record),
+                    Self::entry_size(entry.key(), entry.get()),
+                )?;
+                let status = self.stamp_record(&mut new_record, SetCondition::IfExists);
+                self.account_insert(entry.key(), &new_record, Some(entry.get()));
+                entry.insert(new_record);
+                Ok(status)
+            }
+            Entry::Vacant(_) => Err(CacheError::NotFound),
+        }
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: KeyType,
+        expected_cas: u64,
+        mut new_record: Record,
+    ) -> Result<(SetStatus, Option<Record>)> {
+        key_validation::validate(&key, &self.key_policy)?;
+        self.check_value_size(&new_record)?;
+        let condition = SetCondition::IfCasMatches(expected_cas);
+        match self.memory.entry(key) {
+            Entry::Occupied(mut entry) => {
+                let expired = self.is_expired(entry.get(), self.timer.millis());
+                if !expired && entry.get().header.cas != expected_cas {
+                    return Err(CacheError::KeyExists);
+                }
+                self.check_memory_limit(
+                    Self::entry_size(entry.key(), &new_record),
+                    Self::entry_size(entry.key(), entry.get()),
+                )?;
+                let status = self.stamp_record(&mut new_record, condition);
+                self.account_insert(entry.key(), &new_record, Some(entry.get()));
+                let old_record = entry.insert(new_record);
+                // expired record is treated as not existing
+                Ok((status, (!expired).then_some(old_record)))
+            }
+            Entry::Vacant(entry) => {
+                self.check_memory_limit(Self::entry_size(entry.key(), &new_record), 0)?;
+                let status = self.stamp_record(&mut new_record, condition);
+                self.account_insert(entry.key(), &new_record, None);
+                entry.insert(new_record);
+                Ok((status, None))
+            }
         }
     }
 
     fn delete(&self, key: KeyType, header: CacheMetaData) -> Result<Record> {
+        key_validation::validate(&key, &self.key_policy)?;
         let mut cas_match: Option<bool> = None;
         match self.memory.remove_if(&key, |_key, record| -> bool {
             let result = header.cas == 0 || record.header.cas == header.cas;
             cas_match = Some(result);
             result
         }) {
-            Some(key_value) => Ok(key_value.1),
+            Some((key, record)) => {
+                self.account_remove(&key, &record);
+                Ok(record)
+            }
             None => match cas_match {
-                Some(_value) => Err(CacheError::KeyExists),
+                Some(_value) => Err(CacheError::CasMismatch),
                 None => Err(CacheError::NotFound),
             },
         }
     }
 
-    fn flush(&self, header: CacheMetaData) {
-        if header.time_to_live > 0 {
+    fn flush(&self, header: CacheMetaData) -> usize {
+        let affected = if header.time_to_live > 0 {
+            // records expire time_to_live from now, not from when they
+            // were stored, records expiring sooner keep their expiration
+            let deadline = self.timer.millis() + header.time_to_live;
+            let mut altered = 0;
             self.memory.alter_all(|_key, mut value| {
-                value.header.time_to_live = header.time_to_live;
+                value.header.expire_at(deadline);
+                altered += 1;
                 value
             });
+            altered
         } else {
-            self.memory.clear();
-        }
+            self.clear()
+        };
+        self.flush_affected_items
+            .fetch_add(affected as u64, Ordering::Relaxed);
+        affected
     }
 
-    fn as_read_only(&self) -> Box<dyn CacheReadOnlyView> {
+    fn flush_by_tag(&self, tag: u64) -> Vec<(KeyType, Record)> {
+        let mut removed = Vec::new();
+        self.memory.retain(|key, record| {
+            if record.header.tag != Some(tag) {
+                return true;
+            }
+            self.account_remove(key, record);
+            removed.push((key.clone(), record.clone()));
+            false
+        });
+        removed
+    }
+
+    fn as_read_only(&self) -> Box<dyn CacheReadOnlyView<'_>> {
         let storage_clone = self.memory.clone();
         Box::new(storage_clone.into_read_only())
     }
@@ -145,8 +964,8 @@ impl Cache for MemoryStore {
         let items: Vec<KeyType> = self
             .memory
             .iter()
-            .filter(|record: &RefMulti<KeyType, Record>| f(record.key(), record.value()))
-            .map(|record: RefMulti<KeyType, Record>| record.key().clone())
+            .filter(|record: &RefMulti<KeyType, Record, H>| f(record.key(), record.value()))
+            .map(|record: RefMulti<KeyType, Record, H>| record.key().clone())
             .collect();
 
         let result: Vec<Option<(KeyType, Record)>> =
@@ -155,10 +974,761 @@ impl Cache for MemoryStore {
     }
 
     fn len(&self) -> usize {
-        self.memory.len()
+        self.item_count.load(Ordering::Relaxed)
     }
 
     fn is_empty(&self) -> bool {
-        self.memory.is_empty()
+        self.len() == 0
+    }
+
+    fn memory_used(&self) -> u64 {
+        self.memory_used.load(Ordering::Relaxed)
+    }
+
+    fn max_value_size(&self) -> usize {
+        self.max_value_size
+    }
+
+    // writes which would exceed the limit fail with OutOfMemory, 0 disables it
+    fn set_memory_limit(&self, memory_limit: u64) -> Result<()> {
+        self.memory_limit.store(memory_limit, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn oldest_item_age_secs(&self) -> Option<u64> {
+        let current_time = self.timer.millis();
+        self.live_timestamps(current_time)
+            .min()
+            .map(|timestamp| current_time.saturating_sub(timestamp) / 1000)
+    }
+
+    fn newest_item_age_secs(&self) -> Option<u64> {
+        let current_time = self.timer.millis();
+        self.live_timestamps(current_time)
+            .max()
+            .map(|timestamp| current_time.saturating_sub(timestamp) / 1000)
+    }
+
+    fn count_expired(&self) -> usize {
+        let current_time = self.timer.millis();
+        self.memory
+            .iter()
+            .filter(|record| self.is_expired(record.value(), current_time))
+            .count()
+    }
+
+    fn remove_expired_items(&self) -> RemoveIfResult {
+        let current_time = self.timer.millis();
+        let items: Vec<KeyType> = self
+            .memory
+            .iter()
+            .filter(|record| self.is_expired(record.value(), current_time))
+            .map(|record| record.key().clone())
+            .collect();
+
+        let result: RemoveIfResult = items
+            .iter()
+            .map(|key| self.remove_expired(key, current_time))
+            .collect();
+        let removed = result.iter().flatten().count();
+        self.expired_unfetched
+            .fetch_add(removed as u64, Ordering::Relaxed);
+        result
+    }
+
+    fn expired_unfetched(&self) -> u64 {
+        self.expired_unfetched.load(Ordering::Relaxed)
+    }
+
+    fn flush_affected_items(&self) -> u64 {
+        self.flush_affected_items.load(Ordering::Relaxed)
+    }
+
+    // a store itself doesn't evict, see memory_used
+    fn evictions(&self) -> u64 {
+        0
+    }
+
+    fn start_time(&self) -> u64 {
+        self.start_time
+    }
+
+    fn current_time(&self) -> u64 {
+        self.timer.timestamp()
+    }
+
+    fn current_instant(&self) -> std::time::Instant {
+        self.timer.now_instant()
+    }
+
+    fn current_millis(&self) -> u64 {
+        self.timer.millis()
+    }
+
+    fn per_shard_stats(&self) -> Vec<ShardStats> {
+        // every shard is read locked separately, so stats of different
+        // shards may come from different points in time
+        self.memory
+            .shards()
+            .iter()
+            .enumerate()
+            .map(|(shard_id, shard)| {
+                let shard = shard.read();
+                ShardStats {
+                    shard_id,
+                    item_count: shard.len(),
+                    bytes_used: shard
+                        .iter()
+                        .map(|(key, record)| (key.len() + record.get().heap_size()) as u64)
+                        .sum(),
+                }
+            })
+            .collect()
+    }
+
+    fn shard_for_key(&self, key: &[u8]) -> usize {
+        self.memory.determine_map(key)
+    }
+
+    fn get_random_sample(&self, n: usize) -> Vec<(KeyType, u64)> {
+        let shards = self.memory.shards();
+        let mut rng = rand::thread_rng();
+        let mut sample = Vec::with_capacity(n);
+        while sample.len() < n && !self.memory.is_empty() {
+            // empty shards are skipped, so a record is found
+            // even if most shards are empty
+            let first_shard = rng.gen_range(0..shards.len());
+            let picked = (0..shards.len()).find_map(|offset| {
+                let shard = shards[(first_shard + offset) % shards.len()].read();
+                if shard.is_empty() {
+                    return None;
+                }
+                // iteration order of a shard doesn't change, the first
+                // few records are skipped so not always the same one is picked
+                let skip = rng.gen_range(0..shard.len().min(SAMPLE_MAX_SKIP));
+                shard
+                    .iter()
+                    .nth(skip)
+                    .map(|(key, record)| (key.clone(), record.get().header.accessed_at))
+            });
+            match picked {
+                Some(picked) => sample.push(picked),
+                None => break,
+            }
+        }
+        sample
+    }
+
+    fn value_sizes(&self) -> Vec<u64> {
+        self.memory
+            .iter()
+            .map(|record| record.value().value.len() as u64)
+            .collect()
+    }
+
+    fn keys_by_expiry(&self) -> Vec<(u64, KeyType)> {
+        let current_time = self.timer.millis();
+        let mut keys: Vec<(u64, KeyType)> = self
+            .memory
+            .iter()
+            .filter(|record| !self.is_expired(record.value(), current_time))
+            .map(|record| {
+                let header = &record.value().header;
+                let expiry = match header.time_to_live {
+                    0 => 0,
+                    time_to_live => header.inserted_at + time_to_live,
+                };
+                (expiry, record.key().clone())
+            })
+            .collect();
+        keys.sort_unstable_by_key(|(expiry, key)| (*expiry == 0, *expiry, key.clone()));
+        // sorted by milliseconds, a record is live until a second it expires in ends
+        for (expiry, _key) in keys.iter_mut() {
+            *expiry = expiry.div_ceil(1000);
+        }
+        keys
+    }
+
+    fn prefix_scan(&self, prefix: &[u8]) -> Vec<KeyType> {
+        match &self.prefix_index {
+            Some(index) => index
+                .lock()
+                .unwrap()
+                .range::<[u8], _>((Bound::Included(prefix), Bound::Unbounded))
+                .take_while(|key| key.starts_with(prefix))
+                .cloned()
+                .collect(),
+            None => {
+                let mut keys: Vec<KeyType> = self
+                    .memory
+                    .iter()
+                    .filter(|record| record.key().starts_with(prefix))
+                    .map(|record| record.key().clone())
+                    .collect();
+                keys.sort();
+                keys
+            }
+        }
+    }
+
+    fn take_snapshot(&self) -> StorageSnapshot {
+        StorageSnapshot {
+            records: self
+                .memory
+                .iter()
+                .map(|record| (record.key().clone(), record.value().clone()))
+                .collect(),
+            cas_id: self.cas_id.load(Ordering::Acquire),
+        }
+    }
+
+    fn reset_to_snapshot(&self, snapshot: &StorageSnapshot) {
+        self.clear();
+        for (key, record) in &snapshot.records {
+            match self.memory.entry(key.clone()) {
+                // written concurrently with a reset
+                Entry::Occupied(mut entry) => {
+                    self.account_insert(key, record, Some(entry.get()));
+                    entry.insert(record.clone());
+                }
+                Entry::Vacant(entry) => {
+                    self.account_insert(key, record, None);
+                    entry.insert(record.clone());
+                }
+            }
+        }
+        self.cas_id.store(snapshot.cas_id, Ordering::Release);
+    }
+
+    #[cfg(any(test, feature = "debug"))]
+    fn debug_dump(&self) -> DebugDump {
+        let current_time = self.timer.millis();
+        self.memory
+            .iter()
+            .filter(|record| !self.is_expired(record.value(), current_time))
+            .map(|record| {
+                let header = &record.value().header;
+                (
+                    record.key().clone(),
+                    (
+                        record.value().value.clone(),
+                        header.flags,
+                        header.get_expiration(),
+                        header.cas,
+                    ),
+                )
+            })
+            .collect()
+    }
+}
+
+mod entry;
+pub use entry::StorageEntry;
+
+#[cfg(all(test, memcrs_loom))]
+mod loom_tests;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::mock_server::{MockSystemTimer, SetableTimer};
+    use bytes::Bytes;
+
+    #[test]
+    fn debug_should_print_sample_of_truncated_values() {
+        let store = MemoryStore::new(Arc::new(MockSystemTimer::new()));
+        for id in 0..10 {
+            let value = Bytes::from(format!("{}-{}", id, "x".repeat(32)));
+            store
+                .set(
+                    Bytes::from(format!("key_{}", id)),
+                    Record::new(value, 0, 0, 0),
+                )
+                .unwrap();
+        }
+        let debug = format!("{:?}", store);
+        assert!(debug.starts_with("MemoryStore { len: 10, memory_used: "));
+        assert_eq!(debug.matches("key_").count(), DEBUG_SAMPLE_KEYS);
+        assert_eq!(debug.matches("xxx...").count(), DEBUG_SAMPLE_KEYS);
+        assert!(!debug.contains(&"x".repeat(DEBUG_VALUE_PREFIX)));
+
+        // store stays usable, so no shard lock is left behind
+        store
+            .set(
+                Bytes::from("key"),
+                Record::new(Bytes::from("value"), 0, 0, 0),
+            )
+            .unwrap();
+    }
+
+    fn fill_with_test_data<H: StoreHasher>(store: &MemoryStore<H>) {
+        for prefix in ["user:", "user:1", "session:", "u"] {
+            for id in 0..20 {
+                let key = Bytes::from(format!("{}{}", prefix, id));
+                let record = Record::new(Bytes::from("value"), 0, 0, 0);
+                store.set(key, record).unwrap();
+            }
+        }
+        store
+            .delete(Bytes::from("user:10"), CacheMetaData::new(0, 0, 0))
+            .unwrap();
+        store.remove(&Bytes::from("session:3"));
+        store
+            .entry(Bytes::from("user:new"))
+            .or_insert(Record::new(Bytes::from("value"), 0, 0, 0));
+    }
+
+    #[test]
+    fn try_get_should_not_wait_for_locked_shard() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = MemoryStore::new(timer.clone());
+        let key = Bytes::from("key");
+        store
+            .set(key.clone(), Record::new(Bytes::from("value"), 0, 0, 10))
+            .unwrap();
+        // entry holds a write lock of a key shard
+        let entry = store.entry(key.clone());
+        assert!(store.try_get(&key).is_none());
+        drop(entry);
+        assert_eq!(store.try_get(&key).unwrap().unwrap().value, "value");
+        assert_eq!(
+            store.try_get(&Bytes::from("missing")),
+            Some(Err(CacheError::NotFound))
+        );
+
+        timer.set(10);
+        assert_eq!(store.try_get(&key), Some(Err(CacheError::NotFound)));
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn drain_should_return_all_live_records() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = MemoryStore::new(timer.clone());
+        for id in 0..1000 {
+            let record = Record::new(Bytes::from(format!("value_{}", id)), 0, id, 0);
+            store
+                .set(Bytes::from(format!("key_{}", id)), record)
+                .unwrap();
+        }
+        let expiring = Record::new(Bytes::from("expiring"), 0, 0, 5);
+        store.set(Bytes::from("expiring"), expiring).unwrap();
+        timer.set(5);
+
+        let mut records = store.drain();
+        assert_eq!(records.len(), 1000);
+        records.sort_by_key(|(_key, record)| record.header.flags);
+        for (id, (key, record)) in records.iter().enumerate() {
+            assert_eq!(key, &Bytes::from(format!("key_{}", id)));
+            assert_eq!(record.value, Bytes::from(format!("value_{}", id)));
+        }
+    }
+
+    #[test]
+    fn memory_limit_should_reject_records_which_dont_fit() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let record = Record::new(Bytes::from(vec![b'x'; 100]), 0, 0, 5);
+        let size = MemoryStore::<ahash::RandomState>::entry_size(&Bytes::from("key0"), &record);
+        let store = MemoryStore::new(timer.clone()).with_memory_limit(3 * size);
+        for id in 0..3 {
+            store
+                .set(Bytes::from(format!("key{}", id)), record.clone())
+                .unwrap();
+        }
+        assert_eq!(
+            store.set(Bytes::from("key3"), record.clone()),
+            Err(CacheError::OutOfMemory)
+        );
+        assert_eq!(
+            store.atomic_multi_set(vec![(Bytes::from("key3"), record.clone())]),
+            Err(CacheError::TransactionFailed(vec![(
+                0,
+                CacheError::OutOfMemory
+            )]))
+        );
+        // a replaced record frees its memory
+        store.set(Bytes::from("key0"), record.clone()).unwrap();
+
+        store
+            .delete(Bytes::from("key0"), CacheMetaData::new(0, 0, 0))
+            .unwrap();
+        store.set(Bytes::from("key3"), record.clone()).unwrap();
+        timer.set(5);
+        store.remove_expired_items();
+        assert_eq!(store.memory_used(), 0);
+        store.set(Bytes::from("key4"), record).unwrap();
+    }
+
+    #[test]
+    fn watch_key_should_receive_every_modification() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = MemoryStore::new(timer.clone());
+        let key = Bytes::from("watched");
+        let mut receiver = store.watch_key(key.clone());
+        assert!(receiver.borrow_and_update().is_none());
+
+        let mut events = Vec::new();
+        store
+            .set(key.clone(), Record::new(Bytes::from("first"), 0, 0, 0))
+            .unwrap();
+        events.push(receiver.has_changed().unwrap());
+        assert_eq!(
+            receiver.borrow_and_update().as_ref().unwrap().value,
+            Bytes::from("first")
+        );
+        store
+            .set(key.clone(), Record::new(Bytes::from("second"), 0, 0, 5))
+            .unwrap();
+        // other keys don't notify a watcher
+        store
+            .set(
+                Bytes::from("other"),
+                Record::new(Bytes::from("other"), 0, 0, 0),
+            )
+            .unwrap();
+        events.push(receiver.has_changed().unwrap());
+        assert_eq!(
+            receiver.borrow_and_update().as_ref().unwrap().value,
+            Bytes::from("second")
+        );
+        timer.set(5);
+        store.remove_expired_items();
+        events.push(receiver.has_changed().unwrap());
+        assert!(receiver.borrow_and_update().is_none());
+        assert_eq!(events, vec![true, true, true]);
+        assert!(!receiver.has_changed().unwrap());
+
+        // a sender is removed on a first write after receivers are dropped
+        drop(receiver);
+        store
+            .set(key.clone(), Record::new(Bytes::from("third"), 0, 0, 0))
+            .unwrap();
+        assert!(store.watchers.is_empty());
+        assert_eq!(store.watched_keys.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn store_should_work_with_any_hasher() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let expected = MemoryStore::new(timer.clone());
+        fill_with_test_data(&expected);
+        let mut expected_keys = expected.prefix_scan(b"user:1");
+        expected_keys.sort();
+
+        let store =
+            SipMemoryStore::with_hasher(timer.clone(), Default::default()).with_shard_count(8);
+        fill_with_test_data(&store);
+        assert_eq!(store.len(), expected.len());
+        assert_eq!(store.memory_used(), expected.memory_used());
+        let mut keys = store.prefix_scan(b"user:1");
+        keys.sort();
+        assert_eq!(keys, expected_keys);
+
+        let store = FxMemoryStore::with_hasher(timer.clone(), Default::default());
+        fill_with_test_data(&store);
+        assert_eq!(store.len(), expected.len());
+        assert_eq!(store.memory_used(), expected.memory_used());
+        let mut keys = store.prefix_scan(b"user:1");
+        keys.sort();
+        assert_eq!(keys, expected_keys);
+    }
+
+    #[test]
+    fn shared_store_should_be_usable_from_many_threads() {
+        let store =
+            MemoryStore::new_shared_with_capacity(Arc::new(MockSystemTimer::new()), 1024, 64);
+        let writers: Vec<_> = (0..4)
+            .map(|id| {
+                let store = store.clone();
+                std::thread::spawn(move || {
+                    let record = Record::new(Bytes::from("value"), 0, 0, 0);
+                    store
+                        .set(Bytes::from(format!("key_{}", id)), record)
+                        .unwrap();
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert_eq!(store.len(), 4);
+    }
+
+    #[test]
+    fn concurrent_cas_updates_should_succeed_once_per_round() {
+        const THREADS: usize = 16;
+        const ROUNDS: usize = 100;
+        let store = MemoryStore::new_shared(Arc::new(MockSystemTimer::new()));
+        let key = Bytes::from("key");
+        store
+            .set(key.clone(), Record::new(Bytes::from("0"), 0, 0, 0))
+            .unwrap();
+        // threads read a CAS, then all of them try to replace a record
+        // with it, the main thread checks results between rounds
+        let barrier = Arc::new(std::sync::Barrier::new(THREADS + 1));
+        let succeeded = Arc::new(AtomicUsize::new(0));
+        let writers: Vec<_> = (0..THREADS)
+            .map(|id| {
+                let (store, key) = (store.clone(), key.clone());
+                let (barrier, succeeded) = (Arc::clone(&barrier), Arc::clone(&succeeded));
+                std::thread::spawn(move || {
+                    for _round in 0..ROUNDS {
+                        barrier.wait();
+                        let cas = store.get_cas(&key).unwrap();
+                        barrier.wait();
+                        let record = Record::new(Bytes::from(id.to_string()), cas, 0, 0);
+                        match store.set(key.clone(), record) {
+                            Ok(_status) => {
+                                succeeded.fetch_add(1, Ordering::SeqCst);
+                            }
+                            Err(err) => assert_eq!(err, CacheError::KeyExists),
+                        }
+                        barrier.wait();
+                    }
+                })
+            })
+            .collect();
+        for _round in 0..ROUNDS {
+            barrier.wait();
+            barrier.wait();
+            barrier.wait();
+            assert_eq!(succeeded.swap(0, Ordering::SeqCst), 1);
+        }
+        for writer in writers {
+            writer.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn operations_should_not_depend_on_shard_count() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let expected = MemoryStore::new(timer.clone());
+        fill_with_test_data(&expected);
+        let mut expected_keys = expected.prefix_scan(b"");
+        expected_keys.sort();
+
+        for shard_count in [2, 4, 64] {
+            let store = MemoryStore::new(timer.clone()).with_shard_count(shard_count);
+            fill_with_test_data(&store);
+            let mut keys = store.prefix_scan(b"");
+            keys.sort();
+            assert_eq!(keys, expected_keys);
+            assert_eq!(store.memory_used(), expected.memory_used());
+
+            let stats = store.per_shard_stats();
+            assert_eq!(stats.len(), shard_count);
+            assert_eq!(
+                stats.iter().map(|shard| shard.item_count).sum::<usize>(),
+                store.len()
+            );
+            for key in &keys {
+                let shard = store.shard_for_key(key);
+                assert!(shard < shard_count);
+                assert!(store.memory.shards()[shard].read().contains_key(key));
+            }
+
+            assert_eq!(
+                store.get(&Bytes::from("user:1")).unwrap().value,
+                Bytes::from("value")
+            );
+            assert_eq!(
+                store.get(&Bytes::from("user:10")).unwrap_err(),
+                CacheError::NotFound
+            );
+            let record = Record::new(Bytes::from("other"), 0, 0, 0);
+            assert_eq!(
+                store
+                    .conditional_set(Bytes::from("user:1"), record, SetCondition::IfNotExists)
+                    .unwrap_err(),
+                CacheError::KeyExists
+            );
+            assert_eq!(store.flush(CacheMetaData::new(0, 0, 0)), expected.len());
+            assert!(store.is_empty());
+        }
+    }
+
+    #[test]
+    fn get_should_update_access_time_only_if_tracking_is_enabled() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = MemoryStore::new(timer.clone());
+        let tracking_store = MemoryStore::new(timer.clone()).with_access_tracking();
+        let key = Bytes::from("key");
+        for store in [&store, &tracking_store] {
+            timer.set(10);
+            store
+                .set(key.clone(), Record::new(Bytes::from("value"), 0, 0, 100))
+                .unwrap();
+            let record = store.get(&key).unwrap();
+            assert_eq!(record.header.inserted_at, 10_000);
+            assert_eq!(record.header.accessed_at, 10_000);
+            timer.set(50);
+            store.get(&key).unwrap();
+        }
+
+        let record = store.get(&key).unwrap();
+        assert_eq!(record.header.inserted_at, 10_000);
+        assert_eq!(record.header.accessed_at, 10_000);
+        let record = tracking_store.get(&key).unwrap();
+        assert_eq!(record.header.inserted_at, 10_000);
+        assert_eq!(record.header.accessed_at, 50_000);
+
+        // expiration is counted from insert, not from a last access
+        timer.set(110);
+        assert_eq!(tracking_store.get(&key).unwrap_err(), CacheError::NotFound);
+        assert_eq!(tracking_store.oldest_item_age_secs(), None);
+    }
+
+    #[test]
+    fn get_many_should_return_results_of_get_in_keys_order() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = MemoryStore::new(timer.clone()).with_shard_count(4);
+        let tracking_store = MemoryStore::new(timer.clone())
+            .with_shard_count(4)
+            .with_access_tracking();
+        let keys: Vec<KeyType> = (0..32)
+            .map(|id| Bytes::from(format!("key_{}", id)))
+            .collect();
+        for store in [&store, &tracking_store] {
+            timer.set(0);
+            for (id, key) in keys.iter().enumerate() {
+                // odd keys expire, every fourth key is missing
+                let expiration = (id % 2) as u32 * 5;
+                if id % 4 != 0 {
+                    let value = Bytes::from(id.to_string());
+                    store
+                        .set(key.clone(), Record::new(value, 0, 0, expiration))
+                        .unwrap();
+                }
+            }
+            timer.set(10);
+            let mut batch = keys.clone();
+            batch.push(Bytes::new());
+            batch.push(keys[2].clone());
+            let results = store.get_many(&batch);
+            let expected: Vec<Result<Record>> = batch.iter().map(|key| store.get(key)).collect();
+            assert_eq!(results, expected);
+            assert_eq!(results[2].as_ref().unwrap().value, Bytes::from("2"));
+            assert_eq!(results[32], Err(CacheError::InvalidKey));
+            assert_eq!(results[33], results[2]);
+            // expired records are removed
+            assert_eq!(store.len(), 8);
+        }
+        let record = tracking_store.get_many(&keys[2..3]).remove(0).unwrap();
+        assert_eq!(record.header.accessed_at, 10_000);
+        let record = store.get_many(&keys[2..3]).remove(0).unwrap();
+        assert_eq!(record.header.accessed_at, 0);
+    }
+
+    #[test]
+    fn len_should_not_wait_for_locked_shards() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = MemoryStore::new(timer.clone()).with_shard_count(4);
+        for id in 0..16 {
+            let expiration = (id % 2) * 5;
+            store
+                .set(
+                    Bytes::from(format!("key_{}", id)),
+                    Record::new(Bytes::from("value"), 0, 0, expiration),
+                )
+                .unwrap();
+        }
+        store
+            .set(
+                Bytes::from("key_0"),
+                Record::new(Bytes::from("other"), 0, 0, 0),
+            )
+            .unwrap();
+        store
+            .delete(Bytes::from("key_2"), CacheMetaData::new(0, 0, 0))
+            .unwrap();
+        assert_eq!(store.len(), 15);
+        assert_eq!(store.len(), store.memory.len());
+
+        timer.set(10);
+        assert_eq!(store.remove_expired_items().len(), 8);
+        assert_eq!(store.len(), store.memory.len());
+
+        {
+            // len and memory_used are read while all shards are locked
+            let _guards: Vec<_> = store
+                .memory
+                .shards()
+                .iter()
+                .map(|shard| shard.write())
+                .collect();
+            assert_eq!(store.len(), 7);
+            assert!(!store.is_empty());
+            assert!(store.memory_used() > 0);
+        }
+
+        store.flush(CacheMetaData::new(0, 0, 0));
+        assert!(store.is_empty());
+        assert_eq!(store.memory_used(), 0);
+    }
+
+    #[test]
+    fn records_should_expire_between_timer_seconds() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = MemoryStore::new(timer.clone());
+        let key = Bytes::from("key");
+        let short_key = Bytes::from("short");
+        store
+            .set(key.clone(), Record::new(Bytes::from("value"), 0, 0, 1))
+            .unwrap();
+        let mut record = Record::new(Bytes::from("value"), 0, 0, 0);
+        record.header.time_to_live = 500;
+        store.set(short_key.clone(), record).unwrap();
+
+        timer.set_millis(499);
+        assert!(store.get(&short_key).is_ok());
+        timer.set_millis(500);
+        assert_eq!(store.get(&short_key), Err(CacheError::NotFound));
+        assert!(store.get(&key).is_ok());
+
+        // touch counts expiration from a current millisecond
+        assert!(store.touch(&key, 1).is_ok());
+        timer.set_millis(1499);
+        assert!(store.get(&key).is_ok());
+        timer.set_millis(1500);
+        assert_eq!(store.get(&key), Err(CacheError::NotFound));
+    }
+
+    #[test]
+    fn random_sample_should_find_records_in_sparse_store() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = MemoryStore::new(timer.clone()).with_shard_count(64);
+        assert!(store.get_random_sample(5).is_empty());
+
+        timer.set(7);
+        store
+            .set(
+                Bytes::from("key"),
+                Record::new(Bytes::from("value"), 0, 0, 0),
+            )
+            .unwrap();
+        let sample = store.get_random_sample(5);
+        assert_eq!(sample, vec![(Bytes::from("key"), 7_000); 5]);
+    }
+
+    #[test]
+    fn prefix_scan_should_return_same_keys_as_full_scan() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = MemoryStore::new(timer.clone());
+        let indexed_store = MemoryStore::new(timer).with_prefix_index();
+        fill_with_test_data(&store);
+        fill_with_test_data(&indexed_store);
+
+        for prefix in ["user:", "user:1", "session:", "u", "", "missing"] {
+            let expected = store.prefix_scan(prefix.as_bytes());
+            assert_eq!(indexed_store.prefix_scan(prefix.as_bytes()), expected);
+            assert!(expected
+                .iter()
+                .all(|key| key.starts_with(prefix.as_bytes())));
+        }
+        assert_eq!(indexed_store.prefix_scan(b"session:").len(), 19);
+        assert_eq!(indexed_store.prefix_scan(b"").len(), store.len());
+
+        indexed_store.flush(CacheMetaData::new(0, 0, 0));
+        assert!(indexed_store.prefix_scan(b"").is_empty());
     }
 }
@@ -0,0 +1,168 @@
+use super::{MemoryStore, StoreHasher};
+use crate::cache::cache::{KeyType, Record, SetCondition};
+use dashmap::mapref::entry::Entry;
+
+/// View into a single store entry, used to implement custom atomic
+/// check-and-modify operations.
+///
+/// Entry holds a write lock of a store shard until it is dropped, so
+/// other MemoryStore methods must not be called while it is alive,
+/// otherwise a thread may deadlock.
+pub struct StorageEntry<'a, H: StoreHasher = ahash::RandomState> {
+    store: &'a MemoryStore<H>,
+    entry: Entry<'a, KeyType, Record, H>,
+    current_time: u64,
+}
+
+impl<'a, H: StoreHasher> StorageEntry<'a, H> {
+    pub(super) fn new(store: &'a MemoryStore<H>, entry: Entry<'a, KeyType, Record, H>) -> Self {
+        StorageEntry {
+            store,
+            entry,
+            current_time: store.timer.millis(),
+        }
+    }
+
+    pub fn key(&self) -> &KeyType {
+        self.entry.key()
+    }
+
+    /// Modifies a stored record in place, expired record is treated
+    /// as not existing. Modified record gets a new CAS.
+    pub fn and_modify<F: FnOnce(&mut Record)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self.entry {
+            if !self.store.is_expired(entry.get(), self.current_time) {
+                let old_size = MemoryStore::<H>::entry_size(entry.key(), entry.get());
+                let record = entry.get_mut();
+                f(record);
+                self.store.stamp_record(record, SetCondition::Unconditional);
+                let new_size = MemoryStore::<H>::entry_size(entry.key(), entry.get());
+                self.store.account_resize(old_size, new_size);
+                self.store.notify_watchers(entry.key(), Some(entry.get()));
+            }
+        }
+        self
+    }
+
+    /// Inserts a record if key is not stored or its record expired,
+    /// returns a record which is stored under key
+    pub fn or_insert(self, record: Record) -> Record {
+        self.or_insert_with(|| record)
+    }
+
+    /// Same as or_insert, but f is called only if record is inserted
+    pub fn or_insert_with<F: FnOnce() -> Record>(self, f: F) -> Record {
+        match self.entry {
+            Entry::Occupied(mut entry) => {
+                if !self.store.is_expired(entry.get(), self.current_time) {
+                    return entry.get().clone();
+                }
+                let mut record = f();
+                self.store
+                    .stamp_record(&mut record, SetCondition::Unconditional);
+                self.store
+                    .account_insert(entry.key(), &record, Some(entry.get()));
+                entry.insert(record.clone());
+                record
+            }
+            Entry::Vacant(entry) => {
+                let mut record = f();
+                self.store
+                    .stamp_record(&mut record, SetCondition::Unconditional);
+                self.store.account_insert(entry.key(), &record, None);
+                entry.insert(record).clone()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::cache::{Cache, Record};
+    use crate::memory_store::store::MemoryStore;
+    use crate::mock::mock_server::{MockSystemTimer, SetableTimer};
+    use bytes::{Bytes, BytesMut};
+    use std::sync::Arc;
+
+    const MAX_VALUE_SIZE: usize = 8;
+
+    fn create_store() -> (Arc<MockSystemTimer>, MemoryStore) {
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = MemoryStore::new(timer.clone());
+        (timer, store)
+    }
+
+    fn append_if_small(store: &MemoryStore, key: &str, data: &str) {
+        store
+            .entry(Bytes::from(key.to_string()))
+            .and_modify(|record| {
+                if record.value.len() + data.len() <= MAX_VALUE_SIZE {
+                    let mut value = BytesMut::from(&record.value[..]);
+                    value.extend_from_slice(data.as_bytes());
+                    record.value = value.freeze();
+                }
+            });
+    }
+
+    #[test]
+    fn and_modify_should_update_record_and_cas() {
+        let (_timer, store) = create_store();
+        let key = Bytes::from("key");
+        let cas = store
+            .set(key.clone(), Record::new(Bytes::from("abc"), 0, 0, 0))
+            .unwrap()
+            .cas;
+
+        append_if_small(&store, "key", "def");
+        append_if_small(&store, "key", "ghi");
+        let record = store.get(&key).unwrap();
+        assert_eq!(record.value, Bytes::from("abcdef"));
+        assert!(record.header.cas > cas);
+        assert_eq!(
+            store.memory_used(),
+            (key.len() + record.size_estimate()) as u64
+        );
+    }
+
+    #[test]
+    fn and_modify_should_skip_missing_and_expired_records() {
+        let (timer, store) = create_store();
+        append_if_small(&store, "missing", "data");
+        assert!(store.is_empty());
+
+        store
+            .set(Bytes::from("key"), Record::new(Bytes::from("abc"), 0, 0, 5))
+            .unwrap();
+        timer.set(10);
+        append_if_small(&store, "key", "def");
+        assert!(store.get(&Bytes::from("key")).is_err());
+    }
+
+    #[test]
+    fn or_insert_should_keep_live_record() {
+        let (timer, store) = create_store();
+        let key = Bytes::from("key");
+        let inserted =
+            store
+                .entry(key.clone())
+                .or_insert(Record::new(Bytes::from("first"), 0, 0, 5));
+        assert_eq!(inserted.value, Bytes::from("first"));
+
+        let stored = store
+            .entry(key.clone())
+            .or_insert_with(|| panic!("record is stored"));
+        assert_eq!(stored, inserted);
+
+        timer.set(10);
+        let replaced =
+            store
+                .entry(key.clone())
+                .or_insert(Record::new(Bytes::from("second"), 0, 0, 0));
+        assert_eq!(replaced.value, Bytes::from("second"));
+        assert_eq!(store.get(&key).unwrap(), replaced);
+        assert_eq!(
+            store.memory_used(),
+            (key.len() + replaced.size_estimate()) as u64
+        );
+    }
+}
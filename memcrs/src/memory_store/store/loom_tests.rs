@@ -0,0 +1,191 @@
+// loom cannot see through DashMap shard locks, so MemoryStore is modelled
+// here with a Mutex guarded map, the critical sections mirror MemoryStore:
+// - set holds the lock for the whole check and insert, like DashMap entry,
+// - get reads a record under the lock and checks expiration after the lock
+//   is released, like get_by_key followed by check_if_expired.
+//
+// A dedicated cfg is used, as --cfg loom would switch tokio to its loom build.
+// Run with: RUSTFLAGS="--cfg memcrs_loom" cargo test -p memcrs --release loom_tests
+use crate::cache::cache::{KeyType, Record, SetCondition, SetStatus};
+use crate::cache::error::{CacheError, Result};
+use bytes::Bytes;
+use loom::sync::atomic::{AtomicU64, Ordering};
+use loom::sync::{Arc, Mutex};
+use loom::thread;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+struct LoomStore {
+    memory: Mutex<HashMap<KeyType, Record>>,
+    timestamp: AtomicU64,
+    cas_id: AtomicU64,
+}
+
+impl LoomStore {
+    fn new() -> LoomStore {
+        LoomStore {
+            memory: Mutex::new(HashMap::new()),
+            timestamp: AtomicU64::new(0),
+            cas_id: AtomicU64::new(1),
+        }
+    }
+
+    fn timestamp(&self) -> u64 {
+        self.timestamp.load(Ordering::Acquire)
+    }
+
+    fn get_cas_id(&self) -> u64 {
+        self.cas_id.fetch_add(1, Ordering::Release)
+    }
+
+    fn stamp_record(&self, record: &mut Record, condition: SetCondition) -> SetStatus {
+        record.header.cas = match condition {
+            SetCondition::IfCasMatches(cas) => cas + 1,
+            _ => self.get_cas_id(),
+        };
+        record.header.timestamp = self.timestamp();
+        SetStatus {
+            cas: record.header.cas,
+        }
+    }
+
+    fn set(&self, key: KeyType, mut record: Record) -> Result<SetStatus> {
+        let condition = if record.header.cas > 0 {
+            SetCondition::IfCasMatches(record.header.cas)
+        } else {
+            SetCondition::Unconditional
+        };
+        let mut memory = self.memory.lock().unwrap();
+        match memory.entry(key) {
+            Entry::Occupied(mut entry) => {
+                let expired = entry.get().header.is_expired(self.timestamp());
+                if let SetCondition::IfCasMatches(cas) = condition {
+                    if !expired && entry.get().header.cas != cas {
+                        return Err(CacheError::KeyExists);
+                    }
+                }
+                let status = self.stamp_record(&mut record, condition);
+                entry.insert(record);
+                Ok(status)
+            }
+            Entry::Vacant(entry) => {
+                let status = self.stamp_record(&mut record, condition);
+                entry.insert(record);
+                Ok(status)
+            }
+        }
+    }
+
+    fn get(&self, key: &KeyType) -> Result<Record> {
+        let record = match self.memory.lock().unwrap().get(key) {
+            Some(record) => record.clone(),
+            None => return Err(CacheError::NotFound),
+        };
+        let current_time = self.timestamp();
+        if record.header.is_expired(current_time) {
+            let mut memory = self.memory.lock().unwrap();
+            if let Some(stored) = memory.get(key) {
+                if stored.header.is_expired(current_time) {
+                    memory.remove(key);
+                }
+            }
+            return Err(CacheError::NotFound);
+        }
+        Ok(record)
+    }
+}
+
+#[test]
+fn cas_set_and_get_should_observe_consistent_record() {
+    loom::model(|| {
+        let store = Arc::new(LoomStore::new());
+        let key = Bytes::from("key");
+        let cas = store
+            .set(key.clone(), Record::new(Bytes::from("old"), 0, 0, 0))
+            .unwrap()
+            .cas;
+
+        let writer = {
+            let store = store.clone();
+            let key = key.clone();
+            thread::spawn(move || store.set(key, Record::new(Bytes::from("new"), cas, 0, 0)))
+        };
+        let record = store.get(&key).unwrap();
+        if record.value == "old" {
+            assert_eq!(record.header.cas, cas);
+        } else {
+            assert_eq!(record.value, "new");
+            assert_eq!(record.header.cas, cas + 1);
+        }
+        assert_eq!(writer.join().unwrap(), Ok(SetStatus { cas: cas + 1 }));
+    });
+}
+
+#[test]
+fn concurrent_cas_sets_should_succeed_only_once() {
+    loom::model(|| {
+        let store = Arc::new(LoomStore::new());
+        let key = Bytes::from("key");
+        let cas = store
+            .set(key.clone(), Record::new(Bytes::from("old"), 0, 0, 0))
+            .unwrap()
+            .cas;
+
+        let writers: Vec<_> = ["first", "second"]
+            .into_iter()
+            .map(|value| {
+                let store = store.clone();
+                let key = key.clone();
+                thread::spawn(move || store.set(key, Record::new(Bytes::from(value), cas, 0, 0)))
+            })
+            .collect();
+        let succeeded = writers
+            .into_iter()
+            .map(|writer| writer.join().unwrap())
+            .filter(|result| result.is_ok())
+            .count();
+        assert_eq!(succeeded, 1);
+    });
+}
+
+#[test]
+fn expired_record_removal_should_not_remove_fresh_record() {
+    loom::model(|| {
+        let store = Arc::new(LoomStore::new());
+        let key = Bytes::from("key");
+        store
+            .set(key.clone(), Record::new(Bytes::from("expired"), 0, 0, 1))
+            .unwrap();
+        store.timestamp.store(1, Ordering::Release);
+
+        let reader = {
+            let store = store.clone();
+            let key = key.clone();
+            thread::spawn(move || store.get(&key))
+        };
+        store
+            .set(key.clone(), Record::new(Bytes::from("fresh"), 0, 0, 0))
+            .unwrap();
+        let _ = reader.join().unwrap();
+        assert_eq!(store.get(&key).unwrap().value, "fresh");
+    });
+}
+
+#[test]
+fn concurrent_sets_should_get_unique_cas() {
+    loom::model(|| {
+        let store = Arc::new(LoomStore::new());
+        let writer = {
+            let store = store.clone();
+            thread::spawn(move || {
+                store
+                    .set(Bytes::from("a"), Record::new(Bytes::from("a"), 0, 0, 0))
+                    .unwrap()
+            })
+        };
+        let status = store
+            .set(Bytes::from("b"), Record::new(Bytes::from("b"), 0, 0, 0))
+            .unwrap();
+        assert_ne!(writer.join().unwrap(), status);
+    });
+}
@@ -3,32 +3,55 @@ use crate::memory_store::store::MemoryStore;
 use crate::server::timer;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 pub struct MockSystemTimer {
+    // milliseconds, set in seconds by set or in milliseconds by set_millis
     pub current_time: AtomicUsize,
+    // instant of timestamp 0, so now_instant moves only when
+    // current_time is set
+    start: Instant,
 }
 
 pub trait SetableTimer: timer::Timer {
     fn set(&self, time: u64);
+    fn set_millis(&self, millis: u64);
 }
 
 impl MockSystemTimer {
     pub fn new() -> Self {
         MockSystemTimer {
             current_time: AtomicUsize::new(0),
+            start: Instant::now(),
         }
     }
 }
 
 impl timer::Timer for MockSystemTimer {
     fn timestamp(&self) -> u64 {
+        self.millis() / 1000
+    }
+
+    fn now_instant(&self) -> Instant {
+        self.start + Duration::from_millis(self.millis())
+    }
+
+    fn micros(&self) -> u64 {
+        self.millis() * 1000
+    }
+
+    fn millis(&self) -> u64 {
         self.current_time.load(Ordering::Relaxed) as u64
     }
 }
 
 impl SetableTimer for MockSystemTimer {
     fn set(&self, time: u64) {
-        self.current_time.store(time as usize, Ordering::Relaxed)
+        self.set_millis(time * 1000)
+    }
+
+    fn set_millis(&self, millis: u64) {
+        self.current_time.store(millis as usize, Ordering::Relaxed)
     }
 }
 
@@ -40,9 +63,9 @@ pub struct MockServer {
 impl MockServer {
     pub fn new() -> Self {
         let timer = Arc::new(MockSystemTimer::new());
-        let store = Arc::new(MemoryStore::new(timer.clone()));
+        let store = MemoryStore::new_shared(timer.clone());
         MockServer {
-            timer: timer,
+            timer,
             storage: MemcStore::new(store),
         }
     }
@@ -54,5 +77,23 @@ pub fn create_server() -> MockServer {
 
 pub fn create_storage() -> Arc<MemcStore> {
     let timer = Arc::new(MockSystemTimer::new());
-    Arc::new(MemcStore::new(Arc::new(MemoryStore::new(timer))))
+    Arc::new(MemcStore::new(MemoryStore::new_shared(timer)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::timer::Timer;
+
+    #[test]
+    fn mock_timer_instant_should_move_only_when_time_is_set() {
+        let timer = MockSystemTimer::new();
+        let start = timer.now_instant();
+        assert_eq!(timer.now_instant(), start);
+        timer.set(90);
+        assert_eq!(timer.now_instant() - start, Duration::from_secs(90));
+        timer.set_millis(90_500);
+        assert_eq!(timer.timestamp(), 90);
+        assert_eq!(timer.millis(), 90_500);
+    }
 }
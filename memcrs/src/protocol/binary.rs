@@ -207,13 +207,18 @@ pub struct IncrementResponse {
 pub type DecrementRequest = IncrementRequest;
 pub type DecrementResponse = IncrementResponse;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Debug)]
 pub struct TouchRequest {
+    pub(crate) header: RequestHeader,
     pub(crate) expiration: u32,
+    pub(crate) key: Bytes,
 }
 
 pub type TouchResponse = Response;
 
+pub type GetAndTouchRequest = TouchRequest;
+pub type GetAndTouchResponse = GetResponse;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct FlushRequest {
     pub(crate) header: RequestHeader,
@@ -224,6 +229,29 @@ pub type FlushResponse = Response;
 pub type QuitRequest = Request;
 pub type QuitResponse = Response;
 
+pub type SaslListMechsRequest = Request;
+#[derive(Debug)]
+pub struct SaslListMechsResponse {
+    pub(crate) header: ResponseHeader,
+    pub(crate) mechanisms: &'static str,
+}
+
+/// SASL_AUTH or SASL_STEP request, a key is a mechanism name
+/// and a value is mechanism specific data
+#[derive(Debug)]
+pub struct SaslAuthRequest {
+    pub(crate) header: RequestHeader,
+    pub(crate) mechanism: Bytes,
+    pub(crate) data: Bytes,
+}
+
+pub type SaslStepRequest = SaslAuthRequest;
+#[derive(Debug)]
+pub struct SaslAuthResponse {
+    pub(crate) header: ResponseHeader,
+    pub(crate) message: &'static str,
+}
+
 pub type StatsRequest = Request;
 #[derive(Debug)]
 pub struct StatsResponse {
@@ -239,5 +267,3 @@ pub struct StatsResponse {
 // pub struct StatsResponse {
 //     pub(crate) records: Vec<StatsResponseRecord>,
 // }
-
-/* TODO Get And Touch (GAT) */
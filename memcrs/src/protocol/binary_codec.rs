@@ -1,4 +1,4 @@
-use std::{io, u8};
+use std::io;
 
 use crate::cache::error::CacheError;
 use crate::protocol::binary;
@@ -38,6 +38,14 @@ pub enum BinaryRequest {
     QuitQuietly(binary::QuitRequest),
     ItemTooLarge(binary::SetRequest),
     Stats(binary::StatsRequest),
+    Touch(binary::TouchRequest),
+    GetAndTouch(binary::GetAndTouchRequest),
+    GetAndTouchQuietly(binary::GetAndTouchRequest),
+    GetAndTouchKey(binary::GetAndTouchRequest),
+    GetAndTouchKeyQuietly(binary::GetAndTouchRequest),
+    SaslListMechs(binary::SaslListMechsRequest),
+    SaslAuth(binary::SaslAuthRequest),
+    SaslStep(binary::SaslStepRequest),
 }
 
 impl BinaryRequest {
@@ -70,11 +78,99 @@ impl BinaryRequest {
 
             BinaryRequest::Noop(request)
             | BinaryRequest::Version(request)
-            | BinaryRequest::Stats(request) => &request.header,
+            | BinaryRequest::Stats(request)
+            | BinaryRequest::SaslListMechs(request) => &request.header,
 
             BinaryRequest::Flush(request) | BinaryRequest::FlushQuietly(request) => &request.header,
 
             BinaryRequest::Quit(request) | BinaryRequest::QuitQuietly(request) => &request.header,
+
+            BinaryRequest::Touch(request)
+            | BinaryRequest::GetAndTouch(request)
+            | BinaryRequest::GetAndTouchQuietly(request)
+            | BinaryRequest::GetAndTouchKey(request)
+            | BinaryRequest::GetAndTouchKeyQuietly(request) => &request.header,
+
+            BinaryRequest::SaslAuth(request) | BinaryRequest::SaslStep(request) => &request.header,
+        }
+    }
+
+    /// Name of a command, as memcached names its opcodes
+    pub fn command_name(&self) -> &'static str {
+        match self {
+            BinaryRequest::Delete(_) => "delete",
+            BinaryRequest::DeleteQuiet(_) => "deleteq",
+            BinaryRequest::Get(_) => "get",
+            BinaryRequest::GetQuietly(_) => "getq",
+            BinaryRequest::GetKey(_) => "getk",
+            BinaryRequest::GetKeyQuietly(_) => "getkq",
+            BinaryRequest::Set(_) | BinaryRequest::ItemTooLarge(_) => "set",
+            BinaryRequest::SetQuietly(_) => "setq",
+            BinaryRequest::Append(_) => "append",
+            BinaryRequest::AppendQuietly(_) => "appendq",
+            BinaryRequest::Prepend(_) => "prepend",
+            BinaryRequest::PrependQuietly(_) => "prependq",
+            BinaryRequest::Add(_) => "add",
+            BinaryRequest::AddQuietly(_) => "addq",
+            BinaryRequest::Replace(_) => "replace",
+            BinaryRequest::ReplaceQuietly(_) => "replaceq",
+            BinaryRequest::Increment(_) => "increment",
+            BinaryRequest::IncrementQuiet(_) => "incrementq",
+            BinaryRequest::Decrement(_) => "decrement",
+            BinaryRequest::DecrementQuiet(_) => "decrementq",
+            BinaryRequest::Noop(_) => "noop",
+            BinaryRequest::Flush(_) => "flush",
+            BinaryRequest::FlushQuietly(_) => "flushq",
+            BinaryRequest::Version(_) => "version",
+            BinaryRequest::Quit(_) => "quit",
+            BinaryRequest::QuitQuietly(_) => "quitq",
+            BinaryRequest::Stats(_) => "stat",
+            BinaryRequest::Touch(_) => "touch",
+            BinaryRequest::GetAndTouch(_) => "gat",
+            BinaryRequest::GetAndTouchQuietly(_) => "gatq",
+            BinaryRequest::GetAndTouchKey(_) => "gatk",
+            BinaryRequest::GetAndTouchKeyQuietly(_) => "gatkq",
+            BinaryRequest::SaslListMechs(_) => "sasl_list_mechs",
+            BinaryRequest::SaslAuth(_) => "sasl_auth",
+            BinaryRequest::SaslStep(_) => "sasl_step",
+        }
+    }
+
+    /// Key of a request, None if a command has no key
+    pub fn key(&self) -> Option<&Bytes> {
+        match self {
+            BinaryRequest::Delete(request)
+            | BinaryRequest::DeleteQuiet(request)
+            | BinaryRequest::Get(request)
+            | BinaryRequest::GetKey(request)
+            | BinaryRequest::GetKeyQuietly(request)
+            | BinaryRequest::GetQuietly(request) => Some(&request.key),
+
+            BinaryRequest::Set(request)
+            | BinaryRequest::SetQuietly(request)
+            | BinaryRequest::Replace(request)
+            | BinaryRequest::ReplaceQuietly(request)
+            | BinaryRequest::Add(request)
+            | BinaryRequest::AddQuietly(request)
+            | BinaryRequest::ItemTooLarge(request) => Some(&request.key),
+
+            BinaryRequest::Prepend(request)
+            | BinaryRequest::PrependQuietly(request)
+            | BinaryRequest::Append(request)
+            | BinaryRequest::AppendQuietly(request) => Some(&request.key),
+
+            BinaryRequest::Increment(request)
+            | BinaryRequest::IncrementQuiet(request)
+            | BinaryRequest::Decrement(request)
+            | BinaryRequest::DecrementQuiet(request) => Some(&request.key),
+
+            BinaryRequest::Touch(request)
+            | BinaryRequest::GetAndTouch(request)
+            | BinaryRequest::GetAndTouchQuietly(request)
+            | BinaryRequest::GetAndTouchKey(request)
+            | BinaryRequest::GetAndTouchKeyQuietly(request) => Some(&request.key),
+
+            _ => None,
         }
     }
 }
@@ -100,6 +196,10 @@ pub enum BinaryResponse {
     Decrement(binary::DecrementResponse),
     Quit(binary::QuitResponse),
     Stats(binary::StatsResponse),
+    Touch(binary::TouchResponse),
+    GetAndTouch(binary::GetAndTouchResponse),
+    SaslListMechs(binary::SaslListMechsResponse),
+    SaslAuth(binary::SaslAuthResponse),
 }
 
 impl BinaryResponse {
@@ -123,6 +223,10 @@ impl BinaryResponse {
             BinaryResponse::Decrement(response) => &response.header,
             BinaryResponse::Quit(response) => &response.header,
             BinaryResponse::Stats(response) => &response.header,
+            BinaryResponse::Touch(response) => &response.header,
+            BinaryResponse::GetAndTouch(response) => &response.header,
+            BinaryResponse::SaslListMechs(response) => &response.header,
+            BinaryResponse::SaslAuth(response) => &response.header,
         }
     }
 }
@@ -132,7 +236,25 @@ pub fn storage_error_to_response(
     response_header: &mut binary::ResponseHeader,
 ) -> BinaryResponse {
     let message = err.to_static_string();
-    response_header.status = err as u16;
+    response_header.status = match err {
+        CacheError::CasMismatch => CacheError::KeyExists.code(),
+        CacheError::InvalidKey => CacheError::InvalidArguments.code(),
+        err => err.code(),
+    };
+    response_header.body_length = message.len() as u32;
+    BinaryResponse::Error(binary::ErrorResponse {
+        header: *response_header,
+        error: message,
+    })
+}
+
+/// Response of a client which isn't authenticated or which sent
+/// invalid credentials
+pub fn authentication_error_response(
+    message: &'static str,
+    response_header: &mut binary::ResponseHeader,
+) -> BinaryResponse {
+    response_header.status = binary::ResponseStatus::AuthenticationError as u16;
     response_header.body_length = message.len() as u32;
     BinaryResponse::Error(binary::ErrorResponse {
         header: *response_header,
@@ -222,7 +344,7 @@ impl MemcacheBinaryCodec {
     fn parse_request(&mut self, src: &mut BytesMut) -> Result<Option<BinaryRequest>, io::Error> {
         if self.state != RequestParserState::HeaderParsed {
             error!("Incorrect parser state ({:?})", self.state);
-            return Err(Error::new(ErrorKind::Other, "Header is not parsed"));
+            return Err(Error::other("Header is not parsed"));
         }
 
         if self.header.body_length > self.item_size_limit {
@@ -237,7 +359,7 @@ impl MemcacheBinaryCodec {
                 self.header.body_length,
                 src.len()
             );
-            return Err(Error::new(ErrorKind::Other, "Header body length too large"));
+            return Err(Error::other("Header body length too large"));
         }
 
         let result = match FromPrimitive::from_u8(self.header.opcode) {
@@ -281,12 +403,12 @@ impl MemcacheBinaryCodec {
             | Some(binary::Command::GetAndTouch)
             | Some(binary::Command::GetAndTouchQuiet)
             | Some(binary::Command::GetAndTouchKey)
-            | Some(binary::Command::GetAndTouchKeyQuiet)
-            | Some(binary::Command::SaslAuth)
-            | Some(binary::Command::SaslListMechs)
-            | Some(binary::Command::SaslStep) => {
-                error!("Command not supported, opcode: {:?}", self.header.opcode);
-                Ok(None)
+            | Some(binary::Command::GetAndTouchKeyQuiet) => self.parse_touch_request(src),
+
+            Some(binary::Command::SaslListMechs) => self.parse_header_only_request(src),
+
+            Some(binary::Command::SaslAuth) | Some(binary::Command::SaslStep) => {
+                self.parse_sasl_auth_request(src)
             }
 
             Some(binary::Command::OpCodeMax) => {
@@ -384,6 +506,12 @@ impl MemcacheBinaryCodec {
             Ok(Some(BinaryRequest::QuitQuietly(binary::QuitRequest {
                 header: self.header,
             })))
+        } else if self.header.opcode == binary::Command::SaslListMechs as u8 {
+            Ok(Some(BinaryRequest::SaslListMechs(
+                binary::SaslListMechsRequest {
+                    header: self.header,
+                },
+            )))
         } else {
             Ok(Some(BinaryRequest::Version(binary::VersionRequest {
                 header: self.header,
@@ -491,6 +619,55 @@ impl MemcacheBinaryCodec {
         }
     }
 
+    fn parse_touch_request(&self, src: &mut BytesMut) -> Result<Option<BinaryRequest>, io::Error> {
+        if !self.request_valid(src, true) || self.header.extras_length != 4 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Incorrect touch request",
+            ));
+        }
+        let request = binary::TouchRequest {
+            header: self.header,
+            expiration: src.get_u32(),
+            key: src.split_to(self.header.key_length as usize).freeze(),
+        };
+
+        match FromPrimitive::from_u8(self.header.opcode) {
+            Some(binary::Command::Touch) => Ok(Some(BinaryRequest::Touch(request))),
+            Some(binary::Command::GetAndTouch) => Ok(Some(BinaryRequest::GetAndTouch(request))),
+            Some(binary::Command::GetAndTouchQuiet) => {
+                Ok(Some(BinaryRequest::GetAndTouchQuietly(request)))
+            }
+            Some(binary::Command::GetAndTouchKey) => {
+                Ok(Some(BinaryRequest::GetAndTouchKey(request)))
+            }
+            _ => Ok(Some(BinaryRequest::GetAndTouchKeyQuietly(request))),
+        }
+    }
+
+    fn parse_sasl_auth_request(
+        &self,
+        src: &mut BytesMut,
+    ) -> Result<Option<BinaryRequest>, io::Error> {
+        if !self.request_valid(src, true) || self.header.extras_length != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Incorrect sasl auth request",
+            ));
+        }
+        let data_len = self.get_value_len();
+        let request = binary::SaslAuthRequest {
+            header: self.header,
+            mechanism: src.split_to(self.header.key_length as usize).freeze(),
+            data: src.split_to(data_len).freeze(),
+        };
+        if self.header.opcode == binary::Command::SaslAuth as u8 {
+            Ok(Some(BinaryRequest::SaslAuth(request)))
+        } else {
+            Ok(Some(BinaryRequest::SaslStep(request)))
+        }
+    }
+
     fn parse_item_too_large(
         &self,
         _src: &mut BytesMut,
@@ -656,7 +833,8 @@ impl MemcacheBinaryCodec {
             BinaryResponse::Get(response)
             | BinaryResponse::GetKey(response)
             | BinaryResponse::GetKeyQuietly(response)
-            | BinaryResponse::GetQuietly(response) => {
+            | BinaryResponse::GetQuietly(response)
+            | BinaryResponse::GetAndTouch(response) => {
                 dst.put_u32(response.flags);
                 if !response.key.is_empty() {
                     dst.put_slice(&response.key[..]);
@@ -671,11 +849,18 @@ impl MemcacheBinaryCodec {
             BinaryResponse::Version(response) => {
                 dst.put_slice(response.version.as_bytes());
             }
+            BinaryResponse::SaslListMechs(response) => {
+                dst.put_slice(response.mechanisms.as_bytes());
+            }
+            BinaryResponse::SaslAuth(response) => {
+                dst.put_slice(response.message.as_bytes());
+            }
             BinaryResponse::Noop(_response) => {}
             BinaryResponse::Delete(_response) => {}
             BinaryResponse::Flush(_response) => {}
             BinaryResponse::Quit(_response) => {}
             BinaryResponse::Stats(_response) => {}
+            BinaryResponse::Touch(_response) => {}
             BinaryResponse::Increment(response) | BinaryResponse::Decrement(response) => {
                 dst.put_u64(response.value);
             }
@@ -712,7 +897,8 @@ impl MemcacheBinaryCodec {
             BinaryResponse::Get(response)
             | BinaryResponse::GetKey(response)
             | BinaryResponse::GetKeyQuietly(response)
-            | BinaryResponse::GetQuietly(response) => {
+            | BinaryResponse::GetQuietly(response)
+            | BinaryResponse::GetAndTouch(response) => {
                 dst.put_u32(response.flags);
                 dst.put_slice(&response.key[..]);
                 dst.put(response.value.clone());
@@ -725,11 +911,18 @@ impl MemcacheBinaryCodec {
             BinaryResponse::Version(response) => {
                 dst.put_slice(response.version.as_bytes());
             }
+            BinaryResponse::SaslListMechs(response) => {
+                dst.put_slice(response.mechanisms.as_bytes());
+            }
+            BinaryResponse::SaslAuth(response) => {
+                dst.put_slice(response.message.as_bytes());
+            }
             BinaryResponse::Noop(_response) => {}
             BinaryResponse::Delete(_response) => {}
             BinaryResponse::Flush(_response) => {}
             BinaryResponse::Quit(_response) => {}
             BinaryResponse::Stats(_response) => {}
+            BinaryResponse::Touch(_response) => {}
             BinaryResponse::Increment(response) | BinaryResponse::Decrement(response) => {
                 dst.put_u64(response.value);
             }
@@ -8,7 +8,7 @@ mod tests {
     fn decode_packet(src: &[u8]) -> Result<Option<BinaryRequest>, io::Error> {
         let mut decoder = MemcacheBinaryCodec::new(1024);
         let mut buf = BytesMut::with_capacity(src.len());
-        buf.put_slice(&src);
+        buf.put_slice(src);
         decoder.decode(&mut buf)
     }
 
@@ -966,6 +966,77 @@ mod tests {
         }
     }
 
+    #[test]
+    fn decode_touch_request() {
+        let touch_request_packet: [u8; 31] = [
+            0x80, // magic
+            0x1c, // opcode
+            0x00, 0x03, //key len
+            0x04, // extras len
+            0x00, // data type
+            0x00, 0x00, //vbucket id
+            0x00, 0x00, 0x00, 0x07, // total body len
+            0x00, 0x00, 0x00, 0x00, // opaque
+            0x00, 0x00, 0x00, 0x00, // cas
+            0x00, 0x00, 0x00, 0x00, // cas
+            0x00, 0x00, 0x00, 0x64, // expiration 100
+            0x66, 0x6f, 0x6f, // key 'foo'
+        ];
+
+        match decode_packet(&touch_request_packet) {
+            Ok(Some(BinaryRequest::Touch(req))) => {
+                assert_eq!(req.header.opcode, binary::Command::Touch as u8);
+                assert_eq!(req.expiration, 100);
+                assert_eq!(req.key[..], [b'f', b'o', b'o']);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn decode_get_and_touch_key_quiet_request() {
+        let gat_request_packet: [u8; 31] = [
+            0x80, // magic
+            0x24, // opcode
+            0x00, 0x03, //key len
+            0x04, // extras len
+            0x00, // data type
+            0x00, 0x00, //vbucket id
+            0x00, 0x00, 0x00, 0x07, // total body len
+            0x00, 0x00, 0x00, 0x00, // opaque
+            0x00, 0x00, 0x00, 0x00, // cas
+            0x00, 0x00, 0x00, 0x00, // cas
+            0x00, 0x00, 0x00, 0x64, // expiration 100
+            0x66, 0x6f, 0x6f, // key 'foo'
+        ];
+
+        match decode_packet(&gat_request_packet) {
+            Ok(Some(BinaryRequest::GetAndTouchKeyQuietly(req))) => {
+                assert_eq!(req.expiration, 100);
+                assert_eq!(req.key[..], [b'f', b'o', b'o']);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn decode_touch_request_without_expiration_should_fail() {
+        let touch_request_packet: [u8; 27] = [
+            0x80, // magic
+            0x1c, // opcode
+            0x00, 0x03, //key len
+            0x00, // extras len
+            0x00, // data type
+            0x00, 0x00, //vbucket id
+            0x00, 0x00, 0x00, 0x03, // total body len
+            0x00, 0x00, 0x00, 0x00, // opaque
+            0x00, 0x00, 0x00, 0x00, // cas
+            0x00, 0x00, 0x00, 0x00, // cas
+            0x66, 0x6f, 0x6f, // key 'foo'
+        ];
+        assert!(decode_packet(&touch_request_packet).is_err());
+    }
+
     #[test]
     fn decode_flush_request() {
         let flush_request_packet: [u8; 24] = [
@@ -1036,4 +1107,31 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn decode_sasl_auth_request() {
+        let sasl_auth_request_packet: [u8; 34] = [
+            0x80, // magic
+            0x21, // opcode
+            0x00, 0x05, //key len
+            0x00, // extras len
+            0x00, // data type
+            0x00, 0x00, //vbucket id
+            0x00, 0x00, 0x00, 0x0a, // total body len
+            0x00, 0x00, 0x00, 0x00, // opaque
+            0x00, 0x00, 0x00, 0x00, // cas
+            0x00, 0x00, 0x00, 0x00, // cas
+            0x50, 0x4c, 0x41, 0x49, 0x4e, // key 'PLAIN'
+            0x00, 0x61, 0x00, 0x62, 0x63, // value '\0a\0bc'
+        ];
+
+        match decode_packet(&sasl_auth_request_packet) {
+            Ok(Some(BinaryRequest::SaslAuth(req))) => {
+                assert_eq!(req.header.opcode, binary::Command::SaslAuth as u8);
+                assert_eq!(req.mechanism[..], b"PLAIN"[..]);
+                assert_eq!(req.data[..], b"\0a\0bc"[..]);
+            }
+            _ => unreachable!(),
+        }
+    }
 }
@@ -241,7 +241,7 @@ mod tests {
         let mut header = create_response_header(binary::Command::Get, 0, 0);
         header.body_length = "Not found".len() as u32;
         let err = error::CacheError::NotFound;
-        header.status = error::CacheError::NotFound as u16;
+        header.status = error::CacheError::NotFound.code();
         let response = BinaryResponse::Error(binary::ErrorResponse {
             header,
             error: err.to_static_string(),
@@ -1,29 +1,48 @@
 use crate::protocol::binary_codec::{
     BinaryRequest, BinaryResponse, MemcacheBinaryCodec, ResponseMessage,
 };
+use crate::protocol::client_stream::BoxedClientStream;
 use bytes::BytesMut;
 use std::cmp;
 use std::io;
 use std::io::{Error, ErrorKind};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
 use tokio_util::codec::Decoder;
 
+// responses bigger than this are written directly to a socket
+// instead of being copied to a write buffer
+const MAX_BUFFERED_RESPONSE_SIZE: usize = 16 * 1024;
+
 pub struct MemcacheBinaryConnection {
-    stream: TcpStream,
+    stream: BoxedClientStream,
     codec: MemcacheBinaryCodec,
     buffer: BytesMut,
+    write_buffer: BytesMut,
+    pending_responses: usize,
 }
 
 impl MemcacheBinaryConnection {
-    pub fn new(socket: TcpStream, item_size_limit: u32) -> Self {
+    /// received holds data already read from a stream, i.e. to detect a protocol
+    pub fn new(socket: BoxedClientStream, received: BytesMut, item_size_limit: u32) -> Self {
         MemcacheBinaryConnection {
             stream: socket,
             codec: MemcacheBinaryCodec::new(item_size_limit),
-            buffer: BytesMut::with_capacity(4096),
+            buffer: received,
+            write_buffer: BytesMut::with_capacity(4096),
+            pending_responses: 0,
         }
     }
 
+    /// True if part of a next request was already received
+    pub fn has_buffered_data(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+
+    /// Number of responses written since last flush
+    pub fn pending_responses(&self) -> usize {
+        self.pending_responses
+    }
+
     pub async fn read_frame(&mut self) -> Result<Option<BinaryRequest>, io::Error> {
         let _extras_length: u32 = 8;
         loop {
@@ -52,8 +71,12 @@ impl MemcacheBinaryConnection {
                 }
             }
 
-            // There is not enough buffered data to read a frame. Attempt to
-            // read more data from the socket.
+            // There is not enough buffered data to read a frame, all
+            // pipelined requests were handled so responses have to be sent
+            // before waiting for more data from the socket.
+            self.flush().await?;
+
+            // Attempt to read more data from the socket.
             //
             // On success, the number of bytes is returned. `0` indicates "end
             // of stream".
@@ -126,18 +149,40 @@ impl MemcacheBinaryConnection {
         }
     }
 
+    /// Queues response, it is sent on flush or before next read from a socket
     pub async fn write(&mut self, msg: &BinaryResponse) -> io::Result<()> {
         let message = self.codec.encode_message(msg);
-        self.write_data_to_stream(message).await?;
+        self.pending_responses += 1;
+        if message.data.len() > MAX_BUFFERED_RESPONSE_SIZE {
+            self.write_buffer_to_stream().await?;
+            self.write_data_to_stream(message).await?;
+        } else {
+            self.write_buffer.extend_from_slice(&message.data[..]);
+        }
+        Ok(())
+    }
+
+    /// Sends all queued responses
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.write_buffer_to_stream().await?;
+        self.stream.flush().await?;
+        self.pending_responses = 0;
         Ok(())
     }
 
+    // write_all_buf advances write buffer as bytes are written, so if
+    // read_frame is cancelled on shutdown, shutdown sends only the rest
+    async fn write_buffer_to_stream(&mut self) -> io::Result<()> {
+        self.stream.write_all_buf(&mut self.write_buffer).await
+    }
+
     async fn write_data_to_stream(&mut self, msg: ResponseMessage) -> io::Result<()> {
         self.stream.write_all(&msg.data[..]).await?;
         Ok(())
     }
 
     pub async fn shutdown(&mut self) -> io::Result<()> {
+        self.flush().await?;
         self.stream.shutdown().await?;
         Ok(())
     }
@@ -0,0 +1,154 @@
+use crate::memcache::stats::Stats;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+/// Byte stream of a connected client, i.e. TCP or TLS stream,
+/// so protocol connections don't depend on a transport
+pub trait ClientStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> ClientStream for S {}
+
+pub type BoxedClientStream = Box<dyn ClientStream>;
+
+/// Counts bytes read from and written to a stream as bytes_read
+/// and bytes_written of stats command
+pub struct CountingStream {
+    stream: BoxedClientStream,
+    counters: Arc<Stats>,
+}
+
+impl CountingStream {
+    pub fn new(stream: BoxedClientStream, counters: Arc<Stats>) -> CountingStream {
+        CountingStream { stream, counters }
+    }
+}
+
+impl AsyncRead for CountingStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut self.stream).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            Stats::add(
+                &self.counters.bytes_read,
+                (buf.filled().len() - filled) as u64,
+            );
+        }
+        poll
+    }
+}
+
+impl AsyncWrite for CountingStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.stream).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            Stats::add(&self.counters.bytes_written, written as u64);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+/// Fails reads with TimedOut once a client didn't send any data for
+/// a timeout, it starts again after every read which returned data,
+/// so slow clients which keep sending are not disconnected
+pub struct IdleTimeoutStream {
+    stream: BoxedClientStream,
+    timeout: Duration,
+    deadline: Pin<Box<Sleep>>,
+}
+
+impl IdleTimeoutStream {
+    pub fn new(stream: BoxedClientStream, timeout: Duration) -> IdleTimeoutStream {
+        IdleTimeoutStream {
+            stream,
+            timeout,
+            deadline: Box::pin(tokio::time::sleep(timeout)),
+        }
+    }
+}
+
+impl AsyncRead for IdleTimeoutStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        match Pin::new(&mut self.stream).poll_read(cx, buf) {
+            Poll::Ready(Ok(())) if buf.filled().len() > filled => {
+                let deadline = Instant::now() + self.timeout;
+                self.deadline.as_mut().reset(deadline);
+                Poll::Ready(Ok(()))
+            }
+            Poll::Pending => match self.deadline.as_mut().poll(cx) {
+                Poll::Ready(()) => Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "Idle timeout elapsed",
+                ))),
+                Poll::Pending => Poll::Pending,
+            },
+            poll => poll,
+        }
+    }
+}
+
+impl AsyncWrite for IdleTimeoutStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn idle_timeout_should_restart_after_every_read() {
+        let (client, server) = tokio::io::duplex(64);
+        let mut stream = IdleTimeoutStream::new(Box::new(server), Duration::from_millis(200));
+        let (_reader, mut writer) = tokio::io::split(client);
+        let mut buffer = [0u8; 8];
+        // reads span longer than a timeout, but gaps between them don't
+        for _ in 0..3 {
+            tokio::time::sleep(Duration::from_millis(120)).await;
+            writer.write_all(b"a").await.unwrap();
+            assert_eq!(stream.read(&mut buffer).await.unwrap(), 1);
+        }
+        let err = stream.read(&mut buffer).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+}
@@ -1,3 +1,10 @@
+#[cfg(feature = "binary-protocol")]
 pub mod binary;
+#[cfg(feature = "binary-protocol")]
 pub mod binary_codec;
+#[cfg(feature = "binary-protocol")]
 pub mod binary_connection;
+pub mod client_stream;
+pub mod text;
+pub mod text_codec;
+pub mod text_connection;
@@ -0,0 +1,92 @@
+use bytes::Bytes;
+
+/// Storage command of a text protocol, payload of a StoreRequest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreCommand {
+    Set,
+    Add,
+    Replace,
+    Append,
+    Prepend,
+    Cas(u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GetRequest {
+    pub keys: Vec<Bytes>,
+    /// gets command, CAS is returned with values
+    pub with_cas: bool,
+}
+
+/// gat and gats commands, records are returned as by get
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GetAndTouchRequest {
+    pub keys: Vec<Bytes>,
+    /// seconds, 0 if a record never expires, negative if it is already expired
+    pub expiration: i64,
+    /// gats command, CAS is returned with values
+    pub with_cas: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreRequest {
+    pub command: StoreCommand,
+    pub key: Bytes,
+    pub flags: u32,
+    /// seconds, 0 if a record never expires, negative if it is already expired
+    pub expiration: i64,
+    pub value: Bytes,
+    /// no response is sent unless a request fails with an error
+    pub noreply: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeleteRequest {
+    pub key: Bytes,
+    /// no response is sent unless a request fails with an error
+    pub noreply: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TouchRequest {
+    pub key: Bytes,
+    /// seconds, 0 if a record never expires, negative if it is already expired
+    pub expiration: i64,
+    /// no response is sent unless a request fails with an error
+    pub noreply: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeltaRequest {
+    pub key: Bytes,
+    pub delta: u64,
+    /// no response is sent unless a request fails with an error
+    pub noreply: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlushRequest {
+    pub delay: u32,
+    /// no response is sent unless a request fails with an error
+    pub noreply: bool,
+}
+
+/// Group of counters requested by stats command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsGroup {
+    /// stats without arguments
+    General,
+    Items,
+    Settings,
+    /// keys with most hits, see KeyStats
+    HotKeys,
+}
+
+/// Single record of a get response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Value {
+    pub key: Bytes,
+    pub flags: u32,
+    pub cas: Option<u64>,
+    pub data: Bytes,
+}
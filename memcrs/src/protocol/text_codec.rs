@@ -0,0 +1,454 @@
+use std::io;
+
+use crate::cache::error::CacheError;
+use crate::protocol::text;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::io::{Error, ErrorKind};
+use std::str;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Memcached maximum key length
+const MAX_KEY_LENGTH: usize = 250;
+/// Longest request line accepted, requests with a longer line are
+/// treated as a protocol error and a connection is closed
+const MAX_LINE_LENGTH: usize = 2048;
+const END_OF_LINE: &[u8] = b"\r\n";
+
+/// Client request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextRequest {
+    Get(text::GetRequest),
+    GetAndTouch(text::GetAndTouchRequest),
+    Store(text::StoreRequest),
+    Delete(text::DeleteRequest),
+    Touch(text::TouchRequest),
+    Increment(text::DeltaRequest),
+    Decrement(text::DeltaRequest),
+    FlushAll(text::FlushRequest),
+    Stats(text::StatsGroup),
+    Version,
+    Quit,
+    /// value is bigger than item size limit, it is skipped
+    ItemTooLarge,
+    UnknownCommand,
+    /// request line doesn't match command format
+    BadFormat,
+    /// value is not terminated with \r\n
+    BadDataChunk,
+}
+
+impl TextRequest {
+    /// True if a client asked not to send a response, with noreply argument
+    pub fn is_noreply(&self) -> bool {
+        match self {
+            TextRequest::Store(request) => request.noreply,
+            TextRequest::Delete(request) => request.noreply,
+            TextRequest::Touch(request) => request.noreply,
+            TextRequest::Increment(request) | TextRequest::Decrement(request) => request.noreply,
+            TextRequest::FlushAll(request) => request.noreply,
+            _ => false,
+        }
+    }
+
+    /// Name of a command as sent by a client
+    pub fn command_name(&self) -> &'static str {
+        match self {
+            TextRequest::Get(request) if request.with_cas => "gets",
+            TextRequest::Get(_) => "get",
+            TextRequest::GetAndTouch(request) if request.with_cas => "gats",
+            TextRequest::GetAndTouch(_) => "gat",
+            TextRequest::Store(request) => match request.command {
+                text::StoreCommand::Set => "set",
+                text::StoreCommand::Add => "add",
+                text::StoreCommand::Replace => "replace",
+                text::StoreCommand::Append => "append",
+                text::StoreCommand::Prepend => "prepend",
+                text::StoreCommand::Cas(_) => "cas",
+            },
+            TextRequest::Delete(_) => "delete",
+            TextRequest::Touch(_) => "touch",
+            TextRequest::Increment(_) => "incr",
+            TextRequest::Decrement(_) => "decr",
+            TextRequest::FlushAll(_) => "flush_all",
+            TextRequest::Stats(_) => "stats",
+            TextRequest::Version => "version",
+            TextRequest::Quit => "quit",
+            TextRequest::ItemTooLarge => "item_too_large",
+            TextRequest::UnknownCommand => "unknown",
+            TextRequest::BadFormat => "bad_format",
+            TextRequest::BadDataChunk => "bad_data_chunk",
+        }
+    }
+
+    /// Key of a request, a first one of a multi-key get
+    pub fn key(&self) -> Option<&Bytes> {
+        match self {
+            TextRequest::Get(request) => request.keys.first(),
+            TextRequest::GetAndTouch(request) => request.keys.first(),
+            TextRequest::Store(request) => Some(&request.key),
+            TextRequest::Delete(request) => Some(&request.key),
+            TextRequest::Touch(request) => Some(&request.key),
+            TextRequest::Increment(request) | TextRequest::Decrement(request) => Some(&request.key),
+            _ => None,
+        }
+    }
+}
+
+/// Server response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextResponse {
+    Values(Vec<text::Value>),
+    Stored,
+    NotStored,
+    Exists,
+    NotFound,
+    Deleted,
+    Touched,
+    Ok,
+    Number(u64),
+    /// counter names and values, followed by END
+    Stats(Vec<(String, u64)>),
+    Version(&'static str),
+    Error,
+    ClientError(&'static str),
+    ServerError(&'static str),
+}
+
+impl TextResponse {
+    /// Errors are sent even to noreply requests
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            TextResponse::Error | TextResponse::ClientError(_) | TextResponse::ServerError(_)
+        )
+    }
+}
+
+/// Memcached text protocol response for a store error
+pub fn storage_error_to_response(error: CacheError) -> TextResponse {
+    match error {
+        CacheError::NotFound => TextResponse::NotFound,
+        CacheError::KeyExists | CacheError::CasMismatch => TextResponse::Exists,
+        CacheError::ItemNotStored => TextResponse::NotStored,
+        CacheError::InvalidArguments | CacheError::InvalidKey => {
+            TextResponse::ClientError("bad command line format")
+        }
+        CacheError::ArithOnNonNumeric => {
+            TextResponse::ClientError("cannot increment or decrement non-numeric value")
+        }
+        CacheError::ValueTooLarge => TextResponse::ServerError("object too large for cache"),
+        CacheError::OutOfMemory => TextResponse::ServerError("out of memory storing object"),
+        error => TextResponse::ServerError(error.to_static_string()),
+    }
+}
+
+type Tokens<'a> = Vec<&'a [u8]>;
+
+const NOREPLY: &[u8] = b"noreply";
+
+pub struct MemcacheTextCodec {
+    item_size_limit: u32,
+    // bytes of a too large value which weren't received yet
+    skip_bytes: usize,
+}
+
+impl MemcacheTextCodec {
+    pub fn new(item_size_limit: u32) -> MemcacheTextCodec {
+        MemcacheTextCodec {
+            item_size_limit,
+            skip_bytes: 0,
+        }
+    }
+
+    fn skip(&mut self, src: &mut BytesMut) {
+        let skip = self.skip_bytes.min(src.len());
+        src.advance(skip);
+        self.skip_bytes -= skip;
+    }
+
+    // returns parsed request and number of bytes it takes in src,
+    // None if whole request wasn't received yet
+    fn parse_request(&mut self, line: &[u8], src: &BytesMut) -> Option<(TextRequest, usize)> {
+        let line_length = line.len() + 1;
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let tokens: Tokens = line
+            .split(|byte| *byte == b' ')
+            .filter(|token| !token.is_empty())
+            .collect();
+        let (command, args) = match tokens.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return Some((TextRequest::UnknownCommand, line_length)),
+        };
+        // noreply follows cas unique, so it is split off first
+        let (update_args, noreply) = split_noreply(args);
+        let mut store = |command, args| self.parse_store(command, args, noreply, line_length, src);
+        let request = match command {
+            b"get" => parse_get(args, false),
+            b"gets" => parse_get(args, true),
+            b"gat" => parse_get_and_touch(args, false),
+            b"gats" => parse_get_and_touch(args, true),
+            b"set" => return store(text::StoreCommand::Set, update_args),
+            b"add" => return store(text::StoreCommand::Add, update_args),
+            b"replace" => return store(text::StoreCommand::Replace, update_args),
+            b"append" => return store(text::StoreCommand::Append, update_args),
+            b"prepend" => return store(text::StoreCommand::Prepend, update_args),
+            b"cas" => match update_args.split_last() {
+                Some((cas, args)) => match parse_number(cas) {
+                    Some(cas) => return store(text::StoreCommand::Cas(cas), args),
+                    None => TextRequest::BadFormat,
+                },
+                None => TextRequest::BadFormat,
+            },
+            b"delete" => match update_args {
+                [key] if is_key_valid(key) => TextRequest::Delete(text::DeleteRequest {
+                    key: Bytes::copy_from_slice(key),
+                    noreply,
+                }),
+                _ => TextRequest::BadFormat,
+            },
+            // touch <key> <exptime> [noreply]\r\n
+            b"touch" => match update_args {
+                [key, expiration] if is_key_valid(key) => {
+                    parse_expiration(expiration).map_or(TextRequest::BadFormat, |expiration| {
+                        TextRequest::Touch(text::TouchRequest {
+                            key: Bytes::copy_from_slice(key),
+                            expiration,
+                            noreply,
+                        })
+                    })
+                }
+                _ => TextRequest::BadFormat,
+            },
+            b"incr" => parse_delta(update_args, noreply)
+                .map_or(TextRequest::BadFormat, TextRequest::Increment),
+            b"decr" => parse_delta(update_args, noreply)
+                .map_or(TextRequest::BadFormat, TextRequest::Decrement),
+            b"flush_all" => match update_args {
+                [] => TextRequest::FlushAll(text::FlushRequest { delay: 0, noreply }),
+                [delay] => parse_number(delay).map_or(TextRequest::BadFormat, |delay| {
+                    TextRequest::FlushAll(text::FlushRequest { delay, noreply })
+                }),
+                _ => TextRequest::BadFormat,
+            },
+            b"stats" => match args {
+                [] => TextRequest::Stats(text::StatsGroup::General),
+                [b"items"] => TextRequest::Stats(text::StatsGroup::Items),
+                [b"settings"] => TextRequest::Stats(text::StatsGroup::Settings),
+                [b"hotkeys"] => TextRequest::Stats(text::StatsGroup::HotKeys),
+                _ => TextRequest::UnknownCommand,
+            },
+            b"version" => match args {
+                [] => TextRequest::Version,
+                _ => TextRequest::BadFormat,
+            },
+            b"quit" => TextRequest::Quit,
+            _ => TextRequest::UnknownCommand,
+        };
+        Some((request, line_length))
+    }
+
+    // <command> <key> <flags> <exptime> <bytes> [noreply]\r\n<data>\r\n
+    fn parse_store(
+        &mut self,
+        command: text::StoreCommand,
+        args: &[&[u8]],
+        noreply: bool,
+        line_length: usize,
+        src: &BytesMut,
+    ) -> Option<(TextRequest, usize)> {
+        let (key, flags, expiration, value_length) = match args {
+            [key, flags, expiration, value_length] => {
+                match (
+                    parse_number(flags),
+                    parse_expiration(expiration),
+                    parse_number::<usize>(value_length),
+                ) {
+                    (Some(flags), Some(expiration), Some(value_length)) if is_key_valid(key) => {
+                        (key, flags, expiration, value_length)
+                    }
+                    // a data block of a rejected command is skipped,
+                    // so it isn't parsed as a next command
+                    (_, _, Some(value_length)) => {
+                        self.skip_bytes = value_length.saturating_add(END_OF_LINE.len());
+                        return Some((TextRequest::BadFormat, line_length));
+                    }
+                    _ => return Some((TextRequest::BadFormat, line_length)),
+                }
+            }
+            _ => return Some((TextRequest::BadFormat, line_length)),
+        };
+
+        let data_length = value_length.saturating_add(END_OF_LINE.len());
+        if value_length > self.item_size_limit as usize {
+            self.skip_bytes = data_length;
+            return Some((TextRequest::ItemTooLarge, line_length));
+        }
+        if src.len() < line_length + data_length {
+            return None;
+        }
+        let data = &src[line_length..line_length + data_length];
+        if !data.ends_with(END_OF_LINE) {
+            return Some((TextRequest::BadDataChunk, line_length + data_length));
+        }
+        let request = TextRequest::Store(text::StoreRequest {
+            command,
+            key: Bytes::copy_from_slice(key),
+            flags,
+            expiration,
+            value: Bytes::copy_from_slice(&data[..value_length]),
+            noreply,
+        });
+        Some((request, line_length + data_length))
+    }
+}
+
+fn parse_number<T: str::FromStr>(token: &[u8]) -> Option<T> {
+    str::from_utf8(token).ok()?.parse().ok()
+}
+
+// memcached accepts a negative expiration, a record expires at once then
+fn parse_expiration(token: &[u8]) -> Option<i64> {
+    parse_number::<i64>(token).filter(|expiration| *expiration <= u32::MAX as i64)
+}
+
+// noreply is an optional last argument of commands which change records
+fn split_noreply<'a, 'b>(args: &'a [&'b [u8]]) -> (&'a [&'b [u8]], bool) {
+    match args.split_last() {
+        Some((last, args)) if *last == NOREPLY => (args, true),
+        _ => (args, false),
+    }
+}
+
+fn is_key_valid(key: &[u8]) -> bool {
+    key.len() <= MAX_KEY_LENGTH
+}
+
+fn parse_get(args: &[&[u8]], with_cas: bool) -> TextRequest {
+    if args.is_empty() || !args.iter().all(|key| is_key_valid(key)) {
+        return TextRequest::BadFormat;
+    }
+    TextRequest::Get(text::GetRequest {
+        keys: args.iter().map(|key| Bytes::copy_from_slice(key)).collect(),
+        with_cas,
+    })
+}
+
+// gat <exptime> <key>*\r\n
+fn parse_get_and_touch(args: &[&[u8]], with_cas: bool) -> TextRequest {
+    let (expiration, keys) = match args.split_first() {
+        Some((expiration, keys)) => (*expiration, keys),
+        None => return TextRequest::BadFormat,
+    };
+    match (parse_expiration(expiration), parse_get(keys, with_cas)) {
+        (Some(expiration), TextRequest::Get(get_request)) => {
+            TextRequest::GetAndTouch(text::GetAndTouchRequest {
+                keys: get_request.keys,
+                expiration,
+                with_cas,
+            })
+        }
+        _ => TextRequest::BadFormat,
+    }
+}
+
+fn parse_delta(args: &[&[u8]], noreply: bool) -> Option<text::DeltaRequest> {
+    match args {
+        [key, delta] if is_key_valid(key) => Some(text::DeltaRequest {
+            key: Bytes::copy_from_slice(key),
+            delta: parse_number(delta)?,
+            noreply,
+        }),
+        _ => None,
+    }
+}
+
+impl Decoder for MemcacheTextCodec {
+    type Item = TextRequest;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<TextRequest>, io::Error> {
+        self.skip(src);
+        if self.skip_bytes > 0 {
+            return Ok(None);
+        }
+        let line_end = match src.iter().position(|byte| *byte == b'\n') {
+            Some(line_end) => line_end,
+            None if src.len() > MAX_LINE_LENGTH => {
+                return Err(Error::new(ErrorKind::InvalidData, "Line too long"));
+            }
+            None => return Ok(None),
+        };
+        match self.parse_request(&src[..line_end], src) {
+            Some((request, length)) => {
+                src.advance(length);
+                self.skip(src);
+                Ok(Some(request))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl MemcacheTextCodec {
+    fn write_value(&self, value: &text::Value, dst: &mut BytesMut) {
+        dst.put_slice(b"VALUE ");
+        dst.put_slice(&value.key);
+        dst.put_slice(format!(" {} {}", value.flags, value.data.len()).as_bytes());
+        if let Some(cas) = value.cas {
+            dst.put_slice(format!(" {}", cas).as_bytes());
+        }
+        dst.put_slice(END_OF_LINE);
+        dst.put_slice(&value.data);
+        dst.put_slice(END_OF_LINE);
+    }
+
+    fn write_line(&self, line: &[u8], dst: &mut BytesMut) {
+        dst.put_slice(line);
+        dst.put_slice(END_OF_LINE);
+    }
+
+    fn write_message(&self, prefix: &[u8], message: &str, dst: &mut BytesMut) {
+        dst.put_slice(prefix);
+        dst.put_slice(message.as_bytes());
+        dst.put_slice(END_OF_LINE);
+    }
+}
+
+impl Encoder<&TextResponse> for MemcacheTextCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, msg: &TextResponse, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        match msg {
+            TextResponse::Values(values) => {
+                values.iter().for_each(|value| self.write_value(value, dst));
+                self.write_line(b"END", dst);
+            }
+            TextResponse::Stored => self.write_line(b"STORED", dst),
+            TextResponse::NotStored => self.write_line(b"NOT_STORED", dst),
+            TextResponse::Exists => self.write_line(b"EXISTS", dst),
+            TextResponse::NotFound => self.write_line(b"NOT_FOUND", dst),
+            TextResponse::Deleted => self.write_line(b"DELETED", dst),
+            TextResponse::Touched => self.write_line(b"TOUCHED", dst),
+            TextResponse::Ok => self.write_line(b"OK", dst),
+            TextResponse::Number(value) => self.write_line(value.to_string().as_bytes(), dst),
+            TextResponse::Stats(stats) => {
+                stats.iter().for_each(|(name, value)| {
+                    self.write_line(format!("STAT {} {}", name, value).as_bytes(), dst)
+                });
+                self.write_line(b"END", dst);
+            }
+            TextResponse::Version(version) => self.write_message(b"VERSION ", version, dst),
+            TextResponse::Error => self.write_line(b"ERROR", dst),
+            TextResponse::ClientError(message) => {
+                self.write_message(b"CLIENT_ERROR ", message, dst)
+            }
+            TextResponse::ServerError(message) => {
+                self.write_message(b"SERVER_ERROR ", message, dst)
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod text_codec_tests;
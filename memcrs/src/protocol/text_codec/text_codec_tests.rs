@@ -0,0 +1,343 @@
+use super::*;
+
+const ITEM_SIZE_LIMIT: u32 = 16;
+
+fn decode_all(src: &[u8]) -> Vec<TextRequest> {
+    let mut decoder = MemcacheTextCodec::new(ITEM_SIZE_LIMIT);
+    let mut buf = BytesMut::from(src);
+    let mut requests = Vec::new();
+    while let Some(request) = decoder.decode(&mut buf).unwrap() {
+        requests.push(request);
+    }
+    requests
+}
+
+fn encode(response: TextResponse) -> BytesMut {
+    let mut encoder = MemcacheTextCodec::new(ITEM_SIZE_LIMIT);
+    let mut dst = BytesMut::new();
+    encoder.encode(&response, &mut dst).unwrap();
+    dst
+}
+
+#[test]
+fn decode_get_request() {
+    let requests = decode_all(b"get foo  bar\r\ngets foo\n");
+    assert_eq!(
+        requests,
+        vec![
+            TextRequest::Get(text::GetRequest {
+                keys: vec![Bytes::from("foo"), Bytes::from("bar")],
+                with_cas: false,
+            }),
+            TextRequest::Get(text::GetRequest {
+                keys: vec![Bytes::from("foo")],
+                with_cas: true,
+            }),
+        ]
+    );
+}
+
+#[test]
+fn decode_store_request_after_whole_value_is_received() {
+    let mut decoder = MemcacheTextCodec::new(ITEM_SIZE_LIMIT);
+    let mut buf = BytesMut::from(&b"cas foo 5 10 3 42\r\nba"[..]);
+    assert_eq!(decoder.decode(&mut buf).unwrap(), None);
+
+    buf.extend_from_slice(b"r\r\n");
+    let request = decoder.decode(&mut buf).unwrap();
+    assert_eq!(
+        request,
+        Some(TextRequest::Store(text::StoreRequest {
+            command: text::StoreCommand::Cas(42),
+            key: Bytes::from("foo"),
+            flags: 5,
+            expiration: 10,
+            value: Bytes::from("bar"),
+            noreply: false,
+        }))
+    );
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn decode_too_large_value_should_skip_value() {
+    let mut decoder = MemcacheTextCodec::new(ITEM_SIZE_LIMIT);
+    let mut buf = BytesMut::from(&b"set foo 0 0 20\r\n0123456789"[..]);
+    assert_eq!(
+        decoder.decode(&mut buf).unwrap(),
+        Some(TextRequest::ItemTooLarge)
+    );
+    assert_eq!(decoder.decode(&mut buf).unwrap(), None);
+
+    buf.extend_from_slice(b"0123456789\r\nquit\r\n");
+    assert_eq!(decoder.decode(&mut buf).unwrap(), Some(TextRequest::Quit));
+}
+
+#[test]
+fn decode_negative_expiration() {
+    let requests = decode_all(b"set foo 0 -1 1\r\na\r\ntouch foo -1\r\ngat -1 foo\r\n");
+    assert_eq!(
+        requests,
+        vec![
+            TextRequest::Store(text::StoreRequest {
+                command: text::StoreCommand::Set,
+                key: Bytes::from("foo"),
+                flags: 0,
+                expiration: -1,
+                value: Bytes::from("a"),
+                noreply: false,
+            }),
+            TextRequest::Touch(text::TouchRequest {
+                key: Bytes::from("foo"),
+                expiration: -1,
+                noreply: false,
+            }),
+            TextRequest::GetAndTouch(text::GetAndTouchRequest {
+                keys: vec![Bytes::from("foo")],
+                expiration: -1,
+                with_cas: false,
+            }),
+        ]
+    );
+}
+
+#[test]
+fn decode_bad_store_command_should_skip_value() {
+    let long_key = "a".repeat(251);
+    let requests = decode_all(
+        format!(
+            "set foo bar 0 3\r\nget\r\nset {} 0 0 3\r\nget\r\nset foo 0 4294967296 3\r\nget\r\nquit\r\n",
+            long_key
+        )
+        .as_bytes(),
+    );
+    assert_eq!(
+        requests,
+        vec![
+            TextRequest::BadFormat,
+            TextRequest::BadFormat,
+            TextRequest::BadFormat,
+            TextRequest::Quit,
+        ]
+    );
+}
+
+#[test]
+fn decode_get_and_touch_request() {
+    let requests = decode_all(b"gat 10 foo bar\r\ngats 0 foo\r\ngat 10\r\ngat foo bar\r\n");
+    assert_eq!(
+        requests,
+        vec![
+            TextRequest::GetAndTouch(text::GetAndTouchRequest {
+                keys: vec![Bytes::from("foo"), Bytes::from("bar")],
+                expiration: 10,
+                with_cas: false,
+            }),
+            TextRequest::GetAndTouch(text::GetAndTouchRequest {
+                keys: vec![Bytes::from("foo")],
+                expiration: 0,
+                with_cas: true,
+            }),
+            TextRequest::BadFormat,
+            TextRequest::BadFormat,
+        ]
+    );
+}
+
+#[test]
+fn decode_touch_request() {
+    let requests = decode_all(b"touch foo 60\r\ntouch foo\r\ntouch foo bar\r\n");
+    assert_eq!(
+        requests,
+        vec![
+            TextRequest::Touch(text::TouchRequest {
+                key: Bytes::from("foo"),
+                expiration: 60,
+                noreply: false,
+            }),
+            TextRequest::BadFormat,
+            TextRequest::BadFormat,
+        ]
+    );
+}
+
+#[test]
+fn decode_noreply_argument() {
+    let requests = decode_all(
+        b"set foo 1 0 3 noreply\r\nbar\r\ncas foo 0 0 1 7 noreply\r\na\r\n\
+          delete foo noreply\r\nincr foo 2 noreply\r\ntouch foo 10 noreply\r\n\
+          flush_all noreply\r\nget noreply\r\ndelete foo 0 noreply\r\n",
+    );
+    assert_eq!(
+        requests,
+        vec![
+            TextRequest::Store(text::StoreRequest {
+                command: text::StoreCommand::Set,
+                key: Bytes::from("foo"),
+                flags: 1,
+                expiration: 0,
+                value: Bytes::from("bar"),
+                noreply: true,
+            }),
+            TextRequest::Store(text::StoreRequest {
+                command: text::StoreCommand::Cas(7),
+                key: Bytes::from("foo"),
+                flags: 0,
+                expiration: 0,
+                value: Bytes::from("a"),
+                noreply: true,
+            }),
+            TextRequest::Delete(text::DeleteRequest {
+                key: Bytes::from("foo"),
+                noreply: true,
+            }),
+            TextRequest::Increment(text::DeltaRequest {
+                key: Bytes::from("foo"),
+                delta: 2,
+                noreply: true,
+            }),
+            TextRequest::Touch(text::TouchRequest {
+                key: Bytes::from("foo"),
+                expiration: 10,
+                noreply: true,
+            }),
+            TextRequest::FlushAll(text::FlushRequest {
+                delay: 0,
+                noreply: true,
+            }),
+            // noreply is a key of a get
+            TextRequest::Get(text::GetRequest {
+                keys: vec![Bytes::from("noreply")],
+                with_cas: false,
+            }),
+            TextRequest::BadFormat,
+        ]
+    );
+    assert!(requests.iter().take(6).all(TextRequest::is_noreply));
+}
+
+#[test]
+fn decode_stats_request() {
+    let requests =
+        decode_all(b"stats\r\nstats items\r\nstats settings\r\nstats hotkeys\r\nstats slabs\r\n");
+    assert_eq!(
+        requests,
+        vec![
+            TextRequest::Stats(text::StatsGroup::General),
+            TextRequest::Stats(text::StatsGroup::Items),
+            TextRequest::Stats(text::StatsGroup::Settings),
+            TextRequest::Stats(text::StatsGroup::HotKeys),
+            TextRequest::UnknownCommand,
+        ]
+    );
+}
+
+#[test]
+fn decode_version_request() {
+    let requests = decode_all(b"version\r\nversion 1\r\n");
+    assert_eq!(requests, vec![TextRequest::Version, TextRequest::BadFormat]);
+}
+
+#[test]
+fn decode_invalid_requests() {
+    let long_key = "a".repeat(251);
+    let requests = decode_all(
+        format!(
+            "foo\r\n\r\nget\r\nget {}\r\nincr foo bar\r\nset foo 0 0 3\r\nbarbaz\r\n",
+            long_key
+        )
+        .as_bytes(),
+    );
+    assert_eq!(
+        requests,
+        vec![
+            TextRequest::UnknownCommand,
+            TextRequest::UnknownCommand,
+            TextRequest::BadFormat,
+            TextRequest::BadFormat,
+            TextRequest::BadFormat,
+            TextRequest::BadDataChunk,
+            TextRequest::UnknownCommand,
+        ]
+    );
+}
+
+#[test]
+fn decode_too_long_line_should_fail() {
+    let mut decoder = MemcacheTextCodec::new(ITEM_SIZE_LIMIT);
+    let mut buf = BytesMut::from("a".repeat(MAX_LINE_LENGTH + 1).as_bytes());
+    assert!(decoder.decode(&mut buf).is_err());
+}
+
+#[test]
+fn encode_values_response() {
+    let response = TextResponse::Values(vec![
+        text::Value {
+            key: Bytes::from("foo"),
+            flags: 5,
+            cas: None,
+            data: Bytes::from("bar"),
+        },
+        text::Value {
+            key: Bytes::from("baz"),
+            flags: 0,
+            cas: Some(42),
+            data: Bytes::from(""),
+        },
+    ]);
+    assert_eq!(
+        encode(response),
+        BytesMut::from(&b"VALUE foo 5 3\r\nbar\r\nVALUE baz 0 0 42\r\n\r\nEND\r\n"[..])
+    );
+}
+
+#[test]
+fn encode_stats_response() {
+    let response = TextResponse::Stats(vec![
+        (String::from("uptime"), 5),
+        (String::from("cmd_get"), 12),
+    ]);
+    assert_eq!(
+        encode(response),
+        BytesMut::from(&b"STAT uptime 5\r\nSTAT cmd_get 12\r\nEND\r\n"[..])
+    );
+}
+
+#[test]
+fn encode_status_responses() {
+    assert_eq!(
+        encode(TextResponse::Stored),
+        BytesMut::from(&b"STORED\r\n"[..])
+    );
+    assert_eq!(
+        encode(TextResponse::Touched),
+        BytesMut::from(&b"TOUCHED\r\n"[..])
+    );
+    assert_eq!(
+        encode(TextResponse::Version("1.2.3")),
+        BytesMut::from(&b"VERSION 1.2.3\r\n"[..])
+    );
+    assert_eq!(
+        encode(TextResponse::Number(15)),
+        BytesMut::from(&b"15\r\n"[..])
+    );
+    assert_eq!(
+        encode(TextResponse::ClientError("bad data chunk")),
+        BytesMut::from(&b"CLIENT_ERROR bad data chunk\r\n"[..])
+    );
+}
+
+#[test]
+fn cas_mismatch_should_be_sent_as_exists() {
+    let response = storage_error_to_response(CacheError::CasMismatch);
+    assert_eq!(&encode(response)[..], b"EXISTS\r\n");
+}
+
+#[test]
+fn invalid_key_should_be_sent_as_client_error() {
+    let response = storage_error_to_response(CacheError::InvalidKey);
+    assert_eq!(
+        &encode(response)[..],
+        b"CLIENT_ERROR bad command line format\r\n"
+    );
+}
@@ -0,0 +1,130 @@
+use crate::protocol::client_stream::BoxedClientStream;
+use crate::protocol::text_codec::{MemcacheTextCodec, TextRequest, TextResponse};
+use bytes::BytesMut;
+use std::io;
+use std::io::{Error, ErrorKind};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_util::codec::{Decoder, Encoder};
+
+// write buffer is sent to a socket once it grows bigger than this
+const MAX_BUFFERED_RESPONSE_SIZE: usize = 16 * 1024;
+
+pub struct MemcacheTextConnection {
+    stream: BoxedClientStream,
+    codec: MemcacheTextCodec,
+    buffer: BytesMut,
+    write_buffer: BytesMut,
+    pending_responses: usize,
+}
+
+impl MemcacheTextConnection {
+    /// received holds data already read from a stream, i.e. to detect a protocol
+    pub fn new(socket: BoxedClientStream, received: BytesMut, item_size_limit: u32) -> Self {
+        MemcacheTextConnection {
+            stream: socket,
+            codec: MemcacheTextCodec::new(item_size_limit),
+            buffer: received,
+            write_buffer: BytesMut::with_capacity(4096),
+            pending_responses: 0,
+        }
+    }
+
+    /// True if part of a next request was already received
+    pub fn has_buffered_data(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+
+    /// Number of responses written since last flush
+    pub fn pending_responses(&self) -> usize {
+        self.pending_responses
+    }
+
+    pub async fn read_frame(&mut self) -> Result<Option<TextRequest>, io::Error> {
+        loop {
+            if let Some(frame) = self.codec.decode(&mut self.buffer)? {
+                return Ok(Some(frame));
+            }
+
+            // all pipelined requests were handled, so responses have
+            // to be sent before waiting for more data from the socket
+            self.flush().await?;
+
+            if 0 == self.stream.read_buf(&mut self.buffer).await? {
+                // the peer closed a socket while sending a request
+                if self.buffer.is_empty() {
+                    return Ok(None);
+                } else {
+                    return Err(Error::new(
+                        ErrorKind::ConnectionReset,
+                        "Connection reset by peer",
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Queues response, it is sent on flush or before next read from a socket
+    pub async fn write(&mut self, msg: &TextResponse) -> io::Result<()> {
+        self.codec.encode(msg, &mut self.write_buffer)?;
+        self.pending_responses += 1;
+        if self.write_buffer.len() > MAX_BUFFERED_RESPONSE_SIZE {
+            self.write_buffer_to_stream().await?;
+        }
+        Ok(())
+    }
+
+    /// Sends all queued responses
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.write_buffer_to_stream().await?;
+        self.stream.flush().await?;
+        self.pending_responses = 0;
+        Ok(())
+    }
+
+    // write_all_buf advances write buffer as bytes are written, so if
+    // read_frame is cancelled on shutdown, shutdown sends only the rest
+    async fn write_buffer_to_stream(&mut self) -> io::Result<()> {
+        self.stream.write_all_buf(&mut self.write_buffer).await
+    }
+
+    pub async fn shutdown(&mut self) -> io::Result<()> {
+        self.flush().await?;
+        self.stream.shutdown().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn shutdown_after_cancelled_read_should_not_send_responses_twice() {
+        let (mut client, server) = tokio::io::duplex(16);
+        let mut connection = MemcacheTextConnection::new(Box::new(server), BytesMut::new(), 1024);
+        let counters = (0..4).map(|i| (format!("counter_{}", i), i)).collect();
+        let response = TextResponse::Stats(counters);
+        connection.write(&response).await.unwrap();
+
+        // a response doesn't fit into a pipe, so read_frame is cancelled
+        // while it is flushing, as it is on server shutdown
+        tokio::select! {
+            _frame = connection.read_frame() => unreachable!(),
+            _ = tokio::time::sleep(Duration::from_millis(50)) => {}
+        }
+        let reader = tokio::spawn(async move {
+            let mut received = Vec::new();
+            client.read_to_end(&mut received).await.unwrap();
+            received
+        });
+        connection.shutdown().await.unwrap();
+        drop(connection);
+
+        let mut expected = BytesMut::new();
+        MemcacheTextCodec::new(1024)
+            .encode(&response, &mut expected)
+            .unwrap();
+        assert_eq!(reader.await.unwrap(), expected[..]);
+    }
+}
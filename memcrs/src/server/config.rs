@@ -0,0 +1,139 @@
+use crate::memcache::cli::config_file::ConfigFile;
+use crate::memcache::cli::parser::RuntimeType;
+use crate::memcache::eviction_policy::EvictionPolicy;
+use crate::memcache_server::memc_tcp::Protocol;
+use byte_unit::Byte;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+/// memcrsd configuration grouped by concern, see MemcrsArgs::into_server_config
+#[derive(Debug)]
+pub struct ServerConfig {
+    pub network: NetworkConfig,
+    pub storage: StorageConfig,
+    pub runtime: RuntimeConfig,
+    pub logging: LogConfig,
+    pub profiling: ProfilingConfig,
+    pub telemetry: TelemetryConfig,
+    /// file a config was loaded from, if --config was passed
+    pub config_file: Option<ConfigFile>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NetworkConfig {
+    pub port: u16,
+    pub listen_address: IpAddr,
+    pub backlog_limit: u32,
+    pub connection_limit: u32,
+    pub max_pipeline_depth: u32,
+    /// time connected clients have to finish requests on shutdown
+    pub shutdown_timeout_secs: u32,
+    /// clients which don't send any data for this long are disconnected, 0 if never
+    pub idle_timeout_secs: u32,
+    /// requests handled longer than this are logged, 0 if none are
+    pub slow_log_threshold_us: u64,
+    /// clients of other protocols are disconnected
+    pub protocol: Protocol,
+    /// serve text protocol over UDP on port besides TCP
+    pub enable_udp: bool,
+    /// time all datagrams of a UDP request have to arrive in
+    pub udp_fragment_timeout_ms: u64,
+    /// clients are served over TLS if set
+    pub tls: Option<TlsConfig>,
+    /// clients may also connect to a Unix domain socket if set
+    pub unix_socket: Option<UnixSocketConfig>,
+    /// serve clients over unix_socket only
+    pub no_tcp: bool,
+    /// binary protocol clients authenticate with credentials from this file if set
+    pub sasl_users_file: Option<PathBuf>,
+    /// serve text protocol clients if sasl_users_file is set
+    pub allow_unauthenticated_text: bool,
+    pub tcp_nodelay: bool,
+    /// client socket buffer sizes, OS defaults are used if None
+    pub tcp_recv_buffer_size: Option<usize>,
+    pub tcp_send_buffer_size: Option<usize>,
+    /// listen on a socket passed by systemd instead of binding
+    pub systemd_socket: bool,
+    /// port of HTTP health check endpoints, disabled if None
+    pub health_port: Option<u16>,
+    /// port of HTTP admin API, disabled if None
+    pub admin_port: Option<u16>,
+    /// bearer token required by admin API, not required if None
+    pub admin_token: Option<String>,
+    /// port of Prometheus metrics endpoint, disabled if None
+    pub metrics_port: Option<u16>,
+    /// port of gRPC admin API, disabled if None
+    pub grpc_port: Option<u16>,
+}
+
+/// PEM files of a TLS certificate
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    /// clients have to present a certificate signed by these CAs if set
+    pub ca_path: Option<PathBuf>,
+}
+
+/// Unix domain socket clients connect to
+#[derive(Debug, Clone)]
+pub struct UnixSocketConfig {
+    pub path: PathBuf,
+    /// permissions of a socket file
+    pub mode: u32,
+}
+
+#[derive(Debug)]
+pub struct StorageConfig {
+    /// memory limit in bytes
+    pub memory_limit: u64,
+    pub item_size_limit: Byte,
+    pub eviction_policy: EvictionPolicy,
+    /// expected average item size, used to pre-size a store
+    pub avg_item_size: usize,
+    /// keep sorted key index for prefix scans
+    pub enable_prefix_index: bool,
+    /// number of store shards, DashMap default if None
+    pub shard_count: Option<usize>,
+    /// number of records sampled by lru eviction
+    pub lru_sample_size: usize,
+    /// number of keys counted for stats hotkeys
+    pub key_stats_capacity: usize,
+    /// interval of removal of expired records, disabled if 0
+    pub sweep_interval_ms: u64,
+    pub compression: CompressionConfig,
+}
+
+/// lz4 compression of stored values
+#[derive(Debug, Clone, Copy)]
+pub struct CompressionConfig {
+    pub enabled: bool,
+    /// shorter values are stored as they are
+    pub min_size: usize,
+    /// lz4_flex compresses at a single level, so it is always 1
+    pub level: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RuntimeConfig {
+    pub runtime_type: RuntimeType,
+    pub threads: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LogConfig {
+    pub verbose: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct TelemetryConfig {
+    /// OTLP/gRPC collector spans are exported to, tracing is disabled if None
+    pub otlp_endpoint: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProfilingConfig {
+    /// CPU profile output path, profiling is disabled if None
+    pub cpu_profile: Option<PathBuf>,
+    pub duration_secs: Option<u64>,
+}
@@ -0,0 +1,44 @@
+//! Logging of memcrsd, a level set by --verbose can be changed while
+//! a server is running, i.e. once a config file is reloaded on SIGHUP.
+
+use std::sync::OnceLock;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, reload, Registry};
+
+static LEVEL: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
+
+/// Level of logs for a number of times --verbose was given,
+/// i.e. 'memcrsd -v -v -v' or 'memcrsd -vvv' vs 'memcrsd -v'
+pub fn log_level(verbose: u8) -> LevelFilter {
+    match verbose {
+        0 => LevelFilter::ERROR,
+        1 => LevelFilter::WARN,
+        2 => LevelFilter::INFO,
+        3 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    }
+}
+
+/// Installs global subscriber which writes logs to stdout
+pub fn init(verbose: u8) {
+    let (filter, handle) = reload::Layer::new(log_level(verbose));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer())
+        .init();
+    let _ = LEVEL.set(handle);
+}
+
+/// Changes a level of logs, returns false if it is already set
+/// or logging wasn't initialized
+pub fn set_level(level: LevelFilter) -> bool {
+    let handle = match LEVEL.get() {
+        Some(handle) => handle,
+        None => return false,
+    };
+    if handle.clone_current() == Some(level) {
+        return false;
+    }
+    handle.modify(|filter| *filter = level).is_ok()
+}
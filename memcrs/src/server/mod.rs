@@ -1 +1,8 @@
+pub mod config;
+pub mod logging;
+#[cfg(feature = "cpu-profiling")]
+pub mod profiler;
+pub mod systemd;
+#[cfg(feature = "opentelemetry")]
+pub mod telemetry;
 pub mod timer;
@@ -0,0 +1,52 @@
+use log::info;
+use pprof::protos::Message;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+
+const SAMPLING_FREQUENCY: i32 = 99;
+
+/// Reason why profiling has been finished
+#[derive(Debug, PartialEq, Eq)]
+pub enum ProfilingStop {
+    /// profiling duration elapsed, server keeps running
+    Elapsed,
+    /// SIGTERM received, server should shut down
+    Terminated,
+}
+
+/// Samples CPU usage of a whole process until duration elapses or SIGTERM
+/// is received and writes pprof protobuf profile to output path.
+pub async fn profile_cpu(output: PathBuf, duration: Option<Duration>) -> io::Result<ProfilingStop> {
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(SAMPLING_FREQUENCY)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()
+        .map_err(io::Error::other)?;
+    info!("CPU profiling started, profile will be written to: {:?}", output);
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    let stop = match duration {
+        Some(duration) => tokio::select! {
+            _ = tokio::time::sleep(duration) => ProfilingStop::Elapsed,
+            _ = terminate.recv() => ProfilingStop::Terminated,
+        },
+        None => {
+            terminate.recv().await;
+            ProfilingStop::Terminated
+        }
+    };
+
+    let report = guard.report().build().map_err(io::Error::other)?;
+    drop(guard);
+    write_profile(&report, &output)?;
+    info!("CPU profile written to: {:?}", output);
+    Ok(stop)
+}
+
+fn write_profile(report: &pprof::Report, output: &Path) -> io::Result<()> {
+    let profile = report.pprof().map_err(io::Error::other)?;
+    let content = profile.write_to_bytes().map_err(io::Error::other)?;
+    std::fs::write(output, content)
+}
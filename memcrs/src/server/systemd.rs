@@ -0,0 +1,132 @@
+//! systemd socket activation and readiness notification, see
+//! sd_listen_fds(3) and sd_notify(3). Both are plain environment
+//! variable and datagram protocols, so no libsystemd is needed.
+
+use std::env;
+use std::io;
+use std::net::TcpListener;
+
+/// First file descriptor passed by systemd, after stdin, stdout and stderr
+const LISTEN_FDS_START: i32 = 3;
+
+// LISTEN_PID guards against inheriting variables meant for a parent process
+fn parse_listen_fds(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> usize {
+    match (listen_pid, listen_fds) {
+        (Some(listen_pid), Some(listen_fds)) if listen_pid.parse() == Ok(pid) => {
+            listen_fds.parse().unwrap_or(0)
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(unix)]
+#[allow(unsafe_code)]
+mod unsafe_impls {
+    use super::LISTEN_FDS_START;
+    use std::net::TcpListener;
+    use std::os::unix::io::FromRawFd;
+
+    /// Takes ownership of count sockets passed by systemd. It has to be
+    /// called at most once per process, with the count of LISTEN_FDS
+    /// of this process, otherwise a descriptor could be closed twice.
+    pub(super) fn take_listen_fds(count: i32) -> Vec<TcpListener> {
+        (LISTEN_FDS_START..LISTEN_FDS_START + count)
+            // SAFETY: systemd passes count open sockets starting at fd 3
+            // and nothing else in this process uses them, so each one
+            // is owned only by a returned listener, which closes it on drop
+            .map(|fd| unsafe { TcpListener::from_raw_fd(fd) })
+            .collect()
+    }
+}
+
+/// Takes listening sockets passed by systemd, an empty vector is returned
+/// if a process wasn't socket activated. Variables are removed from
+/// the environment, so sockets are not taken twice.
+#[cfg(unix)]
+pub fn listen_fds() -> io::Result<Vec<TcpListener>> {
+    let count = parse_listen_fds(
+        env::var("LISTEN_PID").ok().as_deref(),
+        env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    );
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+    let count = i32::try_from(count).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    // descriptors are owned by this process from now on, systemd
+    // keeps its own copies to pass them again after a restart.
+    // Variables were removed above, so sockets are taken only once.
+    Ok(unsafe_impls::take_listen_fds(count))
+}
+
+#[cfg(not(unix))]
+pub fn listen_fds() -> io::Result<Vec<TcpListener>> {
+    Ok(Vec::new())
+}
+
+#[cfg(target_os = "linux")]
+fn notify_socket(path: &str, state: &str) -> io::Result<()> {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+    let socket = UnixDatagram::unbound()?;
+    // '@' stands for a socket in the abstract namespace
+    match path.strip_prefix('@') {
+        Some(name) => socket.send_to_addr(
+            state.as_bytes(),
+            &SocketAddr::from_abstract_name(name.as_bytes())?,
+        )?,
+        None => socket.send_to(state.as_bytes(), path)?,
+    };
+    Ok(())
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn notify_socket(path: &str, state: &str) -> io::Result<()> {
+    let socket = std::os::unix::net::UnixDatagram::unbound()?;
+    socket.send_to(state.as_bytes(), path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn notify_socket(_path: &str, _state: &str) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Tells systemd a service finished starting up, false is returned
+/// if a process isn't run by systemd with Type=notify
+pub fn notify_ready() -> io::Result<bool> {
+    match env::var("NOTIFY_SOCKET") {
+        Ok(path) => notify_socket(&path, "READY=1").map(|_| true),
+        Err(_) => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listen_fds_should_be_passed_only_to_a_given_process() {
+        assert_eq!(parse_listen_fds(Some("42"), Some("2"), 42), 2);
+        assert_eq!(parse_listen_fds(Some("41"), Some("2"), 42), 0);
+        assert_eq!(parse_listen_fds(None, Some("2"), 42), 0);
+        assert_eq!(parse_listen_fds(Some("42"), None, 42), 0);
+        assert_eq!(parse_listen_fds(Some("42"), Some("x"), 42), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn notify_socket_should_send_a_state_datagram() {
+        use std::os::unix::net::UnixDatagram;
+
+        let path = env::temp_dir().join(format!("memcrs-notify-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let systemd = UnixDatagram::bind(&path).unwrap();
+        notify_socket(path.to_str().unwrap(), "READY=1").unwrap();
+        let mut buf = [0; 16];
+        let len = systemd.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,57 @@
+//! Export of spans to an OTLP/gRPC collector, spans are created by
+//! memcache::traced_cache::TracedCache.
+
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use std::io;
+use std::sync::OnceLock;
+
+const SERVICE_NAME: &str = "memcrsd";
+
+struct Telemetry {
+    provider: SdkTracerProvider,
+    // a connection to a collector is driven by a runtime of its own,
+    // so exports don't delay clients
+    _runtime: tokio::runtime::Runtime,
+}
+
+static TELEMETRY: OnceLock<Telemetry> = OnceLock::new();
+
+/// Installs global tracer provider which exports spans in batches
+/// to an endpoint, i.e. http://localhost:4317
+pub fn init(endpoint: &str) -> io::Result<()> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("otlp-exporter")
+        .enable_all()
+        .build()?;
+    // a gRPC channel spawns its tasks on a runtime it is created in
+    let exporter = {
+        let _guard = runtime.enter();
+        SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build()
+            .map_err(io::Error::other)?
+    };
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+        .build();
+    opentelemetry::global::set_tracer_provider(provider.clone());
+    let _ = TELEMETRY.set(Telemetry {
+        provider,
+        _runtime: runtime,
+    });
+    Ok(())
+}
+
+/// Exports spans which weren't exported yet, called once a server is shut down
+pub fn shutdown() {
+    if let Some(telemetry) = TELEMETRY.get() {
+        if let Err(err) = telemetry.provider.shutdown() {
+            warn!("Cannot export remaining spans: {}", err);
+        }
+    }
+}
@@ -4,16 +4,37 @@ use std::time::Duration;
 use tokio::time::{interval_at, Instant};
 
 pub trait Timer {
+    /// Seconds elapsed since a timer was started, used for expiration
     fn timestamp(&self) -> u64;
+
+    /// Monotonic time, so code measuring durations doesn't call
+    /// Instant::now directly and can be controlled by a mock timer
+    fn now_instant(&self) -> std::time::Instant;
+
+    /// Microseconds elapsed since a timer was started, for callers
+    /// which need a finer time than timestamp seconds
+    fn micros(&self) -> u64;
+
+    /// Milliseconds elapsed since a timer was started, used for
+    /// expiration, so records can expire between timestamp seconds
+    fn millis(&self) -> u64 {
+        self.timestamp() * 1000
+    }
 }
 
 pub trait SetableTimer {
     fn add_second(&self);
 }
 
-#[derive(Default)]
 pub struct SystemTimer {
     seconds: AtomicU64,
+    start: std::time::Instant,
+}
+
+impl Default for SystemTimer {
+    fn default() -> Self {
+        SystemTimer::new()
+    }
 }
 
 impl SystemTimer {
@@ -21,6 +42,7 @@ impl SystemTimer {
         debug!("Creating system timer");
         SystemTimer {
             seconds: AtomicU64::new(0),
+            start: std::time::Instant::now(),
         }
     }
 
@@ -39,6 +61,18 @@ impl Timer for SystemTimer {
     fn timestamp(&self) -> u64 {
         self.seconds.load(Ordering::Acquire)
     }
+
+    fn now_instant(&self) -> std::time::Instant {
+        std::time::Instant::now()
+    }
+
+    fn micros(&self) -> u64 {
+        self.start.elapsed().as_micros() as u64
+    }
+
+    fn millis(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
 }
 
 impl SetableTimer for SystemTimer {
@@ -0,0 +1,17 @@
+use std::env;
+
+fn main() {
+    println!("cargo:rerun-if-changed=tests/c/roundtrip.c");
+    println!("cargo:rerun-if-changed=include/memcrs.h");
+    // C test is linked only to tests/c_api.rs, which declares the library
+    cc::Build::new()
+        .file("tests/c/roundtrip.c")
+        .include("include")
+        .warnings_into_errors(true)
+        .cargo_metadata(false)
+        .compile("memcrs_c_tests");
+    println!(
+        "cargo:rustc-link-search=native={}",
+        env::var("OUT_DIR").unwrap()
+    );
+}
@@ -0,0 +1,92 @@
+#ifndef MEMCRS_H
+#define MEMCRS_H
+
+/* Generated with cbindgen from src/lib.rs, don't edit manually. */
+
+#include <stdint.h>
+#include <stddef.h>
+
+#define MEMCRS_OK 0
+
+#define MEMCRS_NOT_FOUND 1
+
+#define MEMCRS_KEY_EXISTS 2
+
+#define MEMCRS_VALUE_TOO_LARGE 3
+
+#define MEMCRS_INVALID_ARGUMENTS 4
+
+#define MEMCRS_OUT_OF_MEMORY 130
+
+/**
+ * Opaque handle of a storage
+ */
+typedef struct MemcrsStorage MemcrsStorage;
+
+/**
+ * Creates a storage which holds up to memory_limit bytes of keys
+ * and values, it has to be released with memcrs_destroy
+ */
+MemcrsStorage *memcrs_storage_create(uint64_t memory_limit);
+
+/**
+ * Releases a storage and all its records
+ *
+ * # Safety
+ *
+ * storage has to be returned by memcrs_storage_create and not used
+ * after this call, null is ignored
+ */
+void memcrs_destroy(MemcrsStorage *storage);
+
+/**
+ * Copies a value of a key to a buffer allocated by memcrs, which has
+ * to be released with memcrs_free_value
+ *
+ * # Safety
+ *
+ * storage has to be a live storage, key has to point to key_len bytes,
+ * value_out and value_len_out have to be valid for writes
+ */
+int memcrs_get(const MemcrsStorage *storage,
+               const uint8_t *key,
+               size_t key_len,
+               uint8_t **value_out,
+               size_t *value_len_out);
+
+/**
+ * Stores a value of a key, expiration is in seconds, 0 never expires
+ *
+ * # Safety
+ *
+ * storage has to be a live storage, key and value have to point
+ * to key_len and value_len bytes
+ */
+int memcrs_set(const MemcrsStorage *storage,
+               const uint8_t *key,
+               size_t key_len,
+               const uint8_t *value,
+               size_t value_len,
+               uint32_t flags,
+               uint32_t expiration);
+
+/**
+ * Removes a key, MEMCRS_NOT_FOUND is returned if it doesn't exist
+ *
+ * # Safety
+ *
+ * storage has to be a live storage, key has to point to key_len bytes
+ */
+int memcrs_delete(const MemcrsStorage *storage, const uint8_t *key, size_t key_len);
+
+/**
+ * Releases a value returned by memcrs_get
+ *
+ * # Safety
+ *
+ * value and value_len have to be returned by memcrs_get and value
+ * is not used after this call, null is ignored
+ */
+void memcrs_free_value(uint8_t *value, size_t value_len);
+
+#endif /* MEMCRS_H */
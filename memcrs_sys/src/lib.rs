@@ -0,0 +1,199 @@
+//! C interface of memcrs storage, so it can be embedded as an in-process
+//! cache in applications written in other languages. Declarations are
+//! in include/memcrs.h, generated with cbindgen from this file.
+//!
+//! Functions return MEMCRS_OK or a status with the same value as
+//! a memcached binary protocol status, i.e. MEMCRS_NOT_FOUND.
+//!
+//! memcrs denies unsafe code, pointers passed over the C interface are
+//! converted to references only in this crate. Each exported function
+//! documents what a caller has to guarantee under Safety.
+
+use bytes::Bytes;
+use memcrs::cache::error::CacheError;
+use memcrs::memcache::builder::{MemcacheStoreBuilder, MemcacheStoreConfig};
+use memcrs::memcache::store::{MemcStore, Meta, Record};
+use memcrs::server::timer::Timer;
+use std::os::raw::c_int;
+use std::ptr;
+use std::slice;
+use std::sync::Arc;
+use std::time::Instant;
+
+pub const MEMCRS_OK: c_int = 0;
+pub const MEMCRS_NOT_FOUND: c_int = CacheError::NotFound.code() as c_int;
+pub const MEMCRS_KEY_EXISTS: c_int = CacheError::KeyExists.code() as c_int;
+pub const MEMCRS_VALUE_TOO_LARGE: c_int = CacheError::ValueTooLarge.code() as c_int;
+pub const MEMCRS_INVALID_ARGUMENTS: c_int = CacheError::InvalidArguments.code() as c_int;
+pub const MEMCRS_OUT_OF_MEMORY: c_int = CacheError::OutOfMemory.code() as c_int;
+
+/// Opaque handle of a storage
+pub struct MemcrsStorage {
+    store: MemcStore,
+}
+
+// there is no runtime ticking SystemTimer in an embedding application,
+// so seconds are counted from storage creation on each call
+struct ElapsedTimer {
+    start: Instant,
+}
+
+impl Timer for ElapsedTimer {
+    fn timestamp(&self) -> u64 {
+        self.start.elapsed().as_secs()
+    }
+
+    fn now_instant(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn micros(&self) -> u64 {
+        self.start.elapsed().as_micros() as u64
+    }
+}
+
+fn status<T>(result: Result<T, CacheError>) -> c_int {
+    match result {
+        Ok(_) => MEMCRS_OK,
+        Err(CacheError::InvalidKey) => MEMCRS_INVALID_ARGUMENTS,
+        Err(err) => err.code() as c_int,
+    }
+}
+
+// empty slices may be passed as null pointers
+unsafe fn as_bytes<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+    if len == 0 {
+        return Some(&[]);
+    }
+    if data.is_null() {
+        return None;
+    }
+    // SAFETY: a caller of an exported function guarantees data points
+    // to len readable bytes, which live at least as long as the call
+    Some(slice::from_raw_parts(data, len))
+}
+
+/// Creates a storage which holds up to memory_limit bytes of keys
+/// and values, it has to be released with memcrs_destroy
+#[no_mangle]
+pub extern "C" fn memcrs_storage_create(memory_limit: u64) -> *mut MemcrsStorage {
+    let timer = Arc::new(ElapsedTimer {
+        start: Instant::now(),
+    });
+    let config = MemcacheStoreConfig::new(memory_limit);
+    let store = MemcStore::new(MemcacheStoreBuilder::from_config(config, timer));
+    Box::into_raw(Box::new(MemcrsStorage { store }))
+}
+
+/// Releases a storage and all its records
+///
+/// # Safety
+///
+/// storage has to be returned by memcrs_storage_create and not used
+/// after this call, null is ignored
+#[no_mangle]
+pub unsafe extern "C" fn memcrs_destroy(storage: *mut MemcrsStorage) {
+    if !storage.is_null() {
+        // SAFETY: storage was created by Box::into_raw in memcrs_storage_create
+        drop(Box::from_raw(storage));
+    }
+}
+
+/// Copies a value of a key to a buffer allocated by memcrs, which has
+/// to be released with memcrs_free_value
+///
+/// # Safety
+///
+/// storage has to be a live storage, key has to point to key_len bytes,
+/// value_out and value_len_out have to be valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn memcrs_get(
+    storage: *const MemcrsStorage,
+    key: *const u8,
+    key_len: usize,
+    value_out: *mut *mut u8,
+    value_len_out: *mut usize,
+) -> c_int {
+    let (storage, key) = match (storage.as_ref(), as_bytes(key, key_len)) {
+        (Some(storage), Some(key)) => (storage, key),
+        _ => return MEMCRS_INVALID_ARGUMENTS,
+    };
+    if value_out.is_null() || value_len_out.is_null() {
+        return MEMCRS_INVALID_ARGUMENTS;
+    }
+    match storage.store.get_value_bytes(&Bytes::copy_from_slice(key)) {
+        Ok(value) => {
+            let value: Box<[u8]> = value.to_vec().into_boxed_slice();
+            *value_len_out = value.len();
+            *value_out = Box::into_raw(value) as *mut u8;
+            MEMCRS_OK
+        }
+        Err(err) => err.code() as c_int,
+    }
+}
+
+/// Stores a value of a key, expiration is in seconds, 0 never expires
+///
+/// # Safety
+///
+/// storage has to be a live storage, key and value have to point
+/// to key_len and value_len bytes
+#[no_mangle]
+pub unsafe extern "C" fn memcrs_set(
+    storage: *const MemcrsStorage,
+    key: *const u8,
+    key_len: usize,
+    value: *const u8,
+    value_len: usize,
+    flags: u32,
+    expiration: u32,
+) -> c_int {
+    match (
+        storage.as_ref(),
+        as_bytes(key, key_len),
+        as_bytes(value, value_len),
+    ) {
+        (Some(storage), Some(key), Some(value)) => {
+            let record = Record::new(Bytes::copy_from_slice(value), 0, flags, expiration);
+            status(storage.store.set(Bytes::copy_from_slice(key), record))
+        }
+        _ => MEMCRS_INVALID_ARGUMENTS,
+    }
+}
+
+/// Removes a key, MEMCRS_NOT_FOUND is returned if it doesn't exist
+///
+/// # Safety
+///
+/// storage has to be a live storage, key has to point to key_len bytes
+#[no_mangle]
+pub unsafe extern "C" fn memcrs_delete(
+    storage: *const MemcrsStorage,
+    key: *const u8,
+    key_len: usize,
+) -> c_int {
+    match (storage.as_ref(), as_bytes(key, key_len)) {
+        (Some(storage), Some(key)) => status(
+            storage
+                .store
+                .delete(Bytes::copy_from_slice(key), Meta::new(0, 0, 0)),
+        ),
+        _ => MEMCRS_INVALID_ARGUMENTS,
+    }
+}
+
+/// Releases a value returned by memcrs_get
+///
+/// # Safety
+///
+/// value and value_len have to be returned by memcrs_get and value
+/// is not used after this call, null is ignored
+#[no_mangle]
+pub unsafe extern "C" fn memcrs_free_value(value: *mut u8, value_len: usize) {
+    if !value.is_null() {
+        // SAFETY: value is a boxed slice of value_len bytes leaked by memcrs_get
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            value, value_len,
+        )));
+    }
+}
@@ -0,0 +1,33 @@
+#include <string.h>
+
+#include "memcrs.h"
+
+/* returns 0 on success, otherwise number of a failed check */
+int memcrs_c_roundtrip_test(void) {
+    const uint8_t key[] = "key";
+    const uint8_t value[] = "value";
+    uint8_t *found = NULL;
+    size_t found_len = 0;
+    int result = 0;
+
+    MemcrsStorage *storage = memcrs_storage_create(1024 * 1024);
+    if (storage == NULL) {
+        return 1;
+    }
+    if (memcrs_get(storage, key, 3, &found, &found_len) != MEMCRS_NOT_FOUND) {
+        result = 2;
+    } else if (memcrs_set(storage, key, 3, value, 5, 0, 0) != MEMCRS_OK) {
+        result = 3;
+    } else if (memcrs_get(storage, key, 3, &found, &found_len) != MEMCRS_OK) {
+        result = 4;
+    } else if (found_len != 5 || memcmp(found, value, 5) != 0) {
+        result = 5;
+    } else if (memcrs_delete(storage, key, 3) != MEMCRS_OK) {
+        result = 6;
+    } else if (memcrs_delete(storage, key, 3) != MEMCRS_NOT_FOUND) {
+        result = 7;
+    }
+    memcrs_free_value(found, found_len);
+    memcrs_destroy(storage);
+    return result;
+}
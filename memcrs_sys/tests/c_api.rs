@@ -0,0 +1,14 @@
+use std::os::raw::c_int;
+
+// links the C library to the memcrs_* functions
+extern crate memcrs_sys;
+
+#[link(name = "memcrs_c_tests", kind = "static")]
+extern "C" {
+    fn memcrs_c_roundtrip_test() -> c_int;
+}
+
+#[test]
+fn c_roundtrip_should_set_get_and_delete_value() {
+    assert_eq!(unsafe { memcrs_c_roundtrip_test() }, 0);
+}